@@ -0,0 +1,67 @@
+//! Compare the coalescing backends under many concurrent misses for distinct
+//! keys
+//!
+//! Run with `cargo bench --bench coalesce`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::{
+    convert::Infallible,
+    future::{ready, Future, Ready},
+    pin::pin,
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+use tower::{service_fn, Layer, Service};
+use tower_cache::{noop::NoopProvider, CacheLayer};
+
+const THREADS: usize = 8;
+const SHARDS: usize = 64;
+
+type Provider = NoopProvider<'static, u64, u64>;
+
+fn echo(req: u64) -> Ready<Result<u64, Infallible>> {
+    ready(Ok(req))
+}
+
+/// Send `iters` requests for distinct keys on each thread
+///
+/// The provider never stores anything, so every request is a miss that goes
+/// through the map of in-flight requests.
+fn distinct_misses(layer: &CacheLayer<'static, Provider, ()>, iters: u64) -> Duration {
+    let start = Instant::now();
+    thread::scope(|s| {
+        for t in 0..THREADS as u64 {
+            s.spawn(move || {
+                let mut service = layer.layer(service_fn(echo));
+                for i in 0..iters {
+                    let fut = pin!(service.call(t * iters + i));
+                    match fut.poll(&mut Context::from_waker(Waker::noop())) {
+                        Poll::Ready(res) => res.unwrap(),
+                        Poll::Pending => unreachable!("the inner service is always ready"),
+                    };
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+fn bench_coalesce(c: &mut Criterion) {
+    let mut group = c.benchmark_group("distinct_misses");
+
+    let single = CacheLayer::new(NoopProvider::new::<u64, u64>()).coalesce(true);
+    group.bench_function(BenchmarkId::new("single", THREADS), |b| {
+        b.iter_custom(|iters| distinct_misses(&single, iters))
+    });
+
+    let sharded = CacheLayer::new(NoopProvider::new::<u64, u64>()).coalesce_sharded(SHARDS);
+    group.bench_function(BenchmarkId::new("sharded", THREADS), |b| {
+        b.iter_custom(|iters| distinct_misses(&sharded, iters))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_coalesce);
+criterion_main!(benches);
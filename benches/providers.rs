@@ -0,0 +1,94 @@
+//! Compare in-memory cache providers under parallel load
+//!
+//! Run with `cargo bench --all-features`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+use tower::Service;
+use tower_cache::{dash::DashProvider, lru::LruProvider, ProviderRequest, ProviderResponse};
+
+const KEYS: u64 = 1_000;
+const THREADS: usize = 8;
+
+/// Call an in-memory provider, whose futures are always immediately ready
+fn call<P>(provider: &mut P, request: ProviderRequest<u64, u64>) -> ProviderResponse<u64, u64>
+where
+    P: Service<ProviderRequest<u64, u64>, Response = ProviderResponse<u64, u64>>,
+    P::Future: Unpin,
+    P::Error: std::fmt::Debug,
+{
+    let mut fut = provider.call(request);
+    match Pin::new(&mut fut).poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(res) => res.unwrap(),
+        Poll::Pending => unreachable!("in-memory providers are always ready"),
+    }
+}
+
+/// Run `iters` operations on each thread, with one write every `write_every`
+/// operations
+fn parallel_load<P>(provider: &P, iters: u64, write_every: u64) -> Duration
+where
+    P: Service<ProviderRequest<u64, u64>, Response = ProviderResponse<u64, u64>> + Clone + Send,
+    P::Future: Unpin,
+    P::Error: std::fmt::Debug,
+{
+    let start = Instant::now();
+    thread::scope(|s| {
+        for t in 0..THREADS as u64 {
+            let mut provider = provider.clone();
+            s.spawn(move || {
+                for i in 0..iters {
+                    let key = (i * 7 + t * 13) % KEYS;
+                    if i % write_every == 0 {
+                        call(&mut provider, ProviderRequest::Insert(key, i, None));
+                    } else {
+                        call(&mut provider, ProviderRequest::Get(key));
+                    }
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+fn bench_providers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parallel_load");
+
+    let lru = LruProvider::new::<u64, u64>(KEYS as usize);
+    group.bench_function(BenchmarkId::new("lru", THREADS), |b| {
+        b.iter_custom(|iters| parallel_load(&lru, iters, 10))
+    });
+
+    let dash = DashProvider::new::<u64, u64>();
+    group.bench_function(BenchmarkId::new("dash", THREADS), |b| {
+        b.iter_custom(|iters| parallel_load(&dash, iters, 10))
+    });
+
+    group.finish();
+}
+
+fn bench_lru_reads(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parallel_reads");
+
+    // One write every 1000 operations, to simulate a high hit rate
+    let lru = LruProvider::new::<u64, u64>(KEYS as usize);
+    group.bench_function(BenchmarkId::new("lru", THREADS), |b| {
+        b.iter_custom(|iters| parallel_load(&lru, iters, 1_000))
+    });
+
+    let lru_peek = LruProvider::new::<u64, u64>(KEYS as usize).peek_reads(true);
+    group.bench_function(BenchmarkId::new("lru_peek", THREADS), |b| {
+        b.iter_custom(|iters| parallel_load(&lru_peek, iters, 1_000))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_providers, bench_lru_reads);
+criterion_main!(benches);
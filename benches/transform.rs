@@ -0,0 +1,108 @@
+//! Compare cache hits with a synchronous and an asynchronous transformer
+//!
+//! Both transformers derive the same key. The future of the transformer is
+//! stored inside the future of the service, so both should make the same
+//! number of allocations per request, which is printed before the timings.
+//!
+//! Run with `cargo bench --bench transform`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    convert::Infallible,
+    future::{ready, Future, Ready},
+    pin::pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll, Waker},
+};
+use tower::{service_fn, Layer, Service};
+use tower_cache::{lru::LruProvider, CacheLayer};
+
+const KEYS: u64 = 100;
+
+/// Global allocator counting allocations
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn echo(req: u64) -> Ready<Result<u64, Infallible>> {
+    ready(Ok(req))
+}
+
+/// Call the service, whose provider and inner service are always ready
+fn call<S>(service: &mut S, req: u64) -> u64
+where
+    S: Service<u64, Response = u64>,
+    S::Error: std::fmt::Debug,
+{
+    let fut = pin!(service.call(req));
+    match fut.poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(res) => res.unwrap(),
+        Poll::Pending => unreachable!("the provider and inner service are always ready"),
+    }
+}
+
+/// Fill the cache, then return the average number of allocations per hit
+fn allocations_per_hit<S>(service: &mut S) -> f64
+where
+    S: Service<u64, Response = u64>,
+    S::Error: std::fmt::Debug,
+{
+    for key in 0..KEYS {
+        call(service, key);
+    }
+    let start = ALLOCATIONS.load(Ordering::Relaxed);
+    for key in 0..KEYS {
+        call(service, key);
+    }
+    (ALLOCATIONS.load(Ordering::Relaxed) - start) as f64 / KEYS as f64
+}
+
+fn bench_transform(c: &mut Criterion) {
+    let mut sync = CacheLayer::new(LruProvider::new::<u64, u64>(KEYS as usize))
+        .with_transform(|req: u64| req + 1)
+        .layer(service_fn(echo));
+    let mut async_ = CacheLayer::new(LruProvider::new::<u64, u64>(KEYS as usize))
+        .with_async_transformer(|req: u64| async move { req + 1 })
+        .layer(service_fn(echo));
+
+    println!(
+        "allocations per hit: sync {:.1}, async {:.1}",
+        allocations_per_hit(&mut sync),
+        allocations_per_hit(&mut async_),
+    );
+
+    let mut group = c.benchmark_group("hit");
+    group.bench_function("sync", |b| {
+        let mut key = 0;
+        b.iter(|| {
+            key = (key + 1) % KEYS;
+            call(&mut sync, key)
+        })
+    });
+    group.bench_function("async", |b| {
+        let mut key = 0;
+        b.iter(|| {
+            key = (key + 1) % KEYS;
+            call(&mut async_, key)
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_transform);
+criterion_main!(benches);
@@ -0,0 +1,595 @@
+//! # ARC cache provider
+//!
+//! This is an implementation of a cache provider for [`crate::CacheLayer`]
+//! using the [Adaptive Replacement Cache][arc] algorithm. It balances between
+//! recently and frequently used entries, so that a scan through many keys
+//! used only once doesn't evict the keys that are used again and again.
+//!
+//! Entries found once are kept in a recency list (T1), and entries found
+//! again are moved to a frequency list (T2). The provider also remembers the
+//! keys recently evicted from each list, without their values. When one of
+//! these keys is inserted again, the provider grows the share of the
+//! capacity given to the list it was evicted from. This tunes the provider
+//! to the workload without any configuration beyond its capacity.
+//!
+//! The provider keeps up to `capacity` evicted keys, in addition to the
+//! `capacity` cached entries.
+//!
+//! [arc]: https://www.usenix.org/conference/fast-03/arc-self-tuning-low-overhead-replacement-cache
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, service_fn};
+//! use tower_cache::{
+//!     CacheLayer,
+//!     arc::ArcProvider,
+//! };
+//! async fn handler(req: String) -> Result<String, Infallible> {
+//!     Ok(req.to_uppercase())
+//! }
+//!
+//! // Initialize the cache provider service
+//! let arc_provider = ArcProvider::new::<String, String>(20);
+//!
+//! // Wrap the service with CacheLayer.
+//! let mut my_service = ServiceBuilder::new()
+//!     .layer(CacheLayer::new(arc_provider))
+//!     .service(service_fn(handler));
+//!
+//! # tokio_test::block_on(async move {
+//! // Call the service
+//! let res = my_service.call("Hello".to_string()).await.unwrap();
+//! assert_eq!(res, "HELLO".to_string());
+//! # })
+//! ```
+//!
+
+use crate::{
+    entry::Entry, time, trace, Configure, ProviderConfig, ProviderRequest, ProviderResponse,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
+    future::{ready, Future},
+    hash::Hash,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower::Service;
+
+/// Local ARC cache provider
+#[derive(Debug)]
+pub struct ArcProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    inner: Arc<Mutex<Adaptive<K, V>>>,
+    ttl: Option<Duration>,
+    stale_window: Option<Duration>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> ArcProvider<'a, (), ()> {
+    /// Create a new ARC cache provider with the desired capacity
+    ///
+    /// A capacity of `0` is clamped to `1`.
+    pub fn new<K, V>(capacity: usize) -> ArcProvider<'a, K, V>
+    where
+        K: Eq + Hash,
+    {
+        ArcProvider {
+            inner: Arc::new(Mutex::new(Adaptive::new(capacity.max(1)))),
+            ttl: None,
+            stale_window: None,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Create a new ARC cache provider where entries expire after `ttl`
+    ///
+    /// As with [`ArcProvider::new`], a capacity of `0` is clamped to `1`.
+    pub fn with_ttl<K, V>(capacity: usize, ttl: Duration) -> ArcProvider<'a, K, V>
+    where
+        K: Eq + Hash,
+    {
+        ArcProvider {
+            ttl: Some(ttl),
+            ..Self::new(capacity)
+        }
+    }
+}
+
+impl<'a, K, V> ArcProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    /// Keep entries for `window` after they expire, and return them as
+    /// [`ProviderResponse::FoundStale`] during that time.
+    ///
+    /// This allows [`crate::CacheLayer::stale_while_revalidate`] to serve
+    /// them while they are refreshed. Entries without a TTL never become
+    /// stale.
+    pub fn stale_window(mut self, window: Duration) -> Self {
+        self.stale_window = Some(window);
+        self
+    }
+}
+
+// Custom implementation of Clone as the Clone derive doesn't mark ArcProvider
+// as Clone if K or V is not clone.
+impl<'a, K, V> Clone for ArcProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            ttl: self.ttl,
+            stale_window: self.stale_window,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+/// Sets the TTL. Setting the capacity clears the cache, for all clones of the
+/// provider, so it should be done before using it.
+impl<'a, K, V> Configure for ArcProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    fn configure(mut self, config: &ProviderConfig) -> Self {
+        if let Some(ttl) = config.ttl {
+            self.ttl = Some(ttl);
+        }
+        if let Some(capacity) = config.capacity {
+            // Caches hold at least one entry.
+            if capacity == 0 {
+                trace::unsupported_config::<Self>("capacity");
+            }
+            *self.inner.lock().unwrap() = Adaptive::new(capacity.max(1));
+        }
+        self
+    }
+}
+
+impl<'a, K, V> Service<ProviderRequest<K, V>> for ArcProvider<'a, K, V>
+where
+    K: Eq + Hash + Clone + Send + 'a,
+    V: Clone + Send + 'a,
+{
+    type Response = ProviderResponse<K, V>;
+    type Error = Infallible;
+    type Future = ProviderFuture<'a, K, V>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ProviderRequest<K, V>) -> Self::Future {
+        let mut inner = self.inner.lock().unwrap();
+        Box::pin(ready(Ok(match request {
+            ProviderRequest::Get(key) => {
+                let now = time::now();
+                match inner.get(&key).map(|entry| entry.response_at(now)) {
+                    Some(Some(response)) => response,
+                    // The entry has expired: remove it so it doesn't take up
+                    // capacity anymore.
+                    Some(None) => {
+                        inner.remove(&key);
+                        ProviderResponse::NotFound
+                    }
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            ProviderRequest::Insert(key, value, ttl) => {
+                let entry =
+                    Entry::new(value.clone(), ttl.or(self.ttl)).stale_for(self.stale_window);
+                inner.insert(key, entry);
+                ProviderResponse::Found(value)
+            }
+            ProviderRequest::InsertNegative(key, ttl) => {
+                inner.insert(key, Entry::negative(ttl));
+                ProviderResponse::FoundNegative
+            }
+            ProviderRequest::Clear => {
+                inner.clear();
+                ProviderResponse::Cleared
+            }
+            ProviderRequest::Remove(key) => match inner.remove(&key) {
+                Some(_) => ProviderResponse::Removed,
+                None => ProviderResponse::NotFound,
+            },
+            // Peek at the entry to avoid moving it to the frequency list.
+            ProviderRequest::Contains(key) => {
+                let now = time::now();
+                let present = inner.peek(&key).is_some_and(|entry| !entry.is_expired(now));
+                ProviderResponse::Present(present)
+            }
+            ProviderRequest::Ttl(key) => {
+                let now = time::now();
+                match inner.peek(&key).and_then(|entry| entry.ttl_at(now)) {
+                    Some((remaining, ttl)) => ProviderResponse::Ttl(remaining, ttl),
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            ProviderRequest::Age(key) => {
+                let now = time::now();
+                match inner.peek(&key).and_then(|entry| entry.age_at(now)) {
+                    Some(age) => ProviderResponse::Age(age),
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            ProviderRequest::GetOrInsert(key, value) => {
+                let now = time::now();
+                match inner.get(&key).and_then(|entry| entry.fresh_value_at(now)) {
+                    Some(existing) => ProviderResponse::Found(existing.clone()),
+                    None => {
+                        let entry =
+                            Entry::new(value.clone(), self.ttl).stale_for(self.stale_window);
+                        inner.insert(key, entry);
+                        ProviderResponse::Inserted(value)
+                    }
+                }
+            }
+            ProviderRequest::GetMany(keys) => {
+                let now = time::now();
+                ProviderResponse::Many(
+                    keys.into_iter()
+                        .map(|key| {
+                            let value = inner
+                                .get(&key)
+                                .and_then(|entry| entry.value_at(now))
+                                .cloned();
+                            (key, value)
+                        })
+                        .collect(),
+                )
+            }
+            // Inserting entries one by one is as cheap as a batch in memory.
+            ProviderRequest::InsertMany(_) => ProviderResponse::NotFound,
+            // Evictions are not counted.
+            ProviderRequest::Stats => ProviderResponse::NotFound,
+        })))
+    }
+}
+
+type ProviderFuture<'a, K, V> =
+    Pin<Box<dyn Future<Output = Result<ProviderResponse<K, V>, Infallible>> + Send + 'a>>;
+
+/// Lists of an [`Adaptive`] cache
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum List {
+    /// Entries found once since they were inserted
+    T1,
+    /// Entries found at least twice
+    T2,
+    /// Keys evicted from T1
+    B1,
+    /// Keys evicted from T2
+    B2,
+}
+
+/// Entries of an [`ArcProvider`], with the keys of recently evicted entries
+#[derive(Debug)]
+struct Adaptive<K, V> {
+    entries: HashMap<K, Slot<V>>,
+    /// Position of the keys evicted from T1 or T2
+    ghosts: HashMap<K, (List, u64)>,
+    /// Keys of each list, from least to most recently used
+    lists: [BTreeMap<u64, K>; 4],
+    /// Target size of T1, between `0` and `capacity`
+    target: usize,
+    capacity: usize,
+    tick: u64,
+}
+
+#[derive(Debug)]
+struct Slot<V> {
+    entry: Entry<V>,
+    list: List,
+    tick: u64,
+}
+
+impl<K, V> Adaptive<K, V>
+where
+    K: Eq + Hash,
+{
+    fn new(capacity: usize) -> Self {
+        Adaptive {
+            entries: HashMap::new(),
+            ghosts: HashMap::new(),
+            lists: Default::default(),
+            target: 0,
+            capacity,
+            tick: 0,
+        }
+    }
+
+    fn len(&self, list: List) -> usize {
+        self.lists[list as usize].len()
+    }
+
+    /// Add `key` as the most recently used key of `list`
+    fn push(&mut self, list: List, key: K) -> u64 {
+        self.tick += 1;
+        self.lists[list as usize].insert(self.tick, key);
+        self.tick
+    }
+
+    /// Return the entry for `key`, moving it to the frequency list
+    fn get(&mut self, key: &K) -> Option<&Entry<V>> {
+        let slot = self.entries.get(key)?;
+        let k = self.lists[slot.list as usize]
+            .remove(&slot.tick)
+            .expect("every entry is listed");
+        let tick = self.push(List::T2, k);
+        let slot = self.entries.get_mut(key).expect("the entry exists");
+        slot.list = List::T2;
+        slot.tick = tick;
+        Some(&slot.entry)
+    }
+
+    /// Return the entry for `key` without counting it as a use
+    fn peek(&self, key: &K) -> Option<&Entry<V>> {
+        self.entries.get(key).map(|slot| &slot.entry)
+    }
+
+    /// Store `entry`, evicting an entry if full
+    ///
+    /// Replacing an entry counts as a use. Inserting a key that was recently
+    /// evicted adapts the target size of the recency list.
+    fn insert(&mut self, key: K, entry: Entry<V>)
+    where
+        K: Clone,
+    {
+        if let Some(slot) = self.entries.get_mut(&key) {
+            slot.entry = entry;
+            self.get(&key);
+            return;
+        }
+
+        let list = match self.ghosts.remove(&key) {
+            Some((ghost, tick)) => {
+                let (b1, b2) = (self.len(List::B1), self.len(List::B2));
+                self.lists[ghost as usize].remove(&tick);
+                // Give more room to the list the key was evicted from.
+                self.target = match ghost {
+                    List::B1 => (self.target + (b2 / b1).max(1)).min(self.capacity),
+                    _ => self.target.saturating_sub((b1 / b2).max(1)),
+                };
+                self.replace(ghost == List::B2);
+                List::T2
+            }
+            None => {
+                if self.len(List::T1) + self.len(List::B1) >= self.capacity {
+                    if self.len(List::T1) < self.capacity {
+                        self.forget(List::B1);
+                        self.replace(false);
+                    } else {
+                        // T1 takes up the whole cache, so its oldest entry is
+                        // dropped without being remembered.
+                        if let Some((_, evicted)) = self.lists[List::T1 as usize].pop_first() {
+                            self.entries.remove(&evicted);
+                        }
+                    }
+                } else if self.entries.len() + self.ghosts.len() >= self.capacity {
+                    if self.entries.len() + self.ghosts.len() >= 2 * self.capacity {
+                        self.forget(List::B2);
+                    }
+                    self.replace(false);
+                }
+                List::T1
+            }
+        };
+
+        let tick = self.push(list, key.clone());
+        self.entries.insert(key, Slot { entry, list, tick });
+    }
+
+    /// Evict an entry from T1 or T2 if the cache is full, depending on the
+    /// target size of T1
+    fn replace(&mut self, in_b2: bool)
+    where
+        K: Clone,
+    {
+        if self.entries.len() < self.capacity {
+            return;
+        }
+        let t1 = self.len(List::T1);
+        let (from, to) = match t1 > 0 && (t1 > self.target || (in_b2 && t1 == self.target)) {
+            true => (List::T1, List::B1),
+            false if self.len(List::T2) > 0 => (List::T2, List::B2),
+            false => (List::T1, List::B1),
+        };
+        if let Some((_, evicted)) = self.lists[from as usize].pop_first() {
+            self.entries.remove(&evicted);
+            let tick = self.push(to, evicted.clone());
+            self.ghosts.insert(evicted, (to, tick));
+        }
+    }
+
+    /// Forget the least recently evicted key of a ghost list
+    fn forget(&mut self, list: List) {
+        if let Some((_, key)) = self.lists[list as usize].pop_first() {
+            self.ghosts.remove(&key);
+        }
+    }
+
+    fn remove(&mut self, key: &K) -> Option<Entry<V>> {
+        let slot = self.entries.remove(key)?;
+        self.lists[slot.list as usize].remove(&slot.tick);
+        Some(slot.entry)
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.ghosts.clear();
+        self.lists.iter_mut().for_each(BTreeMap::clear);
+        self.target = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::{check_configure, check_provider};
+
+    #[tokio::test]
+    async fn test_conformance() -> Result<(), Infallible> {
+        check_provider(ArcProvider::new::<String, String>(10)).await
+    }
+
+    /// Insert and reuse two keys, then scan through many keys used only once
+    async fn scan_then_reuse<P>(provider: &mut P) -> Result<(), Infallible>
+    where
+        P: Service<ProviderRequest<usize, usize>, Error = Infallible>,
+    {
+        for key in [0, 1] {
+            provider
+                .call(ProviderRequest::Insert(key, key, None))
+                .await?;
+            provider.call(ProviderRequest::Get(key)).await?;
+        }
+        for key in 100..120 {
+            provider
+                .call(ProviderRequest::Insert(key, key, None))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_scan_keeps_reused_keys() -> Result<(), Infallible> {
+        let mut provider = ArcProvider::new::<usize, usize>(4);
+        scan_then_reuse(&mut provider).await?;
+
+        for key in [0, 1] {
+            let res = provider.call(ProviderRequest::Get(key)).await?;
+            assert!(matches!(res, ProviderResponse::Found(v) if v == key));
+        }
+        // The scan only took the rest of the capacity.
+        let res = provider.call(ProviderRequest::Contains(119)).await?;
+        assert!(matches!(res, ProviderResponse::Present(true)));
+        let res = provider.call(ProviderRequest::Contains(100)).await?;
+        assert!(matches!(res, ProviderResponse::Present(false)));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "lru")]
+    #[tokio::test]
+    async fn test_scan_evicts_lru() -> Result<(), Infallible> {
+        // Same sequence with an LRU provider: the scan evicts reused keys.
+        let mut provider = crate::lru::LruProvider::new::<usize, usize>(4);
+        scan_then_reuse(&mut provider).await?;
+
+        for key in [0, 1] {
+            let res = provider.call(ProviderRequest::Get(key)).await?;
+            assert!(matches!(res, ProviderResponse::NotFound));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_adapts_to_recency() -> Result<(), Infallible> {
+        let mut provider = ArcProvider::new::<usize, usize>(4);
+        scan_then_reuse(&mut provider).await?;
+        assert_eq!(provider.inner.lock().unwrap().target, 0);
+
+        // Reinserting a key recently evicted from T1 grows its target size.
+        let ghost = *provider.inner.lock().unwrap().lists[List::B1 as usize]
+            .values()
+            .last()
+            .unwrap();
+        provider
+            .call(ProviderRequest::Insert(ghost, ghost, None))
+            .await?;
+        assert_eq!(provider.inner.lock().unwrap().target, 1);
+        let res = provider.call(ProviderRequest::Contains(ghost)).await?;
+        assert!(matches!(res, ProviderResponse::Present(true)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bounded() -> Result<(), Infallible> {
+        let mut provider = ArcProvider::new::<usize, usize>(3);
+
+        for i in 0..100 {
+            provider
+                .call(ProviderRequest::Insert(i % 7, i, None))
+                .await?;
+            provider.call(ProviderRequest::Get(i % 5)).await?;
+
+            let inner = provider.inner.lock().unwrap();
+            assert!(inner.entries.len() <= 3);
+            assert!(inner.entries.len() + inner.ghosts.len() <= 6);
+            assert_eq!(
+                inner.lists.iter().map(BTreeMap::len).sum::<usize>(),
+                inner.entries.len() + inner.ghosts.len()
+            );
+            assert!(inner.target <= 3);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ttl_expires() -> Result<(), Infallible> {
+        let mut provider = ArcProvider::with_ttl::<String, String>(10, Duration::ZERO);
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        assert!(provider.inner.lock().unwrap().entries.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remove_clear() -> Result<(), Infallible> {
+        let mut provider = ArcProvider::new::<usize, usize>(10);
+
+        provider.call(ProviderRequest::Insert(1, 1, None)).await?;
+        provider.call(ProviderRequest::Insert(2, 2, None)).await?;
+
+        let res = provider.call(ProviderRequest::Remove(1)).await?;
+        assert!(matches!(res, ProviderResponse::Removed));
+        let res = provider.call(ProviderRequest::Remove(1)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        let res = provider.call(ProviderRequest::Clear).await?;
+        assert!(matches!(res, ProviderResponse::Cleared));
+        let res = provider.call(ProviderRequest::Get(2)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        assert!(provider
+            .inner
+            .lock()
+            .unwrap()
+            .lists
+            .iter()
+            .all(BTreeMap::is_empty));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_configure() -> Result<(), Infallible> {
+        let configure =
+            |config: &ProviderConfig| ArcProvider::new::<usize, usize>(10).configure(config);
+        check_configure(configure, true).await
+    }
+}
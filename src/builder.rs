@@ -0,0 +1,408 @@
+use crate::{
+    BypassIf, CacheLayer, CacheToggle, Config, Configure, Inflight, Metrics, NegativeCache,
+    ProviderConfig, Refresh, StatsHandle, WithMaxAge,
+};
+use std::{error, fmt, marker::PhantomData, time::Duration};
+
+/// Builder for a [`CacheLayer`]
+///
+/// Created with [`CacheLayer::builder`]. A provider must be set before
+/// calling [`CacheLayerBuilder::build`]. Other options default to:
+///
+/// * no request transformation,
+/// * no negative caching,
+/// * every response stored in the cache provider,
+/// * the default expiration policy of the cache provider,
+/// * no coalescing of concurrent cache misses,
+/// * provider errors returned to the caller,
+/// * a new [`StatsHandle`], only shared with the services of this layer,
+/// * caching enabled, see [`CacheLayer::toggle_handle`].
+///
+/// ```rust
+/// use std::time::Duration;
+/// use tower_cache::{CacheLayer, map::MapProvider};
+///
+/// let layer = CacheLayer::builder()
+///     .provider(MapProvider::new::<usize, Option<String>>())
+///     .transform(|req: String| req.len())
+///     .coalesce(true)
+///     .cache_negative(Duration::from_secs(10))
+///     .build()
+///     .unwrap();
+/// ```
+pub struct CacheLayerBuilder<'a, P, T = (), N = (), C = (), D = (), L = ()> {
+    provider: Option<P>,
+    transformer: T,
+    negative: N,
+    predicate: C,
+    ttl: D,
+    listener: L,
+    negative_ttl: Option<Duration>,
+    config: Config,
+    inflight: Inflight,
+    stats: Option<StatsHandle>,
+    refresh: Refresh<'a>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> CacheLayerBuilder<'a, ()> {
+    pub(crate) fn new() -> Self {
+        CacheLayerBuilder {
+            provider: None,
+            transformer: (),
+            negative: (),
+            predicate: (),
+            ttl: (),
+            listener: (),
+            negative_ttl: None,
+            config: Config::default(),
+            inflight: Inflight::default(),
+            stats: None,
+            refresh: Refresh::default(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, P, T, N, C, D, L> CacheLayerBuilder<'a, P, T, N, C, D, L> {
+    /// Set the cache provider
+    pub fn provider<NP>(self, provider: NP) -> CacheLayerBuilder<'a, NP, T, N, C, D, L> {
+        CacheLayerBuilder {
+            provider: Some(provider),
+            transformer: self.transformer,
+            negative: self.negative,
+            predicate: self.predicate,
+            ttl: self.ttl,
+            listener: self.listener,
+            negative_ttl: self.negative_ttl,
+            config: self.config,
+            inflight: self.inflight,
+            stats: self.stats,
+            refresh: self.refresh,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Set the cache provider, after applying `config` to it
+    ///
+    /// See [`ProviderConfig`].
+    pub fn provider_with_config<NP>(
+        self,
+        provider: NP,
+        config: &ProviderConfig,
+    ) -> CacheLayerBuilder<'a, NP, T, N, C, D, L>
+    where
+        NP: Configure,
+    {
+        self.provider(provider.configure(config))
+    }
+
+    /// Set the function transforming requests before sending them to the
+    /// cache provider
+    ///
+    /// See [`CacheLayer::with_transformer`].
+    pub fn transform<NT>(self, transformer: NT) -> CacheLayerBuilder<'a, P, NT, N, C, D, L> {
+        CacheLayerBuilder {
+            provider: self.provider,
+            transformer,
+            negative: self.negative,
+            predicate: self.predicate,
+            ttl: self.ttl,
+            listener: self.listener,
+            negative_ttl: self.negative_ttl,
+            config: self.config,
+            inflight: self.inflight,
+            stats: self.stats,
+            refresh: self.refresh,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Set the function returning the maximum age of the cached responses
+    /// served for a request
+    ///
+    /// See [`CacheLayer::with_max_age`].
+    pub fn max_age<F>(self, max_age: F) -> CacheLayerBuilder<'a, P, WithMaxAge<T, F>, N, C, D, L> {
+        let transformer = WithMaxAge::new(self.transformer, max_age);
+        CacheLayerBuilder {
+            provider: self.provider,
+            transformer,
+            negative: self.negative,
+            predicate: self.predicate,
+            ttl: self.ttl,
+            listener: self.listener,
+            negative_ttl: self.negative_ttl,
+            config: self.config,
+            inflight: self.inflight,
+            stats: self.stats,
+            refresh: self.refresh,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Skip the cache lookup for requests matching a predicate, but still
+    /// store the response
+    ///
+    /// See [`CacheLayer::bypass_if`].
+    pub fn bypass_if<F>(
+        self,
+        predicate: F,
+    ) -> CacheLayerBuilder<'a, P, BypassIf<T, F>, N, C, D, L> {
+        let transformer = BypassIf::new(self.transformer, predicate);
+        CacheLayerBuilder {
+            provider: self.provider,
+            transformer,
+            negative: self.negative,
+            predicate: self.predicate,
+            ttl: self.ttl,
+            listener: self.listener,
+            negative_ttl: self.negative_ttl,
+            config: self.config,
+            inflight: self.inflight,
+            stats: self.stats,
+            refresh: self.refresh,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Cache `None` responses from the inner service for `ttl`
+    ///
+    /// See [`CacheLayer::cache_negative`].
+    pub fn cache_negative(
+        self,
+        ttl: Duration,
+    ) -> CacheLayerBuilder<'a, P, T, NegativeCache, C, D, L> {
+        CacheLayerBuilder {
+            negative_ttl: Some(ttl),
+            ..self.negative_policy(NegativeCache::new(ttl))
+        }
+    }
+
+    /// Set the policy to cache negative responses
+    ///
+    /// See [`CacheLayer::with_negative_policy`].
+    pub fn negative_policy<NN>(self, negative: NN) -> CacheLayerBuilder<'a, P, T, NN, C, D, L> {
+        CacheLayerBuilder {
+            provider: self.provider,
+            transformer: self.transformer,
+            negative,
+            predicate: self.predicate,
+            ttl: self.ttl,
+            listener: self.listener,
+            negative_ttl: None,
+            config: self.config,
+            inflight: self.inflight,
+            stats: self.stats,
+            refresh: self.refresh,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Only store responses for which `predicate` returns `true`
+    ///
+    /// See [`CacheLayer::cache_if`].
+    pub fn cache_if<NC>(self, predicate: NC) -> CacheLayerBuilder<'a, P, T, N, NC, D, L> {
+        CacheLayerBuilder {
+            provider: self.provider,
+            transformer: self.transformer,
+            negative: self.negative,
+            predicate,
+            ttl: self.ttl,
+            listener: self.listener,
+            negative_ttl: self.negative_ttl,
+            config: self.config,
+            inflight: self.inflight,
+            stats: self.stats,
+            refresh: self.refresh,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Set the policy deciding how long each response is cached
+    ///
+    /// See [`CacheLayer::with_ttl_policy`].
+    pub fn ttl_policy<ND>(self, ttl: ND) -> CacheLayerBuilder<'a, P, T, N, C, ND, L> {
+        CacheLayerBuilder {
+            provider: self.provider,
+            transformer: self.transformer,
+            negative: self.negative,
+            predicate: self.predicate,
+            ttl,
+            listener: self.listener,
+            negative_ttl: self.negative_ttl,
+            config: self.config,
+            inflight: self.inflight,
+            stats: self.stats,
+            refresh: self.refresh,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Notify a listener of cache events
+    ///
+    /// See [`CacheLayer::on_event`].
+    pub fn on_event<NL>(self, listener: NL) -> CacheLayerBuilder<'a, P, T, N, C, D, NL> {
+        CacheLayerBuilder {
+            provider: self.provider,
+            transformer: self.transformer,
+            negative: self.negative,
+            predicate: self.predicate,
+            ttl: self.ttl,
+            listener,
+            negative_ttl: self.negative_ttl,
+            config: self.config,
+            inflight: self.inflight,
+            stats: self.stats,
+            refresh: self.refresh,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Randomize the TTL of each entry within `[ttl - jitter, ttl + jitter]`
+    ///
+    /// See [`CacheLayer::ttl_jitter`].
+    pub fn ttl_jitter(mut self, jitter: Duration) -> Self {
+        self.config.ttl_jitter = Some(jitter);
+        self
+    }
+
+    /// Coalesce concurrent cache misses for the same key
+    ///
+    /// See [`CacheLayer::coalesce`].
+    pub fn coalesce(mut self, enabled: bool) -> Self {
+        self.config.coalesce = enabled;
+        self
+    }
+
+    /// Coalesce concurrent cache misses for the same key, using a map of
+    /// in-flight requests split into `shards`
+    ///
+    /// See [`CacheLayer::coalesce_sharded`].
+    pub fn coalesce_sharded(mut self, shards: usize) -> Self {
+        self.config.coalesce = true;
+        self.inflight = Inflight::sharded(shards);
+        self
+    }
+
+    /// Only wait for the inner service to be ready on a cache miss
+    ///
+    /// See [`CacheLayer::lazy_inner_ready`].
+    pub fn lazy_inner_ready(mut self, enabled: bool) -> Self {
+        self.config.lazy_inner_ready = enabled;
+        self
+    }
+
+    /// Fall back to the inner service when the cache provider returns an
+    /// error
+    ///
+    /// See [`CacheLayer::fallback_on_provider_error`].
+    pub fn fallback_on_provider_error(mut self, enabled: bool) -> Self {
+        self.config.fallback_on_provider_error = enabled;
+        self
+    }
+
+    /// Record statistics in an existing handle
+    ///
+    /// This allows multiple layers to share the same statistics.
+    pub fn stats(mut self, stats: StatsHandle) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    /// Build the [`CacheLayer`]
+    ///
+    /// Returns an error if no provider was set, if negative responses are
+    /// cached with a TTL of zero, or if the refresh-ahead factor is invalid.
+    pub fn build(self) -> Result<CacheLayer<'a, P, T, N, C, D, L>, BuildError> {
+        let provider = self.provider.ok_or(BuildError::MissingProvider)?;
+        if self.negative_ttl == Some(Duration::ZERO) {
+            return Err(BuildError::ZeroNegativeTtl);
+        }
+        if let Some(factor) = self.config.refresh_ahead {
+            if !(factor > 0.0 && factor <= 1.0) {
+                return Err(BuildError::InvalidRefreshAhead);
+            }
+        }
+
+        Ok(CacheLayer {
+            provider,
+            transformer: self.transformer,
+            negative: self.negative,
+            predicate: self.predicate,
+            ttl: self.ttl,
+            listener: self.listener,
+            config: self.config,
+            inflight: self.inflight,
+            refresh: self.refresh,
+            stats: self.stats.unwrap_or_default(),
+            toggle: CacheToggle::default(),
+            metrics: Metrics::default(),
+            values: (),
+            errors: (),
+            _phantom: PhantomData,
+        })
+    }
+}
+
+impl<P, T, N, C, D, L> CacheLayerBuilder<'static, P, T, N, C, D, L> {
+    /// Serve stale entries while refreshing them in the background
+    ///
+    /// See [`CacheLayer::stale_while_revalidate`].
+    pub fn stale_while_revalidate(mut self, enabled: bool) -> Self {
+        self.config.stale_while_revalidate = enabled;
+        if enabled {
+            self.refresh = self.refresh.on_tokio();
+        }
+        self
+    }
+
+    /// Store responses in the background instead of before returning them
+    ///
+    /// See [`CacheLayer::write_back`].
+    pub fn write_back(mut self, enabled: bool) -> Self {
+        self.config.write_back = enabled;
+        if enabled {
+            self.refresh = self.refresh.on_tokio();
+        }
+        self
+    }
+
+    /// Refresh entries in the background when they are read with less than
+    /// `factor` of their TTL remaining
+    ///
+    /// See [`CacheLayer::refresh_ahead`]. Unlike the layer method,
+    /// [`CacheLayerBuilder::build`] rejects a factor that isn't between `0.0`
+    /// (excluded) and `1.0`.
+    pub fn refresh_ahead(mut self, factor: f32) -> Self {
+        self.config.refresh_ahead = Some(factor);
+        self.refresh = self.refresh.on_tokio();
+        self
+    }
+}
+
+/// Error returned when building an invalid [`CacheLayer`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// No cache provider was set
+    MissingProvider,
+    /// Negative responses are cached with a TTL of zero
+    ZeroNegativeTtl,
+    /// The refresh-ahead factor isn't between `0.0` (excluded) and `1.0`
+    InvalidRefreshAhead,
+}
+
+impl error::Error for BuildError {}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::MissingProvider => write!(f, "a cache provider is required"),
+            BuildError::ZeroNegativeTtl => {
+                write!(f, "negative cache TTL must be greater than zero")
+            }
+            BuildError::InvalidRefreshAhead => {
+                write!(f, "refresh-ahead factor must be in (0.0, 1.0]")
+            }
+        }
+    }
+}
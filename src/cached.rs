@@ -0,0 +1,289 @@
+//! Responses marked with whether they came from the cache
+//!
+//! By default, a [`crate::CacheService`] returns the response of the inner
+//! service on a miss and the cached response on a hit, and the caller can't
+//! tell them apart. With [`crate::CacheLayer::mark_cached`], responses are
+//! wrapped in [`Cached`] instead, which records where they came from.
+//!
+//! To do so, the inner service is wrapped in [`MarkMisses`], which marks its
+//! responses as not cached. The cache provider still stores the unwrapped
+//! responses, and responses rebuilt from the cache are marked as cached.
+//!
+//! The [`CacheOutcome`] of a response tells more precisely how it was
+//! produced. [`crate::http::HttpCacheLayer`] attaches it to responses as an
+//! extension.
+
+use crate::{
+    CacheEventListener, CachePredicate, NegativePolicy, TtlPolicy, ValueLoader, ValueTransform,
+};
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower::Service;
+
+/// Path taken by a cache service to produce a response
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CacheOutcome {
+    /// The response was returned by the inner service
+    Fresh,
+    /// The response was returned by the cache provider
+    Hit,
+    /// A stale response was returned by the cache provider, and is refreshed
+    /// in the background
+    HitStale,
+    /// A stale response was confirmed by the inner service, for example with
+    /// a `304 Not Modified` response, and returned by the cache provider
+    Revalidated,
+}
+
+impl CacheOutcome {
+    /// Return `true` if the response was returned by the cache provider
+    pub fn is_hit(&self) -> bool {
+        !matches!(self, CacheOutcome::Fresh)
+    }
+}
+
+/// Response returned by a [`crate::CacheService`] created with
+/// [`crate::CacheLayer::mark_cached`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Cached<T> {
+    /// Response of the inner service, or rebuilt from the cache
+    pub value: T,
+    /// Whether the response was returned by the cache provider
+    pub from_cache: bool,
+    /// How the response was produced
+    pub outcome: CacheOutcome,
+}
+
+impl<T> Cached<T> {
+    /// Mark a response with how it was produced
+    pub fn new(value: T, outcome: CacheOutcome) -> Self {
+        Cached {
+            value,
+            from_cache: outcome.is_hit(),
+            outcome,
+        }
+    }
+
+    /// Return the response, discarding where it came from
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+/// Response marking mode
+///
+/// Created by [`crate::CacheLayer::mark_cached`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MarkCached;
+
+impl MarkCached {
+    /// Wrap a policy of the [`crate::CacheLayer`] to apply it to the value of
+    /// [`Cached`] responses
+    pub(crate) fn wrap<X>(&self, inner: X) -> Marked<X> {
+        Marked { inner }
+    }
+}
+
+/// Adapter applying a policy to the value of [`Cached`] responses
+#[derive(Clone, Copy, Debug)]
+pub struct Marked<X> {
+    inner: X,
+}
+
+impl<X, Res> NegativePolicy<Cached<Res>> for Marked<X>
+where
+    X: NegativePolicy<Res>,
+{
+    fn negative_ttl(&self, res: &Cached<Res>) -> Option<Duration> {
+        self.inner.negative_ttl(&res.value)
+    }
+
+    fn empty(&self) -> Option<Cached<Res>> {
+        self.inner
+            .empty()
+            .map(|value| Cached::new(value, CacheOutcome::Hit))
+    }
+}
+
+impl<X, Res> CachePredicate<Cached<Res>> for Marked<X>
+where
+    X: CachePredicate<Res>,
+{
+    fn should_cache(&self, res: &Cached<Res>) -> bool {
+        self.inner.should_cache(&res.value)
+    }
+}
+
+impl<X, Res> TtlPolicy<Cached<Res>> for Marked<X>
+where
+    X: TtlPolicy<Res>,
+{
+    fn ttl(&self, res: &Cached<Res>) -> Option<Duration> {
+        self.inner.ttl(&res.value)
+    }
+}
+
+impl<X, K, V> CacheEventListener<K, V> for Marked<X>
+where
+    X: CacheEventListener<K, V>,
+{
+    fn on_hit(&self, key: &K) {
+        self.inner.on_hit(key);
+    }
+
+    fn on_miss(&self, key: &K) {
+        self.inner.on_miss(key);
+    }
+
+    fn on_insert(&self, key: &K) {
+        self.inner.on_insert(key);
+    }
+
+    fn on_evict(&self, key: &K, value: &V) {
+        self.inner.on_evict(key, value);
+    }
+}
+
+impl<X, Res> ValueTransform<Cached<Res>> for Marked<X>
+where
+    X: ValueTransform<Res>,
+{
+    type Stored = X::Stored;
+
+    fn store(&self, res: &Cached<Res>) -> Self::Stored {
+        self.inner.store(&res.value)
+    }
+
+    fn restore(&self, stored: Self::Stored) -> Cached<Res> {
+        Cached::new(self.inner.restore(stored), CacheOutcome::Hit)
+    }
+
+    fn restore_stale(&self, stored: Self::Stored) -> Cached<Res> {
+        Cached::new(self.inner.restore_stale(stored), CacheOutcome::HitStale)
+    }
+}
+
+impl<X, Req, Res> ValueLoader<Req, Cached<Res>> for Marked<X>
+where
+    X: ValueLoader<Req, Res>,
+{
+    type Stored = X::Stored;
+
+    fn save(&self, res: &Cached<Res>) -> Self::Stored {
+        self.inner.save(&res.value)
+    }
+
+    fn load(&self, stored: Self::Stored, req: &Req) -> Cached<Res> {
+        Cached::new(self.inner.load(stored, req), CacheOutcome::Hit)
+    }
+
+    fn load_stale(&self, stored: Self::Stored, req: &Req) -> Cached<Res> {
+        Cached::new(self.inner.load_stale(stored, req), CacheOutcome::HitStale)
+    }
+}
+
+/// Service marking the responses of the inner service as not cached
+#[derive(Clone, Debug)]
+pub struct MarkMisses<S> {
+    inner: S,
+}
+
+impl<S> MarkMisses<S> {
+    /// Wrap a service
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S, R> Service<R> for MarkMisses<S>
+where
+    S: Service<R>,
+{
+    type Response = Cached<S::Response>;
+    type Error = S::Error;
+    type Future = MarkMissesFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        MarkMissesFuture {
+            inner: self.inner.call(request),
+        }
+    }
+}
+
+pin_project! {
+    /// Future returned by [`MarkMisses`]
+    #[derive(Debug)]
+    pub struct MarkMissesFuture<F> {
+        #[pin]
+        inner: F,
+    }
+}
+
+impl<F, Res, E> Future for MarkMissesFuture<F>
+where
+    F: Future<Output = Result<Res, E>>,
+{
+    type Output = Result<Cached<Res>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.project()
+            .inner
+            .poll(cx)
+            .map_ok(|value| Cached::new(value, CacheOutcome::Fresh))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+
+    #[test]
+    fn test_policies() {
+        let ttl = MarkCached.wrap(|res: &usize| Some(Duration::from_secs(*res as u64)));
+        let res = Cached::new(5, CacheOutcome::Fresh);
+        assert_eq!(ttl.ttl(&res), Some(Duration::from_secs(5)));
+
+        let predicate = MarkCached.wrap(|res: &usize| *res > 5);
+        assert!(!predicate.should_cache(&res));
+
+        let negative = MarkCached.wrap(crate::NegativeCache::new(Duration::from_secs(2)));
+        assert_eq!(
+            negative.empty(),
+            Some(Cached::new(None::<usize>, CacheOutcome::Hit))
+        );
+
+        let values = MarkCached.wrap(());
+        assert_eq!(values.store(&res), 5);
+        assert_eq!(values.restore(5), Cached::new(5, CacheOutcome::Hit));
+        assert_eq!(
+            values.restore_stale(5),
+            Cached {
+                value: 5,
+                from_cache: true,
+                outcome: CacheOutcome::HitStale,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mark_misses() {
+        let mut service = MarkMisses::new(tower::service_fn(|req: usize| async move {
+            Ok::<_, Infallible>(req * 2)
+        }));
+
+        let Ok(res) = service.call(2).await;
+        assert_eq!(res, Cached::new(4, CacheOutcome::Fresh));
+        assert!(!res.from_cache);
+        assert_eq!(res.into_inner(), 4);
+    }
+}
@@ -0,0 +1,488 @@
+//! # Clock cache provider
+//!
+//! This is an implementation of a cache provider for [`crate::CacheLayer`]
+//! that approximates LRU with the clock (second-chance) algorithm. Entries
+//! sit in a ring with a reference bit each, and lookups only set that bit, so
+//! they take a shared lock and never reorder entries.
+//!
+//! When the cache is full, a hand sweeps the ring: entries with their
+//! reference bit set get a second chance and have it cleared, and the first
+//! entry without it is evicted.
+//!
+//! Replacing an existing entry keeps its position and reference bit.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, service_fn};
+//! use tower_cache::{
+//!     CacheLayer,
+//!     clock::ClockProvider,
+//! };
+//! async fn handler(req: String) -> Result<String, Infallible> {
+//!     Ok(req.to_uppercase())
+//! }
+//!
+//! // Initialize the cache provider service
+//! let clock_provider = ClockProvider::new::<String, String>(20);
+//!
+//! // Wrap the service with CacheLayer.
+//! let mut my_service = ServiceBuilder::new()
+//!     .layer(CacheLayer::new(clock_provider))
+//!     .service(service_fn(handler));
+//!
+//! # tokio_test::block_on(async move {
+//! // Call the service
+//! let res = my_service.call("Hello".to_string()).await.unwrap();
+//! assert_eq!(res, "HELLO".to_string());
+//! # })
+//! ```
+//!
+
+use crate::{
+    entry::Entry, time, trace, Configure, ProviderConfig, ProviderRequest, ProviderResponse,
+};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    future::{ready, Future},
+    hash::Hash,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower::Service;
+
+/// Local clock cache provider
+#[derive(Debug)]
+pub struct ClockProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    inner: Arc<RwLock<Clock<K, V>>>,
+    ttl: Option<Duration>,
+    stale_window: Option<Duration>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> ClockProvider<'a, (), ()> {
+    /// Create a new clock cache provider with the desired capacity
+    ///
+    /// A capacity of `0` is clamped to `1`.
+    pub fn new<K, V>(capacity: usize) -> ClockProvider<'a, K, V>
+    where
+        K: Eq + Hash,
+    {
+        ClockProvider {
+            inner: Arc::new(RwLock::new(Clock::new(capacity.max(1)))),
+            ttl: None,
+            stale_window: None,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Create a new clock cache provider where entries expire after `ttl`
+    ///
+    /// As with [`ClockProvider::new`], a capacity of `0` is clamped to `1`.
+    pub fn with_ttl<K, V>(capacity: usize, ttl: Duration) -> ClockProvider<'a, K, V>
+    where
+        K: Eq + Hash,
+    {
+        ClockProvider {
+            ttl: Some(ttl),
+            ..Self::new(capacity)
+        }
+    }
+}
+
+impl<'a, K, V> ClockProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    /// Keep entries for `window` after they expire, and return them as
+    /// [`ProviderResponse::FoundStale`] during that time.
+    ///
+    /// This allows [`crate::CacheLayer::stale_while_revalidate`] to serve
+    /// them while they are refreshed. Entries without a TTL never become
+    /// stale.
+    pub fn stale_window(mut self, window: Duration) -> Self {
+        self.stale_window = Some(window);
+        self
+    }
+}
+
+// Custom implementation of Clone as the Clone derive doesn't mark
+// ClockProvider as Clone if K or V is not clone.
+impl<'a, K, V> Clone for ClockProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            ttl: self.ttl,
+            stale_window: self.stale_window,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+/// Sets the TTL. Setting the capacity clears the cache, for all clones of the
+/// provider, so it should be done before using it.
+impl<'a, K, V> Configure for ClockProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    fn configure(mut self, config: &ProviderConfig) -> Self {
+        if let Some(ttl) = config.ttl {
+            self.ttl = Some(ttl);
+        }
+        if let Some(capacity) = config.capacity {
+            // Caches hold at least one entry.
+            if capacity == 0 {
+                trace::unsupported_config::<Self>("capacity");
+            }
+            *self.inner.write().unwrap() = Clock::new(capacity.max(1));
+        }
+        self
+    }
+}
+
+impl<'a, K, V> Service<ProviderRequest<K, V>> for ClockProvider<'a, K, V>
+where
+    K: Eq + Hash + Clone + Send + 'a,
+    V: Clone + Send + 'a,
+{
+    type Response = ProviderResponse<K, V>;
+    type Error = Infallible;
+    type Future = ProviderFuture<'a, K, V>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ProviderRequest<K, V>) -> Self::Future {
+        Box::pin(ready(Ok(match request {
+            ProviderRequest::Get(key) => {
+                let now = time::now();
+                let response = {
+                    let inner = self.inner.read().unwrap();
+                    inner.get(&key).map(|entry| entry.response_at(now))
+                };
+                match response {
+                    Some(Some(response)) => response,
+                    // The entry has expired: remove it so it doesn't take up
+                    // capacity anymore.
+                    Some(None) => {
+                        let mut inner = self.inner.write().unwrap();
+                        if inner.peek(&key).is_some_and(|entry| entry.is_expired(now)) {
+                            inner.remove(&key);
+                        }
+                        ProviderResponse::NotFound
+                    }
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            ProviderRequest::Insert(key, value, ttl) => {
+                let entry =
+                    Entry::new(value.clone(), ttl.or(self.ttl)).stale_for(self.stale_window);
+                self.inner.write().unwrap().insert(key, entry);
+                ProviderResponse::Found(value)
+            }
+            ProviderRequest::InsertNegative(key, ttl) => {
+                self.inner
+                    .write()
+                    .unwrap()
+                    .insert(key, Entry::negative(ttl));
+                ProviderResponse::FoundNegative
+            }
+            ProviderRequest::Clear => {
+                self.inner.write().unwrap().clear();
+                ProviderResponse::Cleared
+            }
+            ProviderRequest::Remove(key) => match self.inner.write().unwrap().remove(&key) {
+                Some(_) => ProviderResponse::Removed,
+                None => ProviderResponse::NotFound,
+            },
+            ProviderRequest::Contains(key) => {
+                let now = time::now();
+                let inner = self.inner.read().unwrap();
+                let present = inner.peek(&key).is_some_and(|entry| !entry.is_expired(now));
+                ProviderResponse::Present(present)
+            }
+            ProviderRequest::Ttl(key) => {
+                let now = time::now();
+                let inner = self.inner.read().unwrap();
+                match inner.peek(&key).and_then(|entry| entry.ttl_at(now)) {
+                    Some((remaining, ttl)) => ProviderResponse::Ttl(remaining, ttl),
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            ProviderRequest::Age(key) => {
+                let now = time::now();
+                let inner = self.inner.read().unwrap();
+                match inner.peek(&key).and_then(|entry| entry.age_at(now)) {
+                    Some(age) => ProviderResponse::Age(age),
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            // Look up and insert under the same lock.
+            ProviderRequest::GetOrInsert(key, value) => {
+                let now = time::now();
+                let mut inner = self.inner.write().unwrap();
+                match inner.get(&key).and_then(|entry| entry.fresh_value_at(now)) {
+                    Some(existing) => ProviderResponse::Found(existing.clone()),
+                    None => {
+                        let entry =
+                            Entry::new(value.clone(), self.ttl).stale_for(self.stale_window);
+                        inner.insert(key, entry);
+                        ProviderResponse::Inserted(value)
+                    }
+                }
+            }
+            ProviderRequest::GetMany(keys) => {
+                let now = time::now();
+                let inner = self.inner.read().unwrap();
+                ProviderResponse::Many(
+                    keys.into_iter()
+                        .map(|key| {
+                            let value = inner
+                                .get(&key)
+                                .and_then(|entry| entry.value_at(now))
+                                .cloned();
+                            (key, value)
+                        })
+                        .collect(),
+                )
+            }
+            // Inserting entries one by one is as cheap as a batch in memory.
+            ProviderRequest::InsertMany(_) => ProviderResponse::NotFound,
+            // Evictions are not counted.
+            ProviderRequest::Stats => ProviderResponse::NotFound,
+        })))
+    }
+}
+
+type ProviderFuture<'a, K, V> =
+    Pin<Box<dyn Future<Output = Result<ProviderResponse<K, V>, Infallible>> + Send + 'a>>;
+
+/// Entry of a [`ClockProvider`] with its reference bit
+#[derive(Debug)]
+struct Slot<K, V> {
+    key: K,
+    entry: Entry<V>,
+    referenced: AtomicBool,
+}
+
+/// Entries of a [`ClockProvider`], stored in a ring swept by a hand
+#[derive(Debug)]
+struct Clock<K, V> {
+    slots: Vec<Option<Slot<K, V>>>,
+    index: HashMap<K, usize>,
+    free: Vec<usize>,
+    hand: usize,
+    capacity: usize,
+}
+
+impl<K, V> Clock<K, V>
+where
+    K: Eq + Hash,
+{
+    fn new(capacity: usize) -> Self {
+        Clock {
+            slots: Vec::new(),
+            index: HashMap::new(),
+            free: Vec::new(),
+            hand: 0,
+            capacity,
+        }
+    }
+
+    fn slot(&self, key: &K) -> Option<&Slot<K, V>> {
+        let index = *self.index.get(key)?;
+        self.slots[index].as_ref()
+    }
+
+    /// Return the entry for `key` and set its reference bit
+    fn get(&self, key: &K) -> Option<&Entry<V>> {
+        let slot = self.slot(key)?;
+        slot.referenced.store(true, Ordering::Relaxed);
+        Some(&slot.entry)
+    }
+
+    /// Return the entry for `key` without setting its reference bit
+    fn peek(&self, key: &K) -> Option<&Entry<V>> {
+        self.slot(key).map(|slot| &slot.entry)
+    }
+
+    /// Store `entry`, evicting an entry if full
+    fn insert(&mut self, key: K, entry: Entry<V>)
+    where
+        K: Clone,
+    {
+        if let Some(&index) = self.index.get(&key) {
+            if let Some(slot) = &mut self.slots[index] {
+                slot.entry = entry;
+            }
+            return;
+        }
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None if self.slots.len() < self.capacity => {
+                self.slots.push(None);
+                self.slots.len() - 1
+            }
+            None => self.evict(),
+        };
+        self.index.insert(key.clone(), index);
+        self.slots[index] = Some(Slot {
+            key,
+            entry,
+            referenced: AtomicBool::new(false),
+        });
+    }
+
+    /// Advance the hand until it finds an entry without its reference bit,
+    /// clearing the bits it passes, and free that entry's slot
+    ///
+    /// This is only called when the ring is full, so every slot is occupied
+    /// and the hand stops after at most one full turn.
+    fn evict(&mut self) -> usize {
+        loop {
+            let index = self.hand;
+            self.hand = (self.hand + 1) % self.slots.len();
+            if let Some(slot) = &self.slots[index] {
+                if slot.referenced.swap(false, Ordering::Relaxed) {
+                    continue;
+                }
+            }
+            if let Some(slot) = self.slots[index].take() {
+                self.index.remove(&slot.key);
+            }
+            return index;
+        }
+    }
+
+    fn remove(&mut self, key: &K) -> Option<Entry<V>> {
+        let index = self.index.remove(key)?;
+        let slot = self.slots[index].take()?;
+        self.free.push(index);
+        Some(slot.entry)
+    }
+
+    fn clear(&mut self) {
+        self.slots.clear();
+        self.index.clear();
+        self.free.clear();
+        self.hand = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::{check_configure, check_provider};
+
+    #[tokio::test]
+    async fn test_conformance() -> Result<(), Infallible> {
+        check_provider(ClockProvider::new::<String, String>(10)).await
+    }
+
+    #[tokio::test]
+    async fn test_second_chance() -> Result<(), Infallible> {
+        let mut provider = ClockProvider::new::<usize, usize>(2);
+
+        provider.call(ProviderRequest::Insert(1, 1, None)).await?;
+        provider.call(ProviderRequest::Insert(2, 2, None)).await?;
+        let res = provider.call(ProviderRequest::Get(1)).await?;
+        assert!(matches!(res, ProviderResponse::Found(1)));
+
+        // 1 was referenced, so the hand skips it and evicts 2 instead.
+        provider.call(ProviderRequest::Insert(3, 3, None)).await?;
+        let res = provider.call(ProviderRequest::Contains(1)).await?;
+        assert!(matches!(res, ProviderResponse::Present(true)));
+        let res = provider.call(ProviderRequest::Contains(2)).await?;
+        assert!(matches!(res, ProviderResponse::Present(false)));
+
+        // The eviction pass cleared the reference bit of 1, and it wasn't
+        // referenced again, so it is evicted next.
+        provider.call(ProviderRequest::Insert(4, 4, None)).await?;
+        let res = provider.call(ProviderRequest::Get(1)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        let res = provider.call(ProviderRequest::Get(3)).await?;
+        assert!(matches!(res, ProviderResponse::Found(3)));
+        let res = provider.call(ProviderRequest::Get(4)).await?;
+        assert!(matches!(res, ProviderResponse::Found(4)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_all_referenced() -> Result<(), Infallible> {
+        let mut provider = ClockProvider::new::<usize, usize>(3);
+
+        for key in 1..=3 {
+            provider
+                .call(ProviderRequest::Insert(key, key, None))
+                .await?;
+            provider.call(ProviderRequest::Get(key)).await?;
+        }
+
+        // After a full turn clearing every bit, the hand evicts the entry it
+        // started from.
+        provider.call(ProviderRequest::Insert(4, 4, None)).await?;
+        let res = provider.call(ProviderRequest::Get(1)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        for key in 2..=4 {
+            let res = provider.call(ProviderRequest::Get(key)).await?;
+            assert!(matches!(res, ProviderResponse::Found(v) if v == key));
+        }
+        assert_eq!(provider.inner.read().unwrap().index.len(), 3);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remove_clear() -> Result<(), Infallible> {
+        let mut provider = ClockProvider::with_ttl::<usize, usize>(2, Duration::ZERO);
+
+        provider.call(ProviderRequest::Insert(1, 1, None)).await?;
+        let res = provider.call(ProviderRequest::Get(1)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        assert!(provider.inner.read().unwrap().index.is_empty());
+
+        // Freed slots are reused before evicting anything.
+        provider
+            .call(ProviderRequest::Insert(2, 2, Some(Duration::from_secs(10))))
+            .await?;
+        provider
+            .call(ProviderRequest::Insert(3, 3, Some(Duration::from_secs(10))))
+            .await?;
+        let res = provider.call(ProviderRequest::Get(2)).await?;
+        assert!(matches!(res, ProviderResponse::Found(2)));
+        assert_eq!(provider.inner.read().unwrap().slots.len(), 2);
+
+        let res = provider.call(ProviderRequest::Remove(2)).await?;
+        assert!(matches!(res, ProviderResponse::Removed));
+        let res = provider.call(ProviderRequest::Clear).await?;
+        assert!(matches!(res, ProviderResponse::Cleared));
+        let res = provider.call(ProviderRequest::Get(3)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        assert!(provider.inner.read().unwrap().slots.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_configure() -> Result<(), Infallible> {
+        let configure =
+            |config: &ProviderConfig| ClockProvider::new::<usize, usize>(10).configure(config);
+        check_configure(configure, true).await
+    }
+}
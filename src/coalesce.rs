@@ -0,0 +1,307 @@
+//! Request coalescing for concurrent cache misses
+//!
+//! When multiple requests miss the cache for the same key at the same time,
+//! only the first one (the leader) calls the inner service. The other ones
+//! (the followers) wait for the leader to share its response, whether or not
+//! it is stored by the cache provider.
+//!
+//! Errors of the inner service can't be shared, as they don't implement
+//! [`Clone`]. When the leader fails or is cancelled, the followers join again,
+//! so that one of them becomes the leader and calls the inner service.
+//!
+//! As [`crate::CacheLayer`] is not aware of the key type, in-flight requests
+//! are grouped by the hash of their key. Followers check a second, independent
+//! hash of their key against the one of the leader, and call the inner
+//! service themselves on a mismatch, so that a hash collision can only delay a
+//! request, not return the wrong response.
+//!
+//! The in-flight map is behind a synchronous lock, which is only held while
+//! joining or leaving a group, never while waiting for the leader. With
+//! [`crate::CacheLayer::coalesce_sharded`], the map is split into shards with
+//! their own lock, so that misses for different keys rarely contend.
+
+use std::{
+    any::Any,
+    collections::{hash_map::RandomState, HashMap},
+    hash::{BuildHasher, Hash},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::watch;
+
+/// Map of in-flight cache misses, shared between all services created by a
+/// [`crate::CacheLayer`]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Inflight {
+    inner: Arc<InflightInner>,
+}
+
+#[derive(Debug)]
+struct InflightInner {
+    hasher: RandomState,
+    /// Hasher telling apart keys with the same hash
+    check_hasher: RandomState,
+    /// In-flight requests by hash, split by hash into shards
+    shards: Box<[Shard]>,
+}
+
+type Shard = Mutex<HashMap<u64, watch::Receiver<Option<Shared>>>>;
+
+/// Response shared by a leader, with the check hash of its key
+type Shared = Arc<(u64, Box<dyn Any + Send + Sync>)>;
+
+impl Default for InflightInner {
+    fn default() -> Self {
+        InflightInner::new(1)
+    }
+}
+
+impl InflightInner {
+    fn new(shards: usize) -> Self {
+        InflightInner {
+            hasher: RandomState::new(),
+            check_hasher: RandomState::new(),
+            shards: (0..shards.max(1)).map(|_| Mutex::default()).collect(),
+        }
+    }
+
+    /// Return the shard holding the requests for `hash`
+    fn shard(&self, hash: u64) -> &Shard {
+        &self.shards[hash as usize % self.shards.len()]
+    }
+}
+
+/// Role of a request within a group of concurrent misses
+pub(crate) enum Role {
+    /// This request is responsible for calling the inner service. Other
+    /// requests are released when the guard is dropped.
+    Leader(LeaderGuard),
+    /// Another request is already calling the inner service.
+    Follower(Follower),
+}
+
+impl Inflight {
+    /// Create a map of in-flight cache misses split into `shards`
+    ///
+    /// A number of shards of `0` is clamped to `1`.
+    pub(crate) fn sharded(shards: usize) -> Self {
+        Inflight {
+            inner: Arc::new(InflightInner::new(shards)),
+        }
+    }
+
+    /// Register a cache miss for `key`
+    pub(crate) fn join<K: Hash>(&self, key: &K) -> Role {
+        let hash = self.inner.hasher.hash_one(key);
+        let check = self.inner.check_hasher.hash_one(key);
+        let mut requests = self.inner.shard(hash).lock().unwrap();
+
+        match requests.get(&hash) {
+            Some(receiver) => Role::Follower(Follower {
+                receiver: receiver.clone(),
+                check,
+            }),
+            None => {
+                let (sender, receiver) = watch::channel(None);
+                requests.insert(hash, receiver);
+                Role::Leader(LeaderGuard {
+                    inflight: self.clone(),
+                    hash,
+                    check,
+                    sender,
+                })
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        let shards = self.inner.shards.iter();
+        shards.map(|shard| shard.lock().unwrap().len()).sum()
+    }
+}
+
+/// Guard held by the leader of a group of concurrent misses
+///
+/// Dropping the guard removes the entry from the in-flight map and wakes up
+/// all followers, including when the leader's future is cancelled.
+pub(crate) struct LeaderGuard {
+    inflight: Inflight,
+    hash: u64,
+    check: u64,
+    sender: watch::Sender<Option<Shared>>,
+}
+
+impl LeaderGuard {
+    /// Return `true` if other requests are waiting for this one
+    pub(crate) fn has_followers(&self) -> bool {
+        // The in-flight map holds a receiver as well.
+        self.sender.receiver_count() > 1
+    }
+
+    /// Share the response of the leader with its followers
+    ///
+    /// Followers that join until the guard is dropped get it as well.
+    pub(crate) fn share<T>(&self, value: T)
+    where
+        T: Clone + Send + 'static,
+    {
+        let value: Box<dyn Any + Send + Sync> = Box::new(Mutex::new(value));
+        self.sender
+            .send_replace(Some(Arc::new((self.check, value))));
+    }
+}
+
+impl Drop for LeaderGuard {
+    fn drop(&mut self) {
+        self.inflight
+            .inner
+            .shard(self.hash)
+            .lock()
+            .unwrap()
+            .remove(&self.hash);
+    }
+}
+
+/// Request waiting for the leader of a group of concurrent misses
+pub(crate) struct Follower {
+    receiver: watch::Receiver<Option<Shared>>,
+    check: u64,
+}
+
+/// What a follower got from its leader
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Outcome<T> {
+    /// The response shared by the leader
+    Shared(T),
+    /// The leader failed or was cancelled without sharing a response.
+    Failed,
+    /// The leader shared a response for another key.
+    Mismatch,
+}
+
+impl Follower {
+    /// Wait until the leader shares its response or is done
+    pub(crate) async fn wait<T>(mut self) -> Outcome<T>
+    where
+        T: Clone + Send + 'static,
+    {
+        let shared = match self.receiver.wait_for(Option::is_some).await {
+            Ok(shared) => shared.clone(),
+            // The leader was dropped without sharing a response.
+            Err(_) => None,
+        };
+        let Some(shared) = shared else {
+            return Outcome::Failed;
+        };
+        let (check, value) = &*shared;
+        match value.downcast_ref::<Mutex<T>>() {
+            Some(value) if *check == self.check => Outcome::Shared(value.lock().unwrap().clone()),
+            _ => Outcome::Mismatch,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_leader_follower() {
+        let inflight = Inflight::default();
+
+        let leader = match inflight.join(&"key") {
+            Role::Leader(guard) => guard,
+            Role::Follower(_) => panic!("expected leader"),
+        };
+        let follower = match inflight.join(&"key") {
+            Role::Leader(_) => panic!("expected follower"),
+            Role::Follower(follower) => follower,
+        };
+        assert!(matches!(inflight.join(&"other"), Role::Leader(_)));
+        assert_eq!(inflight.len(), 1);
+        assert!(leader.has_followers());
+
+        leader.share("response".to_string());
+        // Followers joining after the response was shared get it too
+        let late = match inflight.join(&"key") {
+            Role::Leader(_) => panic!("expected follower"),
+            Role::Follower(follower) => follower,
+        };
+        drop(leader);
+        assert_eq!(
+            follower.wait::<String>().await,
+            Outcome::Shared("response".to_string())
+        );
+        assert_eq!(
+            late.wait::<String>().await,
+            Outcome::Shared("response".to_string())
+        );
+        assert_eq!(inflight.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_leader_failed() {
+        let inflight = Inflight::default();
+
+        let leader = match inflight.join(&"key") {
+            Role::Leader(guard) => guard,
+            Role::Follower(_) => panic!("expected leader"),
+        };
+        let follower = match inflight.join(&"key") {
+            Role::Leader(_) => panic!("expected follower"),
+            Role::Follower(follower) => follower,
+        };
+        drop(leader);
+        assert_eq!(follower.wait::<String>().await, Outcome::Failed);
+    }
+
+    #[tokio::test]
+    async fn test_mismatch() {
+        let inflight = Inflight::default();
+
+        let leader = match inflight.join(&"key") {
+            Role::Leader(guard) => guard,
+            Role::Follower(_) => panic!("expected leader"),
+        };
+        // Simulate a hash collision with another key
+        let follower = match inflight.join(&"key") {
+            Role::Leader(_) => panic!("expected follower"),
+            Role::Follower(follower) => Follower {
+                check: follower.check.wrapping_add(1),
+                ..follower
+            },
+        };
+        leader.share(1_u64);
+        assert_eq!(follower.wait::<u64>().await, Outcome::Mismatch);
+
+        // A response of another type can't be used either
+        let follower = match inflight.join(&"key") {
+            Role::Leader(_) => panic!("expected follower"),
+            Role::Follower(follower) => follower,
+        };
+        assert_eq!(follower.wait::<String>().await, Outcome::Mismatch);
+    }
+
+    #[tokio::test]
+    async fn test_sharded() {
+        let inflight = Inflight::sharded(4);
+        assert_eq!(inflight.inner.shards.len(), 4);
+
+        let leaders: Vec<_> = (0..16)
+            .map(|key| match inflight.join(&key) {
+                Role::Leader(guard) => guard,
+                Role::Follower(_) => panic!("expected leader"),
+            })
+            .collect();
+        let follower = match inflight.join(&3) {
+            Role::Leader(_) => panic!("expected follower"),
+            Role::Follower(follower) => follower,
+        };
+        assert_eq!(inflight.len(), 16);
+
+        drop(leaders);
+        assert_eq!(follower.wait::<u64>().await, Outcome::Failed);
+        assert_eq!(inflight.len(), 0);
+        assert_eq!(Inflight::sharded(0).inner.shards.len(), 1);
+    }
+}
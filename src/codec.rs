@@ -0,0 +1,311 @@
+//! # Value and key codecs
+//!
+//! Distributed cache providers, such as the Redis provider, store values as
+//! bytes. A [`Codec`] converts values to and from their serialized
+//! representation.
+//!
+//! Their keys are strings, built from cache keys with a [`KeyCodec`]. By
+//! default, keys are formatted with their [`Display`] implementation by
+//! [`DisplayKey`], and [`TupleKey`] supports composite keys.
+//!
+//! Two implementations are provided behind features:
+//!
+//! * [`JsonCodec`] (feature `json`) stores values as human-readable JSON,
+//! * [`BincodeCodec`] (feature `bincode`) stores values in a compact binary
+//!   format.
+//!
+//! ## Custom codecs
+//!
+//! Implement [`Codec`] for the value types you want to support:
+//!
+//! ```rust
+//! use std::{error, fmt};
+//! use tower_cache::codec::Codec;
+//!
+//! /// Store strings as raw UTF-8 bytes
+//! #[derive(Clone, Copy, Debug, Default)]
+//! struct Utf8Codec;
+//!
+//! #[derive(Debug)]
+//! struct Utf8Error;
+//!
+//! impl error::Error for Utf8Error {}
+//!
+//! impl fmt::Display for Utf8Error {
+//!     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+//!         write!(f, "invalid UTF-8")
+//!     }
+//! }
+//!
+//! impl Codec<String> for Utf8Codec {
+//!     type Error = Utf8Error;
+//!
+//!     fn encode(&self, value: &String) -> Result<Vec<u8>, Self::Error> {
+//!         Ok(value.as_bytes().to_vec())
+//!     }
+//!
+//!     fn decode(&self, data: &[u8]) -> Result<String, Self::Error> {
+//!         String::from_utf8(data.to_vec()).map_err(|_| Utf8Error)
+//!     }
+//! }
+//!
+//! let codec = Utf8Codec;
+//! let data = codec.encode(&"hello".to_string()).unwrap();
+//! assert_eq!(codec.decode(&data).unwrap(), "hello");
+//! ```
+//!
+//! ## Key codecs
+//!
+//! Keys must always encode to the same string, so that the same key maps to
+//! the same entry across instances and restarts. Implement [`KeyCodec`] for
+//! structured keys that don't implement [`Display`]:
+//!
+//! ```rust
+//! use tower_cache::codec::KeyCodec;
+//!
+//! struct Search {
+//!     query: String,
+//!     page: u32,
+//! }
+//!
+//! #[derive(Clone, Copy, Debug, Default)]
+//! struct SearchKey;
+//!
+//! impl KeyCodec<Search> for SearchKey {
+//!     fn encode_key(&self, key: &Search) -> String {
+//!         format!("search/{}/{}", key.page, key.query)
+//!     }
+//! }
+//!
+//! let search = Search { query: "cats".to_string(), page: 2 };
+//! assert_eq!(SearchKey.encode_key(&search), "search/2/cats");
+//! ```
+
+use std::{error, fmt::Display};
+
+/// Converts values of type `V` to and from bytes
+pub trait Codec<V> {
+    /// Error returned when a value cannot be encoded or decoded
+    type Error: error::Error + Send + Sync + 'static;
+
+    /// Serialize a value
+    fn encode(&self, value: &V) -> Result<Vec<u8>, Self::Error>;
+
+    /// Deserialize a value
+    fn decode(&self, data: &[u8]) -> Result<V, Self::Error>;
+}
+
+/// Converts keys of type `K` to the strings used by remote providers
+///
+/// Equal keys must always encode to the same string, and different keys
+/// should encode to different strings, as they would otherwise share an
+/// entry.
+pub trait KeyCodec<K> {
+    /// Serialize a key
+    fn encode_key(&self, key: &K) -> String;
+}
+
+/// Key codec formatting keys with their [`Display`] implementation
+///
+/// This is the default key codec of remote providers.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DisplayKey;
+
+impl<K> KeyCodec<K> for DisplayKey
+where
+    K: Display,
+{
+    fn encode_key(&self, key: &K) -> String {
+        key.to_string()
+    }
+}
+
+/// Key codec for tuples of up to 6 elements implementing [`Display`]
+///
+/// Elements are joined with a separator, `:` by default. Occurrences of the
+/// separator and of `\` within an element are escaped with a `\`, so that
+/// different tuples never encode to the same string.
+///
+/// ```rust
+/// use tower_cache::codec::{KeyCodec, TupleKey};
+///
+/// assert_eq!(TupleKey::new().encode_key(&(42u64, "a".to_string())), "42:a");
+/// assert_eq!(TupleKey::new().encode_key(&("a:b", "c")), r"a\:b:c");
+/// assert_eq!(TupleKey::new().encode_key(&("a", "b:c")), r"a:b\:c");
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct TupleKey {
+    separator: char,
+}
+
+impl TupleKey {
+    /// Create a tuple key codec separating elements with `:`
+    pub fn new() -> Self {
+        Self { separator: ':' }
+    }
+
+    /// Separate elements with `separator` instead
+    ///
+    /// # Panics
+    ///
+    /// Panics if `separator` is `\`, which is used for escaping.
+    pub fn with_separator(separator: char) -> Self {
+        assert!(separator != '\\', "`\\` cannot be used as a key separator");
+        Self { separator }
+    }
+
+    /// Format an element, escaping the separator
+    fn escape(&self, part: &dyn Display) -> String {
+        let mut escaped = String::new();
+        for c in part.to_string().chars() {
+            if c == self.separator || c == '\\' {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        escaped
+    }
+}
+
+impl Default for TupleKey {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+macro_rules! tuple_key {
+    ($($name:ident),+) => {
+        impl<$($name),+> KeyCodec<($($name,)+)> for TupleKey
+        where
+            $($name: Display,)+
+        {
+            #[allow(non_snake_case)]
+            fn encode_key(&self, key: &($($name,)+)) -> String {
+                let ($($name,)+) = key;
+                [$(self.escape($name)),+].join(self.separator.encode_utf8(&mut [0; 4]))
+            }
+        }
+    };
+}
+
+tuple_key!(A);
+tuple_key!(A, B);
+tuple_key!(A, B, C);
+tuple_key!(A, B, C, D);
+tuple_key!(A, B, C, D, E);
+tuple_key!(A, B, C, D, E, F);
+
+/// Codec storing values as JSON
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonCodec;
+
+#[cfg(feature = "json")]
+impl<V> Codec<V> for JsonCodec
+where
+    V: serde::Serialize + serde::de::DeserializeOwned,
+{
+    type Error = serde_json::Error;
+
+    fn encode(&self, value: &V) -> Result<Vec<u8>, Self::Error> {
+        serde_json::to_vec(value)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<V, Self::Error> {
+        serde_json::from_slice(data)
+    }
+}
+
+/// Codec storing values with [bincode](https://docs.rs/bincode)
+#[cfg(feature = "bincode")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bincode")))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BincodeCodec;
+
+#[cfg(feature = "bincode")]
+impl<V> Codec<V> for BincodeCodec
+where
+    V: serde::Serialize + serde::de::DeserializeOwned,
+{
+    type Error = bincode::Error;
+
+    fn encode(&self, value: &V) -> Result<Vec<u8>, Self::Error> {
+        bincode::serialize(value)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<V, Self::Error> {
+        bincode::deserialize(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_display_key() {
+        assert_eq!(DisplayKey.encode_key(&"a b"), "a b");
+        assert_eq!(DisplayKey.encode_key(&42u64), "42");
+    }
+
+    #[test]
+    fn test_tuple_key() {
+        let key = (42u64, "en".to_string());
+        assert_eq!(TupleKey::new().encode_key(&key), "42:en");
+        assert_eq!(TupleKey::with_separator('/').encode_key(&key), "42/en");
+
+        // Elements containing the separator don't collide
+        let keys = [("a:b", ""), ("a", "b:"), ("a", ":b"), ("a\\", ":b")];
+        let encoded: HashSet<_> = keys.iter().map(|k| TupleKey::new().encode_key(k)).collect();
+        assert_eq!(encoded.len(), keys.len());
+        assert_eq!(TupleKey::new().encode_key(&("a\\", ":b")), r"a\\:\:b");
+
+        assert_eq!(
+            TupleKey::new().encode_key(&(1, 2, 3, 4, 5, 6)),
+            "1:2:3:4:5:6"
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_tuple_key_backslash() {
+        TupleKey::with_separator('\\');
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_round_trip() {
+        let value = vec![(1u32, "one".to_string()), (2, "two".to_string())];
+
+        let data = JsonCodec.encode(&value).unwrap();
+        assert_eq!(data, br#"[[1,"one"],[2,"two"]]"#);
+        let decoded: Vec<(u32, String)> = JsonCodec.decode(&data).unwrap();
+        assert_eq!(decoded, value);
+
+        assert!(Codec::<String>::decode(&JsonCodec, b"not json").is_err());
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn test_bincode_round_trip() {
+        let value = vec![(1u32, "one".to_string()), (2, "two".to_string())];
+
+        let data = BincodeCodec.encode(&value).unwrap();
+        let decoded: Vec<(u32, String)> = BincodeCodec.decode(&data).unwrap();
+        assert_eq!(decoded, value);
+
+        assert!(Codec::<String>::decode(&BincodeCodec, &[0xff]).is_err());
+    }
+
+    #[cfg(all(feature = "json", feature = "bincode"))]
+    #[test]
+    fn test_bincode_is_compact() {
+        let value = vec![1u8; 32];
+
+        let json = JsonCodec.encode(&value).unwrap();
+        let bincode = BincodeCodec.encode(&value).unwrap();
+        assert!(bincode.len() < json.len());
+    }
+}
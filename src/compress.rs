@@ -0,0 +1,388 @@
+//! # Compressing cache provider
+//!
+//! [`CompressProvider`] wraps another cache provider storing bytes, such as a
+//! remote cache behind a [`crate::codec::Codec`], and compresses values
+//! before they are stored. Values are decompressed when they are read back.
+//!
+//! Two algorithms are provided behind features:
+//!
+//! * [`Gzip`] (feature `gzip`),
+//! * [`Zstd`] (feature `zstd`).
+//!
+//! Other algorithms can be used by implementing [`Compression`].
+//!
+//! ## Usage
+//!
+//! ```rust
+//! # #[cfg(feature = "gzip")]
+//! # {
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, service_fn};
+//! use tower_cache::{
+//!     CacheLayer,
+//!     compress::{CompressProvider, Gzip},
+//!     map::MapProvider,
+//! };
+//! async fn handler(req: String) -> Result<Vec<u8>, Infallible> {
+//!     Ok(req.repeat(100).into_bytes())
+//! }
+//!
+//! // Initialize the cache provider service
+//! let provider = CompressProvider::new(MapProvider::new::<String, Vec<u8>>(), Gzip::default());
+//!
+//! // Wrap the service with CacheLayer.
+//! let mut my_service = ServiceBuilder::new()
+//!     .layer(CacheLayer::new(provider))
+//!     .service(service_fn(handler));
+//!
+//! # tokio_test::block_on(async move {
+//! // Call the service
+//! let res = my_service.call("Hello".to_string()).await.unwrap();
+//! assert_eq!(res, "Hello".repeat(100).into_bytes());
+//! # })
+//! # }
+//! ```
+//!
+
+use crate::{ProviderRequest, ProviderResponse};
+use std::{
+    error, fmt,
+    future::{ready, Future},
+    io,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower::Service;
+
+/// Compression algorithm used by a [`CompressProvider`]
+pub trait Compression {
+    /// Compress a value
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>>;
+
+    /// Decompress a value previously returned by [`Compression::compress`]
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>>;
+}
+
+/// Gzip compression
+#[cfg(feature = "gzip")]
+#[cfg_attr(docsrs, doc(cfg(feature = "gzip")))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Gzip {
+    level: flate2::Compression,
+}
+
+#[cfg(feature = "gzip")]
+impl Gzip {
+    /// Create a gzip compression with a given level, from 0 to 9
+    pub fn new(level: u32) -> Self {
+        Gzip {
+            level: flate2::Compression::new(level.min(9)),
+        }
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl Compression for Gzip {
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), self.level);
+        encoder.write_all(data)?;
+        encoder.finish()
+    }
+
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        use std::io::Read;
+
+        let mut decoded = Vec::new();
+        flate2::read::GzDecoder::new(data).read_to_end(&mut decoded)?;
+        Ok(decoded)
+    }
+}
+
+/// Zstandard compression
+#[cfg(feature = "zstd")]
+#[cfg_attr(docsrs, doc(cfg(feature = "zstd")))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Zstd {
+    level: i32,
+}
+
+#[cfg(feature = "zstd")]
+impl Zstd {
+    /// Create a zstd compression with a given level
+    ///
+    /// A level of 0 uses the zstd default.
+    pub fn new(level: i32) -> Self {
+        Zstd { level }
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl Compression for Zstd {
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        zstd::encode_all(data, self.level)
+    }
+
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        zstd::decode_all(data)
+    }
+}
+
+/// Cache provider compressing values stored in another provider
+#[derive(Clone, Debug)]
+pub struct CompressProvider<'a, P, A> {
+    inner: P,
+    algorithm: A,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a, P, A> CompressProvider<'a, P, A> {
+    /// Create a new compressing cache provider
+    pub fn new(inner: P, algorithm: A) -> Self {
+        CompressProvider {
+            inner,
+            algorithm,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, P, A, K> Service<ProviderRequest<K, Vec<u8>>> for CompressProvider<'a, P, A>
+where
+    P: Service<ProviderRequest<K, Vec<u8>>, Response = ProviderResponse<K, Vec<u8>>>,
+    P::Future: Send + 'a,
+    P::Error: Send + 'a,
+    A: Compression + Clone + Send + 'a,
+    K: Send + 'a,
+{
+    type Response = ProviderResponse<K, Vec<u8>>;
+    type Error = CompressError<P::Error>;
+    type Future = ProviderFuture<'a, K, P::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(CompressError::Provider)
+    }
+
+    fn call(&mut self, request: ProviderRequest<K, Vec<u8>>) -> Self::Future {
+        match request {
+            ProviderRequest::Get(key) => {
+                let fut = self.inner.call(ProviderRequest::Get(key));
+                let algorithm = self.algorithm.clone();
+                Box::pin(async move {
+                    Ok(match fut.await.map_err(CompressError::Provider)? {
+                        ProviderResponse::Found(data) => ProviderResponse::Found(
+                            algorithm
+                                .decompress(&data)
+                                .map_err(CompressError::Compression)?,
+                        ),
+                        ProviderResponse::FoundStale(data) => ProviderResponse::FoundStale(
+                            algorithm
+                                .decompress(&data)
+                                .map_err(CompressError::Compression)?,
+                        ),
+                        res => res,
+                    })
+                })
+            }
+            ProviderRequest::Insert(key, value, ttl) => {
+                let data = match self.algorithm.compress(&value) {
+                    Ok(data) => data,
+                    Err(e) => return Box::pin(ready(Err(CompressError::Compression(e)))),
+                };
+                let fut = self.inner.call(ProviderRequest::Insert(key, data, ttl));
+                Box::pin(async move {
+                    Ok(match fut.await.map_err(CompressError::Provider)? {
+                        // Return the uncompressed value rather than the
+                        // stored bytes.
+                        ProviderResponse::Found(_) => ProviderResponse::Found(value),
+                        res => res,
+                    })
+                })
+            }
+            ProviderRequest::GetOrInsert(key, value) => {
+                let data = match self.algorithm.compress(&value) {
+                    Ok(data) => data,
+                    Err(e) => return Box::pin(ready(Err(CompressError::Compression(e)))),
+                };
+                let fut = self.inner.call(ProviderRequest::GetOrInsert(key, data));
+                let algorithm = self.algorithm.clone();
+                Box::pin(async move {
+                    Ok(match fut.await.map_err(CompressError::Provider)? {
+                        ProviderResponse::Found(data) => ProviderResponse::Found(
+                            algorithm
+                                .decompress(&data)
+                                .map_err(CompressError::Compression)?,
+                        ),
+                        ProviderResponse::Inserted(_) => ProviderResponse::Inserted(value),
+                        res => res,
+                    })
+                })
+            }
+            ProviderRequest::GetMany(keys) => {
+                let fut = self.inner.call(ProviderRequest::GetMany(keys));
+                let algorithm = self.algorithm.clone();
+                Box::pin(async move {
+                    Ok(match fut.await.map_err(CompressError::Provider)? {
+                        ProviderResponse::Many(values) => ProviderResponse::Many(
+                            values
+                                .into_iter()
+                                .map(|(key, data)| {
+                                    let value = data.map(|data| algorithm.decompress(&data));
+                                    value.transpose().map(|value| (key, value))
+                                })
+                                .collect::<Result<_, _>>()
+                                .map_err(CompressError::Compression)?,
+                        ),
+                        res => res,
+                    })
+                })
+            }
+            ProviderRequest::InsertMany(entries) => {
+                let mut values = Vec::with_capacity(entries.len());
+                let mut compressed = Vec::with_capacity(entries.len());
+                for (key, value, ttl) in entries {
+                    match self.algorithm.compress(&value) {
+                        Ok(data) => compressed.push((key, data, ttl)),
+                        Err(e) => return Box::pin(ready(Err(CompressError::Compression(e)))),
+                    }
+                    values.push(value);
+                }
+                let fut = self.inner.call(ProviderRequest::InsertMany(compressed));
+                Box::pin(async move {
+                    Ok(match fut.await.map_err(CompressError::Provider)? {
+                        // Return the uncompressed values rather than the
+                        // stored bytes.
+                        ProviderResponse::Many(inserted) => ProviderResponse::Many(
+                            inserted
+                                .into_iter()
+                                .zip(values)
+                                .map(|((key, _), value)| (key, Some(value)))
+                                .collect(),
+                        ),
+                        res => res,
+                    })
+                })
+            }
+            request => {
+                let fut = self.inner.call(request);
+                Box::pin(async move { fut.await.map_err(CompressError::Provider) })
+            }
+        }
+    }
+}
+
+type ProviderFuture<'a, K, E> = Pin<
+    Box<dyn Future<Output = Result<ProviderResponse<K, Vec<u8>>, CompressError<E>>> + Send + 'a>,
+>;
+
+/// Error returned by a [`CompressProvider`]
+#[derive(Debug)]
+pub enum CompressError<E> {
+    /// The inner provider returned an error
+    Provider(E),
+    /// A value could not be compressed or decompressed
+    Compression(io::Error),
+}
+
+impl<E> error::Error for CompressError<E>
+where
+    E: error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            CompressError::Provider(e) => Some(e),
+            CompressError::Compression(e) => Some(e),
+        }
+    }
+}
+
+impl<E> fmt::Display for CompressError<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompressError::Provider(e) => write!(f, "provider error: {}", e),
+            CompressError::Compression(e) => write!(f, "compression error: {}", e),
+        }
+    }
+}
+
+#[cfg(all(test, any(feature = "gzip", feature = "zstd")))]
+mod tests {
+    use super::*;
+    use crate::map::MapProvider;
+    use std::convert::Infallible;
+
+    async fn round_trip<A>(algorithm: A) -> Result<(), CompressError<Infallible>>
+    where
+        A: Compression + Clone + Send + 'static,
+    {
+        let mut inner = MapProvider::new::<String, Vec<u8>>();
+        let mut provider = CompressProvider::new(inner.clone(), algorithm);
+        let value = b"hello world ".repeat(100);
+
+        let res = provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                value.clone(),
+                None,
+            ))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == value));
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(ref v) if *v == value));
+
+        // The inner provider stores fewer bytes
+        let stored = match inner
+            .call(ProviderRequest::Get("a".to_string()))
+            .await
+            .map_err(CompressError::Provider)?
+        {
+            ProviderResponse::Found(stored) => stored,
+            _ => panic!("value not stored in the inner provider"),
+        };
+        assert!(stored.len() < value.len());
+
+        // Other requests are passed through
+        let res = provider.call(ProviderRequest::Get("b".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        let res = provider.call(ProviderRequest::Clear).await?;
+        assert!(matches!(res, ProviderResponse::Cleared));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn test_gzip_round_trip() -> Result<(), CompressError<Infallible>> {
+        round_trip(Gzip::default()).await
+    }
+
+    #[cfg(feature = "zstd")]
+    #[tokio::test]
+    async fn test_zstd_round_trip() -> Result<(), CompressError<Infallible>> {
+        round_trip(Zstd::default()).await
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn test_invalid_data() {
+        let mut inner = MapProvider::new::<String, Vec<u8>>();
+        let mut provider = CompressProvider::new(inner.clone(), Gzip::default());
+
+        inner
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                b"not gzip".to_vec(),
+                None,
+            ))
+            .await
+            .unwrap();
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await;
+        assert!(matches!(res, Err(CompressError::Compression(_))));
+    }
+}
@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+/// Settings shared by all cache providers
+///
+/// A [`ProviderConfig`] is applied to a provider with [`Configure::configure`],
+/// or when setting the provider of a [`crate::CacheLayerBuilder`] with
+/// [`crate::CacheLayerBuilder::provider_with_config`]. Fields that a provider
+/// can't honor are ignored, with a warning when the `tracing` feature is
+/// enabled.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use tower_cache::{CacheLayer, ProviderConfig, lru::LruProvider};
+///
+/// let config = ProviderConfig::new()
+///     .ttl(Duration::from_secs(60))
+///     .capacity(100);
+///
+/// let layer = CacheLayer::builder()
+///     .provider_with_config(LruProvider::new::<String, String>(10), &config)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ProviderConfig {
+    /// Default TTL of entries inserted without one
+    pub ttl: Option<Duration>,
+    /// Maximum number of entries
+    pub capacity: Option<usize>,
+}
+
+impl ProviderConfig {
+    /// Create an empty configuration, leaving the provider unchanged
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expire entries inserted without a TTL after `ttl`
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Hold at most `capacity` entries
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+}
+
+/// Cache provider that can be set up from a [`ProviderConfig`]
+pub trait Configure {
+    /// Apply the fields of `config` that are set, and return the provider
+    fn configure(self, config: &ProviderConfig) -> Self;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        assert_eq!(ProviderConfig::new(), ProviderConfig::default());
+
+        let config = ProviderConfig::new()
+            .ttl(Duration::from_secs(5))
+            .capacity(10);
+        assert_eq!(config.ttl, Some(Duration::from_secs(5)));
+        assert_eq!(config.capacity, Some(10));
+    }
+}
@@ -0,0 +1,93 @@
+//! Tests shared by all cache providers
+//!
+//! Each provider module calls these from its own tests, and only tests the
+//! behaviour that is specific to it.
+
+use crate::{ProviderConfig, ProviderRequest, ProviderResponse};
+use std::time::Duration;
+use tower::{Service, ServiceExt};
+
+/// Check lookups and inserts against an empty `provider`
+///
+/// This inserts `"A"` for `"a"` without a TTL, so that the caller can check
+/// how it was stored afterwards.
+pub(crate) async fn check_provider<P>(mut provider: P) -> Result<(), P::Error>
+where
+    P: Service<ProviderRequest<String, String>, Response = ProviderResponse<String, String>>
+        + Clone,
+{
+    let res = provider
+        .ready()
+        .await?
+        .call(ProviderRequest::Get("a".to_string()))
+        .await?;
+    assert!(matches!(res, ProviderResponse::NotFound));
+
+    provider
+        .ready()
+        .await?
+        .call(ProviderRequest::Insert(
+            "a".to_string(),
+            "A".to_string(),
+            None,
+        ))
+        .await?;
+
+    // Clones share the stored entries
+    let res = provider
+        .clone()
+        .oneshot(ProviderRequest::Get("a".to_string()))
+        .await?;
+    assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+    let res = provider
+        .ready()
+        .await?
+        .call(ProviderRequest::Get("b".to_string()))
+        .await?;
+    assert!(matches!(res, ProviderResponse::NotFound));
+
+    Ok(())
+}
+
+/// Check that the provider returned by `configure` applies the TTL and the
+/// capacity of a [`ProviderConfig`]
+///
+/// Providers that aren't `bounded` keep all the entries, as they don't
+/// support a capacity.
+pub(crate) async fn check_configure<P, F>(configure: F, bounded: bool) -> Result<(), P::Error>
+where
+    P: Service<ProviderRequest<usize, usize>, Response = ProviderResponse<usize, usize>>,
+    F: FnOnce(&ProviderConfig) -> P,
+{
+    let config = ProviderConfig::new()
+        .ttl(Duration::from_secs(60))
+        .capacity(2);
+    let mut provider = configure(&config);
+
+    for i in 0..3 {
+        provider
+            .ready()
+            .await?
+            .call(ProviderRequest::Insert(i, i, None))
+            .await?;
+    }
+    let mut present = 0;
+    for i in 0..3 {
+        match provider
+            .ready()
+            .await?
+            .call(ProviderRequest::Ttl(i))
+            .await?
+        {
+            ProviderResponse::Ttl(_, ttl) => {
+                assert_eq!(ttl, Duration::from_secs(60));
+                present += 1;
+            }
+            ProviderResponse::NotFound => (),
+            _ => panic!("unexpected response"),
+        }
+    }
+    assert_eq!(present, if bounded { 2 } else { 3 });
+
+    Ok(())
+}
@@ -0,0 +1,423 @@
+//! # Sharded cache provider
+//!
+//! This is an implementation of a cache provider for [`crate::CacheLayer`]
+//! using [`dashmap::DashMap`]. Entries are sharded across multiple maps, so
+//! requests for different keys don't contend on a single lock, unlike
+//! [`crate::lru::LruProvider`].
+//!
+//! **Note:** this provider is unbounded. Entries are never evicted, so it
+//! should only be used when the set of keys is bounded or entries expire
+//! through a TTL (see [`DashProvider::with_ttl`]).
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, service_fn};
+//! use tower_cache::{
+//!     CacheLayer,
+//!     dash::DashProvider,
+//! };
+//! async fn handler(req: String) -> Result<String, Infallible> {
+//!     Ok(req.to_uppercase())
+//! }
+//!
+//! // Initialize the cache provider service
+//! let dash_provider = DashProvider::new::<String, String>();
+//!
+//! // Wrap the service with CacheLayer.
+//! let mut my_service = ServiceBuilder::new()
+//!     .layer(CacheLayer::new(dash_provider))
+//!     .service(service_fn(handler));
+//!
+//! # tokio_test::block_on(async move {
+//! // Call the service
+//! let res = my_service.call("Hello".to_string()).await.unwrap();
+//! assert_eq!(res, "HELLO".to_string());
+//! # })
+//! ```
+//!
+
+use crate::{
+    entry::Entry, time, trace, Configure, ProviderConfig, ProviderRequest, ProviderResponse,
+};
+use dashmap::DashMap;
+use std::{
+    convert::Infallible,
+    future::{ready, Future},
+    hash::Hash,
+    marker::PhantomData,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower::Service;
+
+/// Local, unbounded, sharded cache provider
+#[derive(Debug)]
+pub struct DashProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    inner: Arc<DashMap<K, Entry<V>>>,
+    ttl: Option<Duration>,
+    stale_window: Option<Duration>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> DashProvider<'a, (), ()> {
+    /// Create a new sharded cache provider
+    pub fn new<K, V>() -> DashProvider<'a, K, V>
+    where
+        K: Eq + Hash,
+    {
+        DashProvider {
+            inner: Arc::new(DashMap::new()),
+            ttl: None,
+            stale_window: None,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Create a new sharded cache provider where entries expire after `ttl`
+    ///
+    /// Expired entries are removed lazily when they are looked up.
+    pub fn with_ttl<K, V>(ttl: Duration) -> DashProvider<'a, K, V>
+    where
+        K: Eq + Hash,
+    {
+        DashProvider {
+            ttl: Some(ttl),
+            ..Self::new()
+        }
+    }
+}
+
+// Custom implementation of Clone as the Clone derive doesn't mark DashProvider
+// as Clone if K or V is not clone.
+impl<'a, K, V> Clone for DashProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            ttl: self.ttl,
+            stale_window: self.stale_window,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V> DashProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    /// Keep entries for `window` after they expire, and return them as
+    /// [`ProviderResponse::FoundStale`] during that time.
+    ///
+    /// This allows [`crate::CacheLayer::stale_while_revalidate`] to serve
+    /// them while they are refreshed. Entries without a TTL never become
+    /// stale.
+    pub fn stale_window(mut self, window: Duration) -> Self {
+        self.stale_window = Some(window);
+        self
+    }
+}
+
+/// Sets the TTL. The capacity is ignored, as the provider is unbounded.
+impl<'a, K, V> Configure for DashProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    fn configure(mut self, config: &ProviderConfig) -> Self {
+        if let Some(ttl) = config.ttl {
+            self.ttl = Some(ttl);
+        }
+        if config.capacity.is_some() {
+            trace::unsupported_config::<Self>("capacity");
+        }
+        self
+    }
+}
+
+impl<'a, K, V> Service<ProviderRequest<K, V>> for DashProvider<'a, K, V>
+where
+    K: Eq + Hash + Send + 'a,
+    V: Clone + Send + 'a,
+{
+    type Response = ProviderResponse<K, V>;
+    type Error = Infallible;
+    type Future = ProviderFuture<'a, K, V>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ProviderRequest<K, V>) -> Self::Future {
+        Box::pin(ready(Ok(match request {
+            ProviderRequest::Get(key) => {
+                let now = time::now();
+                // Release the shard lock before removing an expired entry.
+                let response = self.inner.get(&key).map(|entry| entry.response_at(now));
+                match response {
+                    Some(Some(response)) => response,
+                    Some(None) => {
+                        self.inner.remove_if(&key, |_, entry| entry.is_expired(now));
+                        ProviderResponse::NotFound
+                    }
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            ProviderRequest::Insert(key, value, ttl) => {
+                let entry =
+                    Entry::new(value.clone(), ttl.or(self.ttl)).stale_for(self.stale_window);
+                self.inner.insert(key, entry);
+                ProviderResponse::Found(value)
+            }
+            ProviderRequest::InsertNegative(key, ttl) => {
+                self.inner.insert(key, Entry::negative(ttl));
+                ProviderResponse::FoundNegative
+            }
+            ProviderRequest::Clear => {
+                self.inner.clear();
+                ProviderResponse::Cleared
+            }
+            ProviderRequest::Remove(key) => match self.inner.remove(&key) {
+                Some(_) => ProviderResponse::Removed,
+                None => ProviderResponse::NotFound,
+            },
+            ProviderRequest::Contains(key) => {
+                let now = time::now();
+                let present = self
+                    .inner
+                    .get(&key)
+                    .is_some_and(|entry| !entry.is_expired(now));
+                ProviderResponse::Present(present)
+            }
+            ProviderRequest::Ttl(key) => {
+                let now = time::now();
+                match self.inner.get(&key).and_then(|entry| entry.ttl_at(now)) {
+                    Some((remaining, ttl)) => ProviderResponse::Ttl(remaining, ttl),
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            ProviderRequest::Age(key) => {
+                let now = time::now();
+                match self.inner.get(&key).and_then(|entry| entry.age_at(now)) {
+                    Some(age) => ProviderResponse::Age(age),
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            // The entry API holds the shard lock for the key.
+            ProviderRequest::GetOrInsert(key, value) => {
+                let now = time::now();
+                let entry = Entry::new(value.clone(), self.ttl).stale_for(self.stale_window);
+                match self.inner.entry(key) {
+                    dashmap::Entry::Occupied(mut occupied) => {
+                        match occupied.get().fresh_value_at(now) {
+                            Some(existing) => ProviderResponse::Found(existing.clone()),
+                            None => {
+                                occupied.insert(entry);
+                                ProviderResponse::Inserted(value)
+                            }
+                        }
+                    }
+                    dashmap::Entry::Vacant(vacant) => {
+                        vacant.insert(entry);
+                        ProviderResponse::Inserted(value)
+                    }
+                }
+            }
+            ProviderRequest::GetMany(keys) => {
+                let now = time::now();
+                ProviderResponse::Many(
+                    keys.into_iter()
+                        .map(|key| {
+                            let value = self
+                                .inner
+                                .get(&key)
+                                .and_then(|entry| entry.value_at(now).cloned());
+                            (key, value)
+                        })
+                        .collect(),
+                )
+            }
+            // Inserting entries one by one is as cheap as a batch in memory.
+            ProviderRequest::InsertMany(_) => ProviderResponse::NotFound,
+            // Evictions are not counted.
+            ProviderRequest::Stats => ProviderResponse::NotFound,
+        })))
+    }
+}
+
+type ProviderFuture<'a, K, V> =
+    Pin<Box<dyn Future<Output = Result<ProviderResponse<K, V>, Infallible>> + Send + 'a>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::{check_configure, check_provider};
+
+    #[tokio::test]
+    async fn test_conformance() -> Result<(), Infallible> {
+        check_provider(DashProvider::new::<String, String>()).await
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ttl_expires() -> Result<(), Infallible> {
+        let mut provider = DashProvider::with_ttl::<String, String>(Duration::from_millis(50));
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        assert!(provider.inner.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_per_entry_ttl() -> Result<(), Infallible> {
+        let mut provider = DashProvider::with_ttl::<String, String>(Duration::from_secs(10));
+
+        provider
+            .call(ProviderRequest::Insert(
+                "short".to_string(),
+                "S".to_string(),
+                Some(Duration::from_millis(50)),
+            ))
+            .await?;
+        provider
+            .call(ProviderRequest::Insert(
+                "long".to_string(),
+                "L".to_string(),
+                Some(Duration::from_millis(150)),
+            ))
+            .await?;
+        provider
+            .call(ProviderRequest::Insert(
+                "default".to_string(),
+                "D".to_string(),
+                None,
+            ))
+            .await?;
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let res = provider
+            .call(ProviderRequest::Get("short".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        let res = provider
+            .call(ProviderRequest::Get("long".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "L"));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let res = provider
+            .call(ProviderRequest::Get("long".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        // Entries without their own TTL fall back to the provider default.
+        let res = provider
+            .call(ProviderRequest::Get("default".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "D"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_clear() -> Result<(), Infallible> {
+        let mut provider = DashProvider::new::<String, String>();
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        let res = provider.call(ProviderRequest::Clear).await?;
+        assert!(matches!(res, ProviderResponse::Cleared));
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remove() -> Result<(), Infallible> {
+        let mut provider = DashProvider::new::<String, String>();
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        provider
+            .call(ProviderRequest::Insert(
+                "b".to_string(),
+                "B".to_string(),
+                None,
+            ))
+            .await?;
+
+        let res = provider
+            .call(ProviderRequest::Remove("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Removed));
+        let res = provider
+            .call(ProviderRequest::Remove("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        let res = provider.call(ProviderRequest::Get("b".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "B"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_contains() -> Result<(), Infallible> {
+        let mut provider = DashProvider::new::<String, String>();
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        let res = provider
+            .call(ProviderRequest::Contains("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(true)));
+        let res = provider
+            .call(ProviderRequest::Contains("b".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(false)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_configure() -> Result<(), Infallible> {
+        let configure =
+            |config: &ProviderConfig| DashProvider::new::<usize, usize>().configure(config);
+        check_configure(configure, false).await
+    }
+}
@@ -0,0 +1,757 @@
+//! # DynamoDB cache provider
+//!
+//! This is an implementation of a cache provider for [`crate::CacheLayer`]
+//! backed by [DynamoDB](https://aws.amazon.com/dynamodb/), which gives
+//! serverless deployments, such as AWS Lambda functions, a managed cache
+//! shared by all their instances.
+//!
+//! Each entry is stored as an item with the following attributes, whose names
+//! can be configured on the [`DynamoProvider`]:
+//!
+//! * the key, formatted using its [`Display`](fmt::Display) implementation
+//!   by default, as a string partition key (`pk` by default), see
+//!   [`DynamoProvider::with_key_codec`] to use a [`KeyCodec`] instead,
+//! * the value, serialized as JSON by default, as binary (`value` by
+//!   default), see [`DynamoProvider::with_codec`] to use another [`Codec`],
+//! * the expiration time, as a Unix timestamp in seconds (`ttl` by default),
+//! * whether the entry is negative, as a boolean (`neg` by default), in which
+//!   case there is no value.
+//!
+//! Enable [Time to Live] on the table for the expiration attribute, so that
+//! DynamoDB deletes expired items. As it can take a while to do so, the
+//! provider also ignores items past their expiration time. DynamoDB TTLs have
+//! a resolution of one second, so TTLs are rounded up to the next second.
+//!
+//! The provider sends requests through a [`DynamoClient`], which is
+//! implemented for the `aws_sdk_dynamodb::Client` of the AWS SDK.
+//!
+//! [Time to Live]: https://docs.aws.amazon.com/amazondynamodb/latest/developerguide/TTL.html
+//!
+//! ## Usage
+//!
+//! ```rust,ignore
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, service_fn};
+//! use tower_cache::{
+//!     CacheLayer,
+//!     dynamodb::DynamoProvider,
+//! };
+//! async fn handler(req: String) -> Result<String, Infallible> {
+//!     Ok(req.to_uppercase())
+//! }
+//!
+//! # tokio_test::block_on(async move {
+//! // Initialize the cache provider service, with the client of the AWS SDK
+//! let config = aws_config::load_from_env().await;
+//! let client = aws_sdk_dynamodb::Client::new(&config);
+//! let dynamo_provider = DynamoProvider::new::<String, String, _>(client, "my-cache")
+//!     .key_attribute("id");
+//!
+//! // Wrap the service with CacheLayer.
+//! let mut my_service = ServiceBuilder::new()
+//!     .layer(CacheLayer::new(dynamo_provider))
+//!     .service(service_fn(handler));
+//!
+//! // Call the service
+//! let res = my_service.call("Hello".to_string()).await.unwrap();
+//! assert_eq!(res, "HELLO".to_string());
+//! # })
+//! ```
+//!
+
+use crate::{
+    codec::{Codec, DisplayKey, JsonCodec, KeyCodec},
+    trace, Configure, ProviderConfig, ProviderRequest, ProviderResponse,
+};
+use aws_sdk_dynamodb::{
+    primitives::Blob,
+    types::{AttributeValue, ReturnValue},
+};
+use std::{
+    error, fmt,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tower::Service;
+
+/// Client sending requests to DynamoDB
+///
+/// Each method maps to a DynamoDB operation on the item whose key attribute
+/// is `key`, in the [`Table`] configured on the [`DynamoProvider`]. This is
+/// implemented for `aws_sdk_dynamodb::Client`, and can be implemented by
+/// other clients, for example to record requests in tests.
+pub trait DynamoClient: Clone + Send + Sync + 'static {
+    /// Error returned by the client
+    type Error: error::Error + Send + Sync + 'static;
+
+    /// Return the item stored for `key`, if any (`GetItem`)
+    fn get_item(&self, table: &Table, key: String) -> ClientFuture<Option<Item>, Self::Error>;
+
+    /// Store `item` for `key`, replacing any previous item (`PutItem`)
+    fn put_item(&self, table: &Table, key: String, item: Item) -> ClientFuture<(), Self::Error>;
+
+    /// Delete the item stored for `key`, returning whether there was one
+    /// (`DeleteItem`)
+    fn delete_item(&self, table: &Table, key: String) -> ClientFuture<bool, Self::Error>;
+}
+
+/// Future returned by a [`DynamoClient`]
+pub type ClientFuture<T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + Send + 'static>>;
+
+impl DynamoClient for aws_sdk_dynamodb::Client {
+    type Error = aws_sdk_dynamodb::Error;
+
+    fn get_item(&self, table: &Table, key: String) -> ClientFuture<Option<Item>, Self::Error> {
+        let request = self
+            .get_item()
+            .table_name(&table.name)
+            .key(&table.key_attribute, AttributeValue::S(key));
+        let table = table.clone();
+        Box::pin(async move {
+            let output = request.send().await?;
+            Ok(output.item.and_then(|mut item| {
+                let expires_at = item
+                    .get(&table.ttl_attribute)
+                    .and_then(|v| v.as_n().ok()?.parse().ok());
+                let negative = item
+                    .get(&table.negative_attribute)
+                    .and_then(|v| v.as_bool().ok().copied())
+                    .unwrap_or(false);
+                let value = match negative {
+                    true => None,
+                    false => Some(item.remove(&table.value_attribute)?.as_b().ok()?.clone()),
+                };
+                Some(Item {
+                    value: value.map(Blob::into_inner),
+                    expires_at,
+                })
+            }))
+        })
+    }
+
+    fn put_item(&self, table: &Table, key: String, item: Item) -> ClientFuture<(), Self::Error> {
+        let mut request = self
+            .put_item()
+            .table_name(&table.name)
+            .item(&table.key_attribute, AttributeValue::S(key));
+        request = match item.value {
+            Some(value) => {
+                request.item(&table.value_attribute, AttributeValue::B(Blob::new(value)))
+            }
+            None => request.item(&table.negative_attribute, AttributeValue::Bool(true)),
+        };
+        if let Some(expires_at) = item.expires_at {
+            let expires_at = AttributeValue::N(expires_at.to_string());
+            request = request.item(&table.ttl_attribute, expires_at);
+        }
+        Box::pin(async move {
+            request.send().await?;
+            Ok(())
+        })
+    }
+
+    fn delete_item(&self, table: &Table, key: String) -> ClientFuture<bool, Self::Error> {
+        let request = self
+            .delete_item()
+            .table_name(&table.name)
+            .key(&table.key_attribute, AttributeValue::S(key))
+            .return_values(ReturnValue::AllOld);
+        Box::pin(async move { Ok(request.send().await?.attributes.is_some()) })
+    }
+}
+
+/// Table storing the entries of a [`DynamoProvider`], and the names of its
+/// attributes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Table {
+    /// Name of the table
+    pub name: String,
+    /// Name of the string partition key of the table
+    pub key_attribute: String,
+    /// Name of the binary attribute holding the serialized value
+    pub value_attribute: String,
+    /// Name of the number attribute holding the expiration time, which should
+    /// be the Time to Live attribute of the table
+    pub ttl_attribute: String,
+    /// Name of the boolean attribute set on negative entries
+    pub negative_attribute: String,
+}
+
+/// Item stored by a [`DynamoProvider`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Item {
+    /// Serialized value, or `None` for negative entries
+    pub value: Option<Vec<u8>>,
+    /// Expiration time as a Unix timestamp in seconds, if any
+    pub expires_at: Option<u64>,
+}
+
+impl Item {
+    /// Return `true` if the item expired at `now`, a Unix timestamp in
+    /// seconds
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// DynamoDB cache provider
+///
+/// The provider is generic over the [`DynamoClient`]. Cloning the provider
+/// shares the underlying client.
+pub struct DynamoProvider<'a, K, V, C, E = JsonCodec, KC = DisplayKey> {
+    client: C,
+    table: Arc<Table>,
+    ttl: Option<Duration>,
+    codec: E,
+    key_codec: KC,
+    _types: PhantomData<fn() -> (K, V)>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> DynamoProvider<'a, (), (), ()> {
+    /// Create a new DynamoDB cache provider storing entries in `table`
+    ///
+    /// The attributes are named `pk`, `value`, `ttl` and `neg` by default.
+    pub fn new<K, V, C>(client: C, table: impl Into<String>) -> DynamoProvider<'a, K, V, C>
+    where
+        C: DynamoClient,
+    {
+        DynamoProvider {
+            client,
+            table: Arc::new(Table {
+                name: table.into(),
+                key_attribute: "pk".to_string(),
+                value_attribute: "value".to_string(),
+                ttl_attribute: "ttl".to_string(),
+                negative_attribute: "neg".to_string(),
+            }),
+            ttl: None,
+            codec: JsonCodec,
+            key_codec: DisplayKey,
+            _types: PhantomData,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V, C, E, KC> DynamoProvider<'a, K, V, C, E, KC> {
+    /// Use a different [`Codec`] to serialize values
+    pub fn with_codec<NE>(self, codec: NE) -> DynamoProvider<'a, K, V, C, NE, KC> {
+        DynamoProvider {
+            client: self.client,
+            table: self.table,
+            ttl: self.ttl,
+            codec,
+            key_codec: self.key_codec,
+            _types: PhantomData,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Use a different [`KeyCodec`] to build partition keys
+    pub fn with_key_codec<NK>(self, key_codec: NK) -> DynamoProvider<'a, K, V, C, E, NK> {
+        DynamoProvider {
+            client: self.client,
+            table: self.table,
+            ttl: self.ttl,
+            codec: self.codec,
+            key_codec,
+            _types: PhantomData,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Name of the partition key of the table
+    pub fn key_attribute(mut self, name: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.table).key_attribute = name.into();
+        self
+    }
+
+    /// Name of the attribute holding the serialized values
+    pub fn value_attribute(mut self, name: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.table).value_attribute = name.into();
+        self
+    }
+
+    /// Name of the attribute holding the expiration time of entries
+    pub fn ttl_attribute(mut self, name: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.table).ttl_attribute = name.into();
+        self
+    }
+
+    /// Name of the attribute marking negative entries
+    pub fn negative_attribute(mut self, name: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.table).negative_attribute = name.into();
+        self
+    }
+
+    /// Return the table storing the entries
+    pub fn table(&self) -> &Table {
+        &self.table
+    }
+}
+
+// Custom implementation of Clone as the Clone derive doesn't mark
+// DynamoProvider as Clone if K or V is not clone.
+impl<'a, K, V, C, E, KC> Clone for DynamoProvider<'a, K, V, C, E, KC>
+where
+    C: Clone,
+    E: Clone,
+    KC: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            table: self.table.clone(),
+            ttl: self.ttl,
+            codec: self.codec.clone(),
+            key_codec: self.key_codec.clone(),
+            _types: PhantomData,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V, C, E, KC> fmt::Debug for DynamoProvider<'a, K, V, C, E, KC> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DynamoProvider")
+            .field("table", &self.table)
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Sets the default TTL of entries inserted without one. The capacity is
+/// ignored, as DynamoDB tables grow as needed.
+impl<'a, K, V, C, E, KC> Configure for DynamoProvider<'a, K, V, C, E, KC> {
+    fn configure(mut self, config: &ProviderConfig) -> Self {
+        if let Some(ttl) = config.ttl {
+            self.ttl = Some(ttl);
+        }
+        if config.capacity.is_some() {
+            trace::unsupported_config::<Self>("capacity");
+        }
+        self
+    }
+}
+
+impl<'a, K, V, C, E, KC> Service<ProviderRequest<K, V>> for DynamoProvider<'a, K, V, C, E, KC>
+where
+    KC: KeyCodec<K>,
+    V: Send + 'a,
+    C: DynamoClient,
+    E: Codec<V> + Clone + Send + 'a,
+{
+    type Response = ProviderResponse<K, V>;
+    type Error = Error;
+    type Future = ProviderFuture<'a, K, V>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ProviderRequest<K, V>) -> Self::Future {
+        let client = self.client.clone();
+        let codec = self.codec.clone();
+
+        match request {
+            ProviderRequest::Get(key) => {
+                let item = client.get_item(&self.table, self.key_codec.encode_key(&key));
+                Box::pin(async move {
+                    Ok(match live(item.await.map_err(Error::dynamo)?) {
+                        Some(Item {
+                            value: Some(value), ..
+                        }) => ProviderResponse::Found(codec.decode(&value).map_err(Error::codec)?),
+                        Some(Item { value: None, .. }) => ProviderResponse::FoundNegative,
+                        None => ProviderResponse::NotFound,
+                    })
+                })
+            }
+            ProviderRequest::Insert(key, value, ttl) => {
+                let key = self.key_codec.encode_key(&key);
+                let table = self.table.clone();
+                let expires_at = ttl.or(self.ttl).map(expires_at);
+                Box::pin(async move {
+                    let value_data = codec.encode(&value).map_err(Error::codec)?;
+                    let item = Item {
+                        value: Some(value_data),
+                        expires_at,
+                    };
+                    client
+                        .put_item(&table, key, item)
+                        .await
+                        .map_err(Error::dynamo)?;
+                    Ok(ProviderResponse::Found(value))
+                })
+            }
+            ProviderRequest::InsertNegative(key, ttl) => {
+                let item = Item {
+                    value: None,
+                    expires_at: Some(expires_at(ttl)),
+                };
+                let put = client.put_item(&self.table, self.key_codec.encode_key(&key), item);
+                Box::pin(async move {
+                    put.await.map_err(Error::dynamo)?;
+                    Ok(ProviderResponse::FoundNegative)
+                })
+            }
+            ProviderRequest::Remove(key) => {
+                let delete = client.delete_item(&self.table, self.key_codec.encode_key(&key));
+                Box::pin(async move {
+                    Ok(match delete.await.map_err(Error::dynamo)? {
+                        true => ProviderResponse::Removed,
+                        false => ProviderResponse::NotFound,
+                    })
+                })
+            }
+            ProviderRequest::Contains(key) => {
+                let item = client.get_item(&self.table, self.key_codec.encode_key(&key));
+                Box::pin(async move {
+                    let item = live(item.await.map_err(Error::dynamo)?);
+                    Ok(ProviderResponse::Present(item.is_some()))
+                })
+            }
+            // Clearing the cache would scan and delete the whole table, and
+            // the original TTL of an entry isn't stored.
+            ProviderRequest::Clear
+            | ProviderRequest::Ttl(_)
+            | ProviderRequest::Age(_)
+            | ProviderRequest::GetOrInsert(_, _)
+            | ProviderRequest::GetMany(_)
+            | ProviderRequest::InsertMany(_)
+            | ProviderRequest::Stats => Box::pin(async { Ok(ProviderResponse::NotFound) }),
+        }
+    }
+}
+
+/// Return the item unless it has expired
+///
+/// DynamoDB can take a while to delete expired items, and returns them until
+/// then.
+fn live(item: Option<Item>) -> Option<Item> {
+    item.filter(|item| !item.is_expired(now()))
+}
+
+/// Return the current time as a Unix timestamp in seconds
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Convert a TTL to an expiration time
+///
+/// TTLs are rounded up to the next second, so that they never expire
+/// immediately, and saturate at `u64::MAX`.
+fn expires_at(ttl: Duration) -> u64 {
+    let secs = ttl
+        .as_secs()
+        .saturating_add(u64::from(ttl.subsec_nanos() > 0));
+    now().saturating_add(secs.max(1))
+}
+
+type ProviderFuture<'a, K, V> =
+    Pin<Box<dyn Future<Output = Result<ProviderResponse<K, V>, Error>> + Send + 'a>>;
+
+/// Error returned by the [`DynamoProvider`]
+#[derive(Debug)]
+pub enum Error {
+    /// Error returned by the DynamoDB client
+    DynamoError(Box<dyn error::Error + Send + Sync>),
+    /// Error while serializing or deserializing a value
+    CodecError(Box<dyn error::Error + Send + Sync>),
+}
+
+impl Error {
+    fn dynamo<E>(e: E) -> Self
+    where
+        E: error::Error + Send + Sync + 'static,
+    {
+        Error::DynamoError(Box::new(e))
+    }
+
+    fn codec<E>(e: E) -> Self
+    where
+        E: error::Error + Send + Sync + 'static,
+    {
+        Error::CodecError(Box::new(e))
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::DynamoError(e) => Some(e.as_ref()),
+            Error::CodecError(e) => Some(e.as_ref()),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::DynamoError(e) => write!(f, "dynamodb error: {}", e),
+            Error::CodecError(e) => write!(f, "serialization error: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::TupleKey;
+    use crate::conformance::check_provider;
+    use std::{
+        collections::HashMap,
+        convert::Infallible,
+        future::ready,
+        sync::{Arc, Mutex},
+    };
+
+    type MockData = HashMap<(String, String), Item>;
+
+    /// In-memory client storing items by table name and key
+    #[derive(Clone, Default)]
+    struct MockClient {
+        data: Arc<Mutex<MockData>>,
+    }
+
+    impl MockClient {
+        fn item(&self, key: &str) -> Option<Item> {
+            let data = self.data.lock().unwrap();
+            data.get(&("cache".to_string(), key.to_string())).cloned()
+        }
+    }
+
+    impl DynamoClient for MockClient {
+        type Error = Infallible;
+
+        fn get_item(&self, table: &Table, key: String) -> ClientFuture<Option<Item>, Infallible> {
+            let data = self.data.lock().unwrap();
+            Box::pin(ready(Ok(data.get(&(table.name.clone(), key)).cloned())))
+        }
+
+        fn put_item(&self, table: &Table, key: String, item: Item) -> ClientFuture<(), Infallible> {
+            let mut data = self.data.lock().unwrap();
+            data.insert((table.name.clone(), key), item);
+            Box::pin(ready(Ok(())))
+        }
+
+        fn delete_item(&self, table: &Table, key: String) -> ClientFuture<bool, Infallible> {
+            let mut data = self.data.lock().unwrap();
+            let removed = data.remove(&(table.name.clone(), key)).is_some();
+            Box::pin(ready(Ok(removed)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_insert() -> Result<(), Error> {
+        let client = MockClient::default();
+        let mut provider = DynamoProvider::new::<String, String, _>(client.clone(), "cache");
+        check_provider(provider.clone()).await?;
+        assert_eq!(client.item("a").unwrap().value.unwrap(), b"\"A\"");
+        assert_eq!(client.item("a").unwrap().expires_at, None);
+
+        let res = provider
+            .call(ProviderRequest::Contains("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(true)));
+        let res = provider
+            .call(ProviderRequest::Remove("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Removed));
+        let res = provider
+            .call(ProviderRequest::Remove("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ttl() -> Result<(), Error> {
+        let client = MockClient::default();
+        let mut provider = DynamoProvider::new::<String, String, _>(client.clone(), "cache")
+            .configure(&ProviderConfig::new().ttl(Duration::from_secs(60)));
+
+        let request = ProviderRequest::Insert("a".to_string(), "A".to_string(), None);
+        provider.call(request).await?;
+        let request = ProviderRequest::Insert(
+            "b".to_string(),
+            "B".to_string(),
+            Some(Duration::from_millis(1500)),
+        );
+        provider.call(request).await?;
+
+        // TTLs are rounded up to the next second
+        let now = now();
+        let expires_at = client.item("a").unwrap().expires_at.unwrap();
+        assert!((now + 59..=now + 60).contains(&expires_at));
+        let expires_at = client.item("b").unwrap().expires_at.unwrap();
+        assert!((now + 1..=now + 2).contains(&expires_at));
+        assert_eq!(super::expires_at(Duration::MAX), u64::MAX);
+
+        // Expired items that DynamoDB didn't delete yet are ignored
+        client.data.lock().unwrap().insert(
+            ("cache".to_string(), "c".to_string()),
+            Item {
+                value: Some(b"\"C\"".to_vec()),
+                expires_at: Some(now - 1),
+            },
+        );
+        let res = provider.call(ProviderRequest::Get("c".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        let res = provider
+            .call(ProviderRequest::Contains("c".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(false)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_negative() -> Result<(), Error> {
+        let client = MockClient::default();
+        let mut provider = DynamoProvider::new::<String, String, _>(client.clone(), "cache");
+
+        let request = ProviderRequest::InsertNegative("a".to_string(), Duration::from_secs(5));
+        provider.call(request).await?;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::FoundNegative));
+        let item = client.item("a").unwrap();
+        assert_eq!(item.value, None);
+        assert!(item.expires_at.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_codec_error() {
+        let client = MockClient::default();
+        let mut provider = DynamoProvider::new::<String, String, _>(client.clone(), "cache");
+        client.data.lock().unwrap().insert(
+            ("cache".to_string(), "a".to_string()),
+            Item {
+                value: Some(b"not json".to_vec()),
+                expires_at: None,
+            },
+        );
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await;
+        assert!(matches!(res, Err(Error::CodecError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_tuple_key() -> Result<(), Error> {
+        let client = MockClient::default();
+        let mut provider = DynamoProvider::new::<(u64, String), String, _>(client.clone(), "cache")
+            .with_key_codec(TupleKey::new());
+
+        let request = ProviderRequest::Insert((1, "en".to_string()), "A".to_string(), None);
+        provider.call(request).await?;
+        assert!(client.item("1:en").is_some());
+
+        let res = provider
+            .call(ProviderRequest::Get((1, "en".to_string())))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+
+        Ok(())
+    }
+
+    /// Runs against DynamoDB local when `DYNAMODB_ENDPOINT` is set.
+    #[tokio::test]
+    async fn test_dynamodb_local() -> Result<(), Box<dyn error::Error>> {
+        use aws_sdk_dynamodb::{
+            config::{BehaviorVersion, Credentials, Region},
+            types::{
+                AttributeDefinition, BillingMode, KeySchemaElement, KeyType, ScalarAttributeType,
+            },
+        };
+
+        let endpoint = match std::env::var("DYNAMODB_ENDPOINT") {
+            Ok(endpoint) => endpoint,
+            Err(_) => return Ok(()),
+        };
+        let config = aws_sdk_dynamodb::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .endpoint_url(endpoint)
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::new("test", "test", None, None, "test"))
+            .build();
+        let client = aws_sdk_dynamodb::Client::from_conf(config);
+
+        let created = client
+            .create_table()
+            .table_name("tower-cache-test")
+            .attribute_definitions(
+                AttributeDefinition::builder()
+                    .attribute_name("pk")
+                    .attribute_type(ScalarAttributeType::S)
+                    .build()?,
+            )
+            .key_schema(
+                KeySchemaElement::builder()
+                    .attribute_name("pk")
+                    .key_type(KeyType::Hash)
+                    .build()?,
+            )
+            .billing_mode(BillingMode::PayPerRequest)
+            .send()
+            .await;
+        // The table can be left over from a previous run
+        if let Err(err) = created {
+            if !err
+                .as_service_error()
+                .is_some_and(|err| err.is_resource_in_use_exception())
+            {
+                return Err(err.into());
+            }
+        }
+
+        let mut provider = DynamoProvider::new::<u64, String, _>(client, "tower-cache-test");
+        provider
+            .call(ProviderRequest::Insert(1, "one".to_string(), None))
+            .await?;
+        let res = provider.call(ProviderRequest::Get(1)).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "one"));
+
+        let request = ProviderRequest::InsertNegative(2, Duration::from_secs(60));
+        provider.call(request).await?;
+        let res = provider.call(ProviderRequest::Get(2)).await?;
+        assert!(matches!(res, ProviderResponse::FoundNegative));
+
+        let res = provider.call(ProviderRequest::Remove(1)).await?;
+        assert!(matches!(res, ProviderResponse::Removed));
+        let res = provider.call(ProviderRequest::Get(1)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_attributes() {
+        let provider = DynamoProvider::new::<String, String, _>(MockClient::default(), "cache")
+            .key_attribute("id")
+            .value_attribute("data")
+            .ttl_attribute("expires_at")
+            .negative_attribute("negative");
+        let clone = provider.clone();
+
+        assert_eq!(
+            clone.table(),
+            &Table {
+                name: "cache".to_string(),
+                key_attribute: "id".to_string(),
+                value_attribute: "data".to_string(),
+                ttl_attribute: "expires_at".to_string(),
+                negative_attribute: "negative".to_string(),
+            }
+        );
+    }
+}
@@ -0,0 +1,316 @@
+use crate::{time, ProviderResponse};
+use std::time::{Duration, Instant};
+
+/// Value stored by in-memory providers, alongside its expiration time
+///
+/// Negative entries don't have a value. Entries with a stale window are
+/// still returned as stale between `stale_at` and `expires_at`.
+///
+/// See [`crate::lru::LruProvider::with_lock`].
+#[derive(Clone, Debug)]
+#[cfg_attr(not(feature = "lru"), allow(unreachable_pub))]
+pub struct Entry<V> {
+    pub(crate) value: Option<V>,
+    pub(crate) ttl: Option<Duration>,
+    pub(crate) stale_at: Option<Instant>,
+    pub(crate) expires_at: Option<Instant>,
+    pub(crate) inserted_at: Instant,
+}
+
+impl<V> Entry<V> {
+    /// Create an entry expiring after `ttl`, if any
+    ///
+    /// TTLs too large to be represented, such as [`Duration::MAX`], never
+    /// expire.
+    pub(crate) fn new(value: V, ttl: Option<Duration>) -> Self {
+        let now = time::now();
+        Entry {
+            value: Some(value),
+            ttl,
+            stale_at: None,
+            expires_at: ttl.and_then(|ttl| now.checked_add(ttl)),
+            inserted_at: now,
+        }
+    }
+
+    /// Keep the entry for `window` after it becomes stale
+    ///
+    /// Entries that never expire never become stale.
+    pub(crate) fn stale_for(mut self, window: Option<Duration>) -> Self {
+        if let (Some(expires_at), Some(window)) = (self.expires_at, window) {
+            self.stale_at = Some(expires_at);
+            self.expires_at = expires_at.checked_add(window);
+        }
+        self
+    }
+
+    /// Create a negative entry expiring after `ttl`
+    pub(crate) fn negative(ttl: Duration) -> Self {
+        let now = time::now();
+        Entry {
+            value: None,
+            ttl: Some(ttl),
+            stale_at: None,
+            expires_at: now.checked_add(ttl),
+            inserted_at: now,
+        }
+    }
+
+    /// Return the value of the entry, or `None` for a negative entry
+    ///
+    /// Expired entries still have a value until they are removed.
+    #[cfg_attr(not(feature = "lru"), allow(dead_code, unreachable_pub))]
+    pub fn value(&self) -> Option<&V> {
+        self.value.as_ref()
+    }
+
+    /// Return the time after which the entry is removed, if it expires
+    ///
+    /// This includes the stale window, if any.
+    #[cfg_attr(not(feature = "lru"), allow(dead_code, unreachable_pub))]
+    pub fn expires_at(&self) -> Option<Instant> {
+        self.expires_at
+    }
+
+    /// Check if the entry has expired at `now`
+    #[cfg_attr(not(feature = "lru"), allow(unreachable_pub))]
+    pub fn is_expired(&self, now: Instant) -> bool {
+        matches!(self.expires_at, Some(expires_at) if now >= expires_at)
+    }
+
+    /// Return the remaining and original TTL of the entry at `now`
+    ///
+    /// The remaining TTL is the time until the entry becomes stale, if it
+    /// has a stale window. Returns `None` for entries that never expire, or
+    /// that have expired.
+    pub(crate) fn ttl_at(&self, now: Instant) -> Option<(Duration, Duration)> {
+        if self.is_expired(now) {
+            return None;
+        }
+        let deadline = self.stale_at.or(self.expires_at)?;
+        Some((deadline.saturating_duration_since(now), self.ttl?))
+    }
+
+    /// Return how long ago the entry was inserted, unless it has expired at
+    /// `now`
+    pub(crate) fn age_at(&self, now: Instant) -> Option<Duration> {
+        match self.is_expired(now) {
+            true => None,
+            false => Some(now.saturating_duration_since(self.inserted_at)),
+        }
+    }
+
+    /// Return the value of the entry if it hasn't expired at `now`
+    ///
+    /// Stale entries still have a value, but negative entries don't.
+    pub(crate) fn value_at(&self, now: Instant) -> Option<&V> {
+        match self.is_expired(now) {
+            true => None,
+            false => self.value.as_ref(),
+        }
+    }
+
+    /// Return the value of the entry if it is still fresh at `now`
+    ///
+    /// Negative and stale entries don't have a fresh value.
+    pub(crate) fn fresh_value_at(&self, now: Instant) -> Option<&V> {
+        match self.stale_at.or(self.expires_at) {
+            Some(deadline) if now >= deadline => None,
+            _ => self.value.as_ref(),
+        }
+    }
+
+    /// Return the response for this entry if it hasn't expired at `now`
+    pub(crate) fn response_at<K>(&self, now: Instant) -> Option<ProviderResponse<K, V>>
+    where
+        V: Clone,
+    {
+        if self.is_expired(now) {
+            return None;
+        }
+
+        let stale = matches!(self.stale_at, Some(stale_at) if now >= stale_at);
+        Some(match &self.value {
+            Some(value) if stale => ProviderResponse::FoundStale(value.clone()),
+            Some(value) => ProviderResponse::Found(value.clone()),
+            None => ProviderResponse::FoundNegative,
+        })
+    }
+}
+
+/// Expire entries with their own TTL at their expiration time
+///
+/// This covers negative entries, and entries inserted with a TTL. Other
+/// entries only expire through the time-to-live and time-to-idle policies of
+/// the cache.
+#[cfg(any(feature = "moka", feature = "moka-sync"))]
+pub(crate) struct EntryExpiry;
+
+#[cfg(any(feature = "moka", feature = "moka-sync"))]
+impl<K, V> moka::Expiry<K, Entry<V>> for EntryExpiry {
+    fn expire_after_create(
+        &self,
+        _key: &K,
+        value: &Entry<V>,
+        created_at: Instant,
+    ) -> Option<Duration> {
+        value
+            .expires_at
+            .map(|expires_at| expires_at.saturating_duration_since(created_at))
+    }
+
+    fn expire_after_update(
+        &self,
+        key: &K,
+        value: &Entry<V>,
+        updated_at: Instant,
+        _duration_until_expiry: Option<Duration>,
+    ) -> Option<Duration> {
+        // Replacing an entry shouldn't keep its expiration.
+        self.expire_after_create(key, value, updated_at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_response_at() {
+        let now = time::now();
+        let entry = Entry {
+            value: Some(1),
+            ttl: Some(Duration::from_secs(1)),
+            stale_at: None,
+            expires_at: Some(now + Duration::from_secs(1)),
+            inserted_at: now,
+        };
+
+        assert!(matches!(
+            entry.response_at::<()>(now),
+            Some(ProviderResponse::Found(1))
+        ));
+        assert!(entry
+            .response_at::<()>(now + Duration::from_secs(1))
+            .is_none());
+        assert!(entry
+            .response_at::<()>(now + Duration::from_secs(2))
+            .is_none());
+
+        let entry = Entry {
+            value: Some(1),
+            ttl: None,
+            stale_at: None,
+            expires_at: None,
+            inserted_at: now,
+        };
+        assert!(matches!(
+            entry.response_at::<()>(now + Duration::from_secs(3600)),
+            Some(ProviderResponse::Found(1))
+        ));
+
+        let entry = Entry::<usize> {
+            value: None,
+            ttl: Some(Duration::from_secs(1)),
+            stale_at: None,
+            expires_at: Some(now + Duration::from_secs(1)),
+            inserted_at: now,
+        };
+        assert!(matches!(
+            entry.response_at::<()>(now),
+            Some(ProviderResponse::FoundNegative)
+        ));
+    }
+
+    #[test]
+    fn test_entry_max_ttl() {
+        let entry = Entry::new(1, Some(Duration::MAX)).stale_for(Some(Duration::MAX));
+        assert_eq!(entry.expires_at(), None);
+        assert!(matches!(
+            entry.response_at::<()>(time::now() + Duration::from_secs(3600)),
+            Some(ProviderResponse::Found(1))
+        ));
+
+        let entry = Entry::new(1, Some(Duration::from_secs(1))).stale_for(Some(Duration::MAX));
+        assert!(entry.stale_at.is_some());
+        assert_eq!(entry.expires_at(), None);
+
+        let entry = Entry::<usize>::negative(Duration::MAX);
+        assert_eq!(entry.expires_at(), None);
+        assert!(!entry.is_expired(time::now()));
+    }
+
+    #[test]
+    fn test_entry_stale() {
+        let entry =
+            Entry::new(1, Some(Duration::from_secs(1))).stale_for(Some(Duration::from_secs(2)));
+        let stale_at = entry.stale_at.unwrap();
+
+        assert!(matches!(
+            entry.response_at::<()>(stale_at - Duration::from_millis(1)),
+            Some(ProviderResponse::Found(1))
+        ));
+        assert!(matches!(
+            entry.response_at::<()>(stale_at),
+            Some(ProviderResponse::FoundStale(1))
+        ));
+        assert!(!entry.is_expired(stale_at + Duration::from_secs(1)));
+        assert!(entry
+            .response_at::<()>(stale_at + Duration::from_secs(2))
+            .is_none());
+
+        // Entries without a TTL never become stale
+        let entry = Entry::new(1, None).stale_for(Some(Duration::from_secs(2)));
+        assert!(entry.stale_at.is_none());
+        assert!(entry.expires_at.is_none());
+    }
+
+    #[test]
+    fn test_entry_fresh_value_at() {
+        let entry =
+            Entry::new(1, Some(Duration::from_secs(1))).stale_for(Some(Duration::from_secs(2)));
+        let stale_at = entry.stale_at.unwrap();
+
+        assert_eq!(
+            entry.fresh_value_at(stale_at - Duration::from_millis(1)),
+            Some(&1)
+        );
+        assert_eq!(entry.fresh_value_at(stale_at), None);
+        assert_eq!(Entry::new(1, None).fresh_value_at(time::now()), Some(&1));
+        assert_eq!(
+            Entry::<usize>::negative(Duration::from_secs(1)).fresh_value_at(time::now()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_entry_ttl_at() {
+        let entry = Entry::new(1, Some(Duration::from_secs(10)));
+        let expires_at = entry.expires_at.unwrap();
+        assert_eq!(
+            entry.ttl_at(expires_at - Duration::from_secs(2)),
+            Some((Duration::from_secs(2), Duration::from_secs(10)))
+        );
+        assert_eq!(entry.ttl_at(expires_at), None);
+
+        // The remaining TTL counts down to the end of the freshness period.
+        let entry = entry.stale_for(Some(Duration::from_secs(5)));
+        assert_eq!(
+            entry.ttl_at(expires_at),
+            Some((Duration::ZERO, Duration::from_secs(10)))
+        );
+
+        assert_eq!(Entry::new(1, None).ttl_at(time::now()), None);
+    }
+
+    #[test]
+    fn test_entry_age_at() {
+        let entry = Entry::new(1, Some(Duration::from_secs(10)));
+        let inserted_at = entry.inserted_at;
+        assert_eq!(
+            entry.age_at(inserted_at + Duration::from_secs(3)),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(entry.age_at(inserted_at + Duration::from_secs(10)), None);
+    }
+}
@@ -0,0 +1,337 @@
+//! Caching of errors returned by the inner service
+//!
+//! By default, only successful responses are stored in the cache provider,
+//! and every request that fails calls the inner service again. With
+//! [`crate::CacheLayer::cache_errors`], errors are stored as well for a short
+//! time, so that a failing dependency isn't called on every request.
+//!
+//! To store both outcomes, the inner service is wrapped in [`CatchErrors`],
+//! which returns `Ok(Err(error))` instead of `Err(error)`. The cache provider
+//! then stores `Result<Res, E>` values, and [`ErrorCacheService`] turns them
+//! back into the response or error of the inner service.
+
+use crate::{
+    CacheError, CacheEventListener, CachePredicate, NegativePolicy, TtlPolicy, ValueLoader,
+    ValueTransform,
+};
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+use tower::Service;
+
+/// Error caching policy
+///
+/// Created by [`crate::CacheLayer::cache_errors`]. Errors from the inner
+/// service are stored for `ttl`, after which the inner service is called
+/// again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ErrorCache {
+    ttl: Duration,
+}
+
+impl ErrorCache {
+    /// Create a new error caching policy with the given TTL
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl }
+    }
+
+    /// Return how long errors are cached for
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Wrap a policy of the [`crate::CacheLayer`] to apply it to successful
+    /// responses only
+    pub(crate) fn wrap<X>(&self, inner: X) -> WithErrors<X> {
+        WithErrors {
+            inner,
+            error_ttl: self.ttl,
+        }
+    }
+}
+
+/// Adapter applying a policy to the successful responses of a service
+/// wrapped in [`CatchErrors`]
+///
+/// Errors are always cached, with the TTL of the [`ErrorCache`] policy. They
+/// are never treated as negative entries.
+#[derive(Clone, Copy, Debug)]
+pub struct WithErrors<X> {
+    inner: X,
+    error_ttl: Duration,
+}
+
+impl<X, Res, E> NegativePolicy<Result<Res, E>> for WithErrors<X>
+where
+    X: NegativePolicy<Res>,
+{
+    fn negative_ttl(&self, res: &Result<Res, E>) -> Option<Duration> {
+        match res {
+            Ok(res) => self.inner.negative_ttl(res),
+            Err(_) => None,
+        }
+    }
+
+    fn empty(&self) -> Option<Result<Res, E>> {
+        self.inner.empty().map(Ok)
+    }
+}
+
+impl<X, Res, E> CachePredicate<Result<Res, E>> for WithErrors<X>
+where
+    X: CachePredicate<Res>,
+{
+    fn should_cache(&self, res: &Result<Res, E>) -> bool {
+        match res {
+            Ok(res) => self.inner.should_cache(res),
+            Err(_) => true,
+        }
+    }
+}
+
+impl<X, Res, E> TtlPolicy<Result<Res, E>> for WithErrors<X>
+where
+    X: TtlPolicy<Res>,
+{
+    fn ttl(&self, res: &Result<Res, E>) -> Option<Duration> {
+        match res {
+            Ok(res) => self.inner.ttl(res),
+            Err(_) => Some(self.error_ttl),
+        }
+    }
+}
+
+impl<X, K, V, E> CacheEventListener<K, Result<V, E>> for WithErrors<X>
+where
+    X: CacheEventListener<K, V>,
+{
+    fn on_hit(&self, key: &K) {
+        self.inner.on_hit(key);
+    }
+
+    fn on_miss(&self, key: &K) {
+        self.inner.on_miss(key);
+    }
+
+    fn on_insert(&self, key: &K) {
+        self.inner.on_insert(key);
+    }
+
+    fn on_evict(&self, key: &K, value: &Result<V, E>) {
+        if let Ok(value) = value {
+            self.inner.on_evict(key, value);
+        }
+    }
+}
+
+impl<X, Res, E> ValueTransform<Result<Res, E>> for WithErrors<X>
+where
+    X: ValueTransform<Res>,
+    E: Clone,
+{
+    type Stored = Result<X::Stored, E>;
+
+    fn store(&self, res: &Result<Res, E>) -> Self::Stored {
+        match res {
+            Ok(res) => Ok(self.inner.store(res)),
+            Err(e) => Err(e.clone()),
+        }
+    }
+
+    fn restore(&self, stored: Self::Stored) -> Result<Res, E> {
+        stored.map(|stored| self.inner.restore(stored))
+    }
+
+    fn restore_stale(&self, stored: Self::Stored) -> Result<Res, E> {
+        stored.map(|stored| self.inner.restore_stale(stored))
+    }
+}
+
+impl<X, Req, Res, E> ValueLoader<Req, Result<Res, E>> for WithErrors<X>
+where
+    X: ValueLoader<Req, Res>,
+    E: Clone,
+{
+    type Stored = Result<X::Stored, E>;
+
+    fn save(&self, res: &Result<Res, E>) -> Self::Stored {
+        match res {
+            Ok(res) => Ok(self.inner.save(res)),
+            Err(e) => Err(e.clone()),
+        }
+    }
+
+    fn load(&self, stored: Self::Stored, req: &Req) -> Result<Res, E> {
+        stored.map(|stored| self.inner.load(stored, req))
+    }
+
+    fn load_stale(&self, stored: Self::Stored, req: &Req) -> Result<Res, E> {
+        stored.map(|stored| self.inner.load_stale(stored, req))
+    }
+}
+
+/// Service returning the errors of the inner service as successful responses
+#[derive(Clone, Debug)]
+pub struct CatchErrors<S> {
+    inner: S,
+}
+
+impl<S> CatchErrors<S> {
+    /// Wrap a service
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S, R> Service<R> for CatchErrors<S>
+where
+    S: Service<R>,
+{
+    type Response = Result<S::Response, S::Error>;
+    type Error = S::Error;
+    type Future = CatchErrorsFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Readiness errors are not tied to a request, so they can't be cached.
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        CatchErrorsFuture {
+            inner: self.inner.call(request),
+        }
+    }
+}
+
+pin_project! {
+    /// Future returned by [`CatchErrors`]
+    #[derive(Debug)]
+    pub struct CatchErrorsFuture<F> {
+        #[pin]
+        inner: F,
+    }
+}
+
+impl<F, Res, E> Future for CatchErrorsFuture<F>
+where
+    F: Future<Output = Result<Res, E>>,
+{
+    type Output = Result<Result<Res, E>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.project().inner.poll(cx).map(Ok)
+    }
+}
+
+/// Service generated by [`crate::CacheLayer`] when errors are cached
+///
+/// This wraps a [`crate::CacheService`] storing `Result<Res, E>` values, and
+/// returns cached errors as [`CacheError::ServiceError`].
+#[derive(Clone, Debug)]
+pub struct ErrorCacheService<S> {
+    inner: S,
+}
+
+impl<S> ErrorCacheService<S> {
+    pub(crate) fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    /// Return a reference to the wrapped [`crate::CacheService`]
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S, R, Res, E, PE> Service<R> for ErrorCacheService<S>
+where
+    S: Service<R, Response = Result<Res, E>, Error = CacheError<PE, E>>,
+{
+    type Response = Res;
+    type Error = CacheError<PE, E>;
+    type Future = ErrorCacheFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        ErrorCacheFuture {
+            inner: self.inner.call(request),
+        }
+    }
+}
+
+pin_project! {
+    /// Future returned by [`ErrorCacheService`]
+    #[derive(Debug)]
+    pub struct ErrorCacheFuture<F> {
+        #[pin]
+        inner: F,
+    }
+}
+
+impl<F, Res, E, PE> Future for ErrorCacheFuture<F>
+where
+    F: Future<Output = Result<Result<Res, E>, CacheError<PE, E>>>,
+{
+    type Output = Result<Res, CacheError<PE, E>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let res = ready!(self.project().inner.poll(cx));
+        Poll::Ready(res.and_then(|res| res.map_err(CacheError::ServiceError)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_policies() {
+        let policy = ErrorCache::new(Duration::from_secs(1));
+
+        let ttl = policy.wrap(|res: &usize| Some(Duration::from_secs(*res as u64)));
+        assert_eq!(ttl.ttl(&Ok::<_, ()>(5)), Some(Duration::from_secs(5)));
+        assert_eq!(ttl.ttl(&Err::<usize, _>(())), Some(Duration::from_secs(1)));
+
+        let predicate = policy.wrap(|res: &usize| *res > 0);
+        assert!(!predicate.should_cache(&Ok::<_, ()>(0)));
+        assert!(predicate.should_cache(&Err::<usize, _>(())));
+
+        let negative = policy.wrap(crate::NegativeCache::new(Duration::from_secs(2)));
+        assert_eq!(
+            negative.negative_ttl(&Ok::<Option<usize>, ()>(None)),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(negative.negative_ttl(&Err::<Option<usize>, _>(())), None);
+        assert_eq!(negative.empty(), Some(Ok::<Option<usize>, ()>(None)));
+    }
+
+    #[tokio::test]
+    async fn test_catch_errors() {
+        let mut service = CatchErrors::new(tower::service_fn(|req: usize| async move {
+            match req {
+                0 => Err("zero"),
+                req => Ok::<_, &str>(req),
+            }
+        }));
+
+        assert_eq!(service.call(1).await, Ok(Ok(1)));
+        assert_eq!(service.call(0).await, Ok(Err("zero")));
+
+        let mut service =
+            ErrorCacheService::new(service.map_err(CacheError::<Infallible, _>::ServiceError));
+        assert_eq!(service.call(1).await.ok(), Some(1));
+        assert!(matches!(
+            service.call(0).await,
+            Err(CacheError::ServiceError("zero"))
+        ));
+    }
+}
@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+/// # Cache event listener
+///
+/// A listener is notified of the lifecycle of cache entries, for example to
+/// record custom metrics or logs. Keys are the keys sent to the cache
+/// provider, after the request has been transformed.
+///
+/// All methods have a default empty implementation, so a listener only needs
+/// to implement the events it is interested in. Use
+/// [`crate::CacheLayer::on_event`] to register a listener with a
+/// [`crate::CacheLayer`].
+///
+/// ```rust
+/// use std::sync::{
+///     atomic::{AtomicU64, Ordering},
+///     Arc,
+/// };
+/// use tower_cache::CacheEventListener;
+///
+/// #[derive(Clone, Default)]
+/// struct MissCounter(Arc<AtomicU64>);
+///
+/// impl<K, V> CacheEventListener<K, V> for MissCounter {
+///     fn on_miss(&self, _key: &K) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let counter = MissCounter::default();
+/// CacheEventListener::<String, String>::on_miss(&counter, &"Hello".to_string());
+/// assert_eq!(counter.0.load(Ordering::Relaxed), 1);
+/// ```
+///
+/// This is also implemented for `()`, which ignores all events. This is the
+/// default for [`crate::CacheLayer`], and compiles down to nothing.
+///
+/// Cache providers that can detect evictions, such as
+/// [`crate::lru::LruProvider::on_evict`], can also notify a listener when
+/// they evict an entry.
+pub trait CacheEventListener<K, V> {
+    /// A response was served from the cache
+    fn on_hit(&self, _key: &K) {}
+
+    /// A request was sent to the inner service
+    fn on_miss(&self, _key: &K) {}
+
+    /// A response was stored in the cache provider
+    fn on_insert(&self, _key: &K) {}
+
+    /// The cache provider evicted an entry to make room for another one
+    fn on_evict(&self, _key: &K, _value: &V) {}
+}
+
+impl<K, V> CacheEventListener<K, V> for () {}
+
+impl<K, V, L> CacheEventListener<K, V> for Arc<L>
+where
+    L: CacheEventListener<K, V> + ?Sized,
+{
+    fn on_hit(&self, key: &K) {
+        (**self).on_hit(key)
+    }
+
+    fn on_miss(&self, key: &K) {
+        (**self).on_miss(key)
+    }
+
+    fn on_insert(&self, key: &K) {
+        (**self).on_insert(key)
+    }
+
+    fn on_evict(&self, key: &K, value: &V) {
+        (**self).on_evict(key, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl CacheEventListener<u32, String> for Recorder {
+        fn on_hit(&self, key: &u32) {
+            self.0.lock().unwrap().push(format!("hit {}", key));
+        }
+
+        fn on_evict(&self, key: &u32, value: &String) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("evict {} {}", key, value));
+        }
+    }
+
+    #[test]
+    fn test_arc() {
+        let recorder = Arc::new(Recorder::default());
+        let listener: Arc<dyn CacheEventListener<u32, String> + Send + Sync> = recorder.clone();
+
+        listener.on_hit(&1);
+        listener.on_miss(&2);
+        listener.on_evict(&3, &"three".to_string());
+
+        assert_eq!(*recorder.0.lock().unwrap(), ["hit 1", "evict 3 three"]);
+    }
+}
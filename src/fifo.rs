@@ -0,0 +1,408 @@
+//! # FIFO cache provider
+//!
+//! This is an implementation of a cache provider for [`crate::CacheLayer`]
+//! that evicts the oldest inserted entry when it is full. Lookups don't
+//! reorder entries, so they only take a shared lock and eviction order is
+//! predictable.
+//!
+//! Replacing an existing entry keeps its original position.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, service_fn};
+//! use tower_cache::{
+//!     CacheLayer,
+//!     fifo::FifoProvider,
+//! };
+//! async fn handler(req: String) -> Result<String, Infallible> {
+//!     Ok(req.to_uppercase())
+//! }
+//!
+//! // Initialize the cache provider service
+//! let fifo_provider = FifoProvider::new::<String, String>(20);
+//!
+//! // Wrap the service with CacheLayer.
+//! let mut my_service = ServiceBuilder::new()
+//!     .layer(CacheLayer::new(fifo_provider))
+//!     .service(service_fn(handler));
+//!
+//! # tokio_test::block_on(async move {
+//! // Call the service
+//! let res = my_service.call("Hello".to_string()).await.unwrap();
+//! assert_eq!(res, "HELLO".to_string());
+//! # })
+//! ```
+//!
+
+use crate::{
+    entry::Entry, time, trace, Configure, ProviderConfig, ProviderRequest, ProviderResponse,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
+    future::{ready, Future},
+    hash::Hash,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{Arc, RwLock},
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower::Service;
+
+/// Local FIFO cache provider
+#[derive(Debug)]
+pub struct FifoProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    inner: Arc<RwLock<Fifo<K, V>>>,
+    ttl: Option<Duration>,
+    stale_window: Option<Duration>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> FifoProvider<'a, (), ()> {
+    /// Create a new FIFO cache provider with the desired capacity
+    ///
+    /// A capacity of `0` is clamped to `1`.
+    pub fn new<K, V>(capacity: usize) -> FifoProvider<'a, K, V>
+    where
+        K: Eq + Hash,
+    {
+        FifoProvider {
+            inner: Arc::new(RwLock::new(Fifo::new(capacity.max(1)))),
+            ttl: None,
+            stale_window: None,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Create a new FIFO cache provider where entries expire after `ttl`
+    ///
+    /// As with [`FifoProvider::new`], a capacity of `0` is clamped to `1`.
+    pub fn with_ttl<K, V>(capacity: usize, ttl: Duration) -> FifoProvider<'a, K, V>
+    where
+        K: Eq + Hash,
+    {
+        FifoProvider {
+            ttl: Some(ttl),
+            ..Self::new(capacity)
+        }
+    }
+}
+
+impl<'a, K, V> FifoProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    /// Keep entries for `window` after they expire, and return them as
+    /// [`ProviderResponse::FoundStale`] during that time.
+    ///
+    /// This allows [`crate::CacheLayer::stale_while_revalidate`] to serve
+    /// them while they are refreshed. Entries without a TTL never become
+    /// stale.
+    pub fn stale_window(mut self, window: Duration) -> Self {
+        self.stale_window = Some(window);
+        self
+    }
+}
+
+// Custom implementation of Clone as the Clone derive doesn't mark FifoProvider
+// as Clone if K or V is not clone.
+impl<'a, K, V> Clone for FifoProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            ttl: self.ttl,
+            stale_window: self.stale_window,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+/// Sets the TTL. Setting the capacity clears the cache, for all clones of the
+/// provider, so it should be done before using it.
+impl<'a, K, V> Configure for FifoProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    fn configure(mut self, config: &ProviderConfig) -> Self {
+        if let Some(ttl) = config.ttl {
+            self.ttl = Some(ttl);
+        }
+        if let Some(capacity) = config.capacity {
+            // Caches hold at least one entry.
+            if capacity == 0 {
+                trace::unsupported_config::<Self>("capacity");
+            }
+            *self.inner.write().unwrap() = Fifo::new(capacity.max(1));
+        }
+        self
+    }
+}
+
+impl<'a, K, V> Service<ProviderRequest<K, V>> for FifoProvider<'a, K, V>
+where
+    K: Eq + Hash + Clone + Send + 'a,
+    V: Clone + Send + 'a,
+{
+    type Response = ProviderResponse<K, V>;
+    type Error = Infallible;
+    type Future = ProviderFuture<'a, K, V>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ProviderRequest<K, V>) -> Self::Future {
+        Box::pin(ready(Ok(match request {
+            ProviderRequest::Get(key) => {
+                let now = time::now();
+                let response = {
+                    let inner = self.inner.read().unwrap();
+                    inner.get(&key).map(|entry| entry.response_at(now))
+                };
+                match response {
+                    Some(Some(response)) => response,
+                    // The entry has expired: remove it so it doesn't take up
+                    // capacity anymore.
+                    Some(None) => {
+                        let mut inner = self.inner.write().unwrap();
+                        if inner.get(&key).is_some_and(|entry| entry.is_expired(now)) {
+                            inner.remove(&key);
+                        }
+                        ProviderResponse::NotFound
+                    }
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            ProviderRequest::Insert(key, value, ttl) => {
+                let entry =
+                    Entry::new(value.clone(), ttl.or(self.ttl)).stale_for(self.stale_window);
+                self.inner.write().unwrap().insert(key, entry);
+                ProviderResponse::Found(value)
+            }
+            ProviderRequest::InsertNegative(key, ttl) => {
+                self.inner
+                    .write()
+                    .unwrap()
+                    .insert(key, Entry::negative(ttl));
+                ProviderResponse::FoundNegative
+            }
+            ProviderRequest::Clear => {
+                self.inner.write().unwrap().clear();
+                ProviderResponse::Cleared
+            }
+            ProviderRequest::Remove(key) => match self.inner.write().unwrap().remove(&key) {
+                Some(_) => ProviderResponse::Removed,
+                None => ProviderResponse::NotFound,
+            },
+            ProviderRequest::Contains(key) => {
+                let now = time::now();
+                let inner = self.inner.read().unwrap();
+                let present = inner.get(&key).is_some_and(|entry| !entry.is_expired(now));
+                ProviderResponse::Present(present)
+            }
+            ProviderRequest::Ttl(key) => {
+                let now = time::now();
+                let inner = self.inner.read().unwrap();
+                match inner.get(&key).and_then(|entry| entry.ttl_at(now)) {
+                    Some((remaining, ttl)) => ProviderResponse::Ttl(remaining, ttl),
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            ProviderRequest::Age(key) => {
+                let now = time::now();
+                let inner = self.inner.read().unwrap();
+                match inner.get(&key).and_then(|entry| entry.age_at(now)) {
+                    Some(age) => ProviderResponse::Age(age),
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            // Look up and insert under the same lock.
+            ProviderRequest::GetOrInsert(key, value) => {
+                let now = time::now();
+                let mut inner = self.inner.write().unwrap();
+                match inner.get(&key).and_then(|entry| entry.fresh_value_at(now)) {
+                    Some(existing) => ProviderResponse::Found(existing.clone()),
+                    None => {
+                        let entry =
+                            Entry::new(value.clone(), self.ttl).stale_for(self.stale_window);
+                        inner.insert(key, entry);
+                        ProviderResponse::Inserted(value)
+                    }
+                }
+            }
+            ProviderRequest::GetMany(keys) => {
+                let now = time::now();
+                let inner = self.inner.read().unwrap();
+                ProviderResponse::Many(
+                    keys.into_iter()
+                        .map(|key| {
+                            let value = inner
+                                .get(&key)
+                                .and_then(|entry| entry.value_at(now))
+                                .cloned();
+                            (key, value)
+                        })
+                        .collect(),
+                )
+            }
+            // Inserting entries one by one is as cheap as a batch in memory.
+            ProviderRequest::InsertMany(_) => ProviderResponse::NotFound,
+            // Evictions are not counted.
+            ProviderRequest::Stats => ProviderResponse::NotFound,
+        })))
+    }
+}
+
+type ProviderFuture<'a, K, V> =
+    Pin<Box<dyn Future<Output = Result<ProviderResponse<K, V>, Infallible>> + Send + 'a>>;
+
+/// Entries of a [`FifoProvider`], indexed by insertion order
+#[derive(Debug)]
+struct Fifo<K, V> {
+    entries: HashMap<K, (Entry<V>, u64)>,
+    order: BTreeMap<u64, K>,
+    capacity: usize,
+    tick: u64,
+}
+
+impl<K, V> Fifo<K, V>
+where
+    K: Eq + Hash,
+{
+    fn new(capacity: usize) -> Self {
+        Fifo {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            capacity,
+            tick: 0,
+        }
+    }
+
+    fn get(&self, key: &K) -> Option<&Entry<V>> {
+        self.entries.get(key).map(|(entry, _)| entry)
+    }
+
+    /// Store `entry`, evicting the oldest entry if full
+    fn insert(&mut self, key: K, entry: Entry<V>)
+    where
+        K: Clone,
+    {
+        if let Some((old, _)) = self.entries.get_mut(&key) {
+            *old = entry;
+            return;
+        }
+        if self.entries.len() >= self.capacity {
+            if let Some((_, evicted)) = self.order.pop_first() {
+                self.entries.remove(&evicted);
+            }
+        }
+        self.tick += 1;
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, (entry, self.tick));
+    }
+
+    fn remove(&mut self, key: &K) -> Option<Entry<V>> {
+        let (entry, tick) = self.entries.remove(key)?;
+        self.order.remove(&tick);
+        Some(entry)
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::{check_configure, check_provider};
+
+    #[tokio::test]
+    async fn test_conformance() -> Result<(), Infallible> {
+        check_provider(FifoProvider::new::<String, String>(10)).await
+    }
+
+    #[tokio::test]
+    async fn test_reads_dont_prevent_eviction() -> Result<(), Infallible> {
+        let mut provider = FifoProvider::new::<usize, usize>(2);
+
+        provider.call(ProviderRequest::Insert(1, 1, None)).await?;
+        provider.call(ProviderRequest::Insert(2, 2, None)).await?;
+        // With an LRU provider, this would make 2 the next entry to evict.
+        for _ in 0..3 {
+            let res = provider.call(ProviderRequest::Get(1)).await?;
+            assert!(matches!(res, ProviderResponse::Found(1)));
+        }
+        provider.call(ProviderRequest::Insert(3, 3, None)).await?;
+
+        let res = provider.call(ProviderRequest::Get(1)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        let res = provider.call(ProviderRequest::Get(2)).await?;
+        assert!(matches!(res, ProviderResponse::Found(2)));
+        let res = provider.call(ProviderRequest::Get(3)).await?;
+        assert!(matches!(res, ProviderResponse::Found(3)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_replace_keeps_position() -> Result<(), Infallible> {
+        let mut provider = FifoProvider::new::<usize, usize>(2);
+
+        provider.call(ProviderRequest::Insert(1, 1, None)).await?;
+        provider.call(ProviderRequest::Insert(2, 2, None)).await?;
+        provider.call(ProviderRequest::Insert(1, 10, None)).await?;
+        provider.call(ProviderRequest::Insert(3, 3, None)).await?;
+
+        let res = provider.call(ProviderRequest::Get(1)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        let res = provider.call(ProviderRequest::Get(2)).await?;
+        assert!(matches!(res, ProviderResponse::Found(2)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remove_clear() -> Result<(), Infallible> {
+        let mut provider = FifoProvider::with_ttl::<usize, usize>(10, Duration::ZERO);
+
+        provider.call(ProviderRequest::Insert(1, 1, None)).await?;
+        let res = provider.call(ProviderRequest::Get(1)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        assert!(provider.inner.read().unwrap().order.is_empty());
+
+        provider
+            .call(ProviderRequest::Insert(2, 2, Some(Duration::from_secs(10))))
+            .await?;
+        let res = provider.call(ProviderRequest::Remove(2)).await?;
+        assert!(matches!(res, ProviderResponse::Removed));
+
+        provider
+            .call(ProviderRequest::Insert(3, 3, Some(Duration::from_secs(10))))
+            .await?;
+        let res = provider.call(ProviderRequest::Clear).await?;
+        assert!(matches!(res, ProviderResponse::Cleared));
+        let res = provider.call(ProviderRequest::Get(3)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        assert!(provider.inner.read().unwrap().entries.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_configure() -> Result<(), Infallible> {
+        let configure =
+            |config: &ProviderConfig| FifoProvider::new::<usize, usize>(10).configure(config);
+        check_configure(configure, true).await
+    }
+}
@@ -0,0 +1,1616 @@
+//! # HTTP response caching
+//!
+//! [`HttpCacheLayer`] caches [`http::Response`]s returned by services
+//! handling [`http::Request`]s, such as `hyper` or `axum` services, following
+//! the caching rules of HTTP:
+//!
+//! * only `GET` and `HEAD` requests are cached,
+//! * only responses with a cacheable status code, such as `200 OK` or
+//!   `404 Not Found`, are stored,
+//! * `Cache-Control: no-store` on the request or the response bypasses the
+//!   cache, and `no-cache` on the request skips the lookup,
+//! * `Cache-Control: max-age` on the response decides how long it is cached,
+//!   and on the request how old a cached response can be,
+//! * with [`HttpCacheLayer::revalidate_for`], stale responses with an `ETag`
+//!   or `Last-Modified` header are revalidated with a conditional request.
+//!
+//! Responses carry a [`CacheOutcome`] extension telling whether they were
+//! returned by the inner service, the cache provider, or revalidated.
+//!
+//! The cache key is derived from the method, the URI and the request headers
+//! selected with [`HttpCacheLayer::key_header`]. See [`HttpCacheKey`].
+//!
+//! Responses with a `Vary` header are only served to requests with the same
+//! values for the listed headers. Each combination of values is stored under
+//! its own key, so that, for example, compressed and uncompressed responses
+//! can be cached side by side. Responses with `Vary: *` are never cached.
+//!
+//! Response bodies can be any [`http_body::Body`] yielding [`Bytes`], and
+//! reach the caller as an [`HttpCacheBody`]. The body is streamed to the
+//! caller while a copy of it is collected, and the response is stored as a
+//! [`CachedResponse`] once the caller has read the whole body. Use
+//! [`HttpCacheLayer::max_body_bytes`] to avoid collecting large bodies.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use bytes::Bytes;
+//! use http::{Request, Response};
+//! use http_body::Body;
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, ServiceExt, service_fn};
+//! use tower_cache::{
+//!     http::{CachedResponse, HttpCacheKey, HttpCacheLayer},
+//!     map::MapProvider,
+//! };
+//! async fn handler(req: Request<()>) -> Result<Response<String>, Infallible> {
+//!     Ok(Response::builder()
+//!         .header("cache-control", "max-age=60")
+//!         .body(format!("Hello from {}", req.uri().path()))
+//!         .unwrap())
+//! }
+//!
+//! // Initialize the cache provider service
+//! let provider = MapProvider::new::<HttpCacheKey, CachedResponse<Bytes>>();
+//!
+//! // Wrap the service with HttpCacheLayer.
+//! let mut my_service = ServiceBuilder::new()
+//!     .layer(HttpCacheLayer::new(provider))
+//!     .service(service_fn(handler));
+//!
+//! # tokio_test::block_on(async move {
+//! // Call the service
+//! let req = Request::get("/hello").body(()).unwrap();
+//! let res = my_service.ready().await.unwrap().call(req).await.unwrap();
+//! let body = res.into_body().data().await.unwrap().unwrap();
+//! assert_eq!(body, "Hello from /hello");
+//! # })
+//! ```
+//!
+
+use crate::{time, CacheError, CacheOutcome, ProviderRequest, ProviderResponse};
+use ::http::{
+    header::{AGE, CACHE_CONTROL, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, VARY},
+    HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode, Version,
+};
+use bytes::{Bytes, BytesMut};
+use http_body::{Body, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+    time::{Duration, SystemTime},
+};
+use tower::{Layer, Service, ServiceExt};
+
+/// Layer caching HTTP responses
+///
+/// The cache provider must handle [`ProviderRequest`]s with an
+/// [`HttpCacheKey`] as key and a [`CachedResponse`] as value.
+pub struct HttpCacheLayer<'a, P> {
+    provider: P,
+    key_headers: Arc<[HeaderName]>,
+    default_ttl: Option<Duration>,
+    revalidate_for: Option<Duration>,
+    max_body_bytes: Option<usize>,
+    x_cache: Option<HeaderName>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> HttpCacheLayer<'a, ()> {
+    /// Create a new [`HttpCacheLayer`]
+    pub fn new<P>(provider: P) -> HttpCacheLayer<'a, P> {
+        HttpCacheLayer {
+            provider,
+            key_headers: Arc::new([]),
+            default_ttl: None,
+            revalidate_for: None,
+            max_body_bytes: None,
+            x_cache: None,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, P> HttpCacheLayer<'a, P> {
+    /// Include the value of a request header in the cache key.
+    ///
+    /// Requests with different values for this header, or where only one of
+    /// them has the header, are cached separately.
+    pub fn key_header(mut self, name: HeaderName) -> Self {
+        let mut key_headers = self.key_headers.to_vec();
+        key_headers.push(name);
+        self.key_headers = key_headers.into();
+        self
+    }
+
+    /// Expiration of responses without a `max-age` directive.
+    ///
+    /// By default, these responses are sent to the cache provider without a
+    /// TTL, and the provider applies its default expiration.
+    pub fn default_ttl(mut self, ttl: Duration) -> Self {
+        self.default_ttl = Some(ttl);
+        self
+    }
+
+    /// Keep stale responses for `window` so they can be revalidated.
+    ///
+    /// Responses with an `ETag` or `Last-Modified` header are kept in the
+    /// cache provider for `window` after they become stale. Requests for a
+    /// stale response are then sent to the inner service with
+    /// `If-None-Match` or `If-Modified-Since`, and a `304 Not Modified`
+    /// response serves the stored body again. This also allows storing
+    /// responses with `Cache-Control: no-cache` or `max-age=0`, which are
+    /// revalidated before each use.
+    pub fn revalidate_for(mut self, window: Duration) -> Self {
+        self.revalidate_for = Some(window);
+        self
+    }
+
+    /// Don't store responses with a body larger than `max` bytes.
+    ///
+    /// These responses are still returned to the caller unchanged. The copy
+    /// of the body is dropped as soon as it grows past `max`, and the rest of
+    /// the body is streamed without being collected.
+    pub fn max_body_bytes(mut self, max: usize) -> Self {
+        self.max_body_bytes = Some(max);
+        self
+    }
+
+    /// Add an `X-Cache` header to every response.
+    ///
+    /// The header is set to `HIT` for responses served from the cache
+    /// provider, and to `MISS` for responses from the inner service. It is
+    /// never stored in the cache provider. Use
+    /// [`HttpCacheLayer::x_cache_header`] to use another header name.
+    pub fn x_cache(mut self, enabled: bool) -> Self {
+        self.x_cache = match enabled {
+            true => Some(HeaderName::from_static("x-cache")),
+            false => None,
+        };
+        self
+    }
+
+    /// Add a header with the given name to every response, set to `HIT` or
+    /// `MISS`.
+    ///
+    /// See [`HttpCacheLayer::x_cache`].
+    pub fn x_cache_header(mut self, name: HeaderName) -> Self {
+        self.x_cache = Some(name);
+        self
+    }
+}
+
+impl<'a, P> Clone for HttpCacheLayer<'a, P>
+where
+    P: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            provider: self.provider.clone(),
+            key_headers: self.key_headers.clone(),
+            default_ttl: self.default_ttl,
+            revalidate_for: self.revalidate_for,
+            max_body_bytes: self.max_body_bytes,
+            x_cache: self.x_cache.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, P> fmt::Debug for HttpCacheLayer<'a, P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HttpCacheLayer")
+            .field("key_headers", &self.key_headers)
+            .field("default_ttl", &self.default_ttl)
+            .field("revalidate_for", &self.revalidate_for)
+            .field("max_body_bytes", &self.max_body_bytes)
+            .field("x_cache", &self.x_cache)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, P, S> Layer<S> for HttpCacheLayer<'a, P>
+where
+    P: Clone,
+{
+    type Service = HttpCacheService<'a, S, P>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HttpCacheService {
+            inner,
+            provider: self.provider.clone(),
+            key_headers: self.key_headers.clone(),
+            default_ttl: self.default_ttl,
+            revalidate_for: self.revalidate_for,
+            max_body_bytes: self.max_body_bytes,
+            x_cache: self.x_cache.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+/// Service generated by [`HttpCacheLayer`]
+pub struct HttpCacheService<'a, S, P> {
+    inner: S,
+    provider: P,
+    key_headers: Arc<[HeaderName]>,
+    default_ttl: Option<Duration>,
+    revalidate_for: Option<Duration>,
+    max_body_bytes: Option<usize>,
+    x_cache: Option<HeaderName>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a, S, P> Clone for HttpCacheService<'a, S, P>
+where
+    S: Clone,
+    P: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            provider: self.provider.clone(),
+            key_headers: self.key_headers.clone(),
+            default_ttl: self.default_ttl,
+            revalidate_for: self.revalidate_for,
+            max_body_bytes: self.max_body_bytes,
+            x_cache: self.x_cache.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, S, P> fmt::Debug for HttpCacheService<'a, S, P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HttpCacheService")
+            .field("key_headers", &self.key_headers)
+            .field("default_ttl", &self.default_ttl)
+            .field("revalidate_for", &self.revalidate_for)
+            .field("max_body_bytes", &self.max_body_bytes)
+            .field("x_cache", &self.x_cache)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, S, P, ReqBody, ResBody> Service<Request<ReqBody>> for HttpCacheService<'a, S, P>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'a,
+    S::Error: Send + 'a,
+    S::Future: Send + 'a,
+
+    P: Service<
+            ProviderRequest<HttpCacheKey, CachedResponse>,
+            Response = ProviderResponse<HttpCacheKey, CachedResponse>,
+        > + Clone
+        + Send
+        + 'a,
+    P::Error: Send + 'a,
+    P::Future: Send + 'a,
+
+    ReqBody: Send + 'a,
+    ResBody: Body<Data = Bytes> + Send + 'a,
+{
+    type Response = Response<HttpCacheBody<'a, ResBody>>;
+    type Error = CacheError<P::Error, S::Error>;
+    type Future = HttpCacheFuture<'a, Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.provider
+            .poll_ready(cx)
+            .map_err(CacheError::ProviderError)
+    }
+
+    fn call(&mut self, mut request: Request<ReqBody>) -> Self::Future {
+        let clone = self.provider.clone();
+        let mut provider = std::mem::replace(&mut self.provider, clone);
+        let inner = self.inner.clone();
+        let default_ttl = self.default_ttl;
+        let revalidate_for = self.revalidate_for;
+        let max_body_bytes = self.max_body_bytes;
+        let x_cache = self.x_cache.clone();
+
+        let directives = CacheControl::from_headers(request.headers());
+        let cacheable = matches!(*request.method(), Method::GET | Method::HEAD);
+        // Bypass the cache entirely for these requests.
+        if !cacheable || directives.no_store {
+            return Box::pin(async move {
+                let res = inner
+                    .oneshot(request)
+                    .await
+                    .map_err(CacheError::ServiceError)?;
+                Ok(mark(
+                    res.map(HttpCacheBody::new),
+                    x_cache,
+                    CacheOutcome::Fresh,
+                ))
+            });
+        }
+        let key = HttpCacheKey::from_request(&request, &self.key_headers);
+        // Keep the request headers to match against the `Vary` header.
+        let headers = request.headers().clone();
+
+        Box::pin(async move {
+            let cached = lookup(&mut provider, &key, &headers)
+                .await
+                .map_err(CacheError::ProviderError)?;
+
+            // Stored response sent for revalidation to the inner service
+            let mut stale = None;
+            if let Some(cached) = cached {
+                let age = cached.age();
+                // `no-cache` on the request asks for a fresh or revalidated
+                // response.
+                let fresh = !directives.no_cache
+                    && cached.is_fresh(age)
+                    && directives.max_age.is_none_or(|max_age| age <= max_age);
+                if fresh {
+                    return Ok(mark(
+                        cached.into_response_at(age).map(HttpCacheBody::cached),
+                        x_cache,
+                        CacheOutcome::Hit,
+                    ));
+                }
+                // Leave conditional requests from the client untouched, as
+                // their validators might not match the stored response.
+                if !is_conditional(&headers) && cached.has_validators() {
+                    let conditions = [
+                        (IF_NONE_MATCH, cached.headers.get(ETAG)),
+                        (IF_MODIFIED_SINCE, cached.headers.get(LAST_MODIFIED)),
+                    ];
+                    for (name, value) in conditions {
+                        if let Some(value) = value {
+                            request.headers_mut().insert(name, value.clone());
+                        }
+                    }
+                    stale = Some(cached);
+                }
+            }
+
+            let res = inner
+                .oneshot(request)
+                .await
+                .map_err(CacheError::ServiceError)?;
+
+            // The stored response is still valid: serve it with the updated
+            // headers.
+            if let (Some(mut cached), StatusCode::NOT_MODIFIED) = (stale, res.status()) {
+                cached.revalidate(res.headers());
+                if let Some(lifetime) =
+                    lifetime(cached.status, &cached.headers, default_ttl, revalidate_for)
+                {
+                    cached.fresh_for = lifetime.fresh_for;
+                    store(provider, key, cached.clone(), lifetime.ttl)
+                        .await
+                        .map_err(CacheError::ProviderError)?;
+                }
+                return Ok(mark(
+                    cached
+                        .into_response_at(Duration::ZERO)
+                        .map(HttpCacheBody::cached),
+                    x_cache,
+                    CacheOutcome::Revalidated,
+                ));
+            }
+
+            let lifetime = match lifetime(res.status(), res.headers(), default_ttl, revalidate_for)
+            {
+                Some(lifetime) => lifetime,
+                None => {
+                    return Ok(mark(
+                        res.map(HttpCacheBody::new),
+                        x_cache,
+                        CacheOutcome::Fresh,
+                    ))
+                }
+            };
+            let vary = match vary(res.headers()) {
+                Some(names) => header_values(&headers, names.iter()),
+                None => {
+                    return Ok(mark(
+                        res.map(HttpCacheBody::new),
+                        x_cache,
+                        CacheOutcome::Fresh,
+                    ))
+                }
+            };
+            // Don't collect bodies that are known to be too large.
+            let max_body_bytes = max_body_bytes.map(|max| max as u64);
+            if max_body_bytes.is_some_and(|max| res.body().size_hint().lower() > max) {
+                return Ok(mark(
+                    res.map(HttpCacheBody::new),
+                    x_cache,
+                    CacheOutcome::Fresh,
+                ));
+            }
+
+            let (parts, body) = res.into_parts();
+            let mut cached = CachedResponse {
+                status: parts.status,
+                version: parts.version,
+                headers: parts.headers.clone(),
+                body: Bytes::new(),
+                vary,
+                fresh_for: lifetime.fresh_for,
+                stored_at: time::system_now(),
+            };
+            let tee = Tee {
+                buffer: BytesMut::new(),
+                max: max_body_bytes,
+                store: Box::new(move |body| {
+                    cached.body = body;
+                    Box::pin(async move {
+                        // The response was already returned, so errors can't
+                        // be reported.
+                        let _ = store(provider, key, cached, lifetime.ttl).await;
+                    })
+                }),
+            };
+            Ok(mark(
+                Response::from_parts(parts, HttpCacheBody::tee(body, tee)),
+                x_cache,
+                CacheOutcome::Fresh,
+            ))
+        })
+    }
+}
+
+/// Look up the stored response matching a request
+async fn lookup<P, B>(
+    provider: &mut P,
+    key: &HttpCacheKey,
+    headers: &HeaderMap,
+) -> Result<Option<CachedResponse<B>>, P::Error>
+where
+    P: Service<
+            ProviderRequest<HttpCacheKey, CachedResponse<B>>,
+            Response = ProviderResponse<HttpCacheKey, CachedResponse<B>>,
+        > + Clone,
+{
+    match provider.call(ProviderRequest::Get(key.clone())).await? {
+        ProviderResponse::Found(cached) if cached.matches(headers) => Ok(Some(cached)),
+        // The response stored under the primary key was for other values of
+        // the varied headers: look for a variant matching this request.
+        ProviderResponse::Found(cached) => {
+            let names = cached.vary.iter().map(|(name, _)| name);
+            let variant = key.clone().with_vary(header_values(headers, names));
+            match provider
+                .clone()
+                .oneshot(ProviderRequest::Get(variant))
+                .await?
+            {
+                ProviderResponse::Found(cached) if cached.matches(headers) => Ok(Some(cached)),
+                _ => Ok(None),
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Store a response in the cache provider
+///
+/// Varying responses are stored under their variant key, and under the
+/// primary key so that later requests learn which headers the response
+/// varies on.
+async fn store<P, B>(
+    provider: P,
+    key: HttpCacheKey,
+    cached: CachedResponse<B>,
+    ttl: Option<Duration>,
+) -> Result<(), P::Error>
+where
+    P: Service<
+            ProviderRequest<HttpCacheKey, CachedResponse<B>>,
+            Response = ProviderResponse<HttpCacheKey, CachedResponse<B>>,
+        > + Clone,
+    B: Clone,
+{
+    if !cached.vary.is_empty() {
+        let variant = key.clone().with_vary(cached.vary.clone());
+        provider
+            .clone()
+            .oneshot(ProviderRequest::Insert(variant, cached.clone(), ttl))
+            .await?;
+    }
+    provider
+        .oneshot(ProviderRequest::Insert(key, cached, ttl))
+        .await?;
+    Ok(())
+}
+
+/// Return `true` if the client sent a conditional request
+fn is_conditional(headers: &HeaderMap) -> bool {
+    headers.contains_key(IF_NONE_MATCH) || headers.contains_key(IF_MODIFIED_SINCE)
+}
+
+/// Add the [`CacheOutcome`] extension to a response, and set its `X-Cache`
+/// header if enabled
+fn mark<B>(
+    mut res: Response<B>,
+    x_cache: Option<HeaderName>,
+    outcome: CacheOutcome,
+) -> Response<B> {
+    res.extensions_mut().insert(outcome);
+    if let Some(name) = x_cache {
+        let value = match outcome.is_hit() {
+            true => HeaderValue::from_static("HIT"),
+            false => HeaderValue::from_static("MISS"),
+        };
+        res.headers_mut().insert(name, value);
+    }
+    res
+}
+
+/// How long a response is fresh, and how long it is kept in the provider
+#[derive(Clone, Copy, Debug)]
+struct Lifetime {
+    fresh_for: Option<Duration>,
+    ttl: Option<Duration>,
+}
+
+/// Return the lifetime of a response, or `None` if it shouldn't be stored
+fn lifetime(
+    status: StatusCode,
+    headers: &HeaderMap,
+    default_ttl: Option<Duration>,
+    revalidate_for: Option<Duration>,
+) -> Option<Lifetime> {
+    if !is_cacheable_status(status) {
+        return None;
+    }
+    let directives = CacheControl::from_headers(headers);
+    if directives.no_store || directives.private {
+        return None;
+    }
+    // `no-cache` allows storing the response, as long as it is revalidated
+    // before each use.
+    let fresh_for = match directives.no_cache {
+        true => Some(Duration::ZERO),
+        false => directives.s_maxage.or(directives.max_age).or(default_ttl),
+    };
+    let revalidate_for = revalidate_for.filter(|_| has_validators(headers));
+    match (fresh_for, revalidate_for) {
+        (Some(fresh_for), Some(window)) => Some(Lifetime {
+            fresh_for: Some(fresh_for),
+            ttl: Some(fresh_for.saturating_add(window)),
+        }),
+        // Responses that are immediately stale are useless without
+        // revalidation.
+        (Some(fresh_for), None) if fresh_for.is_zero() => None,
+        (fresh_for, _) => Some(Lifetime {
+            fresh_for,
+            ttl: fresh_for,
+        }),
+    }
+}
+
+/// Return `true` if a response can be revalidated with a conditional request
+fn has_validators(headers: &HeaderMap) -> bool {
+    headers.contains_key(ETAG) || headers.contains_key(LAST_MODIFIED)
+}
+
+/// Return the request headers listed in the `Vary` header of a response
+///
+/// Returns `None` for `Vary: *`, as no request can match the response.
+fn vary(headers: &HeaderMap) -> Option<Vec<HeaderName>> {
+    let mut names = Vec::new();
+    let values = headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok());
+    for name in values.flat_map(|value| value.split(',')).map(str::trim) {
+        if name == "*" {
+            return None;
+        }
+        if let Ok(name) = HeaderName::from_bytes(name.as_bytes()) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    Some(names)
+}
+
+/// Return the values of the given request headers
+fn header_values<'h>(
+    headers: &HeaderMap,
+    names: impl Iterator<Item = &'h HeaderName>,
+) -> Vec<(HeaderName, Option<HeaderValue>)> {
+    names
+        .map(|name| (name.clone(), headers.get(name).cloned()))
+        .collect()
+}
+
+/// Status codes that are cacheable by default
+///
+/// See [RFC 9110, section 15.1](https://www.rfc-editor.org/rfc/rfc9110#section-15.1).
+/// `206 Partial Content` is excluded, as range requests are not supported.
+fn is_cacheable_status(status: StatusCode) -> bool {
+    matches!(
+        status.as_u16(),
+        200 | 203 | 204 | 300 | 301 | 308 | 404 | 405 | 410 | 414 | 501
+    )
+}
+
+/// Directives of the `Cache-Control` header relevant to this cache
+#[derive(Clone, Copy, Debug, Default)]
+struct CacheControl {
+    no_store: bool,
+    no_cache: bool,
+    private: bool,
+    max_age: Option<Duration>,
+    s_maxage: Option<Duration>,
+}
+
+impl CacheControl {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let mut directives = Self::default();
+        let values = headers
+            .get_all(CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok());
+        for directive in values.flat_map(|value| value.split(',')) {
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (directive.trim(), None),
+            };
+            let seconds = || value.and_then(delta_seconds);
+            match name.to_ascii_lowercase().as_str() {
+                "no-store" => directives.no_store = true,
+                "no-cache" => directives.no_cache = true,
+                "private" => directives.private = true,
+                "max-age" => directives.max_age = seconds(),
+                "s-maxage" => directives.s_maxage = seconds(),
+                _ => {}
+            }
+        }
+        directives
+    }
+}
+
+/// Largest delta-seconds value, see
+/// [RFC 9111, section 1.2.2](https://www.rfc-editor.org/rfc/rfc9111#section-1.2.2)
+const MAX_DELTA_SECONDS: u64 = 1 << 31;
+
+/// Parse a delta-seconds value, clamping larger values to 2^31 seconds
+fn delta_seconds(value: &str) -> Option<Duration> {
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    // Only digits: the parse can only fail on overflow.
+    let secs = value.parse().unwrap_or(MAX_DELTA_SECONDS);
+    Some(Duration::from_secs(secs.min(MAX_DELTA_SECONDS)))
+}
+
+/// Cache key for HTTP requests
+///
+/// This is made of the method, the URI and the values of the request headers
+/// selected with [`HttpCacheLayer::key_header`]. Keys for responses with a
+/// `Vary` header also contain the values of the varied request headers. It
+/// implements [`Hash`] and [`Eq`] for in-memory providers, and
+/// [`Display`](fmt::Display) for distributed providers.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct HttpCacheKey {
+    method: Method,
+    uri: String,
+    headers: Vec<(HeaderName, Option<HeaderValue>)>,
+    vary: Vec<(HeaderName, Option<HeaderValue>)>,
+}
+
+impl HttpCacheKey {
+    fn from_request<B>(request: &Request<B>, key_headers: &[HeaderName]) -> Self {
+        Self {
+            method: request.method().clone(),
+            uri: request.uri().to_string(),
+            headers: header_values(request.headers(), key_headers.iter()),
+            vary: Vec::new(),
+        }
+    }
+
+    /// Return the key of the variant for the given varied header values
+    fn with_vary(mut self, vary: Vec<(HeaderName, Option<HeaderValue>)>) -> Self {
+        self.vary = vary;
+        self
+    }
+
+    /// Return the method of the request
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// Return the URI of the request
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+}
+
+impl fmt::Display for HttpCacheKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.method, self.uri)?;
+        write_headers(f, &self.headers)?;
+        if !self.vary.is_empty() {
+            write!(f, " vary")?;
+            write_headers(f, &self.vary)?;
+        }
+        Ok(())
+    }
+}
+
+/// Write header names and values for [`HttpCacheKey`]'s `Display`
+fn write_headers(
+    f: &mut fmt::Formatter,
+    headers: &[(HeaderName, Option<HeaderValue>)],
+) -> fmt::Result {
+    for (name, value) in headers {
+        match value {
+            Some(value) => write!(f, " {}={}", name, String::from_utf8_lossy(value.as_bytes()))?,
+            None => write!(f, " {}", name)?,
+        }
+    }
+    Ok(())
+}
+
+/// HTTP response stored in the cache provider
+#[derive(Clone, Debug)]
+pub struct CachedResponse<B = Bytes> {
+    status: StatusCode,
+    version: Version,
+    headers: HeaderMap,
+    body: B,
+    vary: Vec<(HeaderName, Option<HeaderValue>)>,
+    fresh_for: Option<Duration>,
+    stored_at: SystemTime,
+}
+
+impl<B> CachedResponse<B> {
+    /// Return the status code of the response
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Return the headers of the response
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Return the body of the response
+    pub fn body(&self) -> &B {
+        &self.body
+    }
+
+    /// Return the request headers listed in the `Vary` header of the
+    /// response, with the values they had in the request the response was
+    /// stored for
+    pub fn vary(&self) -> &[(HeaderName, Option<HeaderValue>)] {
+        &self.vary
+    }
+
+    /// Return `true` if the response can be served for a request with these
+    /// headers
+    fn matches(&self, headers: &HeaderMap) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| headers.get(name) == value.as_ref())
+    }
+
+    /// Return how long ago the response was stored or last revalidated
+    pub fn age(&self) -> Duration {
+        time::system_now()
+            .duration_since(self.stored_at)
+            .unwrap_or_default()
+    }
+
+    /// Return the `ETag` header of the response
+    pub fn etag(&self) -> Option<&HeaderValue> {
+        self.headers.get(ETAG)
+    }
+
+    /// Return the `Last-Modified` header of the response
+    pub fn last_modified(&self) -> Option<&HeaderValue> {
+        self.headers.get(LAST_MODIFIED)
+    }
+
+    fn has_validators(&self) -> bool {
+        has_validators(&self.headers)
+    }
+
+    /// Return `true` if the response can be served without revalidation
+    fn is_fresh(&self, age: Duration) -> bool {
+        self.fresh_for.is_none_or(|fresh_for| age < fresh_for)
+    }
+
+    /// Update the response with the headers of a `304 Not Modified` response
+    fn revalidate(&mut self, headers: &HeaderMap) {
+        for name in headers.keys() {
+            self.headers.remove(name);
+            for value in headers.get_all(name) {
+                self.headers.append(name.clone(), value.clone());
+            }
+        }
+        self.stored_at = time::system_now();
+    }
+
+    /// Turn the cached response into an [`http::Response`] with an `Age`
+    /// header
+    fn into_response_at(self, age: Duration) -> Response<B> {
+        let mut res = self.into_response();
+        res.headers_mut()
+            .insert(AGE, HeaderValue::from(age.as_secs()));
+        res
+    }
+
+    /// Turn the cached response back into an [`http::Response`]
+    pub fn into_response(self) -> Response<B> {
+        let mut res = Response::new(self.body);
+        *res.status_mut() = self.status;
+        *res.version_mut() = self.version;
+        *res.headers_mut() = self.headers;
+        res
+    }
+}
+
+pin_project! {
+    /// Body of the responses returned by [`HttpCacheService`]
+    ///
+    /// Responses served from the cache provider have their stored body.
+    /// Responses from the inner service have its body, which is also
+    /// collected if the response can be stored. The response is stored when
+    /// the body ends, before the end of the stream is returned to the caller.
+    pub struct HttpCacheBody<'a, B> {
+        #[pin]
+        inner: Option<B>,
+        cached: Option<Bytes>,
+        tee: Option<Tee<'a>>,
+        storing: Option<StoreFuture<'a>>,
+    }
+}
+
+impl<'a, B> HttpCacheBody<'a, B> {
+    /// Body of the inner service, passed through as is
+    fn new(inner: B) -> Self {
+        Self {
+            inner: Some(inner),
+            cached: None,
+            tee: None,
+            storing: None,
+        }
+    }
+
+    /// Body of the inner service, collected by `tee`
+    fn tee(inner: B, tee: Tee<'a>) -> Self {
+        Self {
+            tee: Some(tee),
+            ..Self::new(inner)
+        }
+    }
+
+    /// Body stored in the cache provider
+    fn cached(body: Bytes) -> Self {
+        Self {
+            inner: None,
+            cached: Some(body),
+            tee: None,
+            storing: None,
+        }
+    }
+}
+
+impl<'a, B> Body for HttpCacheBody<'a, B>
+where
+    B: Body<Data = Bytes>,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.project();
+        if let Some(body) = this.cached.take().filter(|body| !body.is_empty()) {
+            return Poll::Ready(Some(Ok(body)));
+        }
+        if let Some(storing) = this.storing {
+            ready!(storing.as_mut().poll(cx));
+            *this.storing = None;
+            return Poll::Ready(None);
+        }
+        let inner = match this.inner.as_pin_mut() {
+            Some(inner) => inner,
+            None => return Poll::Ready(None),
+        };
+        match ready!(inner.poll_data(cx)) {
+            Some(Ok(data)) => {
+                if let Some(tee) = this.tee {
+                    match tee.max {
+                        // Stop collecting the body past the limit.
+                        Some(max) if (tee.buffer.len() + data.len()) as u64 > max => {
+                            *this.tee = None
+                        }
+                        _ => tee.buffer.extend_from_slice(&data),
+                    }
+                }
+                Poll::Ready(Some(Ok(data)))
+            }
+            Some(Err(err)) => {
+                *this.tee = None;
+                Poll::Ready(Some(Err(err)))
+            }
+            None => {
+                if let Some(tee) = this.tee.take() {
+                    let mut storing = (tee.store)(tee.buffer.freeze());
+                    if storing.as_mut().poll(cx).is_pending() {
+                        *this.storing = Some(storing);
+                        return Poll::Pending;
+                    }
+                }
+                Poll::Ready(None)
+            }
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.project().inner.as_pin_mut() {
+            Some(inner) => inner.poll_trailers(cx),
+            None => Poll::Ready(Ok(None)),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        // The end of the stream has to be polled to store the response.
+        let collecting = self.tee.is_some() || self.storing.is_some();
+        match &self.inner {
+            Some(inner) => !collecting && inner.is_end_stream(),
+            None => self.cached.as_ref().is_none_or(Bytes::is_empty),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match (&self.inner, &self.cached) {
+            (Some(inner), _) => inner.size_hint(),
+            (None, Some(body)) => SizeHint::with_exact(body.len() as u64),
+            (None, None) => SizeHint::with_exact(0),
+        }
+    }
+}
+
+impl<'a, B> fmt::Debug for HttpCacheBody<'a, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HttpCacheBody")
+            .field("cached", &self.cached.is_some())
+            .field("collecting", &self.tee.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Copy of a body being collected to store its response
+struct Tee<'a> {
+    buffer: BytesMut,
+    max: Option<u64>,
+    store: Box<dyn FnOnce(Bytes) -> StoreFuture<'a> + Send + 'a>,
+}
+
+type StoreFuture<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+type HttpCacheFuture<'a, T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + Send + 'a>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::MapProvider;
+    use ::http::header::ACCEPT_ENCODING;
+    use std::{
+        collections::VecDeque,
+        convert::Infallible,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+    use tower::{service_fn, util::BoxCloneService};
+
+    fn provider() -> MapProvider<'static, HttpCacheKey, CachedResponse> {
+        MapProvider::new()
+    }
+
+    /// Service counting its calls and answering with the given Cache-Control
+    fn origin(
+        cache_control: &'static str,
+        calls: Arc<AtomicUsize>,
+    ) -> BoxCloneService<Request<()>, Response<String>, Infallible> {
+        BoxCloneService::new(service_fn(move |req: Request<()>| {
+            let count = calls.fetch_add(1, Ordering::SeqCst) + 1;
+            async move {
+                let mut res = Response::builder();
+                if !cache_control.is_empty() {
+                    res = res.header(CACHE_CONTROL, cache_control);
+                }
+                let status = match req.uri().path() {
+                    "/missing" => StatusCode::NOT_FOUND,
+                    "/error" => StatusCode::INTERNAL_SERVER_ERROR,
+                    _ => StatusCode::OK,
+                };
+                Ok(res
+                    .status(status)
+                    .body(format!("{} {}", req.uri().path(), count))
+                    .unwrap())
+            }
+        }))
+    }
+
+    /// Call the service and read the whole body of the response
+    async fn call<S, B>(service: &mut S, req: Request<()>) -> Response<String>
+    where
+        S: Service<Request<()>, Response = Response<B>>,
+        S::Error: fmt::Debug,
+        B: Body<Data = Bytes> + Unpin,
+        B::Error: fmt::Debug,
+    {
+        let res = service.ready().await.unwrap().call(req).await.unwrap();
+        let (parts, mut body) = res.into_parts();
+        let mut data = Vec::new();
+        while let Some(chunk) = body.data().await {
+            data.extend_from_slice(&chunk.unwrap());
+        }
+        Response::from_parts(parts, String::from_utf8(data).unwrap())
+    }
+
+    fn get(uri: &str) -> Request<()> {
+        Request::get(uri).body(()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_hit() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut service =
+            HttpCacheLayer::new(provider()).layer(origin("max-age=60", calls.clone()));
+
+        let res = call(&mut service, get("/a")).await;
+        assert_eq!(res.body(), "/a 1");
+        assert!(res.headers().get(AGE).is_none());
+        assert_eq!(res.extensions().get(), Some(&CacheOutcome::Fresh));
+
+        let res = call(&mut service, get("/a")).await;
+        assert_eq!(res.extensions().get(), Some(&CacheOutcome::Hit));
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.body(), "/a 1");
+        assert_eq!(res.headers()[CACHE_CONTROL], "max-age=60");
+        assert_eq!(res.headers()[AGE], "0");
+
+        let res = call(&mut service, get("/missing")).await;
+        assert_eq!(res.body(), "/missing 2");
+        let res = call(&mut service, get("/missing")).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(res.body(), "/missing 2");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_x_cache() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let cache = provider();
+        let mut service = HttpCacheLayer::new(cache.clone())
+            .x_cache(true)
+            .layer(origin("max-age=60", calls.clone()));
+
+        let res = call(&mut service, get("/a")).await;
+        assert_eq!(res.headers()["x-cache"], "MISS");
+        let res = call(&mut service, get("/a")).await;
+        assert_eq!(res.headers()["x-cache"], "HIT");
+        let res = call(&mut service, Request::post("/a").body(()).unwrap()).await;
+        assert_eq!(res.headers()["x-cache"], "MISS");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // The header isn't stored in the cache provider
+        let key = HttpCacheKey::from_request(&get("/a"), &[]);
+        match cache.oneshot(ProviderRequest::Get(key)).await.unwrap() {
+            ProviderResponse::Found(cached) => assert!(!cached.headers().contains_key("x-cache")),
+            _ => panic!("expected a cached response"),
+        }
+
+        // Custom header name
+        let mut service = HttpCacheLayer::new(provider())
+            .x_cache_header(HeaderName::from_static("x-my-cache"))
+            .layer(origin("max-age=60", calls.clone()));
+        let res = call(&mut service, get("/a")).await;
+        assert_eq!(res.headers()["x-my-cache"], "MISS");
+        assert!(!res.headers().contains_key("x-cache"));
+
+        // Disabled by default
+        let mut service = HttpCacheLayer::new(provider()).layer(origin("", calls.clone()));
+        let res = call(&mut service, get("/a")).await;
+        assert!(!res.headers().contains_key("x-cache"));
+    }
+
+    /// Service answering with an `ETag` and `Last-Modified` for the current
+    /// version, and `304 Not Modified` when the request validators match
+    fn versioned_origin(
+        version: Arc<AtomicUsize>,
+        calls: Arc<AtomicUsize>,
+    ) -> BoxCloneService<Request<()>, Response<String>, Infallible> {
+        BoxCloneService::new(service_fn(move |req: Request<()>| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            let version = version.load(Ordering::SeqCst);
+            let etag = format!("\"{}\"", version);
+            let last_modified = format!("Mon, 0{} Jan 2024 00:00:00 GMT", version);
+            let not_modified = req
+                .headers()
+                .get(IF_NONE_MATCH)
+                .is_some_and(|v| *v == *etag)
+                || req
+                    .headers()
+                    .get(IF_MODIFIED_SINCE)
+                    .is_some_and(|v| *v == *last_modified);
+            async move {
+                let res = Response::builder()
+                    .header(CACHE_CONTROL, "max-age=0")
+                    .header(ETAG, etag)
+                    .header(LAST_MODIFIED, last_modified);
+                Ok(match not_modified {
+                    true => res.status(StatusCode::NOT_MODIFIED).body(String::new()),
+                    false => res.body(format!("v{}", version)),
+                }
+                .unwrap())
+            }
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_revalidate() {
+        let version = Arc::new(AtomicUsize::new(1));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut service = HttpCacheLayer::new(provider())
+            .revalidate_for(Duration::from_secs(60))
+            .x_cache(true)
+            .layer(versioned_origin(version.clone(), calls.clone()));
+
+        let res = call(&mut service, get("/a")).await;
+        assert_eq!(res.body(), "v1");
+        assert_eq!(res.headers()["x-cache"], "MISS");
+        assert_eq!(res.extensions().get(), Some(&CacheOutcome::Fresh));
+
+        // 304: the stored body is reused
+        let res = call(&mut service, get("/a")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.body(), "v1");
+        assert_eq!(res.headers()["x-cache"], "HIT");
+        assert_eq!(res.extensions().get(), Some(&CacheOutcome::Revalidated));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // 200: the body is replaced
+        version.store(2, Ordering::SeqCst);
+        let res = call(&mut service, get("/a")).await;
+        assert_eq!(res.body(), "v2");
+        assert_eq!(res.headers()["x-cache"], "MISS");
+        let res = call(&mut service, get("/a")).await;
+        assert_eq!(res.body(), "v2");
+        assert_eq!(res.headers()[ETAG], "\"2\"");
+        assert_eq!(res.headers()["x-cache"], "HIT");
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_revalidate_last_modified() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let origin = service_fn(|req: Request<()>| {
+            let modified = !req.headers().contains_key(IF_MODIFIED_SINCE);
+            calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                let res = Response::builder()
+                    .header(CACHE_CONTROL, "no-cache")
+                    .header(LAST_MODIFIED, "Mon, 01 Jan 2024 00:00:00 GMT");
+                Ok::<_, Infallible>(
+                    match modified {
+                        true => res.body("body".to_string()),
+                        false => res.status(StatusCode::NOT_MODIFIED).body(String::new()),
+                    }
+                    .unwrap(),
+                )
+            }
+        });
+        let mut service = HttpCacheLayer::new(provider())
+            .revalidate_for(Duration::from_secs(60))
+            .layer(origin);
+
+        for _ in 0..3 {
+            let res = call(&mut service, get("/a")).await;
+            assert_eq!(res.body(), "body");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_no_revalidation() {
+        // Immediately stale responses aren't stored without a window
+        let version = Arc::new(AtomicUsize::new(1));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let cache = provider();
+        let mut service = HttpCacheLayer::new(cache.clone())
+            .layer(versioned_origin(version.clone(), calls.clone()));
+
+        for _ in 0..2 {
+            let res = call(&mut service, get("/a")).await;
+            assert_eq!(res.body(), "v1");
+        }
+        let key = HttpCacheKey::from_request(&get("/a"), &[]);
+        let res = cache.oneshot(ProviderRequest::Get(key)).await.unwrap();
+        assert!(matches!(res, ProviderResponse::NotFound));
+    }
+
+    #[tokio::test]
+    async fn test_max_body_bytes() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let origin = service_fn(|req: Request<()>| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            let size = req.uri().path().len();
+            async move {
+                Ok::<_, Infallible>(
+                    Response::builder()
+                        .header(CACHE_CONTROL, "max-age=60")
+                        .body("x".repeat(size))
+                        .unwrap(),
+                )
+            }
+        });
+        let mut service = HttpCacheLayer::new(provider())
+            .max_body_bytes(4)
+            .layer(origin);
+
+        // Large responses are delivered but not stored
+        for _ in 0..2 {
+            let res = call(&mut service, get("/large")).await;
+            assert_eq!(res.body(), "xxxxxx");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Responses at the limit are stored
+        for _ in 0..2 {
+            let res = call(&mut service, get("/abc")).await;
+            assert_eq!(res.body(), "xxxx");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    /// Body yielding its chunks one by one, without a known size
+    struct Chunks(VecDeque<Bytes>);
+
+    impl Body for Chunks {
+        type Data = Bytes;
+        type Error = Infallible;
+
+        fn poll_data(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+            Poll::Ready(self.0.pop_front().map(Ok))
+        }
+
+        fn poll_trailers(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+            Poll::Ready(Ok(None))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_body_bytes_streamed() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let origin = service_fn(|req: Request<()>| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            let chunks = match req.uri().path() {
+                "/large" => ["ab", "cd", "ef"].as_slice(),
+                _ => ["ab", "cd"].as_slice(),
+            };
+            let body = Chunks(chunks.iter().copied().map(Bytes::from).collect());
+            async move {
+                Ok::<_, Infallible>(
+                    Response::builder()
+                        .header(CACHE_CONTROL, "max-age=60")
+                        .body(body)
+                        .unwrap(),
+                )
+            }
+        });
+        let cache = provider();
+        let mut service = HttpCacheLayer::new(cache.clone())
+            .max_body_bytes(4)
+            .layer(origin);
+
+        // The body goes past the limit while it is read: it is delivered
+        // intact but not stored.
+        for _ in 0..2 {
+            let res = call(&mut service, get("/large")).await;
+            assert_eq!(res.body(), "abcdef");
+            assert_eq!(res.extensions().get(), Some(&CacheOutcome::Fresh));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Bodies that aren't read to the end aren't stored.
+        let res = service.ready().await.unwrap().call(get("/small")).await;
+        let mut body = res.unwrap().into_body();
+        assert_eq!(body.data().await.unwrap().unwrap(), "ab");
+        drop(body);
+        let key = HttpCacheKey::from_request(&get("/small"), &[]);
+        let res = cache.clone().oneshot(ProviderRequest::Get(key)).await;
+        assert!(matches!(res, Ok(ProviderResponse::NotFound)));
+
+        // Bodies within the limit are stored once read.
+        for _ in 0..2 {
+            let res = call(&mut service, get("/small")).await;
+            assert_eq!(res.body(), "abcd");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_uncacheable() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut service =
+            HttpCacheLayer::new(provider()).layer(origin("max-age=60", calls.clone()));
+
+        for _ in 0..2 {
+            call(&mut service, get("/error")).await;
+            let req = Request::post("/a").body(()).unwrap();
+            call(&mut service, req).await;
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_no_store() {
+        // On the response
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut service = HttpCacheLayer::new(provider()).layer(origin("no-store", calls.clone()));
+        call(&mut service, get("/a")).await;
+        call(&mut service, get("/a")).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // On the request
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut service =
+            HttpCacheLayer::new(provider()).layer(origin("max-age=60", calls.clone()));
+        call(&mut service, get("/a")).await;
+        let req = Request::get("/a")
+            .header(CACHE_CONTROL, "no-store")
+            .body(())
+            .unwrap();
+        let res = call(&mut service, req).await;
+        assert_eq!(res.body(), "/a 2");
+        // The cached response wasn't replaced
+        let res = call(&mut service, get("/a")).await;
+        assert_eq!(res.body(), "/a 1");
+    }
+
+    #[tokio::test]
+    async fn test_no_cache_request() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut service =
+            HttpCacheLayer::new(provider()).layer(origin("max-age=60", calls.clone()));
+
+        call(&mut service, get("/a")).await;
+        let req = Request::get("/a")
+            .header(CACHE_CONTROL, "no-cache")
+            .body(())
+            .unwrap();
+        let res = call(&mut service, req).await;
+        assert_eq!(res.body(), "/a 2");
+        // The fresh response replaced the cached one
+        let res = call(&mut service, get("/a")).await;
+        assert_eq!(res.body(), "/a 2");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_max_age_expiry() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut service =
+            HttpCacheLayer::new(provider()).layer(origin("public, max-age=1", calls.clone()));
+
+        call(&mut service, get("/a")).await;
+        let res = call(&mut service, get("/a")).await;
+        assert_eq!(res.body(), "/a 1");
+
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        let res = call(&mut service, get("/a")).await;
+        assert_eq!(res.body(), "/a 2");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_max_age_request() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut service =
+            HttpCacheLayer::new(provider()).layer(origin("max-age=60", calls.clone()));
+
+        call(&mut service, get("/a")).await;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let req = Request::get("/a")
+            .header(CACHE_CONTROL, "max-age=0")
+            .body(())
+            .unwrap();
+        let res = call(&mut service, req).await;
+        assert_eq!(res.body(), "/a 2");
+    }
+
+    #[tokio::test]
+    async fn test_key_header() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut service = HttpCacheLayer::new(provider())
+            .key_header(HeaderName::from_static("x-tenant"))
+            .layer(origin("max-age=60", calls.clone()));
+
+        for tenant in ["a", "b", "a"] {
+            let req = Request::get("/a")
+                .header("x-tenant", tenant)
+                .body(())
+                .unwrap();
+            call(&mut service, req).await;
+        }
+        call(&mut service, get("/a")).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_key_display() {
+        let req = Request::get("/a?b=c")
+            .header("x-tenant", "t")
+            .body(())
+            .unwrap();
+        let key = HttpCacheKey::from_request(
+            &req,
+            &[
+                HeaderName::from_static("x-tenant"),
+                HeaderName::from_static("x-other"),
+            ],
+        );
+        assert_eq!(key.to_string(), "GET /a?b=c x-tenant=t x-other");
+
+        let values = header_values(req.headers(), [HeaderName::from_static("x-tenant")].iter());
+        let key = key.with_vary(values);
+        assert_eq!(
+            key.to_string(),
+            "GET /a?b=c x-tenant=t x-other vary x-tenant=t"
+        );
+    }
+
+    /// Service answering with the given `Vary` header and echoing the
+    /// `Accept-Encoding` of the request
+    fn varying_origin(
+        vary: &'static str,
+        calls: Arc<AtomicUsize>,
+    ) -> BoxCloneService<Request<()>, Response<String>, Infallible> {
+        BoxCloneService::new(service_fn(move |req: Request<()>| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            let encoding = req
+                .headers()
+                .get(ACCEPT_ENCODING)
+                .map(|value| value.to_str().unwrap().to_string());
+            async move {
+                Ok(Response::builder()
+                    .header(CACHE_CONTROL, "max-age=60")
+                    .header(VARY, vary)
+                    .body(encoding.unwrap_or_default())
+                    .unwrap())
+            }
+        }))
+    }
+
+    fn get_encoded(uri: &str, encoding: &str) -> Request<()> {
+        Request::get(uri)
+            .header(ACCEPT_ENCODING, encoding)
+            .body(())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_vary() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut service =
+            HttpCacheLayer::new(provider()).layer(varying_origin("Accept-Encoding", calls.clone()));
+
+        let res = call(&mut service, get_encoded("/a", "gzip")).await;
+        assert_eq!(res.body(), "gzip");
+        let res = call(&mut service, get_encoded("/a", "br")).await;
+        assert_eq!(res.body(), "br");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Both variants are served from the cache
+        for encoding in ["gzip", "br", "gzip"] {
+            let res = call(&mut service, get_encoded("/a", encoding)).await;
+            assert_eq!(res.body(), encoding);
+            assert!(res.headers().contains_key(AGE));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // A request without the header is another variant
+        let res = call(&mut service, get("/a")).await;
+        assert_eq!(res.body(), "");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_vary_star() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut service = HttpCacheLayer::new(provider())
+            .layer(varying_origin("accept-encoding, *", calls.clone()));
+
+        call(&mut service, get_encoded("/a", "gzip")).await;
+        call(&mut service, get_encoded("/a", "gzip")).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_parse_vary() {
+        let mut headers = HeaderMap::new();
+        headers.append(VARY, HeaderValue::from_static("Accept-Encoding, accept"));
+        headers.append(VARY, HeaderValue::from_static("accept-encoding"));
+        assert_eq!(
+            vary(&headers),
+            Some(vec![ACCEPT_ENCODING, ::http::header::ACCEPT])
+        );
+
+        headers.append(VARY, HeaderValue::from_static("*"));
+        assert_eq!(vary(&headers), None);
+    }
+
+    #[test]
+    fn test_cache_control() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            CACHE_CONTROL,
+            HeaderValue::from_static("Max-Age=\"30\", private"),
+        );
+        headers.append(CACHE_CONTROL, HeaderValue::from_static("s-maxage=10"));
+        let directives = CacheControl::from_headers(&headers);
+        assert_eq!(directives.max_age, Some(Duration::from_secs(30)));
+        assert_eq!(directives.s_maxage, Some(Duration::from_secs(10)));
+        assert!(directives.private);
+        assert!(!directives.no_store);
+
+        let max = Some(Duration::from_secs(MAX_DELTA_SECONDS));
+        for value in [
+            "18446744073709551615",
+            "99999999999999999999999",
+            "2147483649",
+        ] {
+            let mut headers = HeaderMap::new();
+            let header = format!("max-age={}", value);
+            headers.insert(CACHE_CONTROL, HeaderValue::from_str(&header).unwrap());
+            assert_eq!(CacheControl::from_headers(&headers).max_age, max);
+        }
+        let mut headers = HeaderMap::new();
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("max-age=-1"));
+        assert_eq!(CacheControl::from_headers(&headers).max_age, None);
+    }
+
+    #[test]
+    fn test_lifetime_saturates() {
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_static("\"1\""));
+        let lifetime = lifetime(
+            StatusCode::OK,
+            &headers,
+            Some(Duration::MAX),
+            Some(Duration::from_secs(60)),
+        )
+        .unwrap();
+        assert_eq!(lifetime.fresh_for, Some(Duration::MAX));
+        assert_eq!(lifetime.ttl, Some(Duration::MAX));
+    }
+
+    #[tokio::test]
+    async fn test_huge_max_age() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut service = HttpCacheLayer::new(provider())
+            .layer(origin("max-age=18446744073709551615", calls.clone()));
+
+        for _ in 0..2 {
+            let res = call(&mut service, get("/a")).await;
+            assert_eq!(res.body(), "/a 1");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
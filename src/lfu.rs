@@ -0,0 +1,501 @@
+//! # LFU cache provider
+//!
+//! This is an implementation of a cache provider for [`crate::CacheLayer`]
+//! that evicts the least-frequently-used entry when it is full. Unlike an LRU
+//! cache, a key that is requested often survives a burst of requests for
+//! other keys.
+//!
+//! Each entry keeps a count of how many times it was inserted or found.
+//! Counts don't decay over time, and ties are broken by evicting the entry
+//! that was used least recently.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, service_fn};
+//! use tower_cache::{
+//!     CacheLayer,
+//!     lfu::LfuProvider,
+//! };
+//! async fn handler(req: String) -> Result<String, Infallible> {
+//!     Ok(req.to_uppercase())
+//! }
+//!
+//! // Initialize the cache provider service
+//! let lfu_provider = LfuProvider::new::<String, String>(20);
+//!
+//! // Wrap the service with CacheLayer.
+//! let mut my_service = ServiceBuilder::new()
+//!     .layer(CacheLayer::new(lfu_provider))
+//!     .service(service_fn(handler));
+//!
+//! # tokio_test::block_on(async move {
+//! // Call the service
+//! let res = my_service.call("Hello".to_string()).await.unwrap();
+//! assert_eq!(res, "HELLO".to_string());
+//! # })
+//! ```
+//!
+
+use crate::{
+    entry::Entry, time, trace, Configure, ProviderConfig, ProviderRequest, ProviderResponse,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
+    future::{ready, Future},
+    hash::Hash,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower::Service;
+
+/// Local LFU cache provider
+#[derive(Debug)]
+pub struct LfuProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    inner: Arc<Mutex<Lfu<K, V>>>,
+    ttl: Option<Duration>,
+    stale_window: Option<Duration>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> LfuProvider<'a, (), ()> {
+    /// Create a new LFU cache provider with the desired capacity
+    ///
+    /// A capacity of `0` is clamped to `1`.
+    pub fn new<K, V>(capacity: usize) -> LfuProvider<'a, K, V>
+    where
+        K: Eq + Hash,
+    {
+        LfuProvider {
+            inner: Arc::new(Mutex::new(Lfu::new(capacity.max(1)))),
+            ttl: None,
+            stale_window: None,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Create a new LFU cache provider where entries expire after `ttl`
+    ///
+    /// As with [`LfuProvider::new`], a capacity of `0` is clamped to `1`.
+    pub fn with_ttl<K, V>(capacity: usize, ttl: Duration) -> LfuProvider<'a, K, V>
+    where
+        K: Eq + Hash,
+    {
+        LfuProvider {
+            ttl: Some(ttl),
+            ..Self::new(capacity)
+        }
+    }
+}
+
+impl<'a, K, V> LfuProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    /// Keep entries for `window` after they expire, and return them as
+    /// [`ProviderResponse::FoundStale`] during that time.
+    ///
+    /// This allows [`crate::CacheLayer::stale_while_revalidate`] to serve
+    /// them while they are refreshed. Entries without a TTL never become
+    /// stale.
+    pub fn stale_window(mut self, window: Duration) -> Self {
+        self.stale_window = Some(window);
+        self
+    }
+}
+
+// Custom implementation of Clone as the Clone derive doesn't mark LfuProvider
+// as Clone if K or V is not clone.
+impl<'a, K, V> Clone for LfuProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            ttl: self.ttl,
+            stale_window: self.stale_window,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+/// Sets the TTL. Setting the capacity clears the cache, for all clones of the
+/// provider, so it should be done before using it.
+impl<'a, K, V> Configure for LfuProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    fn configure(mut self, config: &ProviderConfig) -> Self {
+        if let Some(ttl) = config.ttl {
+            self.ttl = Some(ttl);
+        }
+        if let Some(capacity) = config.capacity {
+            // Caches hold at least one entry.
+            if capacity == 0 {
+                trace::unsupported_config::<Self>("capacity");
+            }
+            *self.inner.lock().unwrap() = Lfu::new(capacity.max(1));
+        }
+        self
+    }
+}
+
+impl<'a, K, V> Service<ProviderRequest<K, V>> for LfuProvider<'a, K, V>
+where
+    K: Eq + Hash + Clone + Send + 'a,
+    V: Clone + Send + 'a,
+{
+    type Response = ProviderResponse<K, V>;
+    type Error = Infallible;
+    type Future = ProviderFuture<'a, K, V>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ProviderRequest<K, V>) -> Self::Future {
+        let mut inner = self.inner.lock().unwrap();
+        Box::pin(ready(Ok(match request {
+            ProviderRequest::Get(key) => {
+                let now = time::now();
+                match inner.get(&key).map(|entry| entry.response_at(now)) {
+                    Some(Some(response)) => response,
+                    // The entry has expired: remove it so it doesn't take up
+                    // capacity anymore.
+                    Some(None) => {
+                        inner.remove(&key);
+                        ProviderResponse::NotFound
+                    }
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            ProviderRequest::Insert(key, value, ttl) => {
+                let entry =
+                    Entry::new(value.clone(), ttl.or(self.ttl)).stale_for(self.stale_window);
+                inner.insert(key, entry);
+                ProviderResponse::Found(value)
+            }
+            ProviderRequest::InsertNegative(key, ttl) => {
+                inner.insert(key, Entry::negative(ttl));
+                ProviderResponse::FoundNegative
+            }
+            ProviderRequest::Clear => {
+                inner.clear();
+                ProviderResponse::Cleared
+            }
+            ProviderRequest::Remove(key) => match inner.remove(&key) {
+                Some(_) => ProviderResponse::Removed,
+                None => ProviderResponse::NotFound,
+            },
+            // Peek at the entry to avoid counting it as a use.
+            ProviderRequest::Contains(key) => {
+                let now = time::now();
+                let present = inner.peek(&key).is_some_and(|entry| !entry.is_expired(now));
+                ProviderResponse::Present(present)
+            }
+            ProviderRequest::Ttl(key) => {
+                let now = time::now();
+                match inner.peek(&key).and_then(|entry| entry.ttl_at(now)) {
+                    Some((remaining, ttl)) => ProviderResponse::Ttl(remaining, ttl),
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            ProviderRequest::Age(key) => {
+                let now = time::now();
+                match inner.peek(&key).and_then(|entry| entry.age_at(now)) {
+                    Some(age) => ProviderResponse::Age(age),
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            ProviderRequest::GetOrInsert(key, value) => {
+                let now = time::now();
+                match inner.get(&key).and_then(|entry| entry.fresh_value_at(now)) {
+                    Some(existing) => ProviderResponse::Found(existing.clone()),
+                    None => {
+                        let entry =
+                            Entry::new(value.clone(), self.ttl).stale_for(self.stale_window);
+                        inner.insert(key, entry);
+                        ProviderResponse::Inserted(value)
+                    }
+                }
+            }
+            ProviderRequest::GetMany(keys) => {
+                let now = time::now();
+                ProviderResponse::Many(
+                    keys.into_iter()
+                        .map(|key| {
+                            let value = inner
+                                .get(&key)
+                                .and_then(|entry| entry.value_at(now))
+                                .cloned();
+                            (key, value)
+                        })
+                        .collect(),
+                )
+            }
+            // Inserting entries one by one is as cheap as a batch in memory.
+            ProviderRequest::InsertMany(_) => ProviderResponse::NotFound,
+            // Evictions are not counted.
+            ProviderRequest::Stats => ProviderResponse::NotFound,
+        })))
+    }
+}
+
+type ProviderFuture<'a, K, V> =
+    Pin<Box<dyn Future<Output = Result<ProviderResponse<K, V>, Infallible>> + Send + 'a>>;
+
+/// Entries of an [`LfuProvider`], indexed by use count
+#[derive(Debug)]
+struct Lfu<K, V> {
+    entries: HashMap<K, Slot<V>>,
+    /// Keys ordered by use count, then by last use
+    order: BTreeMap<(u64, u64), K>,
+    capacity: usize,
+    tick: u64,
+}
+
+#[derive(Debug)]
+struct Slot<V> {
+    entry: Entry<V>,
+    count: u64,
+    tick: u64,
+}
+
+impl<K, V> Lfu<K, V>
+where
+    K: Eq + Hash,
+{
+    fn new(capacity: usize) -> Self {
+        Lfu {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            capacity,
+            tick: 0,
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    /// Return the entry for `key`, counting it as a use
+    fn get(&mut self, key: &K) -> Option<&Entry<V>> {
+        let tick = self.next_tick();
+        let slot = self.entries.get_mut(key)?;
+        let k = self
+            .order
+            .remove(&(slot.count, slot.tick))
+            .expect("every entry is indexed");
+        slot.count += 1;
+        slot.tick = tick;
+        self.order.insert((slot.count, slot.tick), k);
+        Some(&slot.entry)
+    }
+
+    /// Return the entry for `key` without counting it as a use
+    fn peek(&self, key: &K) -> Option<&Entry<V>> {
+        self.entries.get(key).map(|slot| &slot.entry)
+    }
+
+    /// Store `entry`, evicting the least-frequently-used entry if full
+    ///
+    /// Replacing an entry keeps its use count.
+    fn insert(&mut self, key: K, entry: Entry<V>)
+    where
+        K: Clone,
+    {
+        let tick = self.next_tick();
+        let count = match self.entries.get(&key) {
+            Some(slot) => {
+                self.order.remove(&(slot.count, slot.tick));
+                slot.count + 1
+            }
+            None => {
+                if self.entries.len() >= self.capacity {
+                    if let Some((_, evicted)) = self.order.pop_first() {
+                        self.entries.remove(&evicted);
+                    }
+                }
+                1
+            }
+        };
+        self.order.insert((count, tick), key.clone());
+        self.entries.insert(key, Slot { entry, count, tick });
+    }
+
+    fn remove(&mut self, key: &K) -> Option<Entry<V>> {
+        let slot = self.entries.remove(key)?;
+        self.order.remove(&(slot.count, slot.tick));
+        Some(slot.entry)
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::{check_configure, check_provider};
+
+    #[tokio::test]
+    async fn test_conformance() -> Result<(), Infallible> {
+        check_provider(LfuProvider::new::<String, String>(10)).await
+    }
+
+    /// Insert "hot" and hit it a few times, then insert "cold" and hit it
+    /// once, so that "hot" is used more often but "cold" more recently.
+    async fn hot_and_cold<P>(provider: &mut P) -> Result<(), Infallible>
+    where
+        P: Service<ProviderRequest<String, String>, Error = Infallible>,
+    {
+        provider
+            .call(ProviderRequest::Insert(
+                "hot".to_string(),
+                "H".to_string(),
+                None,
+            ))
+            .await?;
+        for _ in 0..3 {
+            provider
+                .call(ProviderRequest::Get("hot".to_string()))
+                .await?;
+        }
+        provider
+            .call(ProviderRequest::Insert(
+                "cold".to_string(),
+                "C".to_string(),
+                None,
+            ))
+            .await?;
+        provider
+            .call(ProviderRequest::Get("cold".to_string()))
+            .await?;
+        provider
+            .call(ProviderRequest::Insert(
+                "new".to_string(),
+                "N".to_string(),
+                None,
+            ))
+            .await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_frequent_key_survives() -> Result<(), Infallible> {
+        let mut provider = LfuProvider::new::<String, String>(2);
+        hot_and_cold(&mut provider).await?;
+
+        let res = provider
+            .call(ProviderRequest::Contains("hot".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(true)));
+        let res = provider
+            .call(ProviderRequest::Contains("cold".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(false)));
+        let res = provider
+            .call(ProviderRequest::Contains("new".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(true)));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "lru")]
+    #[tokio::test]
+    async fn test_lru_evicts_frequent_key() -> Result<(), Infallible> {
+        // Same sequence with an LRU provider: recency wins over frequency.
+        let mut provider = crate::lru::LruProvider::new::<String, String>(2);
+        hot_and_cold(&mut provider).await?;
+
+        let res = provider
+            .call(ProviderRequest::Contains("hot".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(false)));
+        let res = provider
+            .call(ProviderRequest::Contains("cold".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(true)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ties_evict_least_recent() -> Result<(), Infallible> {
+        let mut provider = LfuProvider::new::<usize, usize>(2);
+
+        for i in 0..3 {
+            provider.call(ProviderRequest::Insert(i, i, None)).await?;
+        }
+
+        let res = provider.call(ProviderRequest::Get(0)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        let res = provider.call(ProviderRequest::Get(1)).await?;
+        assert!(matches!(res, ProviderResponse::Found(1)));
+        let res = provider.call(ProviderRequest::Get(2)).await?;
+        assert!(matches!(res, ProviderResponse::Found(2)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ttl_expires() -> Result<(), Infallible> {
+        let mut provider = LfuProvider::with_ttl::<String, String>(10, Duration::ZERO);
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        assert!(provider.inner.lock().unwrap().entries.is_empty());
+        assert!(provider.inner.lock().unwrap().order.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remove_clear() -> Result<(), Infallible> {
+        let mut provider = LfuProvider::new::<usize, usize>(10);
+
+        provider.call(ProviderRequest::Insert(1, 1, None)).await?;
+        provider.call(ProviderRequest::Insert(2, 2, None)).await?;
+
+        let res = provider.call(ProviderRequest::Remove(1)).await?;
+        assert!(matches!(res, ProviderResponse::Removed));
+        let res = provider.call(ProviderRequest::Remove(1)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        let res = provider.call(ProviderRequest::Clear).await?;
+        assert!(matches!(res, ProviderResponse::Cleared));
+        let res = provider.call(ProviderRequest::Get(2)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        assert!(provider.inner.lock().unwrap().order.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_configure() -> Result<(), Infallible> {
+        let configure =
+            |config: &ProviderConfig| LfuProvider::new::<usize, usize>(10).configure(config);
+        check_configure(configure, true).await
+    }
+}
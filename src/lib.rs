@@ -60,14 +60,14 @@
 //! async fn handler(req: String) -> Result<String, Infallible> {
 //!     Ok(req.to_uppercase())
 //! }
-//! 
+//!
 //! fn transform_req(req: String) -> usize {
 //!     req.len()
 //! }
 //!
 //! // Initialize the cache provider service
 //! let lru_provider = LruProvider::new::<usize, String>(20);
-//! 
+//!
 //! let cache_layer = CacheLayer::new(lru_provider)
 //!     .with_transformer(transform_req);
 //!
@@ -80,7 +80,7 @@
 //! // Call the service
 //! let res = my_service.call("Hello".to_string()).await.unwrap();
 //! assert_eq!(res, "HELLO".to_string());
-//! 
+//!
 //! // Since this uses a transformer that takes the length of the String,
 //! // we will get the same result for a string of similar length.
 //! let res = my_service.call("Salut".to_string()).await.unwrap();
@@ -88,181 +88,1864 @@
 //! # })
 //! ```
 //!
+//! If deriving the key requires asynchronous work, such as looking up a
+//! tenant, use [`CacheLayer::with_async_transformer`] instead. To skip the
+//! cache entirely for some requests, use [`CacheLayer::with_try_transformer`]
+//! with a function returning `None` for those requests.
+//!
+//! ## Sharing the cache
+//!
+//! Tower clones services freely, for example once per connection, and
+//! [`CacheLayer::layer`](tower::Layer::layer) clones the provider into each
+//! service it creates. The providers of this crate hold their entries behind
+//! an [`Arc`](std::sync::Arc), or in a shared store such as Redis, so that
+//! all clones of a provider, and of the services using it, read and write
+//! the same entries. Cloning never creates a new, empty cache.
+//!
 //! ## Creating cache providers
 //!
 //! A cache provider is a [`tower::Service`] that takes a [`ProviderRequest`]
-//! as request and returns a [`ProviderResponse`].
+//! as request and returns a [`ProviderResponse`]. Clones of a provider must
+//! share its entries, as described above.
+//!
+//! [`CacheService`] requires the futures of the provider to be [`Send`], and
+//! never holds a lock of its own while awaiting the provider or the inner
+//! service. Providers should follow the same rule: a [`std::sync::Mutex`]
+//! guard held across an `.await` makes the future `!Send`, and blocks every
+//! other request while it waits. Local providers such as
+//! [`lru::LruProvider`] take their lock synchronously in
+//! [`Service::call`] and return a ready future. Providers that need to keep
+//! shared state locked while awaiting should use an async lock, such as
+//! [`tokio::sync::Mutex`], instead:
+//!
+//! ```rust
+//! use std::{
+//!     collections::HashMap,
+//!     convert::Infallible,
+//!     future::Future,
+//!     pin::Pin,
+//!     sync::Arc,
+//!     task::{Context, Poll},
+//! };
+//! use tokio::sync::Mutex;
+//! use tower::Service;
+//! use tower_cache::{ProviderRequest, ProviderResponse};
+//!
+//! #[derive(Clone, Default)]
+//! struct AsyncProvider {
+//!     entries: Arc<Mutex<HashMap<String, String>>>,
+//! }
+//!
+//! impl Service<ProviderRequest<String, String>> for AsyncProvider {
+//!     type Response = ProviderResponse<String, String>;
+//!     type Error = Infallible;
+//!     type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Infallible>> + Send>>;
+//!
+//!     fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+//!         Poll::Ready(Ok(()))
+//!     }
+//!
+//!     fn call(&mut self, request: ProviderRequest<String, String>) -> Self::Future {
+//!         let entries = self.entries.clone();
+//!         Box::pin(async move {
+//!             // The guard can be held across `.await`, as it is `Send`.
+//!             let mut entries = entries.lock().await;
+//!             Ok(match request {
+//!                 ProviderRequest::Get(key) => match entries.get(&key) {
+//!                     Some(value) => ProviderResponse::Found(value.clone()),
+//!                     None => ProviderResponse::NotFound,
+//!                 },
+//!                 ProviderRequest::Insert(key, value, _) => {
+//!                     // For example, write through to a remote store.
+//!                     tokio::task::yield_now().await;
+//!                     entries.insert(key, value.clone());
+//!                     ProviderResponse::Found(value)
+//!                 }
+//!                 _ => ProviderResponse::NotFound,
+//!             })
+//!         })
+//!     }
+//! }
+//! ```
 //!
 
 use std::{
+    convert::Infallible,
     error, fmt,
     future::Future,
     marker::PhantomData,
     pin::Pin,
-    task::{Context, Poll},
+    task::{ready, Context, Poll},
+    time::Duration,
 };
-use tower::{Layer, Service};
+use tower::{Layer, Service, ServiceExt};
+
+pub mod arc;
+pub mod clock;
+
+#[cfg(feature = "dashmap")]
+#[cfg_attr(docsrs, doc(cfg(feature = "dashmap")))]
+pub mod dash;
+
+#[cfg(feature = "moka")]
+#[cfg_attr(docsrs, doc(cfg(feature = "moka")))]
+pub mod moka;
+
+#[cfg(feature = "moka-sync")]
+#[cfg_attr(docsrs, doc(cfg(feature = "moka-sync")))]
+pub mod moka_sync;
+
+#[cfg(feature = "dynamodb")]
+#[cfg_attr(docsrs, doc(cfg(feature = "dynamodb")))]
+pub mod dynamodb;
+
+pub mod fifo;
+
+#[cfg(feature = "http")]
+#[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+pub mod http;
+
+pub mod lfu;
 
 #[cfg(feature = "lru")]
 #[cfg_attr(docsrs, doc(cfg(feature = "lru")))]
 pub mod lru;
 
+pub mod map;
+
+#[cfg(feature = "memcached")]
+#[cfg_attr(docsrs, doc(cfg(feature = "memcached")))]
+pub mod memcached;
+
+pub mod mock;
+
+pub mod noop;
+
+pub mod random;
+
+#[cfg(feature = "redis")]
+#[cfg_attr(docsrs, doc(cfg(feature = "redis")))]
+pub mod redis;
+
+#[cfg(feature = "sled")]
+#[cfg_attr(docsrs, doc(cfg(feature = "sled")))]
+pub mod sled;
+
+pub mod tiered;
+pub mod timeout;
+pub mod tinylfu;
+
+pub mod codec;
+pub mod compress;
+
+mod builder;
+pub use builder::{BuildError, CacheLayerBuilder};
+
+mod cached;
+pub use cached::{CacheOutcome, Cached, MarkCached, MarkMisses, MarkMissesFuture, Marked};
+
+mod coalesce;
+mod entry;
+use coalesce::{Inflight, Outcome, Role};
+
+mod config;
+pub use config::{Configure, ProviderConfig};
+
+#[cfg(test)]
+mod conformance;
+
+mod error_cache;
+pub use error_cache::{
+    CatchErrors, CatchErrorsFuture, ErrorCache, ErrorCacheFuture, ErrorCacheService, WithErrors,
+};
+
+mod event;
+pub use event::CacheEventListener;
+
+mod metric;
+#[cfg(feature = "metrics")]
+#[cfg_attr(docsrs, doc(cfg(feature = "metrics")))]
+pub use metric::CacheMetrics;
+use metric::Metrics;
+
+mod namespace;
+pub use namespace::{Namespace, Namespaced, WithPrefix};
+
+mod negative;
+pub use negative::{NegativeCache, NegativePolicy};
+
+mod predicate;
+pub use predicate::CachePredicate;
+
+mod refresh;
+use refresh::Refresh;
+
+mod stats;
+pub use stats::{CacheStats, ProviderStats, StatsHandle};
+
+mod toggle;
+pub use toggle::CacheToggle;
+
+mod time;
+mod trace;
+
+mod ttl;
+pub use ttl::TtlPolicy;
+
 mod transform;
-pub use transform::Transform;
+pub use transform::{
+    compose, AsyncTransformFn, AsyncTransformFuture, ByKey, BypassIf, CacheKey, HashTransform,
+    IntoCacheKey, KeyConversionError, KeyTransformFn, Keyed, Then, Transform, TransformAsync,
+    TransformFn, TransformRef, TransformRefFn, TryTransform, TryTransformFn, WithMaxAge,
+};
+
+mod value;
+pub use value::{
+    ValueLoader, ValueLoaderFn, ValueTransform, ValueTransformFn, ValueTransformLoader,
+};
+
+type TransformFnLoader<F, G> = ValueTransformLoader<ValueTransformFn<F, G>>;
 
 /// Layer that adds cache to a [`tower::Service`]
 ///
 /// This works by using a cache provider service that takes a [`ProviderRequest`]
 /// and returns a [`ProviderResponse`].
-pub struct CacheLayer<'a, P, T> {
+///
+/// The provider doesn't need to know about the requests and responses of the
+/// inner service: its keys are derived from requests by the transform (see
+/// [`CacheLayer::with_transform`]), and its values from responses by the
+/// value transform (see [`CacheLayer::with_value_transform`]). By default,
+/// both are used as-is.
+///
+/// Cloning the layer, or the services it creates, doesn't create a new
+/// cache: all clones share the same entries, statistics and in-flight
+/// requests. See [Sharing the cache](crate#sharing-the-cache).
+#[derive(Clone)]
+pub struct CacheLayer<'a, P, T, N = (), C = (), D = (), L = (), V = (), E = ()> {
     provider: P,
     transformer: T,
+    negative: N,
+    predicate: C,
+    ttl: D,
+    listener: L,
+    config: Config,
+    inflight: Inflight,
+    refresh: Refresh<'a>,
+    stats: StatsHandle,
+    toggle: CacheToggle,
+    metrics: Metrics,
+    values: V,
+    errors: E,
     _phantom: PhantomData<&'a ()>,
 }
 
+/// Options shared by [`CacheLayer`] and [`CacheService`]
+#[derive(Clone, Copy, Debug, Default)]
+struct Config {
+    fallback_on_provider_error: bool,
+    coalesce: bool,
+    stale_while_revalidate: bool,
+    refresh_ahead: Option<f32>,
+    ttl_jitter: Option<Duration>,
+    lazy_inner_ready: bool,
+    write_back: bool,
+}
+
 impl<'a> CacheLayer<'a, (), ()> {
     /// Create a new [`CacheLayer`]
     pub fn new<P>(provider: P) -> CacheLayer<'a, P, ()> {
-        CacheLayer {
-            provider,
-            transformer: (),
-            _phantom: PhantomData,
-        }
+        Self::builder()
+            .provider(provider)
+            .build()
+            .expect("a builder with only a provider is always valid")
+    }
+
+    /// Create a new [`CacheLayer`] querying several cache providers in order
+    ///
+    /// Values found in a provider are written back to the providers before
+    /// it. This is a shorthand for [`CacheLayer::new`] with a
+    /// [`tiered::ChainProvider`]. To skip the providers returning an error,
+    /// create the [`tiered::ChainProvider`] with
+    /// [`tiered::ChainProvider::fallback_on_error`] instead.
+    ///
+    /// ```rust
+    /// use tower_cache::{CacheLayer, map::MapProvider};
+    ///
+    /// let layer = CacheLayer::with_providers(vec![
+    ///     MapProvider::new::<String, String>(),
+    ///     MapProvider::new::<String, String>(),
+    /// ]);
+    /// ```
+    pub fn with_providers<P>(
+        providers: Vec<P>,
+    ) -> CacheLayer<'a, tiered::ChainProvider<'a, P>, ()> {
+        Self::new(tiered::ChainProvider::new(providers))
+    }
+
+    /// Create a [`CacheLayerBuilder`] to configure a [`CacheLayer`]
+    pub fn builder() -> CacheLayerBuilder<'a, ()> {
+        CacheLayerBuilder::new()
     }
 }
 
-impl<'a, P, T> CacheLayer<'a, P, T> {
+impl<'a, P, T, N, C, D, L, V, E> CacheLayer<'a, P, T, N, C, D, L, V, E> {
     /// Provide a function to transform requests before sending them to the
     /// cache provider.
-    pub fn with_transformer<NT>(self, transformer: NT) -> CacheLayer<'a, P, NT> {
+    pub fn with_transformer<NT>(self, transformer: NT) -> CacheLayer<'a, P, NT, N, C, D, L, V, E> {
+        CacheLayer {
+            provider: self.provider,
+            transformer,
+            negative: self.negative,
+            predicate: self.predicate,
+            ttl: self.ttl,
+            listener: self.listener,
+            config: self.config,
+            inflight: self.inflight,
+            refresh: self.refresh,
+            stats: self.stats,
+            toggle: self.toggle,
+            metrics: self.metrics,
+            values: self.values,
+            errors: self.errors,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Provide a [`Transform`] deriving the cache key from requests.
+    ///
+    /// Unlike [`CacheLayer::with_transformer`], this accepts any [`Transform`],
+    /// including structs holding configuration, by wrapping it in a
+    /// [`TransformFn`]. See [`Transform`] for an example. The key sent to the
+    /// cache provider is the [`Transform::Output`] of the transform, while the
+    /// default `()` transform uses the request itself as key.
+    ///
+    /// ```rust
+    /// use std::convert::Infallible;
+    /// use tower::{Service, ServiceBuilder, service_fn};
+    /// use tower_cache::{
+    ///     CacheLayer,
+    ///     lru::LruProvider,
+    /// };
+    /// #[derive(Clone)]
+    /// struct MyReq {
+    ///     id: u64,
+    ///     name: String,
+    /// }
+    ///
+    /// async fn handler(req: MyReq) -> Result<String, Infallible> {
+    ///     Ok(req.name.to_uppercase())
+    /// }
+    ///
+    /// // Entries are stored by request ID
+    /// let lru_provider = LruProvider::new::<u64, String>(20);
+    ///
+    /// let mut my_service = ServiceBuilder::new()
+    ///     .layer(CacheLayer::new(lru_provider).with_transform(|req: MyReq| req.id))
+    ///     .service(service_fn(handler));
+    ///
+    /// # tokio_test::block_on(async move {
+    /// let res = my_service.call(MyReq { id: 1, name: "Hello".to_string() }).await.unwrap();
+    /// assert_eq!(res, "HELLO".to_string());
+    ///
+    /// // Requests with the same ID get the cached response
+    /// let res = my_service.call(MyReq { id: 1, name: "Salut".to_string() }).await.unwrap();
+    /// assert_eq!(res, "HELLO".to_string());
+    /// # })
+    /// ```
+    pub fn with_transform<NT>(
+        self,
+        transform: NT,
+    ) -> CacheLayer<'a, P, TransformFn<NT>, N, C, D, L, V, E> {
+        self.with_transformer(TransformFn::new(transform))
+    }
+
+    /// Provide a transformer whose output is converted into the key type of
+    /// the cache provider.
+    ///
+    /// Unlike [`CacheLayer::with_transformer`], the output doesn't need to
+    /// match the keys of the provider exactly, as long as it implements
+    /// [`IntoCacheKey`]. If it doesn't, the compiler reports it here rather
+    /// than where the layer is applied. Requests for which the conversion
+    /// fails bypass the cache.
+    ///
+    /// ```rust
+    /// use tower_cache::{CacheLayer, lru::LruProvider};
+    ///
+    /// let layer = CacheLayer::new(LruProvider::new::<String, String>(10))
+    ///     .with_key_transformer(|req: String| req.to_lowercase());
+    /// ```
+    pub fn with_key_transformer<NT, R, K, PV>(
+        self,
+        transformer: NT,
+    ) -> CacheLayer<'a, P, KeyTransformFn<NT, K>, N, C, D, L, V, E>
+    where
+        NT: Transform<R>,
+        NT::Output: IntoCacheKey<K>,
+        P: Service<ProviderRequest<K, PV>>,
+    {
+        self.with_transformer(KeyTransformFn::new(transformer))
+    }
+
+    /// Provide an async function to transform requests before sending them to
+    /// the cache provider.
+    ///
+    /// This is useful when the cache key depends on data that has to be
+    /// looked up asynchronously.
+    pub fn with_async_transformer<F>(
+        self,
+        transformer: F,
+    ) -> CacheLayer<'a, P, AsyncTransformFn<F>, N, C, D, L, V, E> {
+        self.with_transformer(AsyncTransformFn::new(transformer))
+    }
+
+    /// Provide a fallible function to transform requests before sending them
+    /// to the cache provider.
+    ///
+    /// Requests for which the transformer returns `None` bypass the cache and
+    /// go directly to the inner service.
+    pub fn with_try_transformer<NT>(
+        self,
+        transformer: NT,
+    ) -> CacheLayer<'a, P, TryTransformFn<NT>, N, C, D, L, V, E> {
+        self.with_transformer(TryTransformFn::new(transformer))
+    }
+
+    /// Provide a function deriving the cache key from a reference to the
+    /// request.
+    ///
+    /// Unlike [`CacheLayer::with_transformer`], this doesn't require cloning
+    /// the request before passing it to the inner service.
+    pub fn with_ref_transformer<NT>(
+        self,
+        transformer: NT,
+    ) -> CacheLayer<'a, P, TransformRefFn<NT>, N, C, D, L, V, E> {
+        self.with_transformer(TransformRefFn::new(transformer))
+    }
+
+    /// Provide a function returning the maximum age of the cached responses
+    /// served for a request.
+    ///
+    /// Cached responses older than this are treated as misses: the request
+    /// is sent to the inner service, and its response replaces the cached
+    /// one. Requests for which the function returns `None` are served from
+    /// the cache until the entry expires. The age of entries is reported by
+    /// providers through [`ProviderRequest::Age`], before each lookup with a
+    /// maximum age; providers that don't track it serve cached responses
+    /// regardless of the maximum age.
+    ///
+    /// Keys are still derived by the current transformer, so call this after
+    /// setting it. The function can read the maximum age from the request
+    /// itself, such as an `http::Request` extension.
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use tower_cache::{CacheLayer, lru::LruProvider};
+    ///
+    /// #[derive(Clone, Hash, PartialEq, Eq)]
+    /// struct Request {
+    ///     id: u64,
+    ///     max_age: Option<Duration>,
+    /// }
+    ///
+    /// let layer = CacheLayer::new(LruProvider::new::<u64, String>(10))
+    ///     .with_transformer(|req: Request| req.id)
+    ///     .with_max_age(|req: &Request| req.max_age);
+    /// ```
+    pub fn with_max_age<F>(
+        self,
+        max_age: F,
+    ) -> CacheLayer<'a, P, WithMaxAge<T, F>, N, C, D, L, V, E> {
+        let transformer = WithMaxAge::new(self.transformer, max_age);
+        CacheLayer {
+            provider: self.provider,
+            transformer,
+            negative: self.negative,
+            predicate: self.predicate,
+            ttl: self.ttl,
+            listener: self.listener,
+            config: self.config,
+            inflight: self.inflight,
+            refresh: self.refresh,
+            stats: self.stats,
+            toggle: self.toggle,
+            metrics: self.metrics,
+            values: self.values,
+            errors: self.errors,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Cache `None` responses from the inner service for `ttl`.
+    ///
+    /// This is a shorthand for [`CacheLayer::with_negative_policy`] with a
+    /// [`NegativeCache`] policy, for services returning an `Option`.
+    pub fn cache_negative(
+        self,
+        ttl: Duration,
+    ) -> CacheLayer<'a, P, T, NegativeCache, C, D, L, V, E> {
+        self.with_negative_policy(NegativeCache::new(ttl))
+    }
+
+    /// Provide a policy to cache responses representing the absence of a
+    /// value as negative entries.
+    pub fn with_negative_policy<NN>(self, negative: NN) -> CacheLayer<'a, P, T, NN, C, D, L, V, E> {
+        CacheLayer {
+            provider: self.provider,
+            transformer: self.transformer,
+            negative,
+            predicate: self.predicate,
+            ttl: self.ttl,
+            listener: self.listener,
+            config: self.config,
+            inflight: self.inflight,
+            refresh: self.refresh,
+            stats: self.stats,
+            toggle: self.toggle,
+            metrics: self.metrics,
+            values: self.values,
+            errors: self.errors,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Skip the cache lookup for requests for which `predicate` returns
+    /// `true`.
+    ///
+    /// Matching requests go straight to the inner service, and its response
+    /// replaces the cached one, which forces a refresh of the entry. This is
+    /// unlike a [`TryTransform`] returning `None`, which doesn't store the
+    /// response either. The predicate can read a flag from the request
+    /// itself, such as a `Cache-Control: no-cache` header or an
+    /// `http::Request` extension.
+    ///
+    /// Keys are still derived by the current transformer, so call this after
+    /// setting it.
+    ///
+    /// ```rust
+    /// use tower_cache::{CacheLayer, lru::LruProvider};
+    ///
+    /// #[derive(Clone, Hash, PartialEq, Eq)]
+    /// struct Request {
+    ///     id: u64,
+    ///     force_refresh: bool,
+    /// }
+    ///
+    /// let layer = CacheLayer::new(LruProvider::new::<u64, String>(10))
+    ///     .with_transformer(|req: Request| req.id)
+    ///     .bypass_if(|req: &Request| req.force_refresh);
+    /// ```
+    pub fn bypass_if<F>(self, predicate: F) -> CacheLayer<'a, P, BypassIf<T, F>, N, C, D, L, V, E> {
+        let transformer = BypassIf::new(self.transformer, predicate);
         CacheLayer {
             provider: self.provider,
             transformer,
+            negative: self.negative,
+            predicate: self.predicate,
+            ttl: self.ttl,
+            listener: self.listener,
+            config: self.config,
+            inflight: self.inflight,
+            refresh: self.refresh,
+            stats: self.stats,
+            toggle: self.toggle,
+            metrics: self.metrics,
+            values: self.values,
+            errors: self.errors,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Only store responses for which `predicate` returns `true`.
+    ///
+    /// The predicate is called after the inner service returns. Responses
+    /// rejected by the predicate are returned to the caller, but not stored in
+    /// the cache provider.
+    pub fn cache_if<NC>(self, predicate: NC) -> CacheLayer<'a, P, T, N, NC, D, L, V, E> {
+        CacheLayer {
+            provider: self.provider,
+            transformer: self.transformer,
+            negative: self.negative,
+            predicate,
+            ttl: self.ttl,
+            listener: self.listener,
+            config: self.config,
+            inflight: self.inflight,
+            refresh: self.refresh,
+            stats: self.stats,
+            toggle: self.toggle,
+            metrics: self.metrics,
+            values: self.values,
+            errors: self.errors,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Provide a policy deciding how long each response is cached.
+    ///
+    /// The TTL is sent to the cache provider alongside the response. When
+    /// the policy returns `None`, the provider applies its default expiration.
+    pub fn with_ttl_policy<ND>(self, ttl: ND) -> CacheLayer<'a, P, T, N, C, ND, L, V, E> {
+        CacheLayer {
+            provider: self.provider,
+            transformer: self.transformer,
+            negative: self.negative,
+            predicate: self.predicate,
+            ttl,
+            listener: self.listener,
+            config: self.config,
+            inflight: self.inflight,
+            refresh: self.refresh,
+            stats: self.stats,
+            toggle: self.toggle,
+            metrics: self.metrics,
+            values: self.values,
+            errors: self.errors,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Notify a listener of cache events.
+    ///
+    /// The listener is called when a response is served from the cache, when
+    /// a request is sent to the inner service, and when a response is stored
+    /// in the cache provider. See [`CacheEventListener`].
+    pub fn on_event<NL>(self, listener: NL) -> CacheLayer<'a, P, T, N, C, D, NL, V, E> {
+        CacheLayer {
+            provider: self.provider,
+            transformer: self.transformer,
+            negative: self.negative,
+            predicate: self.predicate,
+            ttl: self.ttl,
+            listener,
+            config: self.config,
+            inflight: self.inflight,
+            refresh: self.refresh,
+            stats: self.stats,
+            toggle: self.toggle,
+            metrics: self.metrics,
+            values: self.values,
+            errors: self.errors,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Store a value derived from the responses of the inner service.
+    ///
+    /// `store` derives the value stored in the cache provider from a
+    /// response, and `restore` rebuilds the response from that value on a
+    /// cache hit. This is useful to store a compact summary of a large
+    /// response. See [`ValueTransform`].
+    pub fn with_value_transform<F, G>(
+        self,
+        store: F,
+        restore: G,
+    ) -> CacheLayer<'a, P, T, N, C, D, L, TransformFnLoader<F, G>, E> {
+        self.with_value_transformer(ValueTransformFn::new(store, restore))
+    }
+
+    /// Store a value derived from the responses of the inner service, and
+    /// rebuild responses from it and the current request.
+    ///
+    /// Unlike [`CacheLayer::with_value_transform`], `load` has access to the
+    /// request, so that parts of the response that can't be stored, such as
+    /// handles to live resources, can be recreated on a cache hit. See
+    /// [`ValueLoader`].
+    ///
+    /// As the response is returned to the caller on a cache miss, `save`
+    /// takes it by reference.
+    ///
+    /// ```rust
+    /// use std::convert::Infallible;
+    /// use tower::{Service, ServiceBuilder, service_fn};
+    /// use tower_cache::{CacheLayer, lru::LruProvider};
+    ///
+    /// #[derive(Clone, Debug, PartialEq)]
+    /// struct Page {
+    ///     body: String,
+    ///     // Not cached, rebuilt for each request
+    ///     url: String,
+    /// }
+    ///
+    /// async fn handler(req: String) -> Result<Page, Infallible> {
+    ///     Ok(Page { body: "hello".to_string(), url: format!("https://example.com/{req}") })
+    /// }
+    ///
+    /// let layer = CacheLayer::new(LruProvider::new::<String, String>(10)).with_value_loader(
+    ///     |page: &Page| page.body.clone(),
+    ///     |body: String, req: &String| Page { body, url: format!("https://example.com/{req}") },
+    /// );
+    /// let mut service = ServiceBuilder::new().layer(layer).service(service_fn(handler));
+    ///
+    /// # tokio_test::block_on(async move {
+    /// let page = service.call("a".to_string()).await.unwrap();
+    /// assert_eq!(service.call("a".to_string()).await.unwrap(), page);
+    /// # })
+    /// ```
+    pub fn with_value_loader<F, G>(
+        self,
+        save: F,
+        load: G,
+    ) -> CacheLayer<'a, P, T, N, C, D, L, ValueLoaderFn<F, G>, E> {
+        self.with_loader(ValueLoaderFn::new(save, load))
+    }
+
+    /// Provide a [`ValueTransform`] deciding which value is stored in the
+    /// cache provider for a response.
+    ///
+    /// Other policies of the layer still apply to the responses of the inner
+    /// service, before they are transformed.
+    pub fn with_value_transformer<NV>(
+        self,
+        values: NV,
+    ) -> CacheLayer<'a, P, T, N, C, D, L, ValueTransformLoader<NV>, E> {
+        self.with_loader(ValueTransformLoader::new(values))
+    }
+
+    /// Provide a [`ValueLoader`] deciding which value is stored in the cache
+    /// provider for a response, and how responses are rebuilt from it.
+    ///
+    /// See [`CacheLayer::with_value_loader`].
+    pub fn with_loader<NV>(self, values: NV) -> CacheLayer<'a, P, T, N, C, D, L, NV, E> {
+        CacheLayer {
+            provider: self.provider,
+            transformer: self.transformer,
+            negative: self.negative,
+            predicate: self.predicate,
+            ttl: self.ttl,
+            listener: self.listener,
+            config: self.config,
+            inflight: self.inflight,
+            refresh: self.refresh,
+            stats: self.stats,
+            toggle: self.toggle,
+            metrics: self.metrics,
+            values,
+            errors: self.errors,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Randomize the TTL of each entry within `[ttl - jitter, ttl + jitter]`.
+    ///
+    /// Entries inserted at the same time with the same TTL would otherwise
+    /// all expire at the same time, and the inner service would receive a
+    /// burst of requests. This applies to TTLs returned by the
+    /// [`TtlPolicy`] and to negative entries. Entries stored with the
+    /// default expiration of the provider are not affected.
+    pub fn ttl_jitter(mut self, jitter: Duration) -> Self {
+        self.config.ttl_jitter = Some(jitter);
+        self
+    }
+
+    /// Fall back to the inner service when the cache provider returns an
+    /// error.
+    ///
+    /// When enabled, a provider error on lookup is treated as a cache miss,
+    /// and a provider error when storing the response is ignored. If the
+    /// provider fails to become ready, the next request is sent to the inner
+    /// service without going through the provider. By default, provider
+    /// errors are returned to the caller.
+    pub fn fallback_on_provider_error(mut self, enabled: bool) -> Self {
+        self.config.fallback_on_provider_error = enabled;
+        self
+    }
+
+    /// Only wait for the inner service to be ready on a cache miss.
+    ///
+    /// By default, [`CacheService::poll_ready`] waits for both the cache
+    /// provider and the inner service, as the request could go to either.
+    /// For inner services that reserve resources when they are ready, such
+    /// as a concurrency limit permit, this holds them for requests that end
+    /// up being served from the cache.
+    ///
+    /// When enabled, only the cache provider is polled up front. The request
+    /// is kept in the response future, and the inner service is driven to
+    /// readiness once a cache miss is determined, before the request is sent
+    /// to it. Back-pressure from the inner service then applies to misses
+    /// only.
+    pub fn lazy_inner_ready(mut self, enabled: bool) -> Self {
+        self.config.lazy_inner_ready = enabled;
+        self
+    }
+
+    /// Coalesce concurrent cache misses for the same key.
+    ///
+    /// When enabled, only one request at a time calls the inner service for
+    /// a given key. Other requests for the same key wait for its response,
+    /// which is shared with them even if it isn't stored. When the inner
+    /// service fails, one of the waiting requests calls it again. All
+    /// services created by this layer share the same set of in-flight
+    /// requests.
+    pub fn coalesce(mut self, enabled: bool) -> Self {
+        self.config.coalesce = enabled;
+        self
+    }
+
+    /// Coalesce concurrent cache misses for the same key, using a map of
+    /// in-flight requests split into `shards`.
+    ///
+    /// This behaves like [`CacheLayer::coalesce`], but each shard has its own
+    /// lock, so misses for different keys rarely wait for each other. This
+    /// helps with many concurrent misses for distinct keys. A number of
+    /// shards of `0` is clamped to `1`.
+    pub fn coalesce_sharded(mut self, shards: usize) -> Self {
+        self.config.coalesce = true;
+        self.inflight = Inflight::sharded(shards);
+        self
+    }
+
+    /// Return a handle to the statistics of the services created by this
+    /// layer.
+    pub fn stats_handle(&self) -> StatsHandle {
+        self.stats.clone()
+    }
+
+    /// Return a handle to turn caching on and off for the services created
+    /// by this layer.
+    pub fn toggle_handle(&self) -> CacheToggle {
+        self.toggle.clone()
+    }
+
+    /// Record metrics for the services created by this layer with the
+    /// `metrics` crate.
+    ///
+    /// This records hits, misses, inserts and the duration of provider calls,
+    /// labeled with the name of the cache. See [`CacheMetrics`].
+    #[cfg(feature = "metrics")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "metrics")))]
+    pub fn with_metrics(mut self, metrics: CacheMetrics) -> Self {
+        self.metrics = Metrics::new(metrics);
+        self
+    }
+}
+
+impl<'a, P, T, N, C, D, L, V> CacheLayer<'a, P, T, N, C, D, L, V> {
+    /// Cache errors from the inner service for `ttl`.
+    ///
+    /// By default, errors are returned to the caller without being stored,
+    /// and the next request calls the inner service again. When errors are
+    /// cached, they are stored in the cache provider and returned as
+    /// [`CacheError::ServiceError`] until they expire, which avoids calling a
+    /// failing dependency on every request.
+    ///
+    /// The cache provider stores `Result<Res, E>` values, where `Res` and `E`
+    /// are the response and error types of the inner service, and the error
+    /// type needs to implement `Clone`. Other policies of the layer only
+    /// apply to successful responses. See [`ErrorCacheService`].
+    ///
+    /// This can't be combined with [`CacheLayer::mark_cached`].
+    pub fn cache_errors(self, ttl: Duration) -> CacheLayer<'a, P, T, N, C, D, L, V, ErrorCache> {
+        CacheLayer {
+            provider: self.provider,
+            transformer: self.transformer,
+            negative: self.negative,
+            predicate: self.predicate,
+            ttl: self.ttl,
+            listener: self.listener,
+            config: self.config,
+            inflight: self.inflight,
+            refresh: self.refresh,
+            stats: self.stats,
+            toggle: self.toggle,
+            metrics: self.metrics,
+            values: self.values,
+            errors: ErrorCache::new(ttl),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Wrap responses in [`Cached`] to tell whether they came from the cache.
+    ///
+    /// Responses of the inner service are returned with `from_cache` set to
+    /// `false`, and responses returned by the cache provider with
+    /// `from_cache` set to `true`. [`Cached::outcome`] also tells stale
+    /// responses served by [`CacheLayer::stale_while_revalidate`] apart. The
+    /// cache provider still stores the unwrapped responses, and other
+    /// policies of the layer apply to the [`Cached::value`]. See
+    /// [`MarkMisses`].
+    ///
+    /// This can't be combined with [`CacheLayer::cache_errors`].
+    pub fn mark_cached(self) -> CacheLayer<'a, P, T, N, C, D, L, V, MarkCached> {
+        CacheLayer {
+            provider: self.provider,
+            transformer: self.transformer,
+            negative: self.negative,
+            predicate: self.predicate,
+            ttl: self.ttl,
+            listener: self.listener,
+            config: self.config,
+            inflight: self.inflight,
+            refresh: self.refresh,
+            stats: self.stats,
+            toggle: self.toggle,
+            metrics: self.metrics,
+            values: self.values,
+            errors: MarkCached,
             _phantom: PhantomData,
         }
     }
 }
 
-impl<'a, P, T, S> Layer<S> for CacheLayer<'a, P, T>
+impl<P, T, N, C, D, L, V, E> CacheLayer<'static, P, T, N, C, D, L, V, E> {
+    /// Serve stale entries while refreshing them in the background.
+    ///
+    /// Providers with a stale window, such as
+    /// [`map::MapProvider::stale_window`], keep entries for a while after
+    /// they expire and return them as [`ProviderResponse::FoundStale`]. When
+    /// enabled, these entries are returned immediately, and the inner service
+    /// is called in a background task to update the cache. Only one refresh
+    /// runs at a time for a given key. By default, stale entries are treated
+    /// as cache misses.
+    ///
+    /// Background tasks are spawned on the current Tokio runtime. Outside of
+    /// a runtime, stale entries are still served, but not refreshed.
+    pub fn stale_while_revalidate(mut self, enabled: bool) -> Self {
+        self.config.stale_while_revalidate = enabled;
+        if enabled {
+            self.refresh = self.refresh.on_tokio();
+        }
+        self
+    }
+
+    /// Store responses in the background instead of before returning them.
+    ///
+    /// By default, a response from the inner service is returned once the
+    /// cache provider has stored it (write-through). In write-back mode, the
+    /// response is returned immediately, and the insert runs in a background
+    /// task. This hides the latency of the provider, at the cost of requests
+    /// that arrive before the insert completes missing the cache, and of
+    /// provider errors on insert being ignored.
+    ///
+    /// With [`CacheLayer::coalesce`], concurrent requests for the same key
+    /// get the response right away as well, and later ones share it until
+    /// the insert completes.
+    ///
+    /// Background tasks are spawned on the current Tokio runtime. Outside of
+    /// a runtime, responses are stored before returning them.
+    pub fn write_back(mut self, enabled: bool) -> Self {
+        self.config.write_back = enabled;
+        if enabled {
+            self.refresh = self.refresh.on_tokio();
+        }
+        self
+    }
+
+    /// Refresh entries in the background when they are read with less than
+    /// `factor` of their TTL remaining.
+    ///
+    /// For example, with a factor of `0.2`, an entry with a TTL of 10 minutes
+    /// is refreshed when it is read during its last 2 minutes, so that it is
+    /// replaced before it expires. The current request still gets the cached
+    /// response, and only one refresh runs at a time for a given key.
+    ///
+    /// The remaining TTL is read with [`ProviderRequest::Ttl`] after each hit,
+    /// and a background task is only spawned for entries close to expiry, so
+    /// this has no effect with providers that don't support it. `factor` is
+    /// clamped between `0.0` and `1.0`, and a NaN factor never triggers a
+    /// refresh.
+    ///
+    /// Background tasks are spawned on the current Tokio runtime. Outside of
+    /// a runtime, entries are only refreshed once they expire.
+    pub fn refresh_ahead(mut self, factor: f32) -> Self {
+        let factor = match factor.is_nan() {
+            true => 0.0,
+            false => factor.clamp(0.0, 1.0),
+        };
+        self.config.refresh_ahead = Some(factor);
+        self.refresh = self.refresh.on_tokio();
+        self
+    }
+}
+
+impl<'a, P, T, N, C, D, L, V, S> Layer<S> for CacheLayer<'a, P, T, N, C, D, L, V>
 where
     P: Clone,
     T: Clone,
+    N: Clone,
+    C: Clone,
+    D: Clone,
+    L: Clone,
+    V: Clone,
 {
-    type Service = CacheService<'a, S, P, T>;
+    type Service = CacheService<'a, S, P, T, N, C, D, L, V>;
 
     fn layer(&self, inner: S) -> Self::Service {
         CacheService {
             inner,
             provider: self.provider.clone(),
             transformer: self.transformer.clone(),
+            negative: self.negative.clone(),
+            predicate: self.predicate.clone(),
+            ttl: self.ttl.clone(),
+            listener: self.listener.clone(),
+            config: self.config,
+            inflight: self.inflight.clone(),
+            refresh: self.refresh.clone(),
+            stats: self.stats.clone(),
+            toggle: self.toggle.clone(),
+            metrics: self.metrics.clone(),
+            values: self.values.clone(),
+            skip_provider: false,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, P, T, N, C, D, L, V, S> Layer<S> for CacheLayer<'a, P, T, N, C, D, L, V, ErrorCache>
+where
+    P: Clone,
+    T: Clone,
+    N: Clone,
+    C: Clone,
+    D: Clone,
+    L: Clone,
+    V: Clone,
+{
+    type Service = ErrorCacheService<
+        CacheService<
+            'a,
+            CatchErrors<S>,
+            P,
+            T,
+            WithErrors<N>,
+            WithErrors<C>,
+            WithErrors<D>,
+            WithErrors<L>,
+            WithErrors<V>,
+        >,
+    >;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ErrorCacheService::new(CacheService {
+            inner: CatchErrors::new(inner),
+            provider: self.provider.clone(),
+            transformer: self.transformer.clone(),
+            negative: self.errors.wrap(self.negative.clone()),
+            predicate: self.errors.wrap(self.predicate.clone()),
+            ttl: self.errors.wrap(self.ttl.clone()),
+            listener: self.errors.wrap(self.listener.clone()),
+            values: self.errors.wrap(self.values.clone()),
+            config: self.config,
+            inflight: self.inflight.clone(),
+            refresh: self.refresh.clone(),
+            stats: self.stats.clone(),
+            toggle: self.toggle.clone(),
+            metrics: self.metrics.clone(),
+            skip_provider: false,
+            _phantom: PhantomData,
+        })
+    }
+}
+
+impl<'a, P, T, N, C, D, L, V, S> Layer<S> for CacheLayer<'a, P, T, N, C, D, L, V, MarkCached>
+where
+    P: Clone,
+    T: Clone,
+    N: Clone,
+    C: Clone,
+    D: Clone,
+    L: Clone,
+    V: Clone,
+{
+    type Service = CacheService<
+        'a,
+        MarkMisses<S>,
+        P,
+        T,
+        Marked<N>,
+        Marked<C>,
+        Marked<D>,
+        Marked<L>,
+        Marked<V>,
+    >;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CacheService {
+            inner: MarkMisses::new(inner),
+            provider: self.provider.clone(),
+            transformer: self.transformer.clone(),
+            negative: self.errors.wrap(self.negative.clone()),
+            predicate: self.errors.wrap(self.predicate.clone()),
+            ttl: self.errors.wrap(self.ttl.clone()),
+            listener: self.errors.wrap(self.listener.clone()),
+            values: self.errors.wrap(self.values.clone()),
+            config: self.config,
+            inflight: self.inflight.clone(),
+            refresh: self.refresh.clone(),
+            stats: self.stats.clone(),
+            toggle: self.toggle.clone(),
+            metrics: self.metrics.clone(),
+            skip_provider: false,
             _phantom: PhantomData,
         }
     }
 }
 
 /// Service generated by [`CacheLayer`].
-pub struct CacheService<'a, S, P, T> {
+///
+/// With the `tracing` feature, each request is wrapped in a debug-level
+/// `cache` span with `cache.hit` and `cache.provider_duration_us` fields, and
+/// emits `cache.hit`, `cache.miss` and `cache.insert` events. Lookups and
+/// inserts also get a child `cache.provider` span with `cache.operation`,
+/// `cache.provider` and `cache.hit` fields, and `otel.kind` set to `client`
+/// for `tracing-opentelemetry`.
+///
+/// Tower clones services, for example once per connection. Clones share the
+/// cache provider of the original service, see
+/// [Sharing the cache](crate#sharing-the-cache).
+#[derive(Clone)]
+pub struct CacheService<'a, S, P, T, N = (), C = (), D = (), L = (), V = ()> {
     inner: S,
     provider: P,
     transformer: T,
+    negative: N,
+    predicate: C,
+    ttl: D,
+    listener: L,
+    config: Config,
+    inflight: Inflight,
+    refresh: Refresh<'a>,
+    stats: StatsHandle,
+    toggle: CacheToggle,
+    metrics: Metrics,
+    values: V,
+    /// The provider wasn't polled, or failed to become ready, so the next
+    /// request goes straight to the inner service
+    skip_provider: bool,
     _phantom: PhantomData<&'a ()>,
 }
 
-impl<'a, S, P, T, R> Service<R> for CacheService<'a, S, P, T>
+impl<'a, S, P, T, N, C, D, L, V> CacheService<'a, S, P, T, N, C, D, L, V> {
+    /// Return a snapshot of the cache statistics
+    ///
+    /// Statistics are shared with all services created by the same
+    /// [`CacheLayer`].
+    pub fn stats(&self) -> CacheStats {
+        self.stats.stats()
+    }
+
+    /// Return the statistics reported by the cache provider
+    ///
+    /// Unlike [`CacheService::stats`], which only counts the requests going
+    /// through the service, this asks the provider for its size and eviction
+    /// count with [`ProviderRequest::Stats`]. Returns `None` if the provider
+    /// doesn't report them.
+    pub fn provider_stats<K, Res>(
+        &self,
+    ) -> impl Future<Output = Result<Option<ProviderStats>, P::Error>> + 'a
+    where
+        P: Service<ProviderRequest<K, Res>, Response = ProviderResponse<K, Res>> + Clone + 'a,
+        K: 'a,
+        Res: 'a,
+    {
+        let provider = self.provider.clone();
+        async move {
+            Ok(match provider.oneshot(ProviderRequest::Stats).await? {
+                ProviderResponse::Stats {
+                    len,
+                    capacity,
+                    evictions,
+                } => Some(ProviderStats {
+                    len,
+                    capacity,
+                    evictions,
+                }),
+                _ => None,
+            })
+        }
+    }
+
+    /// Turn caching on or off
+    ///
+    /// This affects all services created by the same [`CacheLayer`]. While
+    /// caching is disabled, requests go directly to the inner service. See
+    /// [`CacheToggle`].
+    pub fn set_enabled(&self, enabled: bool) {
+        self.toggle.set_enabled(enabled);
+    }
+
+    /// Return `true` if caching is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.toggle.is_enabled()
+    }
+
+    /// Remove all entries from the cache provider
+    ///
+    /// As providers are shared between services, this clears the cache for
+    /// all services using the same provider. To clear the cache outside of
+    /// the request path, you can also keep a clone of the provider and send
+    /// it a [`ProviderRequest::Clear`] directly.
+    pub fn clear<K, Res>(&self) -> impl Future<Output = Result<(), P::Error>> + 'a
+    where
+        P: Service<ProviderRequest<K, Res>, Response = ProviderResponse<K, Res>> + Clone + 'a,
+        K: 'a,
+        Res: 'a,
+    {
+        let provider = self.provider.clone();
+        async move {
+            provider.oneshot(ProviderRequest::Clear).await?;
+            Ok(())
+        }
+    }
+
+    /// Remove the entry for `key` from the cache provider
+    ///
+    /// `key` is the key sent to the provider, after the request has been
+    /// transformed. Returns `true` if an entry was removed.
+    pub fn invalidate<K, Res>(&self, key: K) -> impl Future<Output = Result<bool, P::Error>> + 'a
+    where
+        P: Service<ProviderRequest<K, Res>, Response = ProviderResponse<K, Res>> + Clone + 'a,
+        K: 'a,
+        Res: 'a,
+    {
+        let provider = self.provider.clone();
+        async move {
+            let res = provider.oneshot(ProviderRequest::Remove(key)).await?;
+            Ok(matches!(res, ProviderResponse::Removed))
+        }
+    }
+
+    /// Insert entries into the cache provider without calling the inner
+    /// service, for example to warm up the cache on startup
+    ///
+    /// As with [`CacheService::invalidate`], keys are the keys sent to the
+    /// provider, and values are the values it stores. Entries use the
+    /// default expiration policy of the provider.
+    ///
+    /// The entries are sent as a single [`ProviderRequest::InsertMany`], or
+    /// one by one if the provider doesn't support batch inserts.
+    pub fn prime<K, Res>(
+        &self,
+        entries: impl IntoIterator<Item = (K, Res)>,
+    ) -> impl Future<Output = Result<(), P::Error>> + 'a
+    where
+        P: Service<ProviderRequest<K, Res>, Response = ProviderResponse<K, Res>> + Clone + 'a,
+        K: Clone + 'a,
+        Res: Clone + 'a,
+    {
+        let provider = self.provider.clone();
+        let entries: Vec<_> = entries
+            .into_iter()
+            .map(|(key, value)| (key, value, None))
+            .collect();
+        async move {
+            let res = provider
+                .clone()
+                .oneshot(ProviderRequest::InsertMany(entries.clone()))
+                .await?;
+            if !matches!(res, ProviderResponse::Many(_)) {
+                for (key, value, ttl) in entries {
+                    provider
+                        .clone()
+                        .oneshot(ProviderRequest::Insert(key, value, ttl))
+                        .await?;
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+impl<'a, S, P, T, N, C, D, L, V, R> Service<R> for CacheService<'a, S, P, T, N, C, D, L, V>
 where
     S: Service<R> + Clone + Send + 'a,
-    S::Response: Clone + Send + 'a,
-    S::Error: Into<Box<dyn error::Error + Send + Sync>>,
+    S::Response: Send + 'a,
+    S::Error: Send + 'a,
     S::Future: Send + 'a,
 
-    P: Service<ProviderRequest<T::Output, S::Response>, Response = ProviderResponse<S::Response>>
-        + Clone
+    P: Service<
+            ProviderRequest<T::Output, V::Stored>,
+            Response = ProviderResponse<T::Output, V::Stored>,
+        > + Clone
         + Send
         + 'a,
     P::Response: Send + 'a,
-    P::Error: Into<Box<dyn error::Error + Send + Sync>> + Send,
+    P::Error: Send + 'a,
     P::Future: Send + 'a,
 
-    T: Transform<R>,
-    T::Output: Clone + Send + 'a,
-    R: Clone + Send + Sync + 'a,
+    T: TransformAsync<R>,
+    T::Output: CacheKey + 'a,
+    T::Future: Send + 'a,
+    N: NegativePolicy<S::Response> + Clone + Send + 'a,
+    C: CachePredicate<S::Response> + Clone + Send + 'a,
+    D: TtlPolicy<S::Response> + Clone + Send + 'a,
+    L: CacheEventListener<T::Output, V::Stored> + Clone + Send + 'a,
+    V: ValueLoader<R, S::Response> + Clone + Send + 'a,
+    V::Stored: Clone + Send + 'static,
+    R: Send + 'a,
 {
     type Response = S::Response;
-    type Error = Error;
-    type Future = CacheFuture<'a, R, S>;
+    type Error = CacheError<P::Error, S::Error>;
+    type Future = CacheFuture<'a, S::Response, Self::Error>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.provider
-            .poll_ready(cx)
-            .map_err(|e| Error::ServiceError(e.into()))
+        // Both services need to be ready, as the request could go to either,
+        // unless the inner service is only polled on a miss. When caching is
+        // turned off, the request only goes to the inner service.
+        if !self.toggle.is_enabled() {
+            self.skip_provider = true;
+        } else {
+            match ready!(self.provider.poll_ready(cx)) {
+                Ok(()) => self.skip_provider = false,
+                // The provider isn't available, but the request can still be
+                // served by the inner service.
+                Err(_) if self.config.fallback_on_provider_error => {
+                    trace::provider_fallback();
+                    self.skip_provider = true;
+                }
+                Err(e) => return Poll::Ready(Err(CacheError::ProviderError(e))),
+            }
+        }
+        if self.config.lazy_inner_ready {
+            return Poll::Ready(Ok(()));
+        }
+        self.inner.poll_ready(cx).map_err(CacheError::ServiceError)
     }
 
     fn call(&mut self, request: R) -> Self::Future {
-        let mut provider = self.provider.clone();
-        let mut inner = self.inner.clone();
-        let cache_request = self.transformer.transform(request.clone());
-        let idem_fut = self
-            .provider
-            .call(ProviderRequest::Get(cache_request.clone()));
-
-        Box::pin(async move {
-            let res = match idem_fut.await {
-                // If we have a response in the cache, we can immediately return without
-                // calling the inner service.
-                Ok(ProviderResponse::Found(res)) => Ok(res),
-                // Response not found - we need to call the inner service and update the
-                // cache.
-                Ok(ProviderResponse::NotFound) => {
-                    // Fetch the response from the inner service.
-                    let response = inner
-                        .call(request)
+        // Move the services that were driven to readiness into the future, as
+        // they are only called once the key has been derived.
+        let clone = self.provider.clone();
+        let mut provider = std::mem::replace(&mut self.provider, clone);
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        // Caching was turned off at runtime, or the provider wasn't driven to
+        // readiness.
+        if std::mem::take(&mut self.skip_provider) || !self.toggle.is_enabled() {
+            let lazy = self.config.lazy_inner_ready;
+            return Box::pin(async move {
+                trace::bypass();
+                call_inner(&mut inner, request, lazy)
+                    .await
+                    .map_err(CacheError::ServiceError)
+            });
+        }
+        let key_fut = self.transformer.transform_async(&request);
+        let max_age = self.transformer.max_age(&request);
+        let bypass = self.transformer.bypass(&request);
+
+        // The span covers both the provider lookup and the inner service call.
+        let span = trace::request_span();
+
+        let negative = self.negative.clone();
+        let predicate = self.predicate.clone();
+        let ttl_policy = self.ttl.clone();
+        let config = self.config;
+        let inflight = self.inflight.clone();
+        let refresh = self.refresh.clone();
+        let stats = self.stats.clone();
+        let listener = self.listener.clone();
+        let values = self.values.clone();
+        let metrics = self.metrics.clone();
+
+        let fut = async move {
+            let cache_request = match key_fut.await {
+                Some(cache_request) => cache_request,
+                // The transformer asked to bypass the cache for this request.
+                None => {
+                    trace::bypass();
+                    return call_inner(&mut inner, request, config.lazy_inner_ready)
                         .await
-                        .map_err(|e| Error::ServiceError(e.into()));
+                        .map_err(CacheError::ServiceError);
+                }
+            };
+            // Entries older than the maximum age of the request are treated as
+            // misses. If the provider can't tell the age, the entry is served.
+            // The age is read before the lookup: an entry replaced in between
+            // is only younger, so the lookup never returns one that is too old.
+            let too_old = match (bypass, max_age) {
+                (false, Some(max_age)) => {
+                    // The provider driven to readiness is kept for the lookup.
+                    let age_request = ProviderRequest::Age(cache_request.clone());
+                    matches!(
+                        call_provider(&mut provider.clone(), age_request).await,
+                        Ok(ProviderResponse::Age(age)) if age > max_age
+                    )
+                }
+                _ => false,
+            };
+            let (hit, stale) = match bypass {
+                // The lookup is skipped, but the response of the inner service
+                // is still stored.
+                true => {
+                    trace::force_refresh();
+                    (None, false)
+                }
+                false if too_old => (None, false),
+                false => {
+                    let timer = trace::Timer::start();
+                    let metrics_timer = metrics.timer();
+                    let response = trace::call_provider(
+                        &mut provider,
+                        ProviderRequest::Get(cache_request.clone()),
+                    )
+                    .await;
+                    timer.record();
+                    metrics.record("get", metrics_timer);
                     match response {
-                        Ok(res) => {
-                            // Store the response in the cache provider.
-                            let new_res = res.clone();
-                            match provider
-                                .call(ProviderRequest::Insert(cache_request, new_res))
-                                .await
-                            {
-                                Ok(_) => Ok(res),
-                                Err(e) => Err(Error::ProviderError(e.into())),
-                            }
+                        Ok(ProviderResponse::FoundStale(res)) if config.stale_while_revalidate => {
+                            (Some(values.load_stale(res, &request)), true)
+                        }
+                        response => {
+                            let hit = lookup(response, &negative, &values, &request, config)?;
+                            (hit, false)
                         }
-                        res => res,
                     }
                 }
-                Err(e) => Err(Error::ProviderError(e.into())),
             };
+            if let Some(res) = hit {
+                match stale {
+                    true => trace::stale(),
+                    false => trace::hit(),
+                }
+                stats.hit();
+                metrics.hit();
+                listener.on_hit(&cache_request);
 
-            res
-        })
-    }
-}
+                // Fresh entries are only refreshed close to expiry.
+                let refresh_due = match (stale, config.refresh_ahead) {
+                    (true, _) => true,
+                    (false, Some(factor)) => {
+                        let ttl_request = ProviderRequest::Ttl(cache_request.clone());
+                        matches!(
+                            call_provider(&mut provider, ttl_request).await,
+                            Ok(ProviderResponse::Ttl(remaining, ttl))
+                                if remaining <= ttl.mul_f32(factor)
+                        )
+                    }
+                    (false, None) => false,
+                };
 
-/// Requests sent to the cache provider
-#[derive(Clone, Debug)]
-pub enum ProviderRequest<Req, Res> {
-    /// Check if the provider has a similar request
-    Get(Req),
-    /// Insert a response into the provider
-    Insert(Req, Res),
-}
+                // Update the cache in the background, without delaying the
+                // current request.
+                if refresh_due {
+                    let key = cache_request.clone();
+                    refresh.spawn(&key, async move {
+                        let res = match inner.oneshot(request).await {
+                            Ok(res) => res,
+                            Err(_) => return,
+                        };
+                        if !predicate.should_cache(&res) {
+                            return;
+                        }
+                        let insert_request = insert_request(
+                            &negative,
+                            &ttl_policy,
+                            &values,
+                            config,
+                            cache_request.clone(),
+                            &res,
+                        );
+                        if provider.oneshot(insert_request).await.is_ok() {
+                            stats.insert();
+                            metrics.insert();
+                            listener.on_insert(&cache_request);
+                        }
+                    });
+                }
+                return Ok(res);
+            }
 
-/// Responses sent by the cache provider
-#[derive(Debug)]
-pub enum ProviderResponse<Res> {
+            // Only let one request through to the inner service for a given
+            // key, and share its response with the other ones.
+            let guard = match config.coalesce {
+                true => loop {
+                    let follower = match inflight.join(&cache_request) {
+                        Role::Leader(guard) => break Some(guard),
+                        Role::Follower(follower) => follower,
+                    };
+                    // Shared responses were just fetched by the leader, so
+                    // they are never older than the maximum age.
+                    let res = match follower.wait::<Option<V::Stored>>().await {
+                        Outcome::Shared(Some(stored)) => Some(values.load(stored, &request)),
+                        Outcome::Shared(None) => negative.empty(),
+                        // Errors can't be shared: join again, as the leader
+                        // if no other follower did first.
+                        Outcome::Failed => continue,
+                        Outcome::Mismatch => None,
+                    };
+                    if let Some(res) = res {
+                        trace::hit();
+                        stats.hit();
+                        metrics.hit();
+                        listener.on_hit(&cache_request);
+                        return Ok(res);
+                    }
+                    break None;
+                },
+                false => None,
+            };
+
+            // Fetch the response from the inner service.
+            trace::miss();
+            stats.miss();
+            metrics.miss();
+            listener.on_miss(&cache_request);
+            let res = call_inner(&mut inner, request, config.lazy_inner_ready)
+                .await
+                .map_err(CacheError::ServiceError)?;
+
+            // Some responses shouldn't be stored at all, but are still shared
+            // with coalesced requests.
+            let followers = guard.as_ref().filter(|guard| guard.has_followers());
+            if !predicate.should_cache(&res) {
+                if let Some(guard) = followers {
+                    guard.share(Some(values.save(&res)));
+                }
+                return Ok(res);
+            }
+
+            // Store the response in the cache provider.
+            let insert_request = insert_request(
+                &negative,
+                &ttl_policy,
+                &values,
+                config,
+                cache_request.clone(),
+                &res,
+            );
+            if let Some(guard) = followers {
+                match &insert_request {
+                    ProviderRequest::Insert(_, stored, _) => guard.share(Some(stored.clone())),
+                    _ => guard.share(None::<V::Stored>),
+                }
+            }
+            let store = async move {
+                let metrics_timer = metrics.timer();
+                let response = call_provider(&mut provider, insert_request).await;
+                metrics.record("insert", metrics_timer);
+                response.map(|_| {
+                    trace::insert();
+                    stats.insert();
+                    metrics.insert();
+                    listener.on_insert(&cache_request);
+                })
+            };
+
+            // Return the response right away, and store it in the background.
+            // Coalesced requests keep waiting until it is stored.
+            if config.write_back {
+                let store = async move {
+                    let _ = store.await;
+                    drop(guard);
+                };
+                if let Err(store) = refresh.detach(store) {
+                    store.await;
+                }
+                return Ok(res);
+            }
+
+            let response = store.await;
+            drop(guard);
+            match response {
+                Ok(()) => Ok(res),
+                Err(_) if config.fallback_on_provider_error => {
+                    trace::provider_fallback();
+                    Ok(res)
+                }
+                Err(e) => Err(CacheError::ProviderError(e)),
+            }
+        };
+
+        Box::pin(trace::instrument(fut, span))
+    }
+}
+
+/// Call the inner service, waiting for it to be ready first if it wasn't
+/// polled in [`CacheService::poll_ready`]
+async fn call_inner<S, R>(inner: &mut S, request: R, lazy: bool) -> Result<S::Response, S::Error>
+where
+    S: Service<R>,
+{
+    if lazy {
+        inner.ready().await?;
+    }
+    inner.call(request).await
+}
+
+/// Call the provider again, waiting for it to be ready first
+///
+/// [`CacheService::poll_ready`] only covers the first call to the provider.
+/// Providers such as `RedisPool` reserve resources when they become
+/// ready, so every further call needs to go through readiness again.
+async fn call_provider<P, K, V>(
+    provider: &mut P,
+    request: ProviderRequest<K, V>,
+) -> Result<ProviderResponse<K, V>, P::Error>
+where
+    P: Service<ProviderRequest<K, V>, Response = ProviderResponse<K, V>>,
+{
+    provider.ready().await?;
+    trace::call_provider(provider, request).await
+}
+
+/// Build the request storing a response from the inner service
+fn insert_request<N, D, V, R, Req, Res>(
+    negative: &N,
+    ttl_policy: &D,
+    values: &V,
+    config: Config,
+    cache_request: Req,
+    res: &Res,
+) -> ProviderRequest<Req, V::Stored>
+where
+    N: NegativePolicy<Res>,
+    D: TtlPolicy<Res>,
+    V: ValueLoader<R, Res>,
+{
+    let jitter = |ttl| match config.ttl_jitter {
+        Some(jitter) => ttl::jitter(ttl, jitter),
+        None => ttl,
+    };
+    match negative.negative_ttl(res) {
+        Some(ttl) => ProviderRequest::InsertNegative(cache_request, jitter(ttl)),
+        None => ProviderRequest::Insert(
+            cache_request,
+            values.save(res),
+            ttl_policy.ttl(res).map(jitter),
+        ),
+    }
+}
+
+/// Turn the response of a cache provider lookup into a cached response
+///
+/// Returns `None` on a cache miss, or if the provider failed and
+/// `fallback_on_provider_error` is enabled.
+fn lookup<N, V, R, K, Res, PE, SE>(
+    response: Result<ProviderResponse<K, V::Stored>, PE>,
+    negative: &N,
+    values: &V,
+    request: &R,
+    config: Config,
+) -> Result<Option<Res>, CacheError<PE, SE>>
+where
+    N: NegativePolicy<Res>,
+    V: ValueLoader<R, Res>,
+{
+    match response {
+        // If we have a response in the cache, we can immediately return without
+        // calling the inner service.
+        Ok(ProviderResponse::Found(stored)) => Ok(Some(values.load(stored, request))),
+        // The cache knows that there is no value for this request.
+        Ok(ProviderResponse::FoundNegative) => Ok(negative.empty()),
+        // Response not found - we need to call the inner service and update the
+        // cache. Stale responses, when they are not served, and responses that
+        // don't apply to a lookup are treated the same way.
+        Ok(_) => Ok(None),
+        // The provider failed, but we can treat this as a cache miss.
+        Err(_) if config.fallback_on_provider_error => {
+            trace::provider_fallback();
+            Ok(None)
+        }
+        Err(e) => Err(CacheError::ProviderError(e)),
+    }
+}
+
+/// Requests sent to the cache provider
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProviderRequest<Req, Res> {
+    /// Check if the provider has a similar request
+    Get(Req),
+    /// Insert a response into the provider
+    ///
+    /// The entry should expire after the given duration if any. Otherwise,
+    /// the provider applies its default expiration policy. This replaces any
+    /// existing entry: use [`ProviderRequest::GetOrInsert`] to only insert
+    /// the response if there is none.
+    Insert(Req, Res, Option<Duration>),
+    /// Insert a negative entry into the provider, marking that there is no
+    /// response for this request
+    ///
+    /// The entry should expire after the given duration. Providers that don't
+    /// support negative entries can ignore this request and return
+    /// [`ProviderResponse::NotFound`].
+    InsertNegative(Req, Duration),
+    /// Remove all entries from the provider
+    ///
+    /// Providers that cannot remove their entries should return
+    /// [`ProviderResponse::NotFound`].
+    Clear,
+    /// Remove the entry for a similar request from the provider
+    ///
+    /// Providers should return [`ProviderResponse::NotFound`] if there was no
+    /// such entry.
+    Remove(Req),
+    /// Check if the provider has a similar request, without returning the
+    /// response
+    ///
+    /// Unlike [`ProviderRequest::Get`], this should not affect the eviction
+    /// order of the provider.
+    Contains(Req),
+    /// Return how long the entry for a similar request has left before it
+    /// expires
+    ///
+    /// Providers should return [`ProviderResponse::Ttl`], or
+    /// [`ProviderResponse::NotFound`] if there is no such entry, if it never
+    /// expires, or if they don't track expiration times.
+    Ttl(Req),
+    /// Return the response for a similar request if there is one, or insert
+    /// the given response otherwise
+    ///
+    /// The lookup and the insertion should happen atomically, so that only
+    /// one of many concurrent requests inserts its response. Providers should
+    /// return [`ProviderResponse::Found`] with the existing response, or
+    /// [`ProviderResponse::Inserted`] if they stored the given one. Stale and
+    /// negative entries are replaced. The inserted entry uses the default
+    /// expiration policy of the provider.
+    ///
+    /// This is also how to insert a response only if there is none. When
+    /// concurrent callers race on the same key, only the first value sticks:
+    /// every other caller gets [`ProviderResponse::Found`] with that value,
+    /// and theirs is dropped. The response variant tells whether the given
+    /// value was inserted.
+    ///
+    /// Providers that cannot do this atomically should return
+    /// [`ProviderResponse::NotFound`].
+    #[doc(alias = "InsertIfAbsent")]
+    GetOrInsert(Req, Res),
+    /// Check if the provider has similar requests for multiple keys at once
+    ///
+    /// Providers should return [`ProviderResponse::Many`] with one item per
+    /// request, paired with its key, in the order of the request. Stale
+    /// entries are returned, while negative entries are returned as `None`.
+    ///
+    /// Providers that don't support batch lookups should return
+    /// [`ProviderResponse::NotFound`].
+    GetMany(Vec<Req>),
+    /// Insert responses for multiple requests at once
+    ///
+    /// Each entry has the same meaning as in [`ProviderRequest::Insert`].
+    /// Providers should return [`ProviderResponse::Many`] with the inserted
+    /// responses, paired with their keys, in the order of the request.
+    ///
+    /// Providers that don't support batch inserts should return
+    /// [`ProviderResponse::NotFound`] without inserting anything, so callers
+    /// can fall back to individual inserts.
+    InsertMany(Vec<(Req, Res, Option<Duration>)>),
+    /// Return the number of entries, capacity and eviction count of the
+    /// provider
+    ///
+    /// Providers should return [`ProviderResponse::Stats`]. Providers that
+    /// don't track these should return [`ProviderResponse::NotFound`].
+    Stats,
+    /// Return how long ago the entry for a similar request was inserted
+    ///
+    /// Providers should return [`ProviderResponse::Age`], or
+    /// [`ProviderResponse::NotFound`] if there is no such entry, if it has
+    /// expired, or if they don't track insertion times. See
+    /// [`CacheLayer::with_max_age`].
+    Age(Req),
+}
+
+/// Responses sent by the cache provider
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProviderResponse<Req, Res> {
     /// The cache provider found a similar request
     Found(Res),
+    /// The cache provider found a similar request, but the entry is past its
+    /// freshness deadline
+    ///
+    /// Returned by providers that keep entries for a while after they expire.
+    /// See [`CacheLayer::stale_while_revalidate`].
+    FoundStale(Res),
+    /// The cache provider found a negative entry for a similar request
+    FoundNegative,
     /// The cache provider did not find a similar request
     NotFound,
+    /// The cache provider removed all its entries
+    Cleared,
+    /// The cache provider removed the entry for a similar request
+    Removed,
+    /// Whether the cache provider has an entry for a similar request
+    Present(bool),
+    /// Remaining and original time-to-live of the entry for a similar
+    /// request
+    Ttl(Duration, Duration),
+    /// The cache provider inserted the given response, as there was no
+    /// response for a similar request
+    ///
+    /// See [`ProviderRequest::GetOrInsert`].
+    Inserted(Res),
+    /// Responses for multiple requests, each paired with its key, with `None`
+    /// for requests that weren't found
+    ///
+    /// Callers can match items with their requests through the keys, without
+    /// relying on their order, such as when batches are written back in the
+    /// background.
+    ///
+    /// See [`ProviderRequest::GetMany`] and [`ProviderRequest::InsertMany`].
+    Many(Vec<(Req, Option<Res>)>),
+    /// Statistics of the cache provider
+    ///
+    /// See [`ProviderRequest::Stats`].
+    Stats {
+        /// Number of entries currently stored
+        len: usize,
+        /// Maximum number of entries
+        capacity: usize,
+        /// Number of entries evicted to make room for others
+        evictions: u64,
+    },
+    /// Time since the entry for a similar request was inserted
+    ///
+    /// See [`ProviderRequest::Age`].
+    Age(Duration),
+}
+
+/// Error returned by the [`CacheService`]
+///
+/// Errors can come from both the cache provider or the inner service, and
+/// keep their original type. For providers that cannot fail, such as
+/// [`lru::LruProvider`], `P` is [`Infallible`] and
+/// [`CacheError::into_service_error`] can be used to get the inner service
+/// error directly.
+#[derive(Debug)]
+pub enum CacheError<P, S> {
+    /// Error generated by the cache provider
+    ProviderError(P),
+    /// Error generated by the inner service
+    ServiceError(S),
+}
+
+impl<S> CacheError<Infallible, S> {
+    /// Return the inner service error
+    ///
+    /// As the cache provider cannot fail, the error must come from the inner
+    /// service.
+    pub fn into_service_error(self) -> S {
+        match self {
+            CacheError::ProviderError(e) => match e {},
+            CacheError::ServiceError(e) => e,
+        }
+    }
+}
+
+impl<P, S> error::Error for CacheError<P, S>
+where
+    P: fmt::Debug + fmt::Display,
+    S: fmt::Debug + fmt::Display,
+{
 }
 
-/// Error returned by the [`CacheLayer`]
+impl<P, S> fmt::Display for CacheError<P, S>
+where
+    P: fmt::Display,
+    S: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CacheError::ProviderError(e) => write!(f, "provider error: {}", e),
+            CacheError::ServiceError(e) => write!(f, "service error: {}", e),
+        }
+    }
+}
+
+/// Type-erased error
 ///
-/// As errors can come from both the cache provider, the inner service, or
-/// the layer itself, this uses a custom enum to propagate errors.
+/// Any [`CacheError`] whose provider and service errors can be boxed can be
+/// converted into this type, which is convenient when the concrete error
+/// types don't matter.
 #[derive(Debug)]
 pub enum Error {
     /// Error generated by the cache provider
@@ -285,8 +1968,25 @@ impl fmt::Display for Error {
     }
 }
 
-type CacheFuture<'a, R, S> =
-    Pin<Box<dyn Future<Output = Result<<S as Service<R>>::Response, Error>> + Send + 'a>>;
+impl<P, S> From<CacheError<P, S>> for Error
+where
+    P: Into<Box<dyn error::Error + Send + Sync>>,
+    S: Into<Box<dyn error::Error + Send + Sync>>,
+{
+    fn from(e: CacheError<P, S>) -> Self {
+        match e {
+            CacheError::ProviderError(e) => Error::ProviderError(e.into()),
+            CacheError::ServiceError(e) => Error::ServiceError(e.into()),
+        }
+    }
+}
+
+/// Future returned by a [`CacheService`]
+///
+/// This is an `async` block, which can only be named by boxing it until
+/// `impl Trait` is stable in associated types. It is allocated once per
+/// request, whatever the transformer.
+type CacheFuture<'a, T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + Send + 'a>>;
 
 #[cfg(test)]
 mod tests {
@@ -294,7 +1994,11 @@ mod tests {
     use std::{
         collections::HashMap,
         future::ready,
-        sync::{Arc, Mutex},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
     };
     use tower::{service_fn, Service, ServiceBuilder};
 
@@ -310,7 +2014,7 @@ mod tests {
     where
         R: Eq + std::hash::Hash + Clone + Send + 'static,
     {
-        type Response = ProviderResponse<R>;
+        type Response = ProviderResponse<R, R>;
         type Error = Error;
         type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
 
@@ -324,14 +2028,159 @@ mod tests {
                     Some(res) => Ok(ProviderResponse::Found(res.clone())),
                     None => Ok(ProviderResponse::NotFound),
                 },
-                ProviderRequest::Insert(req, res) => {
+                ProviderRequest::Insert(req, res, _) => {
                     self.cache.lock().unwrap().insert(req, res.clone());
                     Ok(ProviderResponse::Found(res))
                 }
+                ProviderRequest::InsertNegative(_, _) => Ok(ProviderResponse::NotFound),
+                ProviderRequest::Clear => {
+                    self.cache.lock().unwrap().clear();
+                    Ok(ProviderResponse::Cleared)
+                }
+                ProviderRequest::Remove(req) => match self.cache.lock().unwrap().remove(&req) {
+                    Some(_) => Ok(ProviderResponse::Removed),
+                    None => Ok(ProviderResponse::NotFound),
+                },
+                ProviderRequest::Contains(req) => Ok(ProviderResponse::Present(
+                    self.cache.lock().unwrap().contains_key(&req),
+                )),
+                ProviderRequest::Ttl(_) => Ok(ProviderResponse::NotFound),
+                ProviderRequest::GetOrInsert(_, _) => Ok(ProviderResponse::NotFound),
+                ProviderRequest::GetMany(_) => Ok(ProviderResponse::NotFound),
+                ProviderRequest::InsertMany(_) => Ok(ProviderResponse::NotFound),
+                ProviderRequest::Stats => Ok(ProviderResponse::NotFound),
+                ProviderRequest::Age(_) => Ok(ProviderResponse::NotFound),
+            }))
+        }
+    }
+
+    /// Service that acquires a permit when it is ready, and requires one to
+    /// be called
+    ///
+    /// Clones start without a permit, like
+    /// `tower::limit::ConcurrencyLimit`.
+    #[derive(Debug, Default)]
+    struct PermitService {
+        acquired: Arc<AtomicUsize>,
+        permit: bool,
+    }
+
+    impl Clone for PermitService {
+        fn clone(&self) -> Self {
+            Self {
+                acquired: self.acquired.clone(),
+                permit: false,
+            }
+        }
+    }
+
+    impl Service<String> for PermitService {
+        type Response = String;
+        type Error = Error;
+        type Future = std::future::Ready<Result<String, Error>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            if !self.permit {
+                self.acquired.fetch_add(1, Ordering::SeqCst);
+                self.permit = true;
+            }
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: String) -> Self::Future {
+            assert!(std::mem::take(&mut self.permit), "called without a permit");
+            ready(Ok(req.to_uppercase()))
+        }
+    }
+
+    /// Provider that fails on `Get` and/or `Insert`
+    #[derive(Clone, Debug)]
+    struct FailingCache {
+        fail_ready: bool,
+        fail_get: bool,
+        fail_insert: bool,
+    }
+
+    impl<R> Service<ProviderRequest<R, R>> for FailingCache
+    where
+        R: Send + 'static,
+    {
+        type Response = ProviderResponse<R, R>;
+        type Error = &'static str;
+        type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            if self.fail_ready {
+                return Poll::Ready(Err("not ready"));
+            }
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: ProviderRequest<R, R>) -> Self::Future {
+            assert!(!self.fail_ready, "called without being ready");
+            Box::pin(ready(match request {
+                ProviderRequest::Get(_) if self.fail_get => Err("get failed"),
+                ProviderRequest::Get(_) => Ok(ProviderResponse::NotFound),
+                ProviderRequest::Insert(_, _, _) if self.fail_insert => Err("insert failed"),
+                ProviderRequest::Insert(_, res, _) => Ok(ProviderResponse::Found(res)),
+                ProviderRequest::InsertNegative(_, _) if self.fail_insert => Err("insert failed"),
+                ProviderRequest::InsertNegative(_, _) => Ok(ProviderResponse::NotFound),
+                ProviderRequest::Clear => Ok(ProviderResponse::Cleared),
+                ProviderRequest::Remove(_) => Ok(ProviderResponse::NotFound),
+                ProviderRequest::Contains(_) => Ok(ProviderResponse::Present(false)),
+                ProviderRequest::Ttl(_) => Ok(ProviderResponse::NotFound),
+                ProviderRequest::GetOrInsert(_, _) => Ok(ProviderResponse::NotFound),
+                ProviderRequest::GetMany(_) => Ok(ProviderResponse::NotFound),
+                ProviderRequest::InsertMany(_) => Ok(ProviderResponse::NotFound),
+                ProviderRequest::Stats => Ok(ProviderResponse::NotFound),
+                ProviderRequest::Age(_) => Ok(ProviderResponse::NotFound),
             }))
         }
     }
 
+    /// Service that isn't ready for the first `pending` polls, counting its
+    /// calls
+    #[derive(Clone, Debug)]
+    struct Backpressure<S> {
+        inner: S,
+        pending: Arc<AtomicUsize>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl<S> Backpressure<S> {
+        fn new(inner: S, pending: usize) -> Self {
+            Self {
+                inner,
+                pending: Arc::new(AtomicUsize::new(pending)),
+                calls: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+    }
+
+    impl<S, Req> Service<Req> for Backpressure<S>
+    where
+        S: Service<Req>,
+    {
+        type Response = S::Response;
+        type Error = S::Error;
+        type Future = S::Future;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            let pending = self.pending.load(Ordering::SeqCst);
+            if pending > 0 {
+                self.pending.store(pending - 1, Ordering::SeqCst);
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            self.inner.poll_ready(cx)
+        }
+
+        fn call(&mut self, req: Req) -> Self::Future {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.inner.call(req)
+        }
+    }
+
     async fn service(req: String) -> Result<String, Error> {
         Ok(req.to_uppercase())
     }
@@ -409,4 +2258,1970 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_async_transformer() -> Result<(), Error> {
+        let cache = SimpleCache::default();
+        let cache_layer =
+            CacheLayer::new(cache.clone()).with_async_transformer(|req: String| async move {
+                // Simulate an asynchronous lookup before producing the key
+                tokio::task::yield_now().await;
+                req.len()
+            });
+
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .service(service_fn(service_num));
+
+        let res = service.call(String::from("Hello")).await?;
+        assert_eq!(res, 10);
+        assert_eq!(cache.cache.lock().unwrap().get(&5), Some(&10));
+
+        // Same key, so this is served from the cache
+        let res = service.call(String::from("Salut")).await?;
+        assert_eq!(res, 10);
+        assert_eq!(cache.cache.lock().unwrap().len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_try_transformer_bypass() -> Result<(), Error> {
+        // Never cache requests for private resources
+        fn transform(req: String) -> Option<String> {
+            (!req.starts_with("private")).then_some(req)
+        }
+
+        let cache = SimpleCache::default();
+        let cache_layer = CacheLayer::new(cache.clone()).with_try_transformer(transform);
+        let stats = cache_layer.stats_handle();
+
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .service(service_fn(service));
+
+        let res = service.call(String::from("private-a")).await?;
+        assert_eq!(res, "PRIVATE-A");
+        assert_eq!(cache.cache.lock().unwrap().len(), 0);
+
+        let res = service.call(String::from("public-a")).await?;
+        assert_eq!(res, "PUBLIC-A");
+        assert_eq!(
+            cache
+                .cache
+                .lock()
+                .unwrap()
+                .get("public-a")
+                .map(String::as_str),
+            Some("PUBLIC-A")
+        );
+
+        // Bypassed requests count neither as hits nor as misses
+        assert_eq!(stats.stats().misses, 1);
+        assert_eq!(stats.stats().hits, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ref_transformer() -> Result<(), Error> {
+        // Request type that can't be cloned
+        struct Request {
+            path: String,
+        }
+
+        let cache = SimpleCache::default();
+        let cache_layer =
+            CacheLayer::new(cache.clone()).with_ref_transformer(|req: &Request| req.path.clone());
+
+        let mut service = ServiceBuilder::new().layer(cache_layer).service(service_fn(
+            |req: Request| async move {
+                // The inner service takes ownership of the request
+                Ok::<_, Error>(req.path.to_uppercase())
+            },
+        ));
+
+        let res = service
+            .call(Request {
+                path: String::from("hello"),
+            })
+            .await?;
+        assert_eq!(res, "HELLO");
+        assert_eq!(
+            cache.cache.lock().unwrap().get("hello").cloned(),
+            Some(String::from("HELLO"))
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "lru")]
+    #[tokio::test]
+    async fn test_infallible_provider_error() {
+        let cache_layer = CacheLayer::new(lru::LruProvider::new::<String, String>(10));
+
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .service(service_fn(|_: String| ready(Err::<String, _>("failed"))));
+
+        let err = service.call(String::from("Hello")).await.unwrap_err();
+        assert_eq!(err.into_service_error(), "failed");
+    }
+
+    #[tokio::test]
+    async fn test_provider_error_strict() {
+        let cache = FailingCache {
+            fail_ready: false,
+            fail_get: true,
+            fail_insert: true,
+        };
+        let mut service = ServiceBuilder::new()
+            .layer(CacheLayer::new(cache))
+            .service(service_fn(service));
+
+        let err = service.call(String::from("Hello")).await.unwrap_err();
+        assert!(matches!(err, CacheError::ProviderError("get failed")));
+    }
+
+    #[tokio::test]
+    async fn test_provider_error_fallback_get() -> Result<(), Error> {
+        let cache = FailingCache {
+            fail_ready: false,
+            fail_get: true,
+            fail_insert: true,
+        };
+        let mut service = ServiceBuilder::new()
+            .layer(CacheLayer::new(cache).fallback_on_provider_error(true))
+            .service(service_fn(service));
+
+        let res = service.call(String::from("Hello")).await?;
+        assert_eq!(res, String::from("HELLO"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_provider_error_fallback_insert() -> Result<(), Error> {
+        let cache = FailingCache {
+            fail_ready: false,
+            fail_get: false,
+            fail_insert: true,
+        };
+
+        let mut strict = ServiceBuilder::new()
+            .layer(CacheLayer::new(cache.clone()))
+            .service(service_fn(service));
+        let err = strict.call(String::from("Hello")).await.unwrap_err();
+        assert!(matches!(err, CacheError::ProviderError("insert failed")));
+
+        let mut fallback = ServiceBuilder::new()
+            .layer(CacheLayer::new(cache).fallback_on_provider_error(true))
+            .service(service_fn(service));
+        let res = fallback.call(String::from("Hello")).await?;
+        assert_eq!(res, String::from("HELLO"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_provider_error_fallback_ready() -> Result<(), Error> {
+        let cache = FailingCache {
+            fail_ready: true,
+            fail_get: false,
+            fail_insert: false,
+        };
+
+        let mut strict = ServiceBuilder::new()
+            .layer(CacheLayer::new(cache.clone()))
+            .service(service_fn(service));
+        let res = strict.ready().await.map(|_| ());
+        assert!(matches!(res, Err(CacheError::ProviderError("not ready"))));
+
+        let mut fallback = ServiceBuilder::new()
+            .layer(CacheLayer::new(cache).fallback_on_provider_error(true))
+            .service(service_fn(service));
+        let res = fallback.ready().await?.call(String::from("Hello")).await?;
+        assert_eq!(res, String::from("HELLO"));
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_coalesce() -> Result<(), Error> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let slow_service = {
+            let calls = calls.clone();
+            service_fn(move |req: String| {
+                let calls = calls.clone();
+                async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    Ok::<_, Error>(req.to_uppercase())
+                }
+            })
+        };
+
+        let cache = SimpleCache::default();
+        let cache_layer = CacheLayer::new(cache.clone()).coalesce(true);
+        let inflight = cache_layer.inflight.clone();
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .service(slow_service);
+
+        let handles: Vec<_> = (0..20)
+            .map(|_| tokio::spawn(service.call(String::from("Hello"))))
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.await.unwrap()?, String::from("HELLO"));
+        }
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(inflight.len(), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_coalesce_sharded() -> Result<(), Error> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let slow_service = {
+            let calls = calls.clone();
+            service_fn(move |req: String| {
+                let calls = calls.clone();
+                async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    Ok::<_, Error>(req.to_uppercase())
+                }
+            })
+        };
+
+        let cache_layer = CacheLayer::builder()
+            .provider(SimpleCache::default())
+            .coalesce_sharded(8)
+            .build()
+            .unwrap();
+        let inflight = cache_layer.inflight.clone();
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .service(slow_service);
+
+        let handles: Vec<_> = (0..20)
+            .map(|i| tokio::spawn(service.call(format!("hello {}", i % 4))))
+            .collect();
+        for handle in handles {
+            assert!(handle.await.unwrap()?.starts_with("HELLO"));
+        }
+
+        // One call per distinct key
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+        assert_eq!(inflight.len(), 0);
+
+        Ok(())
+    }
+
+    /// Service taking 50ms to answer, failing its first `failures` calls
+    fn slow_service(
+        calls: Arc<AtomicUsize>,
+        failures: usize,
+    ) -> impl Service<String, Response = String, Error = Error, Future = impl Send> + Clone {
+        service_fn(move |req: String| {
+            let call = calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                match call < failures {
+                    true => Err(Error::ServiceError("unavailable".into())),
+                    false => Ok(req.to_uppercase()),
+                }
+            }
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_coalesce_uncacheable() -> Result<(), Error> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut service = ServiceBuilder::new()
+            .layer(
+                CacheLayer::new(SimpleCache::default())
+                    .coalesce(true)
+                    .cache_if(|_: &String| false),
+            )
+            .service(slow_service(calls.clone(), 0));
+
+        let handles: Vec<_> = (0..20)
+            .map(|_| tokio::spawn(service.call(String::from("Hello"))))
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.await.unwrap()?, String::from("HELLO"));
+        }
+        // The response wasn't stored, but was shared with all requests
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Nothing is shared with requests arriving later
+        service.call(String::from("Hello")).await?;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_coalesce_not_stored() -> Result<(), Error> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut service = ServiceBuilder::new()
+            .layer(CacheLayer::new(noop::NoopProvider::new::<String, String>()).coalesce(true))
+            .service(slow_service(calls.clone(), 0));
+
+        let handles: Vec<_> = (0..20)
+            .map(|_| tokio::spawn(service.call(String::from("Hello"))))
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.await.unwrap()?, String::from("HELLO"));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_coalesce_leader_error() -> Result<(), Error> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut service = ServiceBuilder::new()
+            .layer(CacheLayer::new(SimpleCache::default()).coalesce(true))
+            .service(slow_service(calls.clone(), 1));
+
+        let handles: Vec<_> = (0..20)
+            .map(|_| tokio::spawn(service.call(String::from("Hello"))))
+            .collect();
+        let mut errors = 0;
+        for handle in handles {
+            match handle.await.unwrap() {
+                Ok(res) => assert_eq!(res, "HELLO"),
+                Err(_) => errors += 1,
+            }
+        }
+        // Only the leader failed, and a single follower took over
+        assert_eq!(errors, 1);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        Ok(())
+    }
+
+    /// Provider that takes `delay` to insert entries
+    #[derive(Clone, Default)]
+    struct SlowInsert {
+        cache: SimpleCache<String>,
+        delay: Duration,
+    }
+
+    impl Service<ProviderRequest<String, String>> for SlowInsert {
+        type Response = ProviderResponse<String, String>;
+        type Error = Error;
+        type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: ProviderRequest<String, String>) -> Self::Future {
+            let delay = match request {
+                ProviderRequest::Insert(_, _, _) => self.delay,
+                _ => Duration::ZERO,
+            };
+            let fut = self.cache.call(request);
+            Box::pin(async move {
+                tokio::time::sleep(delay).await;
+                fut.await
+            })
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_write_back() -> Result<(), Error> {
+        let provider = SlowInsert {
+            delay: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let entries = provider.cache.cache.clone();
+        let mut service = ServiceBuilder::new()
+            .layer(CacheLayer::new(provider).write_back(true))
+            .service(service_fn(service));
+
+        // The response is returned before the provider stores it.
+        let res = tokio::time::timeout(
+            Duration::from_millis(50),
+            service.ready().await?.call(String::from("hello")),
+        )
+        .await
+        .expect("the insert shouldn't delay the response")?;
+        assert_eq!(res, "HELLO");
+        assert!(entries.lock().unwrap().is_empty());
+        assert_eq!(service.stats().inserts, 0);
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(
+            entries.lock().unwrap().get("hello").map(String::as_str),
+            Some("HELLO")
+        );
+        assert_eq!(service.stats().inserts, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_write_through() -> Result<(), Error> {
+        let provider = SlowInsert {
+            delay: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let entries = provider.cache.cache.clone();
+        let mut service = ServiceBuilder::new()
+            .layer(CacheLayer::new(provider))
+            .service(service_fn(service));
+
+        // By default, the response is only returned once it is stored.
+        let res = service.ready().await?.call(String::from("hello")).await?;
+        assert_eq!(res, "HELLO");
+        assert_eq!(entries.lock().unwrap().len(), 1);
+
+        Ok(())
+    }
+
+    /// Provider counting the calls that weren't preceded by readiness
+    #[derive(Default)]
+    struct ReadyCheck {
+        cache: SimpleCache<String>,
+        ready: bool,
+        unready: Arc<AtomicUsize>,
+    }
+
+    // Clones haven't been driven to readiness.
+    impl Clone for ReadyCheck {
+        fn clone(&self) -> Self {
+            Self {
+                cache: self.cache.clone(),
+                ready: false,
+                unready: self.unready.clone(),
+            }
+        }
+    }
+
+    impl Service<ProviderRequest<String, String>> for ReadyCheck {
+        type Response = ProviderResponse<String, String>;
+        type Error = Error;
+        type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.ready = true;
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: ProviderRequest<String, String>) -> Self::Future {
+            if !std::mem::take(&mut self.ready) {
+                self.unready.fetch_add(1, Ordering::SeqCst);
+            }
+            self.cache.call(request)
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_provider_ready() -> Result<(), Error> {
+        let provider = ReadyCheck::default();
+        let unready = provider.unready.clone();
+        let cache_layer = CacheLayer::new(provider)
+            .with_max_age(|_: &String| Some(Duration::from_secs(60)))
+            .coalesce(true);
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .service(tower::service_fn(|req: String| async move {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Ok::<_, Error>(req.to_uppercase())
+            }));
+
+        // Misses insert the response, followers share it, and lookups check
+        // the age of the entry first.
+        let mut handles = Vec::new();
+        for _ in 0..5 {
+            let fut = service.ready().await?.call(String::from("hello"));
+            handles.push(tokio::spawn(fut));
+        }
+        for handle in handles {
+            assert_eq!(handle.await.unwrap()?, "HELLO");
+        }
+        let res = service.ready().await?.call(String::from("hello")).await?;
+        assert_eq!(res, "HELLO");
+
+        assert_eq!(unready.load(Ordering::SeqCst), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lazy_inner_ready() -> Result<(), Error> {
+        let inner = PermitService::default();
+        let acquired = inner.acquired.clone();
+        let cache_layer = CacheLayer::new(SimpleCache::default()).lazy_inner_ready(true);
+        let mut service = ServiceBuilder::new().layer(cache_layer).service(inner);
+
+        // The permit is only acquired once the request is known to be a miss.
+        let fut = service.ready().await?.call(String::from("hello"));
+        assert_eq!(acquired.load(Ordering::SeqCst), 0);
+        assert_eq!(fut.await?, "HELLO");
+        assert_eq!(acquired.load(Ordering::SeqCst), 1);
+
+        // Cache hits never acquire a permit.
+        for _ in 0..3 {
+            let res = service.ready().await?.call(String::from("hello")).await?;
+            assert_eq!(res, "HELLO");
+        }
+        assert_eq!(acquired.load(Ordering::SeqCst), 1);
+
+        // Cache misses still go through readiness.
+        let res = service.ready().await?.call(String::from("world")).await?;
+        assert_eq!(res, "WORLD");
+        assert_eq!(acquired.load(Ordering::SeqCst), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_eager_inner_ready() -> Result<(), Error> {
+        let inner = PermitService::default();
+        let acquired = inner.acquired.clone();
+        let cache_layer = CacheLayer::new(SimpleCache::default());
+        let mut service = ServiceBuilder::new().layer(cache_layer).service(inner);
+
+        for _ in 0..3 {
+            let res = service.ready().await?.call(String::from("hello")).await?;
+            assert_eq!(res, "HELLO");
+        }
+        // By default, each request acquires a permit, even on a cache hit.
+        assert_eq!(acquired.load(Ordering::SeqCst), 3);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "lru")]
+    #[tokio::test(start_paused = true)]
+    async fn test_cache_negative() -> Result<(), Error> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let lookup_service = {
+            let calls = calls.clone();
+            service_fn(move |req: String| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                ready(Ok::<_, Error>((req == "Hello").then(|| req.to_uppercase())))
+            })
+        };
+
+        let cache_layer = CacheLayer::new(lru::LruProvider::new::<String, Option<String>>(10))
+            .cache_negative(Duration::from_millis(50));
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .service(lookup_service);
+
+        assert_eq!(service.call(String::from("Missing")).await?, None);
+        assert_eq!(service.call(String::from("Missing")).await?, None);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // The negative entry expires with its own TTL.
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(service.call(String::from("Missing")).await?, None);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Values are still cached normally.
+        assert_eq!(
+            service.call(String::from("Hello")).await?,
+            Some(String::from("HELLO"))
+        );
+        assert_eq!(
+            service.call(String::from("Hello")).await?,
+            Some(String::from("HELLO"))
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cache_errors() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let failing_service = {
+            let calls = calls.clone();
+            service_fn(move |req: String| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                ready(match req.as_str() {
+                    "down" => Err("backend down"),
+                    _ => Ok(req.to_uppercase()),
+                })
+            })
+        };
+
+        let provider = map::MapProvider::new::<String, Result<String, &str>>();
+        let cache_layer = CacheLayer::new(provider).cache_errors(Duration::from_millis(50));
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .service(failing_service);
+
+        for _ in 0..3 {
+            let res = service.call(String::from("down")).await;
+            assert_eq!(res.map_err(|e| e.into_service_error()), Err("backend down"));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // The backend is called again once the error expires.
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let res = service.call(String::from("down")).await;
+        assert_eq!(res.map_err(|e| e.into_service_error()), Err("backend down"));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Successful responses are still cached normally.
+        for _ in 0..2 {
+            let res = service.call(String::from("up")).await;
+            assert_eq!(
+                res.map_err(|e| e.into_service_error()),
+                Ok(String::from("UP"))
+            );
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(service.get_ref().stats().hits, 3);
+    }
+
+    #[tokio::test]
+    async fn test_mark_cached() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let upper_service = {
+            let calls = calls.clone();
+            service_fn(move |req: String| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                ready(Ok::<_, Infallible>(req.to_uppercase()))
+            })
+        };
+
+        let provider = map::MapProvider::new::<String, String>();
+        let cache_layer = CacheLayer::new(provider).mark_cached();
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .service(upper_service);
+
+        let res = service.call(String::from("hello")).await;
+        assert_eq!(
+            res.ok(),
+            Some(Cached {
+                value: String::from("HELLO"),
+                from_cache: false,
+                outcome: CacheOutcome::Fresh,
+            })
+        );
+
+        let res = service.call(String::from("hello")).await;
+        assert_eq!(
+            res.ok(),
+            Some(Cached {
+                value: String::from("HELLO"),
+                from_cache: true,
+                outcome: CacheOutcome::Hit,
+            })
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_mark_cached_stale() -> Result<(), Error> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let provider =
+            map::MapProvider::new::<String, String>().stale_window(Duration::from_secs(60));
+        let cache_layer = CacheLayer::new(provider)
+            .with_ttl_policy(|_: &String| Some(Duration::from_millis(50)))
+            .stale_while_revalidate(true)
+            .mark_cached();
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .service(versioned_service(calls.clone()));
+
+        // Cold miss, then warm hit
+        let res = service.call(String::from("a")).await?;
+        assert_eq!(res, Cached::new("a-1".to_string(), CacheOutcome::Fresh));
+        let res = service.call(String::from("a")).await?;
+        assert_eq!(res, Cached::new("a-1".to_string(), CacheOutcome::Hit));
+
+        // The expired entry is served stale while it is refreshed
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let res = service.call(String::from("a")).await?;
+        assert_eq!(res, Cached::new("a-1".to_string(), CacheOutcome::HitStale));
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        let res = service.call(String::from("a")).await?;
+        assert_eq!(res, Cached::new("a-2".to_string(), CacheOutcome::Hit));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cache_errors_with_policies() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let lookup_service = {
+            let calls = calls.clone();
+            service_fn(move |req: String| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                ready(match req.as_str() {
+                    "down" => Err(String::from("backend down")),
+                    "missing" => Ok(None),
+                    _ => Ok(Some(req.to_uppercase())),
+                })
+            })
+        };
+
+        let provider = map::MapProvider::new::<String, Result<Option<String>, String>>();
+        let cache_layer = CacheLayer::new(provider)
+            .cache_negative(Duration::from_secs(1))
+            .cache_if(|res: &Option<String>| res.as_deref() != Some("SKIP"))
+            .cache_errors(Duration::from_secs(1));
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .service(lookup_service);
+
+        for req in ["down", "missing", "skip", "down", "missing", "skip"] {
+            let _ = service.call(String::from(req)).await;
+        }
+        // Only the response rejected by the predicate isn't cached.
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+        let res = service.call(String::from("missing")).await;
+        assert_eq!(res.map_err(|e| e.into_service_error()), Ok(None));
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Report {
+        items: Vec<u32>,
+        total: u32,
+    }
+
+    async fn report_service(req: String) -> Result<Report, &'static str> {
+        let items: Vec<u32> = req.split(',').filter_map(|s| s.parse().ok()).collect();
+        match items.is_empty() {
+            true => Err("no items"),
+            false => Ok(Report {
+                total: items.iter().sum(),
+                items,
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_distinct_types() -> Result<(), Infallible> {
+        #[derive(Clone)]
+        struct Lookup {
+            id: u64,
+            name: &'static str,
+        }
+
+        #[derive(Debug, PartialEq)]
+        struct Profile {
+            id: u64,
+            name: String,
+        }
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let inner = {
+            let calls = calls.clone();
+            service_fn(move |req: Lookup| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                ready(Ok::<_, Infallible>(Profile {
+                    id: req.id,
+                    name: req.name.to_uppercase(),
+                }))
+            })
+        };
+
+        // The request, key, response and stored value all have different
+        // types.
+        let provider = map::MapProvider::new::<u64, String>();
+        let cache_layer = CacheLayer::new(provider.clone())
+            .with_transform(|req: Lookup| req.id)
+            .with_value_transform(
+                |res: &Profile| res.name.clone(),
+                |name: String| Profile { id: 0, name },
+            );
+        let mut service = ServiceBuilder::new().layer(cache_layer).service(inner);
+
+        let res = service.call(Lookup { id: 1, name: "a" }).await;
+        assert_eq!(
+            res.ok(),
+            Some(Profile {
+                id: 1,
+                name: String::from("A")
+            })
+        );
+        let res = service.call(Lookup { id: 1, name: "b" }).await;
+        assert_eq!(
+            res.ok(),
+            Some(Profile {
+                id: 0,
+                name: String::from("A")
+            })
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let res = provider.oneshot(ProviderRequest::Get(1)).await?;
+        assert_eq!(res, ProviderResponse::Found(String::from("A")));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_value_transform() -> Result<(), &'static str> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let inner = {
+            let calls = calls.clone();
+            service_fn(move |req: String| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                report_service(req)
+            })
+        };
+
+        // Only the total is stored, not the items
+        let provider = map::MapProvider::new::<String, u32>();
+        let cache_layer = CacheLayer::new(provider.clone()).with_value_transform(
+            |res: &Report| res.total,
+            |total: u32| Report {
+                items: Vec::new(),
+                total,
+            },
+        );
+        let mut service = ServiceBuilder::new().layer(cache_layer).service(inner);
+
+        // A miss returns the response of the inner service
+        let res = service
+            .call(String::from("1,2,3"))
+            .await
+            .map_err(|e| e.into_service_error())?;
+        assert_eq!(
+            res,
+            Report {
+                items: vec![1, 2, 3],
+                total: 6
+            }
+        );
+
+        // A hit rebuilds the response from the stored value
+        let res = service
+            .call(String::from("1,2,3"))
+            .await
+            .map_err(|e| e.into_service_error())?;
+        assert_eq!(
+            res,
+            Report {
+                items: vec![],
+                total: 6
+            }
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let res = provider
+            .clone()
+            .oneshot(ProviderRequest::Get(String::from("1,2,3")))
+            .await
+            .unwrap();
+        assert_eq!(res, ProviderResponse::Found(6));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_value_transform_errors() {
+        let provider = map::MapProvider::new::<String, Result<u32, &str>>();
+        let cache_layer = CacheLayer::new(provider)
+            .with_value_transform(
+                |res: &Report| res.total,
+                |total: u32| Report {
+                    items: Vec::new(),
+                    total,
+                },
+            )
+            .cache_errors(Duration::from_secs(1));
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .service(service_fn(report_service));
+
+        for _ in 0..2 {
+            let res = service.call(String::new()).await;
+            assert_eq!(res.map_err(|e| e.into_service_error()), Err("no items"));
+        }
+        service.call(String::from("4")).await.unwrap();
+        let res = service.call(String::from("4")).await;
+        assert_eq!(
+            res.map_err(|e| e.into_service_error()),
+            Ok(Report {
+                items: vec![],
+                total: 4
+            })
+        );
+        assert_eq!(service.get_ref().stats().hits, 2);
+    }
+
+    #[tokio::test]
+    async fn test_value_loader() -> Result<(), &'static str> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let inner = {
+            let calls = calls.clone();
+            service_fn(move |req: String| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                report_service(req)
+            })
+        };
+
+        // Only the total is stored, and the items are parsed again from the
+        // request on a hit
+        let provider = map::MapProvider::new::<String, u32>();
+        let cache_layer = CacheLayer::new(provider.clone())
+            .with_transformer(|req: String| req.replace(' ', ""))
+            .with_value_loader(
+                |res: &Report| res.total,
+                |total: u32, req: &String| Report {
+                    items: req
+                        .split(',')
+                        .filter_map(|s| s.trim().parse().ok())
+                        .collect(),
+                    total,
+                },
+            );
+        let mut service = ServiceBuilder::new().layer(cache_layer).service(inner);
+
+        let res = service
+            .call(String::from("1,2,3"))
+            .await
+            .map_err(|e| e.into_service_error())?;
+        assert_eq!(res.items, vec![1, 2, 3]);
+
+        // Both requests share the same key, but the items come from the
+        // current request
+        let res = service
+            .call(String::from("1, 2, 3"))
+            .await
+            .map_err(|e| e.into_service_error())?;
+        assert_eq!(
+            res,
+            Report {
+                items: vec![1, 2, 3],
+                total: 6
+            }
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let res = provider
+            .clone()
+            .oneshot(ProviderRequest::Get(String::from("1,2,3")))
+            .await
+            .unwrap();
+        assert_eq!(res, ProviderResponse::Found(6));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_value_loader_marked() {
+        let provider = map::MapProvider::new::<String, u32>();
+        let cache_layer = CacheLayer::new(provider)
+            .with_value_loader(
+                |res: &Report| res.total,
+                |total: u32, req: &String| Report {
+                    items: req.split(',').filter_map(|s| s.parse().ok()).collect(),
+                    total,
+                },
+            )
+            .mark_cached();
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .service(service_fn(report_service));
+
+        let res = service.call(String::from("4,5")).await.unwrap();
+        assert_eq!(res.outcome, CacheOutcome::Fresh);
+        let res = service.call(String::from("4,5")).await.unwrap();
+        assert_eq!(res.outcome, CacheOutcome::Hit);
+        assert_eq!(res.value.items, vec![4, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_builder() -> Result<(), Error> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let lookup_service = {
+            let calls = calls.clone();
+            service_fn(move |req: String| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                ready(Ok::<_, Error>((req == "Hello").then(|| req.to_uppercase())))
+            })
+        };
+
+        let stats = StatsHandle::default();
+        let cache_layer = CacheLayer::builder()
+            .provider(map::MapProvider::new::<String, Option<String>>())
+            .transform(|req: String| req.to_lowercase())
+            .coalesce(true)
+            .cache_negative(Duration::from_secs(10))
+            .stats(stats.clone())
+            .build()
+            .unwrap();
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .service(lookup_service);
+
+        assert_eq!(
+            service.call(String::from("Hello")).await?,
+            Some(String::from("HELLO"))
+        );
+        // Same key after transformation
+        assert_eq!(
+            service.call(String::from("hello")).await?,
+            Some(String::from("HELLO"))
+        );
+        // Negative responses are cached
+        assert_eq!(service.call(String::from("Missing")).await?, None);
+        assert_eq!(service.call(String::from("Missing")).await?, None);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Statistics are recorded in the provided handle
+        assert_eq!(
+            stats.stats(),
+            CacheStats {
+                hits: 2,
+                misses: 2,
+                inserts: 2,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_invalid() {
+        let res = CacheLayer::builder().coalesce(true).build();
+        assert_eq!(res.err(), Some(BuildError::MissingProvider));
+
+        let res = CacheLayer::builder()
+            .provider(map::MapProvider::new::<String, Option<String>>())
+            .cache_negative(Duration::ZERO)
+            .build();
+        assert_eq!(res.err(), Some(BuildError::ZeroNegativeTtl));
+
+        let res = CacheLayer::builder()
+            .provider(map::MapProvider::new::<String, String>())
+            .refresh_ahead(1.5)
+            .build();
+        assert_eq!(res.err(), Some(BuildError::InvalidRefreshAhead));
+    }
+
+    #[tokio::test]
+    async fn test_cache_if() -> Result<(), Error> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let inner = {
+            let calls = calls.clone();
+            service_fn(move |req: String| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                ready(Ok::<_, Error>(req.trim().to_uppercase()))
+            })
+        };
+
+        let cache = SimpleCache::default();
+        let cache_layer = CacheLayer::new(cache.clone()).cache_if(|res: &String| !res.is_empty());
+        let mut service = ServiceBuilder::new().layer(cache_layer).service(inner);
+
+        // Empty responses are returned, but not cached
+        assert_eq!(service.call(String::from("  ")).await?, "");
+        assert_eq!(service.call(String::from("  ")).await?, "");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(cache.cache.lock().unwrap().len(), 0);
+
+        // Other responses are cached as usual
+        assert_eq!(service.call(String::from("hello")).await?, "HELLO");
+        assert_eq!(service.call(String::from("hello")).await?, "HELLO");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(cache.cache.lock().unwrap().len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ttl_policy() -> Result<(), Error> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let inner = {
+            let calls = calls.clone();
+            service_fn(move |req: String| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                // The inner service decides how long its response is valid
+                let max_age = if req == "short" { 50 } else { 150 };
+                ready(Ok::<_, Error>((max_age, req.to_uppercase())))
+            })
+        };
+
+        let cache_layer = CacheLayer::new(map::MapProvider::new::<String, (u64, String)>())
+            .with_ttl_policy(|res: &(u64, String)| Some(Duration::from_millis(res.0)));
+        let mut service = ServiceBuilder::new().layer(cache_layer).service(inner);
+
+        service.call(String::from("short")).await?;
+        service.call(String::from("long")).await?;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Only the short-lived entry has expired
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        service.call(String::from("short")).await?;
+        service.call(String::from("long")).await?;
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        service.call(String::from("long")).await?;
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ttl_jitter() -> Result<(), Error> {
+        let provider = map::MapProvider::new::<String, String>();
+        let cache_layer = CacheLayer::new(provider.clone())
+            .with_ttl_policy(|_: &String| Some(Duration::from_secs(60)))
+            .ttl_jitter(Duration::from_secs(10));
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .service(service_fn(service));
+
+        for i in 0..10 {
+            service.call(format!("key-{}", i)).await?;
+        }
+
+        let mut ttls = Vec::new();
+        for i in 0..10 {
+            let res = provider
+                .clone()
+                .oneshot(ProviderRequest::Ttl(format!("key-{}", i)))
+                .await;
+            match res {
+                Ok(ProviderResponse::Ttl(_, ttl)) => ttls.push(ttl),
+                _ => panic!("missing entry"),
+            }
+        }
+        assert!(ttls
+            .iter()
+            .all(|ttl| *ttl >= Duration::from_secs(50) && *ttl <= Duration::from_secs(70)));
+        // The entries don't expire at the same time
+        ttls.sort();
+        ttls.dedup();
+        assert!(ttls.len() > 1);
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_on_event() -> Result<(), Error> {
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<String>>);
+
+        impl CacheEventListener<String, String> for Recorder {
+            fn on_hit(&self, key: &String) {
+                self.0.lock().unwrap().push(format!("hit {}", key));
+            }
+
+            fn on_miss(&self, key: &String) {
+                self.0.lock().unwrap().push(format!("miss {}", key));
+            }
+
+            fn on_insert(&self, key: &String) {
+                self.0.lock().unwrap().push(format!("insert {}", key));
+            }
+        }
+
+        let recorder = Arc::new(Recorder::default());
+        let cache_layer =
+            CacheLayer::new(map::MapProvider::new::<String, String>()).on_event(recorder.clone());
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .service(service_fn(service));
+
+        service.call("a".to_string()).await?;
+        service.call("a".to_string()).await?;
+        service.call("b".to_string()).await?;
+
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            ["miss a", "insert a", "hit a", "miss b", "insert b"]
+        );
+
+        Ok(())
+    }
+
+    /// Inner service returning how many times it was called, after a short
+    /// delay
+    fn versioned_service(
+        calls: Arc<AtomicUsize>,
+    ) -> impl Service<String, Response = String, Error = Error, Future = impl Send> + Clone {
+        service_fn(move |req: String| {
+            let version = calls.fetch_add(1, Ordering::SeqCst) + 1;
+            async move {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Ok::<_, Error>(format!("{}-{}", req, version))
+            }
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stale_while_revalidate() -> Result<(), Error> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let provider =
+            map::MapProvider::new::<String, String>().stale_window(Duration::from_millis(200));
+        let cache_layer = CacheLayer::new(provider)
+            .with_ttl_policy(|_: &String| Some(Duration::from_millis(50)))
+            .stale_while_revalidate(true);
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .service(versioned_service(calls.clone()));
+
+        // Fresh entry
+        assert_eq!(service.call(String::from("a")).await?, "a-1");
+        assert_eq!(service.call(String::from("a")).await?, "a-1");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Stale entry: served immediately, refreshed once in the background
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(service.call(String::from("a")).await?, "a-1");
+        assert_eq!(service.call(String::from("a")).await?, "a-1");
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(service.call(String::from("a")).await?, "a-2");
+
+        // Past the stale window, the entry is a miss
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(service.call(String::from("a")).await?, "a-3");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stale_disabled() -> Result<(), Error> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let provider =
+            map::MapProvider::new::<String, String>().stale_window(Duration::from_millis(200));
+        let cache_layer =
+            CacheLayer::new(provider).with_ttl_policy(|_: &String| Some(Duration::from_millis(50)));
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .service(versioned_service(calls.clone()));
+
+        assert_eq!(service.call(String::from("a")).await?, "a-1");
+
+        // Stale entries are treated as misses
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(service.call(String::from("a")).await?, "a-2");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_refresh_ahead() -> Result<(), Error> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let cache_layer = CacheLayer::new(map::MapProvider::new::<String, String>())
+            .with_ttl_policy(|_: &String| Some(Duration::from_millis(200)))
+            .refresh_ahead(0.5);
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .service(versioned_service(calls.clone()));
+
+        assert_eq!(service.call(String::from("a")).await?, "a-1");
+
+        // A brand-new entry is not refreshed
+        assert_eq!(service.call(String::from("a")).await?, "a-1");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(service.call(String::from("a")).await?, "a-1");
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Close to expiry, the cached response is returned and the entry is
+        // refreshed in the background
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(service.call(String::from("a")).await?, "a-1");
+        assert_eq!(service.call(String::from("a")).await?, "a-1");
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // The original entry would have expired by now
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(service.call(String::from("a")).await?, "a-2");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_max_age() -> Result<(), Error> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let cache_layer = CacheLayer::new(map::MapProvider::new::<String, String>())
+            .with_transformer(|(key, _): (String, Option<Duration>)| key)
+            .with_max_age(|(_, max_age): &(String, Option<Duration>)| *max_age);
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .map_request(|(key, _): (String, Option<Duration>)| key)
+            .service(versioned_service(calls.clone()));
+
+        assert_eq!(service.call(("a".to_string(), None)).await?, "a-1");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // A lenient max age serves the cached response
+        let lenient = Some(Duration::from_secs(60));
+        assert_eq!(service.call(("a".to_string(), lenient)).await?, "a-1");
+        assert_eq!(service.call(("a".to_string(), None)).await?, "a-1");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // A tight max age forces a refresh, which is then cached
+        let tight = Some(Duration::from_millis(10));
+        assert_eq!(service.call(("a".to_string(), tight)).await?, "a-2");
+        assert_eq!(service.call(("a".to_string(), lenient)).await?, "a-2");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bypass_if() -> Result<(), Error> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let cache_layer = CacheLayer::new(map::MapProvider::new::<String, String>())
+            .with_transformer(|(key, _): (String, bool)| key)
+            .bypass_if(|(_, force_refresh): &(String, bool)| *force_refresh);
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .map_request(|(key, _): (String, bool)| key)
+            .service(versioned_service(calls.clone()));
+
+        assert_eq!(service.call(("a".to_string(), false)).await?, "a-1");
+        assert_eq!(service.call(("a".to_string(), false)).await?, "a-1");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Flagged requests skip the lookup, and refresh the entry
+        assert_eq!(service.call(("a".to_string(), true)).await?, "a-2");
+        assert_eq!(service.call(("a".to_string(), false)).await?, "a-2");
+        assert_eq!(service.call(("a".to_string(), true)).await?, "a-3");
+        assert_eq!(service.call(("a".to_string(), false)).await?, "a-3");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_with_key_transformer() -> Result<(), Error> {
+        struct Id(String);
+
+        impl IntoCacheKey<u64> for Id {
+            fn into_cache_key(self) -> Result<u64, KeyConversionError> {
+                self.0.parse().map_err(KeyConversionError::new)
+            }
+        }
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let provider = map::MapProvider::new::<u64, String>();
+        let cache_layer = CacheLayer::new(provider.clone()).with_key_transformer(Id);
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .service(versioned_service(calls.clone()));
+
+        assert_eq!(service.call("1".to_string()).await?, "1-1");
+        assert_eq!(service.call("1".to_string()).await?, "1-1");
+        let Ok(res) = provider.clone().call(ProviderRequest::Get(1)).await;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "1-1"));
+
+        // Requests that don't convert into a key bypass the cache
+        assert_eq!(service.call("a".to_string()).await?, "a-2");
+        assert_eq!(service.call("a".to_string()).await?, "a-3");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_with_providers() -> Result<(), Error> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let levels: Vec<_> = (0..3)
+            .map(|_| map::MapProvider::new::<String, String>())
+            .collect();
+        let mut service = ServiceBuilder::new()
+            .layer(CacheLayer::with_providers(levels.clone()))
+            .service(versioned_service(calls.clone()));
+
+        let request = ProviderRequest::Insert("a".to_string(), "a-0".to_string(), None);
+        let Ok(_) = levels[2].clone().call(request).await;
+
+        // A hit in L3 is served without calling the inner service, and is
+        // written back to L1 and L2
+        assert_eq!(service.call("a".to_string()).await?, "a-0");
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        for level in &levels[..2] {
+            let Ok(res) = level
+                .clone()
+                .call(ProviderRequest::Get("a".to_string()))
+                .await;
+            assert!(matches!(res, ProviderResponse::Found(v) if v == "a-0"));
+        }
+
+        // Misses are stored in every level
+        assert_eq!(service.call("b".to_string()).await?, "b-1");
+        for level in &levels {
+            let Ok(res) = level
+                .clone()
+                .call(ProviderRequest::Get("b".to_string()))
+                .await;
+            assert!(matches!(res, ProviderResponse::Found(v) if v == "b-1"));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stateful_transform() -> Result<(), Error> {
+        // Users of the same tenant share their cache entries
+        #[derive(Clone)]
+        struct ByTenant {
+            tenants: std::collections::HashMap<&'static str, &'static str>,
+        }
+
+        impl Transform<String> for ByTenant {
+            type Output = String;
+
+            fn transform(&self, req: String) -> Self::Output {
+                self.tenants
+                    .get(req.as_str())
+                    .unwrap_or(&"none")
+                    .to_string()
+            }
+        }
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let transform = ByTenant {
+            tenants: [("alice", "acme"), ("bob", "acme"), ("carol", "initech")].into(),
+        };
+        let mut service = ServiceBuilder::new()
+            .layer(
+                CacheLayer::new(map::MapProvider::new::<String, String>())
+                    .with_transform(transform),
+            )
+            .service(versioned_service(calls.clone()));
+
+        assert_eq!(service.call("alice".to_string()).await?, "alice-1");
+        assert_eq!(service.call("bob".to_string()).await?, "alice-1");
+        assert_eq!(service.call("carol".to_string()).await?, "carol-2");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        Ok(())
+    }
+
+    /// Check that clones of a layer and of its services share the provider
+    async fn assert_shared<P>(provider: P)
+    where
+        P: Service<ProviderRequest<String, String>, Response = ProviderResponse<String, String>>
+            + Clone
+            + Send
+            + 'static,
+        P::Error: fmt::Debug + Send + 'static,
+        P::Future: Send + 'static,
+    {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let layer = CacheLayer::new(provider);
+        let mut first = layer.layer(versioned_service(calls.clone()));
+        let mut second = first.clone();
+        let mut third = layer.clone().layer(versioned_service(calls.clone()));
+
+        // Inserted through the first service, read through the others
+        for service in [&mut first, &mut second, &mut third] {
+            let res = service.ready().await.unwrap().call("a".to_string()).await;
+            assert_eq!(res.unwrap(), "a-1");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    /// Check that batch responses of `provider` are paired with their keys
+    async fn assert_paired<P>(mut provider: P)
+    where
+        P: Service<ProviderRequest<String, String>, Response = ProviderResponse<String, String>>,
+        P::Error: fmt::Debug,
+    {
+        let keys = ["c", "x", "a", "b", "y", "d"].map(String::from);
+        for key in ["a", "b", "c", "d"] {
+            let request = ProviderRequest::Insert(key.to_string(), key.to_uppercase(), None);
+            provider.call(request).await.unwrap();
+        }
+
+        let request = ProviderRequest::GetMany(keys.to_vec());
+        let ProviderResponse::Many(pairs) = provider.call(request).await.unwrap() else {
+            panic!("not a batch response");
+        };
+        let returned: Vec<_> = pairs.iter().map(|(key, _)| key.clone()).collect();
+        assert_eq!(returned, keys);
+        for (key, value) in pairs {
+            match key.as_str() {
+                "x" | "y" => assert_eq!(value, None),
+                _ => assert_eq!(value, Some(key.to_uppercase())),
+            }
+        }
+
+        let entries = ["e", "f"].map(|key| (key.to_string(), key.to_uppercase(), None));
+        let res = provider
+            .call(ProviderRequest::InsertMany(entries.to_vec()))
+            .await;
+        if let ProviderResponse::Many(pairs) = res.unwrap() {
+            assert_eq!(pairs.len(), entries.len());
+            for (key, value) in pairs {
+                assert_eq!(value, Some(key.to_uppercase()));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_responses_paired() {
+        assert_paired(map::MapProvider::new()).await;
+        assert_paired(arc::ArcProvider::new(10)).await;
+        assert_paired(clock::ClockProvider::new(10)).await;
+        assert_paired(fifo::FifoProvider::new(10)).await;
+        assert_paired(lfu::LfuProvider::new(10)).await;
+        assert_paired(random::RandomProvider::new(10)).await;
+        assert_paired(tinylfu::TinyLfuProvider::new(10)).await;
+        assert_paired(tiered::TieredProvider::new(
+            map::MapProvider::new(),
+            map::MapProvider::new(),
+        ))
+        .await;
+        #[cfg(feature = "lru")]
+        assert_paired(lru::LruProvider::new(10)).await;
+        #[cfg(feature = "dashmap")]
+        assert_paired(dash::DashProvider::new()).await;
+        #[cfg(feature = "moka")]
+        assert_paired(moka::MokaProvider::builder().max_capacity(10).build()).await;
+        #[cfg(feature = "moka-sync")]
+        assert_paired(
+            moka_sync::MokaSyncProvider::builder()
+                .max_capacity(10)
+                .build(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_clones_share_cache() {
+        assert_shared(map::MapProvider::new()).await;
+        assert_shared(arc::ArcProvider::new(10)).await;
+        assert_shared(clock::ClockProvider::new(10)).await;
+        assert_shared(fifo::FifoProvider::new(10)).await;
+        assert_shared(lfu::LfuProvider::new(10)).await;
+        assert_shared(random::RandomProvider::new(10)).await;
+        assert_shared(tinylfu::TinyLfuProvider::new(10)).await;
+        assert_shared(mock::RecordingProvider::new()).await;
+        assert_shared(tiered::TieredProvider::new(
+            map::MapProvider::new(),
+            map::MapProvider::new(),
+        ))
+        .await;
+        #[cfg(feature = "lru")]
+        {
+            assert_shared(lru::LruProvider::new(10)).await;
+            assert_shared(lru::WeightedLruProvider::new(10, |v: &String| v.len())).await;
+        }
+        #[cfg(feature = "dashmap")]
+        assert_shared(dash::DashProvider::new()).await;
+        #[cfg(feature = "moka")]
+        assert_shared(moka::MokaProvider::builder().max_capacity(10).build()).await;
+        #[cfg(feature = "moka-sync")]
+        assert_shared(
+            moka_sync::MokaSyncProvider::builder()
+                .max_capacity(10)
+                .build(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_stats() -> Result<(), Error> {
+        let cache = SimpleCache::default();
+        let cache_layer = CacheLayer::new(cache.clone());
+        let handle = cache_layer.stats_handle();
+
+        let mut service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .service(service_fn(service));
+
+        service.call(String::from("Hello")).await?;
+        service.call(String::from("Hello")).await?;
+        service.call(String::from("Hello")).await?;
+        service.call(String::from("World")).await?;
+
+        let stats = service.stats();
+        assert_eq!(
+            stats,
+            CacheStats {
+                hits: 2,
+                misses: 2,
+                inserts: 2,
+            }
+        );
+        assert_eq!(stats.hit_ratio(), 0.5);
+        assert_eq!(handle.stats(), stats);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_clear() -> Result<(), Error> {
+        let cache = SimpleCache::default();
+        let mut service = ServiceBuilder::new()
+            .layer(CacheLayer::new(cache.clone()))
+            .service(service_fn(service));
+
+        service.call(String::from("Hello")).await?;
+        assert_eq!(cache.cache.lock().unwrap().len(), 1);
+
+        service.clear().await?;
+        assert_eq!(cache.cache.lock().unwrap().len(), 0);
+
+        // The next request is a miss again.
+        service.call(String::from("Hello")).await?;
+        assert_eq!(service.stats().misses, 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invalidate() -> Result<(), Error> {
+        let cache = SimpleCache::default();
+        let mut service = ServiceBuilder::new()
+            .layer(CacheLayer::new(cache.clone()))
+            .service(service_fn(service));
+
+        service.call(String::from("Hello")).await?;
+        service.call(String::from("World")).await?;
+
+        assert!(service.invalidate(String::from("Hello")).await?);
+        assert!(!service.invalidate(String::from("Hello")).await?);
+
+        // "Hello" is a miss, while "World" is still cached.
+        service.call(String::from("Hello")).await?;
+        service.call(String::from("World")).await?;
+        let stats = service.stats();
+        assert_eq!(stats.misses, 3);
+        assert_eq!(stats.hits, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_prime() -> Result<(), Error> {
+        // SimpleCache doesn't support batch inserts.
+        let cache = SimpleCache::default();
+        let mut service = ServiceBuilder::new()
+            .layer(CacheLayer::new(cache.clone()))
+            .service(service_fn(service));
+
+        service
+            .prime([("Hello".to_string(), "primed".to_string())])
+            .await?;
+        assert_eq!(cache.cache.lock().unwrap().len(), 1);
+
+        let res = service.call(String::from("Hello")).await?;
+        assert_eq!(res, "primed");
+        assert_eq!(service.stats().misses, 0);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "lru")]
+    #[tokio::test]
+    async fn test_prime_lru() -> Result<(), Error> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut service = ServiceBuilder::new()
+            .layer(CacheLayer::new(lru::LruProvider::new::<String, String>(10)))
+            .service(service_fn({
+                let calls = calls.clone();
+                move |req: String| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    service(req)
+                }
+            }));
+
+        let Ok(()) = service
+            .prime(["a", "b"].map(|key| (key.to_string(), format!("primed {}", key))))
+            .await;
+        for key in ["a", "b"] {
+            let res = service.ready().await?.call(key.to_string()).await?;
+            assert_eq!(res, format!("primed {}", key));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "lru")]
+    #[tokio::test]
+    async fn test_arc_shared() -> Result<(), Error> {
+        let provider: lru::ArcLruProvider<String, String> = lru::LruProvider::new(10);
+        let mut service = ServiceBuilder::new()
+            .layer(CacheLayer::new(provider.clone()))
+            .service(service_fn(|req: String| {
+                ready(Ok::<_, Error>(Arc::new(req.to_uppercase())))
+            }));
+
+        // On a miss, the provider stores the same value that is returned.
+        let res = service.ready().await?.call(String::from("hello")).await?;
+        let Ok(ProviderResponse::Found(stored)) = provider
+            .clone()
+            .oneshot(ProviderRequest::Get(String::from("hello")))
+            .await
+        else {
+            panic!("the response should be cached");
+        };
+        assert!(Arc::ptr_eq(&res, &stored));
+
+        let hit = service.ready().await?.call(String::from("hello")).await?;
+        assert!(Arc::ptr_eq(&res, &hit));
+        assert_eq!(Arc::strong_count(&res), 4);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "lru")]
+    #[tokio::test]
+    async fn test_provider_stats() -> Result<(), Error> {
+        let mut service = ServiceBuilder::new()
+            .layer(CacheLayer::new(lru::LruProvider::new::<String, String>(2)))
+            .service(service_fn(service));
+
+        for key in ["a", "b", "c", "a"] {
+            service.ready().await?.call(key.to_string()).await?;
+        }
+        let Ok(stats) = service.provider_stats().await;
+        assert_eq!(
+            stats,
+            Some(ProviderStats {
+                len: 2,
+                capacity: 2,
+                evictions: 2
+            })
+        );
+        assert_eq!(service.stats().misses, 4);
+
+        // Providers that don't track evictions don't report anything.
+        let service = ServiceBuilder::new()
+            .layer(CacheLayer::new(SimpleCache::default()))
+            .service(service_fn(service));
+        assert_eq!(service.provider_stats::<String, String>().await?, None);
+
+        Ok(())
+    }
+
+    /// Provider keeping its state locked while awaiting
+    #[derive(Clone, Default)]
+    struct AsyncCache {
+        cache: Arc<tokio::sync::Mutex<HashMap<String, String>>>,
+    }
+
+    impl Service<ProviderRequest<String, String>> for AsyncCache {
+        type Response = ProviderResponse<String, String>;
+        type Error = Infallible;
+        type Future = Pin<
+            Box<dyn Future<Output = Result<ProviderResponse<String, String>, Infallible>> + Send>,
+        >;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: ProviderRequest<String, String>) -> Self::Future {
+            let cache = self.cache.clone();
+            Box::pin(async move {
+                let mut cache = cache.lock().await;
+                tokio::task::yield_now().await;
+                Ok(match request {
+                    ProviderRequest::Get(key) => match cache.get(&key) {
+                        Some(value) => ProviderResponse::Found(value.clone()),
+                        None => ProviderResponse::NotFound,
+                    },
+                    ProviderRequest::Insert(key, value, _) => {
+                        cache.insert(key, value.clone());
+                        ProviderResponse::Found(value)
+                    }
+                    _ => ProviderResponse::NotFound,
+                })
+            })
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_async_provider() -> Result<(), Error> {
+        let cache = AsyncCache::default();
+        let mut service = ServiceBuilder::new()
+            .layer(CacheLayer::new(cache.clone()).coalesce(true))
+            .service(service_fn(service));
+
+        // Spawning the requests checks that the futures are `Send`, even with
+        // the provider holding a lock across `.await`.
+        let mut tasks = Vec::new();
+        for i in 0..16 {
+            let req = format!("hello {}", i % 4);
+            tasks.push(tokio::spawn(service.ready().await?.call(req)));
+        }
+        for task in tasks {
+            assert!(task.await.unwrap()?.starts_with("HELLO"));
+        }
+        assert_eq!(cache.cache.lock().await.len(), 4);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_provider_backpressure() -> Result<(), Error> {
+        let provider = Backpressure::new(SimpleCache::default(), 3);
+        let calls = provider.calls.clone();
+        let mut my_service = ServiceBuilder::new()
+            .layer(CacheLayer::new(provider))
+            .service(service_fn(service));
+
+        let mut ready = tokio_test::task::spawn(my_service.ready());
+        for _ in 0..3 {
+            assert!(ready.poll().is_pending());
+        }
+        assert!(ready.poll().is_ready());
+        drop(ready);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        let res = my_service.call("Hello".to_string()).await?;
+        assert_eq!(res, "HELLO");
+        // Lookup and insertion
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_service_backpressure() -> Result<(), Error> {
+        let inner = Backpressure::new(service_fn(service), 2);
+        let calls = inner.calls.clone();
+        let mut my_service = ServiceBuilder::new()
+            .layer(CacheLayer::new(SimpleCache::default()))
+            .service(inner);
+
+        let mut ready = tokio_test::task::spawn(my_service.ready());
+        for _ in 0..2 {
+            assert!(ready.poll().is_pending());
+        }
+        assert!(ready.poll().is_ready());
+        drop(ready);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        let res = my_service.call("Hello".to_string()).await?;
+        assert_eq!(res, "HELLO");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_provider_request_eq() {
+        let request = ProviderRequest::Insert("a", 1, Some(Duration::from_secs(1)));
+        assert_eq!(request.clone(), request);
+        assert_ne!(request, ProviderRequest::Insert("a", 1, None));
+        assert_ne!(
+            ProviderRequest::<_, usize>::Get("a"),
+            ProviderRequest::Remove("a")
+        );
+        assert_eq!(
+            format!("{:?}", ProviderRequest::<_, usize>::GetMany(vec!["a", "b"])),
+            r#"GetMany(["a", "b"])"#
+        );
+    }
+
+    #[test]
+    fn test_provider_response_eq() {
+        let response = ProviderResponse::Many(vec![("a", Some(1)), ("b", None)]);
+        assert_eq!(response.clone(), response);
+        assert_ne!(
+            ProviderResponse::Found(1),
+            ProviderResponse::<(), _>::FoundStale(1)
+        );
+        assert_eq!(
+            ProviderResponse::<&str, usize>::Ttl(Duration::from_secs(1), Duration::from_secs(2)),
+            ProviderResponse::Ttl(Duration::from_secs(1), Duration::from_secs(2))
+        );
+        assert_eq!(
+            format!("{:?}", ProviderResponse::<(), _>::Found("a")),
+            r#"Found("a")"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_provider_messages_unconstrained() {
+        // Neither Clone, Debug nor PartialEq
+        struct Opaque;
+
+        let mut provider = noop::NoopProvider::new::<Opaque, Opaque>();
+        let res = provider
+            .call(ProviderRequest::Insert(Opaque, Opaque, None))
+            .await
+            .unwrap();
+        assert!(matches!(res, ProviderResponse::Found(Opaque)));
+    }
+
+    #[tokio::test]
+    async fn test_set_enabled() -> Result<(), Error> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let handler = |req: String| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            ready(Ok::<_, Error>(req.to_uppercase()))
+        };
+        let cache_layer = CacheLayer::new(SimpleCache::default());
+        let toggle = cache_layer.toggle_handle();
+        let mut my_service = ServiceBuilder::new()
+            .layer(cache_layer)
+            .service(service_fn(handler));
+
+        my_service.call("Hello".to_string()).await?;
+        my_service.call("Hello".to_string()).await?;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Pass-through while disabled, including for new keys
+        my_service.set_enabled(false);
+        assert!(!toggle.is_enabled());
+        my_service.call("Hello".to_string()).await?;
+        my_service.call("World".to_string()).await?;
+        my_service.call("World".to_string()).await?;
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+        assert_eq!(my_service.stats().misses, 1);
+
+        // Cached entries are served again once enabled
+        toggle.set_enabled(true);
+        assert!(my_service.is_enabled());
+        let res = my_service.call("Hello".to_string()).await?;
+        assert_eq!(res, "HELLO");
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_set_enabled_poll_ready() -> Result<(), Error> {
+        let cache = FailingCache {
+            fail_ready: true,
+            fail_get: false,
+            fail_insert: false,
+        };
+        let mut my_service = ServiceBuilder::new()
+            .layer(CacheLayer::new(cache))
+            .service(service_fn(service));
+
+        // The provider isn't polled while disabled
+        my_service.set_enabled(false);
+        let res = my_service
+            .ready()
+            .await?
+            .call(String::from("Hello"))
+            .await?;
+        assert_eq!(res, "HELLO");
+
+        // The request doesn't go to the provider if it is enabled in between
+        my_service.ready().await?;
+        my_service.set_enabled(true);
+        let res = my_service.call(String::from("Hello")).await?;
+        assert_eq!(res, "HELLO");
+
+        Ok(())
+    }
 }
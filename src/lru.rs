@@ -3,6 +3,17 @@
 //! This is an implementation of a cache provider for [`crate::CacheLayer`]
 //! using [`lru::LruCache`].
 //!
+//! [`LruProvider`] holds a fixed number of entries. To bound the cache by the
+//! size of its values instead, use [`WeightedLruProvider`].
+//!
+//! Both providers use a synchronous lock, which is only held while handling
+//! a request in [`Service::call`] and never across an `.await`: the futures
+//! they return are already complete.
+//!
+//! Their futures are `Send`, which requires `K: Send` and `V: Send`. For
+//! keys or values that are not `Send`, wrap an [`LruProvider`] in a
+//! [`LocalLruProvider`].
+//!
 //! ## Usage
 //!
 //! ```rust
@@ -32,83 +43,2377 @@
 //! ```
 //!
 
-use crate::{ProviderRequest, ProviderResponse};
-use lru::LruCache;
+pub use crate::entry::Entry;
+
+use crate::{
+    time, trace, CacheEventListener, Configure, ProviderConfig, ProviderRequest, ProviderResponse,
+};
+use lru::{DefaultHasher, LruCache};
 use std::{
+    borrow::Borrow,
     clone::Clone,
     convert::Infallible,
-    future::{ready, Future},
-    hash::Hash,
+    error, fmt,
+    future::{ready, Future, Ready},
+    hash::{BuildHasher, Hash},
     marker::PhantomData,
+    num::NonZeroUsize,
     pin::Pin,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
+use tokio::{task::JoinHandle, time::MissedTickBehavior};
 use tower::Service;
 
 /// Local LRU cache provider
+///
+/// Keys are hashed with `S`, which defaults to the hasher used by
+/// [`LruCache`]. Use [`LruProvider::with_hasher`] to provide a different one.
+///
+/// ## Thread safety
+///
+/// Clones of the provider share the same cache behind an [`Arc`] and a
+/// [`RwLock`], so the provider is `Send` and `Sync` when `K`, `V` and `S` are
+/// all `Send` and `Sync`. Eviction listeners are always `Send` and `Sync`.
+///
+/// As a [`Service`], the provider returns `Send` futures, which requires
+/// `V: Send` even when the provider itself doesn't need to be `Send`. For
+/// values that are not `Send`, such as an [`Rc`](std::rc::Rc), use a
+/// [`LocalLruProvider`] on a single-threaded executor.
 #[derive(Debug)]
-pub struct LruProvider<'a, K, V>
+pub struct LruProvider<'a, K, V, S = DefaultHasher>
 where
     K: Eq + Hash,
+    S: BuildHasher,
 {
-    inner: Arc<Mutex<LruCache<K, V>>>,
+    inner: Arc<RwLock<LruCache<K, Entry<V>, S>>>,
+    ttl: Option<Duration>,
+    stale_window: Option<Duration>,
+    peek_reads: bool,
+    evict: EvictListener<'a, K, V>,
+    evictions: Arc<AtomicU64>,
     _phantom: PhantomData<&'a ()>,
 }
 
+/// Listener notified when the LRU cache evicts an entry
+struct EvictListener<'a, K, V>(Option<Arc<dyn CacheEventListener<K, V> + Send + Sync + 'a>>);
+
+impl<'a, K, V> Clone for EvictListener<'a, K, V> {
+    fn clone(&self) -> Self {
+        EvictListener(self.0.clone())
+    }
+}
+
+impl<'a, K, V> fmt::Debug for EvictListener<'a, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(_) => write!(f, "Some(..)"),
+            None => write!(f, "None"),
+        }
+    }
+}
+
+/// Eviction listener calling a function, see [`LruProvider::on_evict_fn`]
+struct EvictFn<F>(F);
+
+impl<K, V, F> CacheEventListener<K, V> for EvictFn<F>
+where
+    F: Fn(&K, &V),
+{
+    fn on_evict(&self, key: &K, value: &V) {
+        (self.0)(key, value)
+    }
+}
+
 impl<'a> LruProvider<'a, (), ()> {
     /// Create a new LRU cache provider with the desired capacity
+    ///
+    /// The underlying [`LruCache`] cannot hold zero entries, so a capacity of
+    /// `0` is clamped to `1` instead of panicking. Use
+    /// [`LruProvider::try_new`] to reject a zero capacity instead.
     pub fn new<K, V>(capacity: usize) -> LruProvider<'a, K, V>
     where
         K: Eq + Hash,
     {
+        Self::with_capacity(NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN))
+    }
+
+    /// Create a new LRU cache provider, returning an error if the capacity is
+    /// `0`
+    pub fn try_new<K, V>(capacity: usize) -> Result<LruProvider<'a, K, V>, CapacityError>
+    where
+        K: Eq + Hash,
+    {
+        NonZeroUsize::new(capacity)
+            .map(Self::with_capacity)
+            .ok_or(CapacityError)
+    }
+
+    /// Create a new LRU cache provider with a non-zero capacity
+    pub fn with_capacity<K, V>(capacity: NonZeroUsize) -> LruProvider<'a, K, V>
+    where
+        K: Eq + Hash,
+    {
+        LruProvider {
+            inner: Arc::new(RwLock::new(LruCache::new(capacity))),
+            ttl: None,
+            stale_window: None,
+            peek_reads: false,
+            evict: EvictListener(None),
+            evictions: Arc::default(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Create a new LRU cache provider where entries expire after `ttl`
+    ///
+    /// Expired entries are removed lazily: a `Get` for an entry that is at
+    /// least `ttl` old returns [`ProviderResponse::NotFound`] and drops the
+    /// entry from the cache. As with [`LruProvider::new`], a capacity of `0`
+    /// is clamped to `1`.
+    pub fn with_ttl<K, V>(capacity: usize, ttl: Duration) -> LruProvider<'a, K, V>
+    where
+        K: Eq + Hash,
+    {
+        LruProvider {
+            ttl: Some(ttl),
+            ..Self::new(capacity)
+        }
+    }
+
+    /// Create a new LRU cache provider hashing keys with `hasher`
+    ///
+    /// As with [`LruProvider::new`], a capacity of `0` is clamped to `1`.
+    pub fn with_hasher<K, V, S>(capacity: usize, hasher: S) -> LruProvider<'a, K, V, S>
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
         LruProvider {
-            inner: Arc::new(Mutex::new(LruCache::new(capacity))),
+            inner: Arc::new(RwLock::new(LruCache::with_hasher(capacity, hasher))),
+            ttl: None,
+            stale_window: None,
+            peek_reads: false,
+            evict: EvictListener(None),
+            evictions: Arc::default(),
             _phantom: PhantomData,
         }
     }
 }
 
+/// Local LRU cache provider storing values behind an [`Arc`]
+///
+/// Cache hits only clone the [`Arc`] pointer instead of the value itself,
+/// which is useful for large values. The wrapped service must return
+/// `Arc<V>` responses.
+///
+/// ```rust
+/// use std::{convert::Infallible, sync::Arc};
+/// use tower::{Service, ServiceBuilder, service_fn};
+/// use tower_cache::{
+///     CacheLayer,
+///     lru::{ArcLruProvider, LruProvider},
+/// };
+/// async fn handler(req: String) -> Result<Arc<String>, Infallible> {
+///     Ok(Arc::new(req.to_uppercase()))
+/// }
+///
+/// let lru_provider: ArcLruProvider<String, String> = LruProvider::new(20);
+///
+/// let mut my_service = ServiceBuilder::new()
+///     .layer(CacheLayer::new(lru_provider))
+///     .service(service_fn(handler));
+///
+/// # tokio_test::block_on(async move {
+/// let first = my_service.call("Hello".to_string()).await.unwrap();
+/// let second = my_service.call("Hello".to_string()).await.unwrap();
+/// assert!(Arc::ptr_eq(&first, &second));
+/// # })
+/// ```
+pub type ArcLruProvider<'a, K, V> = LruProvider<'a, K, Arc<V>>;
+
 // Custom implementation of Clone as the Clone derive doesn't mark LruProvider
 // as Clone if K or V is not clone.
-impl<'a, K, V> Clone for LruProvider<'a, K, V>
+impl<'a, K, V, S> Clone for LruProvider<'a, K, V, S>
 where
     K: Eq + Hash,
+    S: BuildHasher,
 {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            ttl: self.ttl,
+            stale_window: self.stale_window,
+            peek_reads: self.peek_reads,
+            evict: self.evict.clone(),
+            evictions: self.evictions.clone(),
             _phantom: PhantomData,
         }
     }
 }
 
-impl<'a, K, V> Service<ProviderRequest<K, V>> for LruProvider<'a, K, V>
+impl<'a, K, V, S> LruProvider<'a, K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    /// Look up entries without updating their recency.
+    ///
+    /// By default, a `Get` takes an exclusive lock on the cache to move the
+    /// entry to the front of the LRU list. When enabled, a `Get` only takes a
+    /// shared lock and doesn't update recency, which allows concurrent reads
+    /// at the cost of evicting entries in insertion order rather than in
+    /// least-recently-used order.
+    pub fn peek_reads(mut self, enabled: bool) -> Self {
+        self.peek_reads = enabled;
+        self
+    }
+
+    /// Keep entries for `window` after they expire, and return them as
+    /// [`ProviderResponse::FoundStale`] during that time.
+    ///
+    /// This allows [`crate::CacheLayer::stale_while_revalidate`] to serve
+    /// them while they are refreshed. Entries without a TTL never become
+    /// stale.
+    pub fn stale_window(mut self, window: Duration) -> Self {
+        self.stale_window = Some(window);
+        self
+    }
+
+    /// Change the capacity of the cache.
+    ///
+    /// This affects all clones of the provider. When shrinking, the least
+    /// recently used entries are evicted immediately.
+    pub fn resize(&self, capacity: NonZeroUsize) {
+        let mut inner = self.write();
+        let mut evicted = Vec::new();
+        while inner.len() > capacity.get() {
+            evicted.extend(inner.pop_lru());
+        }
+        inner.resize(capacity);
+        drop(inner);
+
+        for (key, entry) in evicted {
+            self.notify_evict(&key, &entry);
+        }
+    }
+
+    /// Return the number of entries in the cache.
+    ///
+    /// Expired entries are removed lazily, so they are counted until they
+    /// are looked up or evicted.
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    /// Return `true` if the cache has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    /// Remove all expired entries from the cache, returning how many were
+    /// removed.
+    ///
+    /// The expired keys are collected under a read lock, then removed in
+    /// chunks, releasing the write lock between them so that requests are
+    /// not blocked for the whole sweep. Entries that expire in the meantime
+    /// are removed by the next sweep. Removed entries are not counted as
+    /// evictions.
+    pub fn sweep(&self) -> usize
+    where
+        K: Clone,
+    {
+        sweep(&self.inner)
+    }
+
+    /// Spawn a task removing expired entries every `interval`.
+    ///
+    /// Expired entries are otherwise only removed when they are looked up or
+    /// evicted, so they keep using memory until then. See
+    /// [`LruProvider::sweep`]. This must be called from within a Tokio
+    /// runtime.
+    ///
+    /// The task stops once all clones of the provider are dropped, or when
+    /// aborted with the returned handle.
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use tower_cache::lru::LruProvider;
+    ///
+    /// # tokio_test::block_on(async move {
+    /// let provider = LruProvider::with_ttl::<String, String>(100, Duration::from_secs(60));
+    /// let sweeper = provider.start_sweeper(Duration::from_secs(30));
+    ///
+    /// // Stop sweeping
+    /// sweeper.abort();
+    /// # })
+    /// ```
+    pub fn start_sweeper(&self, interval: Duration) -> JoinHandle<()>
+    where
+        K: Clone + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        S: Send + Sync + 'static,
+    {
+        let inner = Arc::downgrade(&self.inner);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            // The first tick completes immediately.
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let Some(inner) = inner.upgrade() else {
+                    return;
+                };
+                let removed = sweep(&inner);
+                trace::swept(removed);
+            }
+        })
+    }
+
+    /// Return a snapshot of the keys in the cache.
+    ///
+    /// Keys are ordered from the most recently used to the least recently
+    /// used, which is the next one to be evicted. As with
+    /// [`LruProvider::len`], this includes expired entries that haven't been
+    /// removed yet.
+    pub fn keys(&self) -> Vec<K>
+    where
+        K: Clone,
+    {
+        self.read().iter().map(|(key, _)| key.clone()).collect()
+    }
+
+    /// Look up the entry for a borrowed key, as with a
+    /// [`ProviderRequest::Get`].
+    ///
+    /// A `Get` request takes an owned key, which has to be cloned when the
+    /// caller only has a reference to it. This accepts any borrowed form of
+    /// the key instead, such as a `&str` for `String` keys. It updates the
+    /// recency of the entry unless [`LruProvider::peek_reads`] is enabled,
+    /// and removes the entry if it has expired.
+    ///
+    /// ```rust
+    /// use tower_cache::{ProviderResponse, lru::LruProvider};
+    ///
+    /// let provider = LruProvider::new::<String, usize>(10);
+    /// provider.import([("hello".to_string(), 5)]);
+    ///
+    /// assert_eq!(provider.get("hello"), ProviderResponse::Found(5));
+    /// assert_eq!(provider.get("world"), ProviderResponse::NotFound);
+    /// ```
+    pub fn get<Q>(&self, key: &Q) -> ProviderResponse<K, V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+        V: Clone,
+    {
+        let now = time::now();
+        let response = if self.peek_reads {
+            let inner = self.read();
+            inner.peek(key).map(|entry| entry.response_at(now))
+        } else {
+            let mut inner = self.write();
+            inner.get(key).map(|entry| entry.response_at(now))
+        };
+        match response {
+            Some(Some(response)) => response,
+            // The entry has expired: remove it so it doesn't take up
+            // capacity anymore.
+            Some(None) => {
+                self.remove_expired(key, now);
+                ProviderResponse::NotFound
+            }
+            None => ProviderResponse::NotFound,
+        }
+    }
+
+    /// Return a snapshot of the entries in the cache, to restore them later
+    /// with [`LruProvider::import`].
+    ///
+    /// Entries are ordered as with [`LruProvider::keys`]. Negative, stale
+    /// and expired entries are skipped, and the expiration time of entries
+    /// isn't kept.
+    pub fn export(&self) -> Vec<(K, V)>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let now = time::now();
+        self.read()
+            .iter()
+            .filter_map(|(key, entry)| Some((key.clone(), entry.fresh_value_at(now)?.clone())))
+            .collect()
+    }
+
+    /// Insert entries exported with [`LruProvider::export`].
+    ///
+    /// Entries are expected from the most recently used to the least
+    /// recently used, and are inserted in reverse order under a single lock,
+    /// so that the cache ends up with the same recency order. They expire
+    /// with the default TTL of the provider. If there are more entries than
+    /// the capacity, the least recently used ones are evicted.
+    pub fn import(&self, entries: impl IntoIterator<Item = (K, V)>) {
+        let entries: Vec<_> = entries.into_iter().collect();
+        let mut inner = self.write();
+        let mut evicted = Vec::new();
+        for (key, value) in entries.into_iter().rev() {
+            let entry = Entry::new(value, self.ttl).stale_for(self.stale_window);
+            evicted.extend(Self::push(&mut inner, key, entry));
+        }
+        drop(inner);
+        for (key, entry) in &evicted {
+            self.notify_evict(key, entry);
+        }
+    }
+
+    /// Return the least recently used entry, which is the next one to be
+    /// evicted, without removing it or updating its recency.
+    ///
+    /// The value is `None` for negative entries.
+    pub fn peek_lru(&self) -> Option<(K, Option<V>)>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        self.read()
+            .peek_lru()
+            .map(|(key, entry)| (key.clone(), entry.value.clone()))
+    }
+
+    /// Remove and return the least recently used entry.
+    ///
+    /// The value is `None` for negative entries. This doesn't notify the
+    /// eviction listener.
+    pub fn pop_lru(&self) -> Option<(K, Option<V>)> {
+        self.write()
+            .pop_lru()
+            .map(|(key, entry)| (key, entry.value))
+    }
+
+    /// Notify `listener` when an entry is evicted to make room for another
+    /// one.
+    ///
+    /// Entries that are replaced, removed, cleared or that have expired
+    /// don't trigger the listener. See
+    /// [`CacheEventListener::on_evict`].
+    pub fn on_evict<L>(mut self, listener: L) -> Self
+    where
+        L: CacheEventListener<K, V> + Send + Sync + 'a,
+    {
+        self.evict = EvictListener(Some(Arc::new(listener)));
+        self
+    }
+
+    /// Call `f` with the key and value of each entry evicted to make room for
+    /// another one.
+    ///
+    /// This is a shorthand for [`LruProvider::on_evict`] with a listener that
+    /// only handles evictions.
+    ///
+    /// ```rust
+    /// use tower_cache::lru::LruProvider;
+    ///
+    /// let provider = LruProvider::new::<String, String>(20)
+    ///     .on_evict_fn(|key, value| println!("evicted {key}: {value}"));
+    /// ```
+    pub fn on_evict_fn<F>(self, f: F) -> Self
+    where
+        F: Fn(&K, &V) + Send + Sync + 'a,
+    {
+        self.on_evict(EvictFn(f))
+    }
+
+    /// Run `f` on the underlying [`LruCache`], under the write lock, and
+    /// return its result.
+    ///
+    /// This gives access to operations that the provider doesn't cover, such
+    /// as custom iteration or bulk changes. Values are wrapped in an
+    /// [`Entry`] with their expiration time. Entries removed by `f` don't
+    /// notify the eviction listener, and are not counted as evictions.
+    ///
+    /// The lock is not reentrant: calling the provider, or one of its
+    /// clones, from `f` deadlocks.
+    ///
+    /// ```rust
+    /// use tower_cache::lru::LruProvider;
+    ///
+    /// let provider = LruProvider::new::<String, String>(20);
+    /// provider.import([
+    ///     ("a".to_string(), "A".to_string()),
+    ///     ("b".to_string(), String::new()),
+    /// ]);
+    ///
+    /// // Remove the entries with an empty value
+    /// let removed = provider.with_lock(|cache| {
+    ///     let keys: Vec<_> = cache
+    ///         .iter()
+    ///         .filter(|(_, entry)| entry.value().is_some_and(|value| value.is_empty()))
+    ///         .map(|(key, _)| key.clone())
+    ///         .collect();
+    ///     keys.iter().filter_map(|key| cache.pop(key)).count()
+    /// });
+    /// assert_eq!(removed, 1);
+    /// assert_eq!(provider.keys(), vec!["a".to_string()]);
+    /// ```
+    pub fn with_lock<R>(&self, f: impl FnOnce(&mut LruCache<K, Entry<V>, S>) -> R) -> R {
+        f(&mut self.write())
+    }
+
+    /// Lock the cache for reading
+    ///
+    /// A panic while holding the lock poisons it. The cache is still usable
+    /// afterwards, so this recovers the guard instead of propagating the
+    /// panic to every later request.
+    fn read(&self) -> RwLockReadGuard<'_, LruCache<K, Entry<V>, S>> {
+        self.inner.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Lock the cache for writing
+    ///
+    /// See [`LruProvider::read`] for poisoning.
+    fn write(&self) -> RwLockWriteGuard<'_, LruCache<K, Entry<V>, S>> {
+        self.inner.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Store `entry`, notifying the eviction listener if another entry had
+    /// to make room for it
+    ///
+    /// This takes the lock from the caller, so that it can look up the
+    /// entry first.
+    fn put(
+        &self,
+        mut inner: RwLockWriteGuard<'_, LruCache<K, Entry<V>, S>>,
+        key: K,
+        entry: Entry<V>,
+    ) {
+        let Some((key, entry)) = Self::push(&mut inner, key, entry) else {
+            return;
+        };
+        drop(inner);
+        self.notify_evict(&key, &entry);
+    }
+
+    /// Store `entry`, returning the entry evicted to make room for it if any
+    fn push(
+        inner: &mut LruCache<K, Entry<V>, S>,
+        key: K,
+        entry: Entry<V>,
+    ) -> Option<(K, Entry<V>)> {
+        let (key, entry) = inner.push(key, entry)?;
+        // `push` also returns the previous entry for the same key.
+        (!inner.contains(&key)).then_some((key, entry))
+    }
+
+    /// Count an eviction, and notify the eviction listener, if any, that
+    /// `entry` was evicted
+    fn notify_evict(&self, key: &K, entry: &Entry<V>) {
+        self.evictions.fetch_add(1, Ordering::Relaxed);
+        if let (Some(listener), Some(value)) = (&self.evict.0, &entry.value) {
+            if !entry.is_expired(time::now()) {
+                listener.on_evict(key, value);
+            }
+        }
+    }
+
+    /// Remove the entry for `key` if it has expired
+    fn remove_expired<Q>(&self, key: &Q, now: Instant)
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let mut inner = self.write();
+        // The entry could have been replaced since it was looked up.
+        if inner.peek(key).is_some_and(|entry| entry.is_expired(now)) {
+            inner.pop(key);
+        }
+    }
+}
+
+/// Number of entries removed by [`LruProvider::sweep`] for each write lock
+const SWEEP_CHUNK: usize = 256;
+
+/// Remove expired entries from `inner`
+///
+/// The expired keys are collected under a read lock, then removed one chunk
+/// at a time, so writers are only blocked for short periods.
+fn sweep<K, V, S>(inner: &RwLock<LruCache<K, Entry<V>, S>>) -> usize
+where
+    K: Clone + Eq + Hash,
+    S: BuildHasher,
+{
+    let now = time::now();
+    let expired: Vec<K> = inner
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .filter(|(_, entry)| entry.is_expired(now))
+        .map(|(key, _)| key.clone())
+        .collect();
+
+    let mut removed = 0;
+    for chunk in expired.chunks(SWEEP_CHUNK) {
+        let mut inner = inner.write().unwrap_or_else(PoisonError::into_inner);
+        for key in chunk {
+            // The entry could have been replaced since the keys were collected.
+            if inner.peek(key).is_some_and(|entry| entry.is_expired(now)) {
+                inner.pop(key);
+                removed += 1;
+            }
+        }
+    }
+    removed
+}
+
+/// Sets the TTL and resizes the cache, see [`LruProvider::resize`].
+impl<'a, K, V, S> Configure for LruProvider<'a, K, V, S>
 where
     K: Eq + Hash,
+    S: BuildHasher,
+{
+    fn configure(mut self, config: &ProviderConfig) -> Self {
+        if let Some(ttl) = config.ttl {
+            self.ttl = Some(ttl);
+        }
+        if let Some(capacity) = config.capacity {
+            // Caches hold at least one entry.
+            let capacity = NonZeroUsize::new(capacity).unwrap_or_else(|| {
+                trace::unsupported_config::<Self>("capacity");
+                NonZeroUsize::MIN
+            });
+            self.resize(capacity);
+        }
+        self
+    }
+}
+
+impl<'a, K, V, S> LruProvider<'a, K, V, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    S: BuildHasher,
+{
+    /// Handle a request under the lock, shared by [`LruProvider`] and
+    /// [`LocalLruProvider`]
+    fn handle(&self, request: ProviderRequest<K, V>) -> ProviderResponse<K, V> {
+        match request {
+            ProviderRequest::Get(key) => self.get(&key),
+            // Store a clone and return the original, so that inserting only
+            // clones the value once.
+            ProviderRequest::Insert(key, value, ttl) => {
+                let entry =
+                    Entry::new(value.clone(), ttl.or(self.ttl)).stale_for(self.stale_window);
+                self.put(self.write(), key, entry);
+                ProviderResponse::Found(value)
+            }
+            ProviderRequest::InsertNegative(key, ttl) => {
+                self.put(self.write(), key, Entry::negative(ttl));
+                ProviderResponse::FoundNegative
+            }
+            ProviderRequest::Clear => {
+                self.write().clear();
+                ProviderResponse::Cleared
+            }
+            ProviderRequest::Remove(key) => match self.write().pop(&key) {
+                Some(_) => ProviderResponse::Removed,
+                None => ProviderResponse::NotFound,
+            },
+            // Peek at the entry to avoid updating its recency.
+            ProviderRequest::Contains(key) => {
+                let now = time::now();
+                let inner = self.read();
+                let present = inner.peek(&key).is_some_and(|entry| !entry.is_expired(now));
+                ProviderResponse::Present(present)
+            }
+            ProviderRequest::Ttl(key) => {
+                let now = time::now();
+                let inner = self.read();
+                match inner.peek(&key).and_then(|entry| entry.ttl_at(now)) {
+                    Some((remaining, ttl)) => ProviderResponse::Ttl(remaining, ttl),
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            ProviderRequest::Age(key) => {
+                let now = time::now();
+                let inner = self.read();
+                match inner.peek(&key).and_then(|entry| entry.age_at(now)) {
+                    Some(age) => ProviderResponse::Age(age),
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            // Look up and insert under the same lock.
+            ProviderRequest::GetOrInsert(key, value) => {
+                let now = time::now();
+                let mut inner = self.write();
+                match inner.get(&key).and_then(|entry| entry.fresh_value_at(now)) {
+                    Some(existing) => ProviderResponse::Found(existing.clone()),
+                    None => {
+                        let entry =
+                            Entry::new(value.clone(), self.ttl).stale_for(self.stale_window);
+                        self.put(inner, key, entry);
+                        ProviderResponse::Inserted(value)
+                    }
+                }
+            }
+            // Look up all keys under the same lock.
+            ProviderRequest::GetMany(keys) => {
+                let now = time::now();
+                let mut inner = self.write();
+                ProviderResponse::Many(
+                    keys.into_iter()
+                        .map(|key| {
+                            let entry = match self.peek_reads {
+                                true => inner.peek(&key),
+                                false => inner.get(&key),
+                            };
+                            let value = entry.and_then(|entry| entry.value_at(now)).cloned();
+                            (key, value)
+                        })
+                        .collect(),
+                )
+            }
+            // Insert all entries under the same lock.
+            ProviderRequest::InsertMany(entries) => {
+                let mut inner = self.write();
+                let mut values = Vec::with_capacity(entries.len());
+                let mut evicted = Vec::new();
+                for (key, value, ttl) in entries {
+                    let entry =
+                        Entry::new(value.clone(), ttl.or(self.ttl)).stale_for(self.stale_window);
+                    evicted.extend(Self::push(&mut inner, key.clone(), entry));
+                    values.push((key, Some(value)));
+                }
+                drop(inner);
+                for (key, entry) in &evicted {
+                    self.notify_evict(key, entry);
+                }
+                ProviderResponse::Many(values)
+            }
+            ProviderRequest::Stats => {
+                let inner = self.read();
+                ProviderResponse::Stats {
+                    len: inner.len(),
+                    capacity: inner.cap().get(),
+                    evictions: self.evictions.load(Ordering::Relaxed),
+                }
+            }
+        }
+    }
+}
+
+impl<'a, K, V, S> Service<ProviderRequest<K, V>> for LruProvider<'a, K, V, S>
+where
+    K: Eq + Hash + Clone + Send + 'a,
     V: Clone + Send + 'a,
+    S: BuildHasher,
 {
-    type Response = ProviderResponse<V>;
+    type Response = ProviderResponse<K, V>;
     type Error = Infallible;
-    type Future = ProviderFuture<'a, V>;
+    type Future = ProviderFuture<'a, K, V>;
 
     fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         Poll::Ready(Ok(()))
     }
 
     fn call(&mut self, request: ProviderRequest<K, V>) -> Self::Future {
-        Box::pin(ready(Ok(match request {
-            ProviderRequest::Get(key) => match self.inner.lock().unwrap().get(&key) {
-                Some(value) => ProviderResponse::Found(value.clone()),
-                None => ProviderResponse::NotFound,
-            },
-            ProviderRequest::Insert(key, value) => {
-                self.inner.lock().unwrap().put(key, value.clone());
-                ProviderResponse::Found(value)
-            }
-        })))
+        Box::pin(ready(Ok(self.handle(request))))
+    }
+}
+
+/// LRU cache provider returning futures that are not `Send`
+///
+/// This handles requests like the [`LruProvider`] it wraps, without requiring
+/// values to be `Send`, for use on single-threaded executors. Configure an
+/// [`LruProvider`], then convert it with [`From`]. As the [`CacheLayer`]
+/// requires `Send` providers, this is meant to be called directly.
+///
+/// [`CacheLayer`]: crate::CacheLayer
+///
+/// ```rust
+/// use std::rc::Rc;
+/// use tower::{Service, ServiceExt};
+/// use tower_cache::{ProviderRequest, ProviderResponse, lru::{LocalLruProvider, LruProvider}};
+///
+/// let mut provider = LocalLruProvider::from(LruProvider::new::<u32, Rc<String>>(10));
+///
+/// # tokio_test::block_on(async move {
+/// let request = ProviderRequest::Insert(1, Rc::new("a".to_string()), None);
+/// provider.ready().await.unwrap().call(request).await.unwrap();
+///
+/// let res = provider.ready().await.unwrap().call(ProviderRequest::Get(1)).await.unwrap();
+/// assert!(matches!(res, ProviderResponse::Found(value) if *value == "a"));
+/// # })
+/// ```
+#[derive(Debug)]
+pub struct LocalLruProvider<'a, K, V, S = DefaultHasher>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    inner: LruProvider<'a, K, V, S>,
+}
+
+impl<'a> LocalLruProvider<'a, (), ()> {
+    /// Create a new LRU cache provider with the desired capacity
+    ///
+    /// As with [`LruProvider::new`], a capacity of `0` is clamped to `1`.
+    pub fn new<K, V>(capacity: usize) -> LocalLruProvider<'a, K, V>
+    where
+        K: Eq + Hash,
+    {
+        LruProvider::new(capacity).into()
+    }
+}
+
+impl<'a, K, V, S> Clone for LocalLruProvider<'a, K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K, V, S> From<LruProvider<'a, K, V, S>> for LocalLruProvider<'a, K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    fn from(inner: LruProvider<'a, K, V, S>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a, K, V, S> LocalLruProvider<'a, K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    /// Return the wrapped [`LruProvider`]
+    ///
+    /// It shares its entries with this provider.
+    pub fn into_inner(self) -> LruProvider<'a, K, V, S> {
+        self.inner
+    }
+}
+
+impl<'a, K, V, S> Service<ProviderRequest<K, V>> for LocalLruProvider<'a, K, V, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    S: BuildHasher,
+{
+    type Response = ProviderResponse<K, V>;
+    type Error = Infallible;
+    type Future = Ready<Result<ProviderResponse<K, V>, Infallible>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ProviderRequest<K, V>) -> Self::Future {
+        ready(Ok(self.inner.handle(request)))
+    }
+}
+
+/// Local LRU cache provider bounded by the total weight of its values
+///
+/// Each value is given a weight by a weigher function, usually its size in
+/// bytes, and the least recently used entries are evicted to keep the total
+/// weight within a budget. Inserting a heavy value can evict several entries
+/// at once. Negative entries have a weight of `1`.
+///
+/// Values heavier than the whole budget are not stored. Inserting one still
+/// removes the previous entry for its key, so that it isn't served anymore.
+///
+/// ```rust
+/// use tower_cache::{ProviderRequest, lru::WeightedLruProvider};
+/// use tower::{Service, ServiceExt};
+///
+/// let mut provider = WeightedLruProvider::new::<u32, String>(8, |v: &String| v.len());
+///
+/// # tokio_test::block_on(async move {
+/// for key in 0..3 {
+///     let request = ProviderRequest::Insert(key, "abcd".to_string(), None);
+///     provider.ready().await.unwrap().call(request).await.unwrap();
+/// }
+///
+/// // Only the last two values fit in the budget
+/// assert_eq!(provider.weight(), 8);
+/// assert_eq!(provider.keys(), vec![2, 1]);
+/// # })
+/// ```
+pub struct WeightedLruProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    inner: Arc<Mutex<Weighted<K, V>>>,
+    weigher: Arc<dyn Fn(&V) -> usize + Send + Sync + 'a>,
+    ttl: Option<Duration>,
+    stale_window: Option<Duration>,
+}
+
+impl<'a> WeightedLruProvider<'a, (), ()> {
+    /// Create a new weighted LRU cache provider
+    ///
+    /// `weigher` returns the weight of a value, and the total weight of the
+    /// entries never goes over `budget`.
+    pub fn new<K, V>(
+        budget: usize,
+        weigher: impl Fn(&V) -> usize + Send + Sync + 'a,
+    ) -> WeightedLruProvider<'a, K, V>
+    where
+        K: Eq + Hash,
+    {
+        WeightedLruProvider {
+            inner: Arc::new(Mutex::new(Weighted {
+                entries: LruCache::unbounded(),
+                weight: 0,
+                budget,
+            })),
+            weigher: Arc::new(weigher),
+            ttl: None,
+            stale_window: None,
+        }
+    }
+
+    /// Create a new weighted LRU cache provider where entries expire after
+    /// `ttl`
+    pub fn with_ttl<K, V>(
+        budget: usize,
+        ttl: Duration,
+        weigher: impl Fn(&V) -> usize + Send + Sync + 'a,
+    ) -> WeightedLruProvider<'a, K, V>
+    where
+        K: Eq + Hash,
+    {
+        WeightedLruProvider {
+            ttl: Some(ttl),
+            ..Self::new(budget, weigher)
+        }
+    }
+}
+
+// Custom implementation of Clone as the Clone derive doesn't mark
+// WeightedLruProvider as Clone if K or V is not clone.
+impl<'a, K, V> Clone for WeightedLruProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            weigher: self.weigher.clone(),
+            ttl: self.ttl,
+            stale_window: self.stale_window,
+        }
+    }
+}
+
+impl<'a, K, V> fmt::Debug for WeightedLruProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inner = self.lock();
+        f.debug_struct("WeightedLruProvider")
+            .field("len", &inner.entries.len())
+            .field("weight", &inner.weight)
+            .field("budget", &inner.budget)
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Sets the TTL. The capacity is ignored, as the size of the cache is
+/// bounded by the weight of its entries instead.
+impl<'a, K, V> Configure for WeightedLruProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    fn configure(mut self, config: &ProviderConfig) -> Self {
+        if let Some(ttl) = config.ttl {
+            self.ttl = Some(ttl);
+        }
+        if config.capacity.is_some() {
+            trace::unsupported_config::<Self>("capacity");
+        }
+        self
     }
 }
 
-type ProviderFuture<'a, V> =
-    Pin<Box<dyn Future<Output = Result<ProviderResponse<V>, Infallible>> + Send + 'a>>;
+impl<'a, K, V> WeightedLruProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    /// Keep entries for `window` after they expire, and return them as
+    /// [`ProviderResponse::FoundStale`] during that time.
+    ///
+    /// See [`LruProvider::stale_window`].
+    pub fn stale_window(mut self, window: Duration) -> Self {
+        self.stale_window = Some(window);
+        self
+    }
+
+    /// Return the total weight of the entries in the cache.
+    ///
+    /// As with [`LruProvider::len`], this includes expired entries that
+    /// haven't been removed yet.
+    pub fn weight(&self) -> usize {
+        self.lock().weight
+    }
+
+    /// Return the maximum total weight of the cache.
+    pub fn budget(&self) -> usize {
+        self.lock().budget
+    }
+
+    /// Return the number of entries in the cache.
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Return `true` if the cache has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.lock().entries.is_empty()
+    }
+
+    /// Return a snapshot of the keys in the cache, from the most recently
+    /// used to the least recently used.
+    pub fn keys(&self) -> Vec<K>
+    where
+        K: Clone,
+    {
+        self.lock()
+            .entries
+            .iter()
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Lock the cache
+    ///
+    /// See [`LruProvider::read`] for poisoning.
+    fn lock(&self) -> MutexGuard<'_, Weighted<K, V>> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Create an entry for `value`, with the default TTL if `ttl` is `None`
+    fn entry(&self, value: V, ttl: Option<Duration>) -> (Entry<V>, usize) {
+        let weight = (self.weigher)(&value);
+        let entry = Entry::new(value, ttl.or(self.ttl)).stale_for(self.stale_window);
+        (entry, weight)
+    }
+}
+
+impl<'a, K, V> Service<ProviderRequest<K, V>> for WeightedLruProvider<'a, K, V>
+where
+    K: Eq + Hash + Send + 'a,
+    V: Clone + Send + 'a,
+{
+    type Response = ProviderResponse<K, V>;
+    type Error = Infallible;
+    type Future = ProviderFuture<'a, K, V>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ProviderRequest<K, V>) -> Self::Future {
+        let response = match request {
+            ProviderRequest::Get(key) => {
+                let now = time::now();
+                let mut inner = self.lock();
+                match inner
+                    .entries
+                    .get(&key)
+                    .map(|(entry, _)| entry.response_at(now))
+                {
+                    Some(Some(response)) => response,
+                    // The entry has expired: remove it so it doesn't take up
+                    // budget anymore.
+                    Some(None) => {
+                        inner.remove(&key);
+                        ProviderResponse::NotFound
+                    }
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            ProviderRequest::Insert(key, value, ttl) => {
+                let (entry, weight) = self.entry(value.clone(), ttl);
+                self.lock().insert(key, entry, weight);
+                ProviderResponse::Found(value)
+            }
+            ProviderRequest::InsertNegative(key, ttl) => {
+                self.lock().insert(key, Entry::negative(ttl), 1);
+                ProviderResponse::FoundNegative
+            }
+            ProviderRequest::Clear => {
+                let mut inner = self.lock();
+                inner.entries.clear();
+                inner.weight = 0;
+                ProviderResponse::Cleared
+            }
+            ProviderRequest::Remove(key) => match self.lock().remove(&key) {
+                Some(_) => ProviderResponse::Removed,
+                None => ProviderResponse::NotFound,
+            },
+            // Peek at the entry to avoid updating its recency.
+            ProviderRequest::Contains(key) => {
+                let now = time::now();
+                let inner = self.lock();
+                let present = inner
+                    .entries
+                    .peek(&key)
+                    .is_some_and(|(entry, _)| !entry.is_expired(now));
+                ProviderResponse::Present(present)
+            }
+            ProviderRequest::Ttl(key) => {
+                let now = time::now();
+                let inner = self.lock();
+                match inner
+                    .entries
+                    .peek(&key)
+                    .and_then(|(entry, _)| entry.ttl_at(now))
+                {
+                    Some((remaining, ttl)) => ProviderResponse::Ttl(remaining, ttl),
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            ProviderRequest::Age(key) => {
+                let now = time::now();
+                let inner = self.lock();
+                match inner
+                    .entries
+                    .peek(&key)
+                    .and_then(|(entry, _)| entry.age_at(now))
+                {
+                    Some(age) => ProviderResponse::Age(age),
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            // Look up and insert under the same lock.
+            ProviderRequest::GetOrInsert(key, value) => {
+                let now = time::now();
+                let mut inner = self.lock();
+                let existing = inner
+                    .entries
+                    .get(&key)
+                    .and_then(|(entry, _)| entry.fresh_value_at(now));
+                match existing {
+                    Some(existing) => ProviderResponse::Found(existing.clone()),
+                    None => {
+                        let (entry, weight) = self.entry(value.clone(), None);
+                        inner.insert(key, entry, weight);
+                        ProviderResponse::Inserted(value)
+                    }
+                }
+            }
+            ProviderRequest::GetMany(keys) => {
+                let now = time::now();
+                let mut inner = self.lock();
+                ProviderResponse::Many(
+                    keys.into_iter()
+                        .map(|key| {
+                            let value = inner
+                                .entries
+                                .get(&key)
+                                .and_then(|(entry, _)| entry.value_at(now))
+                                .cloned();
+                            (key, value)
+                        })
+                        .collect(),
+                )
+            }
+            // Inserting entries one by one is as cheap as a batch in memory.
+            ProviderRequest::InsertMany(_) => ProviderResponse::NotFound,
+            // Evictions are not counted.
+            ProviderRequest::Stats => ProviderResponse::NotFound,
+        };
+        Box::pin(ready(Ok(response)))
+    }
+}
+
+/// Entries of a [`WeightedLruProvider`], with their weight
+struct Weighted<K, V> {
+    entries: LruCache<K, (Entry<V>, usize)>,
+    weight: usize,
+    budget: usize,
+}
+
+impl<K, V> Weighted<K, V>
+where
+    K: Eq + Hash,
+{
+    /// Store `entry`, evicting the least recently used entries until the
+    /// total weight fits in the budget
+    fn insert(&mut self, key: K, entry: Entry<V>, weight: usize) {
+        self.remove(&key);
+        if weight > self.budget {
+            return;
+        }
+        self.weight += weight;
+        self.entries.push(key, (entry, weight));
+        while self.weight > self.budget {
+            match self.entries.pop_lru() {
+                Some((_, (_, evicted))) => self.weight -= evicted,
+                None => break,
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &K) -> Option<Entry<V>> {
+        let (entry, weight) = self.entries.pop(key)?;
+        self.weight -= weight;
+        Some(entry)
+    }
+}
+
+type ProviderFuture<'a, K, V> =
+    Pin<Box<dyn Future<Output = Result<ProviderResponse<K, V>, Infallible>> + Send + 'a>>;
+
+/// Error returned when creating an [`LruProvider`] with a capacity of `0`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CapacityError;
+
+impl error::Error for CapacityError {}
+
+impl fmt::Display for CapacityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "capacity must be greater than zero")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{hash::BuildHasherDefault, sync::atomic::AtomicUsize};
+
+    #[test]
+    fn test_try_new_zero() {
+        let res = LruProvider::try_new::<String, String>(0);
+        assert_eq!(res.err(), Some(CapacityError));
+    }
+
+    #[tokio::test]
+    async fn test_new_zero_clamped() -> Result<(), Infallible> {
+        let mut provider = LruProvider::new::<String, String>(0);
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_with_hasher() -> Result<(), Infallible> {
+        // Deterministic hasher, unlike the default one
+        type Hasher = BuildHasherDefault<std::collections::hash_map::DefaultHasher>;
+
+        let mut provider = LruProvider::with_hasher::<String, String, _>(10, Hasher::default());
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        let res = provider
+            .clone()
+            .call(ProviderRequest::Get("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_capacity_one_evicts() -> Result<(), Infallible> {
+        let mut provider = LruProvider::try_new::<String, String>(1).unwrap();
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        provider
+            .call(ProviderRequest::Insert(
+                "b".to_string(),
+                "B".to_string(),
+                None,
+            ))
+            .await?;
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        let res = provider.call(ProviderRequest::Get("b".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "B"));
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ttl_expires() -> Result<(), Infallible> {
+        let mut provider = LruProvider::with_ttl::<String, String>(10, Duration::from_millis(50));
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        // The expired entry is removed lazily on Get.
+        assert_eq!(provider.inner.read().unwrap().len(), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_sweep() -> Result<(), Infallible> {
+        let mut provider = LruProvider::new::<usize, usize>(1000);
+
+        // Spans several chunks, with expired entries in all of them
+        for i in 0..600 {
+            let ttl = (i % 2 == 0).then_some(Duration::from_millis(20));
+            provider.call(ProviderRequest::Insert(i, i, ttl)).await?;
+        }
+        tokio::time::sleep(Duration::from_millis(30)).await;
+
+        assert_eq!(provider.sweep(), 300);
+        assert_eq!(provider.len(), 300);
+        assert!(provider.keys().iter().all(|i| i % 2 == 1));
+        assert_eq!(provider.sweep(), 0);
+        assert_eq!(provider.evictions.load(Ordering::Relaxed), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_sweeper() -> Result<(), Infallible> {
+        let mut provider = LruProvider::with_ttl::<usize, usize>(100, Duration::from_millis(20));
+        let sweeper = provider.start_sweeper(Duration::from_millis(10));
+
+        for i in 0..10 {
+            provider.call(ProviderRequest::Insert(i, i, None)).await?;
+        }
+        assert_eq!(provider.len(), 10);
+
+        // Expired entries are removed without being looked up
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(provider.len(), 0);
+
+        // The sweeper stops once the provider is dropped
+        drop(provider);
+        tokio::time::timeout(Duration::from_secs(1), sweeper)
+            .await
+            .expect("sweeper didn't stop")
+            .unwrap();
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_per_entry_ttl() -> Result<(), Infallible> {
+        let mut provider = LruProvider::with_ttl::<String, String>(10, Duration::from_secs(10));
+
+        provider
+            .call(ProviderRequest::Insert(
+                "short".to_string(),
+                "S".to_string(),
+                Some(Duration::from_millis(50)),
+            ))
+            .await?;
+        provider
+            .call(ProviderRequest::Insert(
+                "long".to_string(),
+                "L".to_string(),
+                Some(Duration::from_millis(150)),
+            ))
+            .await?;
+        provider
+            .call(ProviderRequest::Insert(
+                "default".to_string(),
+                "D".to_string(),
+                None,
+            ))
+            .await?;
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let res = provider
+            .call(ProviderRequest::Get("short".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        let res = provider
+            .call(ProviderRequest::Get("long".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "L"));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let res = provider
+            .call(ProviderRequest::Get("long".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        // Entries without their own TTL fall back to the provider default.
+        let res = provider
+            .call(ProviderRequest::Get("default".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "D"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ttl_boundary() -> Result<(), Infallible> {
+        // With a zero TTL, `now >= inserted + ttl` holds immediately.
+        let mut provider = LruProvider::with_ttl::<String, String>(10, Duration::ZERO);
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_peek_reads() -> Result<(), Infallible> {
+        let mut provider = LruProvider::new::<String, String>(2).peek_reads(true);
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        provider
+            .call(ProviderRequest::Insert(
+                "b".to_string(),
+                "B".to_string(),
+                None,
+            ))
+            .await?;
+
+        // Reading "a" doesn't promote it, so it is still evicted first.
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+        provider
+            .call(ProviderRequest::Insert(
+                "c".to_string(),
+                "C".to_string(),
+                None,
+            ))
+            .await?;
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        let res = provider.call(ProviderRequest::Get("b".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "B"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_peek_reads_ttl() -> Result<(), Infallible> {
+        let mut provider =
+            LruProvider::with_ttl::<String, String>(10, Duration::ZERO).peek_reads(true);
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        assert_eq!(provider.inner.read().unwrap().len(), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_arc_shared_allocation() -> Result<(), Infallible> {
+        let mut provider: ArcLruProvider<String, String> = LruProvider::new(10);
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                Arc::new("A".to_string()),
+                None,
+            ))
+            .await?;
+
+        let first = tokio::spawn(provider.call(ProviderRequest::Get("a".to_string())));
+        let second = tokio::spawn(provider.call(ProviderRequest::Get("a".to_string())));
+
+        match (first.await.unwrap()?, second.await.unwrap()?) {
+            (ProviderResponse::Found(first), ProviderResponse::Found(second)) => {
+                assert!(Arc::ptr_eq(&first, &second));
+            }
+            _ => panic!("expected two cache hits"),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_on_evict_fn() -> Result<(), Infallible> {
+        let evicted = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut provider = LruProvider::new::<usize, usize>(2).on_evict_fn({
+            let evicted = evicted.clone();
+            move |key, value| evicted.lock().unwrap().push((*key, *value))
+        });
+
+        for i in 0..4 {
+            provider
+                .call(ProviderRequest::Insert(i, i * 10, None))
+                .await?;
+        }
+
+        // Entries are evicted in least-recently-used order.
+        assert_eq!(*evicted.lock().unwrap(), [(0, 0), (1, 10)]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_borrowed() -> Result<(), Infallible> {
+        let mut provider = LruProvider::new::<String, String>(2);
+        for key in ["a", "b"] {
+            provider
+                .call(ProviderRequest::Insert(
+                    key.to_string(),
+                    key.to_uppercase(),
+                    None,
+                ))
+                .await?;
+        }
+
+        // Look up `a` with a `&str`, which also makes it the most recently
+        // used entry.
+        assert_eq!(provider.get("a"), ProviderResponse::Found("A".to_string()));
+        provider
+            .call(ProviderRequest::Insert(
+                "c".to_string(),
+                "C".to_string(),
+                None,
+            ))
+            .await?;
+        assert_eq!(provider.get("b"), ProviderResponse::NotFound);
+        assert_eq!(provider.get("a"), ProviderResponse::Found("A".to_string()));
+
+        // Expired entries are removed.
+        provider
+            .call(ProviderRequest::Insert(
+                "d".to_string(),
+                "D".to_string(),
+                Some(Duration::ZERO),
+            ))
+            .await?;
+        assert_eq!(provider.get("d"), ProviderResponse::NotFound);
+        assert_eq!(provider.len(), 1);
+
+        Ok(())
+    }
+
+    /// Value counting how many times it was cloned
+    #[derive(Debug, Default)]
+    struct Counted(Arc<AtomicUsize>);
+
+    impl Clone for Counted {
+        fn clone(&self) -> Self {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Self(self.0.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clones() -> Result<(), Infallible> {
+        let clones = Arc::new(AtomicUsize::new(0));
+        let mut provider = LruProvider::new::<usize, Counted>(10);
+
+        let res = provider
+            .call(ProviderRequest::Insert(1, Counted(clones.clone()), None))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Found(_)));
+        assert_eq!(clones.load(Ordering::SeqCst), 1);
+
+        let entries = (2..4).map(|key| (key, Counted(clones.clone()), None));
+        provider
+            .call(ProviderRequest::InsertMany(entries.collect()))
+            .await?;
+        assert_eq!(clones.load(Ordering::SeqCst), 3);
+
+        provider
+            .call(ProviderRequest::GetOrInsert(4, Counted(clones.clone())))
+            .await?;
+        assert_eq!(clones.load(Ordering::SeqCst), 4);
+
+        // Lookups clone the stored value for the response.
+        provider.call(ProviderRequest::Get(1)).await?;
+        assert_eq!(clones.load(Ordering::SeqCst), 5);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stats() -> Result<(), Infallible> {
+        let mut provider = LruProvider::new::<usize, usize>(3);
+
+        for i in 0..5 {
+            provider.call(ProviderRequest::Insert(i, i, None)).await?;
+        }
+        // Replacing an entry doesn't evict anything.
+        provider.call(ProviderRequest::Insert(4, 40, None)).await?;
+        let res = provider.call(ProviderRequest::Stats).await?;
+        assert_eq!(
+            res,
+            ProviderResponse::Stats {
+                len: 3,
+                capacity: 3,
+                evictions: 2
+            }
+        );
+
+        // Entries dropped when shrinking count as evictions too.
+        provider.resize(NonZeroUsize::MIN);
+        let res = provider.clone().call(ProviderRequest::Stats).await?;
+        assert_eq!(
+            res,
+            ProviderResponse::Stats {
+                len: 1,
+                capacity: 1,
+                evictions: 4
+            }
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_on_evict() -> Result<(), Infallible> {
+        #[derive(Default)]
+        struct Recorder(std::sync::Mutex<Vec<(String, String)>>);
+
+        impl CacheEventListener<String, String> for Recorder {
+            fn on_evict(&self, key: &String, value: &String) {
+                self.0.lock().unwrap().push((key.clone(), value.clone()));
+            }
+        }
+
+        let recorder = Arc::new(Recorder::default());
+        let mut provider = LruProvider::new::<String, String>(1).on_evict(recorder.clone());
+
+        for (key, value) in [("a", "A"), ("a", "AA"), ("b", "B")] {
+            provider
+                .call(ProviderRequest::Insert(
+                    key.to_string(),
+                    value.to_string(),
+                    None,
+                ))
+                .await?;
+        }
+        provider
+            .call(ProviderRequest::Remove("b".to_string()))
+            .await?;
+
+        // Replacing "a" and removing "b" are not evictions.
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [("a".to_string(), "AA".to_string())]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_or_insert() -> Result<(), Infallible> {
+        let mut provider = LruProvider::new::<String, String>(10);
+
+        let res = provider
+            .call(ProviderRequest::GetOrInsert(
+                "a".to_string(),
+                "A".to_string(),
+            ))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Inserted(v) if v == "A"));
+        let res = provider
+            .call(ProviderRequest::GetOrInsert(
+                "a".to_string(),
+                "B".to_string(),
+            ))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+
+        // Negative entries are replaced.
+        provider
+            .call(ProviderRequest::InsertNegative(
+                "b".to_string(),
+                Duration::from_secs(10),
+            ))
+            .await?;
+        let res = provider
+            .call(ProviderRequest::GetOrInsert(
+                "b".to_string(),
+                "B".to_string(),
+            ))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Inserted(v) if v == "B"));
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_get_or_insert_race() {
+        let provider = LruProvider::new::<usize, usize>(1000);
+
+        for key in 0..100 {
+            // The lookup happens when calling the provider, so call it from
+            // the spawned tasks.
+            let mut p1 = provider.clone();
+            let first =
+                tokio::spawn(async move { p1.call(ProviderRequest::GetOrInsert(key, 1)).await });
+            let mut p2 = provider.clone();
+            let second =
+                tokio::spawn(async move { p2.call(ProviderRequest::GetOrInsert(key, 2)).await });
+
+            let (first, second) = (first.await.unwrap(), second.await.unwrap());
+            let (value, inserted) = match (first, second) {
+                (Ok(ProviderResponse::Inserted(a)), Ok(ProviderResponse::Found(b)))
+                | (Ok(ProviderResponse::Found(b)), Ok(ProviderResponse::Inserted(a))) => (b, a),
+                res => panic!("expected exactly one insertion, got {:?}", res),
+            };
+            assert_eq!(value, inserted);
+
+            // Only the first value is stored.
+            let res = provider.clone().call(ProviderRequest::Get(key)).await;
+            assert!(matches!(res, Ok(ProviderResponse::Found(v)) if v == inserted));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_export_import() -> Result<(), Infallible> {
+        let mut provider = LruProvider::new::<usize, String>(10);
+
+        for i in 0..3 {
+            provider
+                .call(ProviderRequest::Insert(i, i.to_string(), None))
+                .await?;
+        }
+        provider.call(ProviderRequest::Get(0)).await?;
+        provider
+            .call(ProviderRequest::InsertNegative(9, Duration::from_secs(10)))
+            .await?;
+
+        // The negative entry isn't exported.
+        let entries = provider.export();
+        assert_eq!(
+            entries,
+            [
+                (0, "0".to_string()),
+                (2, "2".to_string()),
+                (1, "1".to_string())
+            ]
+        );
+
+        let mut restored = LruProvider::new::<usize, String>(10);
+        restored.import(entries.clone());
+        assert_eq!(restored.keys(), [0, 2, 1]);
+        assert_eq!(restored.export(), entries);
+        let res = restored.call(ProviderRequest::Get(2)).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "2"));
+
+        // With a smaller capacity, the least recently used entries are
+        // dropped.
+        let smaller = LruProvider::new::<usize, String>(2);
+        smaller.import(entries);
+        assert_eq!(smaller.keys(), [0, 2]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_insert_many() -> Result<(), Infallible> {
+        let evicted = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut provider = LruProvider::new::<usize, usize>(2).on_evict_fn({
+            let evicted = evicted.clone();
+            move |key, _| evicted.lock().unwrap().push(*key)
+        });
+
+        let entries = (0..3).map(|i| (i, i * 10, None)).collect();
+        let res = provider.call(ProviderRequest::InsertMany(entries)).await?;
+        assert!(
+            matches!(res, ProviderResponse::Many(v) if v == [(0, Some(0)), (1, Some(10)), (2, Some(20))])
+        );
+
+        assert_eq!(provider.keys(), [2, 1]);
+        assert_eq!(*evicted.lock().unwrap(), [0]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_many() -> Result<(), Infallible> {
+        let mut provider = LruProvider::new::<String, String>(10);
+
+        for key in ["a", "c"] {
+            provider
+                .call(ProviderRequest::Insert(
+                    key.to_string(),
+                    key.to_uppercase(),
+                    None,
+                ))
+                .await?;
+        }
+        provider
+            .call(ProviderRequest::InsertNegative(
+                "d".to_string(),
+                Duration::from_secs(10),
+            ))
+            .await?;
+
+        let keys = ["c", "b", "a", "d"].map(|key| key.to_string()).to_vec();
+        let res = provider.call(ProviderRequest::GetMany(keys)).await?;
+        match res {
+            ProviderResponse::Many(values) => assert_eq!(
+                values,
+                [
+                    ("c".to_string(), Some("C".to_string())),
+                    ("b".to_string(), None),
+                    ("a".to_string(), Some("A".to_string())),
+                    ("d".to_string(), None),
+                ]
+            ),
+            _ => panic!("expected a batch response"),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_resize() -> Result<(), Infallible> {
+        let mut provider = LruProvider::new::<usize, usize>(2);
+        let other = provider.clone();
+
+        other.resize(NonZeroUsize::new(4).unwrap());
+        for i in 0..4 {
+            provider.call(ProviderRequest::Insert(i, i, None)).await?;
+        }
+        assert_eq!(provider.inner.read().unwrap().len(), 4);
+
+        // The least recently used entries are evicted right away.
+        provider.call(ProviderRequest::Get(0)).await?;
+        other.resize(NonZeroUsize::new(2).unwrap());
+        assert_eq!(provider.inner.read().unwrap().len(), 2);
+        for (key, present) in [(0, true), (1, false), (2, false), (3, true)] {
+            let res = provider.call(ProviderRequest::Contains(key)).await?;
+            assert!(matches!(res, ProviderResponse::Present(p) if p == present));
+        }
+
+        // The new capacity applies to later inserts.
+        provider.call(ProviderRequest::Insert(4, 4, None)).await?;
+        assert_eq!(provider.inner.read().unwrap().len(), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_configure() -> Result<(), Infallible> {
+        let config = ProviderConfig::new()
+            .ttl(Duration::from_secs(60))
+            .capacity(2);
+        let mut provider = LruProvider::new::<usize, usize>(10).configure(&config);
+
+        for i in 0..3 {
+            provider.call(ProviderRequest::Insert(i, i, None)).await?;
+        }
+        assert_eq!(provider.keys(), vec![2, 1]);
+
+        let res = provider.call(ProviderRequest::Ttl(1)).await?;
+        assert!(matches!(res, ProviderResponse::Ttl(_, ttl) if ttl == Duration::from_secs(60)));
+
+        // Fields that are not set leave the provider unchanged.
+        let mut provider = provider.configure(&ProviderConfig::new());
+        provider.call(ProviderRequest::Insert(3, 3, None)).await?;
+        assert_eq!(provider.len(), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_age() -> Result<(), Infallible> {
+        let mut provider = LruProvider::new::<usize, usize>(10);
+        provider.call(ProviderRequest::Insert(1, 1, None)).await?;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let res = provider.call(ProviderRequest::Age(1)).await?;
+        assert!(matches!(res, ProviderResponse::Age(age) if age >= Duration::from_millis(20)));
+        let res = provider.call(ProviderRequest::Age(2)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_with_lock() -> Result<(), Infallible> {
+        let mut provider = LruProvider::new::<usize, usize>(10);
+        for i in 0..6 {
+            provider.call(ProviderRequest::Insert(i, i, None)).await?;
+        }
+        provider
+            .call(ProviderRequest::InsertNegative(6, Duration::from_secs(60)))
+            .await?;
+
+        // Drop the odd values and the negative entries in one pass.
+        let removed = provider.with_lock(|cache| {
+            let keys: Vec<_> = cache
+                .iter()
+                .filter(|(_, entry)| entry.value().is_none_or(|value| value % 2 == 1))
+                .map(|(key, _)| *key)
+                .collect();
+            for key in &keys {
+                cache.pop(key);
+            }
+            keys.len()
+        });
+        assert_eq!(removed, 4);
+        assert_eq!(provider.keys(), vec![4, 2, 0]);
+
+        let expired = provider.with_lock(|cache| {
+            let now = time::now();
+            cache
+                .iter()
+                .filter(|(_, entry)| entry.is_expired(now))
+                .count()
+        });
+        assert_eq!(expired, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_len_keys() -> Result<(), Infallible> {
+        let mut provider = LruProvider::new::<usize, usize>(3);
+        assert!(provider.is_empty());
+
+        for i in 0..3 {
+            provider.call(ProviderRequest::Insert(i, i, None)).await?;
+            assert_eq!(provider.len(), i + 1);
+        }
+        provider.call(ProviderRequest::Get(0)).await?;
+        assert_eq!(provider.keys(), [0, 2, 1]);
+
+        // Evictions keep the length at the capacity.
+        provider.call(ProviderRequest::Insert(3, 3, None)).await?;
+        assert_eq!(provider.len(), 3);
+        assert_eq!(provider.keys(), [3, 0, 2]);
+
+        provider.call(ProviderRequest::Remove(0)).await?;
+        assert_eq!(provider.len(), 2);
+        provider.call(ProviderRequest::Clear).await?;
+        assert!(provider.is_empty());
+        assert!(provider.keys().is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_peek_pop_lru() -> Result<(), Infallible> {
+        let mut provider = LruProvider::new::<usize, usize>(3);
+        assert_eq!(provider.peek_lru(), None);
+
+        for i in 0..3 {
+            provider
+                .call(ProviderRequest::Insert(i, i * 10, None))
+                .await?;
+        }
+        assert_eq!(provider.peek_lru(), Some((0, Some(0))));
+        provider.call(ProviderRequest::Get(0)).await?;
+        provider.call(ProviderRequest::Get(1)).await?;
+        // Peeking doesn't change the recency order.
+        assert_eq!(provider.peek_lru(), Some((2, Some(20))));
+        assert_eq!(provider.peek_lru(), Some((2, Some(20))));
+
+        assert_eq!(provider.pop_lru(), Some((2, Some(20))));
+        assert_eq!(provider.len(), 2);
+        // The freed capacity doesn't evict anything on the next insert.
+        provider.call(ProviderRequest::Insert(3, 30, None)).await?;
+        assert_eq!(provider.keys(), [3, 1, 0]);
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_insert_negative() -> Result<(), Infallible> {
+        let mut provider = LruProvider::new::<String, String>(10);
+
+        provider
+            .call(ProviderRequest::InsertNegative(
+                "a".to_string(),
+                Duration::from_millis(50),
+            ))
+            .await?;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::FoundNegative));
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_clear() -> Result<(), Infallible> {
+        let mut provider = LruProvider::new::<String, String>(10);
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        let res = provider.call(ProviderRequest::Clear).await?;
+        assert!(matches!(res, ProviderResponse::Cleared));
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remove() -> Result<(), Infallible> {
+        let mut provider = LruProvider::new::<String, String>(10);
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        provider
+            .call(ProviderRequest::Insert(
+                "b".to_string(),
+                "B".to_string(),
+                None,
+            ))
+            .await?;
+
+        let res = provider
+            .call(ProviderRequest::Remove("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Removed));
+        let res = provider
+            .call(ProviderRequest::Remove("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        let res = provider.call(ProviderRequest::Get("b".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "B"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_contains_no_promote() -> Result<(), Infallible> {
+        let mut provider = LruProvider::new::<String, String>(2);
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        provider
+            .call(ProviderRequest::Insert(
+                "b".to_string(),
+                "B".to_string(),
+                None,
+            ))
+            .await?;
+
+        // "a" is the least recently used entry, and checking for it doesn't
+        // save it from eviction.
+        let res = provider
+            .call(ProviderRequest::Contains("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(true)));
+        provider
+            .call(ProviderRequest::Insert(
+                "c".to_string(),
+                "C".to_string(),
+                None,
+            ))
+            .await?;
+
+        let res = provider
+            .call(ProviderRequest::Contains("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(false)));
+        let res = provider
+            .call(ProviderRequest::Contains("b".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(true)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_contains_expired() -> Result<(), Infallible> {
+        let mut provider = LruProvider::with_ttl::<String, String>(10, Duration::ZERO);
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        let res = provider
+            .call(ProviderRequest::Contains("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(false)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_poisoned_lock() -> Result<(), Infallible> {
+        let mut provider = LruProvider::new::<String, String>(10);
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+
+        // Panic while holding the lock
+        let inner = provider.inner.clone();
+        let res = std::thread::spawn(move || {
+            let _guard = inner.write().unwrap();
+            panic!("poison the lock");
+        })
+        .join();
+        assert!(res.is_err());
+        assert!(provider.inner.is_poisoned());
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+        provider
+            .call(ProviderRequest::Insert(
+                "b".to_string(),
+                "B".to_string(),
+                None,
+            ))
+            .await?;
+        let res = provider.call(ProviderRequest::Get("b".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "B"));
+        assert_eq!(provider.len(), 2);
+
+        Ok(())
+    }
+
+    fn weighted() -> WeightedLruProvider<'static, u32, String> {
+        WeightedLruProvider::new(10, |v: &String| v.len())
+    }
+
+    #[tokio::test]
+    async fn test_weighted_evicts_oldest() -> Result<(), Infallible> {
+        let mut provider = weighted();
+
+        for (key, value) in [(1, "aaa"), (2, "bbbb"), (3, "cc")] {
+            provider
+                .call(ProviderRequest::Insert(key, value.to_string(), None))
+                .await?;
+        }
+        assert_eq!(provider.weight(), 9);
+        assert_eq!(provider.keys(), vec![3, 2, 1]);
+
+        // Key 1 is now the most recently used
+        provider.call(ProviderRequest::Get(1)).await?;
+
+        // A large value evicts several entries at once
+        provider
+            .call(ProviderRequest::Insert(4, "dddddd".to_string(), None))
+            .await?;
+        assert_eq!(provider.keys(), vec![4, 1]);
+        assert_eq!(provider.weight(), 9);
+        assert!(provider.weight() <= provider.budget());
+
+        let res = provider.call(ProviderRequest::Get(2)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        let res = provider.call(ProviderRequest::Get(1)).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "aaa"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_weighted_budget() -> Result<(), Infallible> {
+        let mut provider = weighted();
+
+        for key in 0..20 {
+            let value = "x".repeat(key as usize % 7);
+            provider
+                .call(ProviderRequest::Insert(key, value, None))
+                .await?;
+            assert!(provider.weight() <= 10);
+        }
+
+        // Replacing an entry doesn't count its previous weight
+        provider.call(ProviderRequest::Clear).await?;
+        for _ in 0..3 {
+            provider
+                .call(ProviderRequest::Insert(1, "aaaa".to_string(), None))
+                .await?;
+        }
+        assert_eq!(provider.weight(), 4);
+        assert_eq!(provider.len(), 1);
+
+        // Values heavier than the budget are not stored, and replace the
+        // previous entry
+        provider
+            .call(ProviderRequest::Insert(1, "a".repeat(11), None))
+            .await?;
+        assert!(provider.is_empty());
+        assert_eq!(provider.weight(), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_weighted_configure() -> Result<(), Infallible> {
+        let config = ProviderConfig::new()
+            .ttl(Duration::from_secs(60))
+            .capacity(1);
+        let mut provider = weighted().configure(&config);
+
+        for key in 0..2 {
+            provider
+                .call(ProviderRequest::Insert(key, "aaaa".to_string(), None))
+                .await?;
+        }
+        // The budget still applies instead of the capacity.
+        assert_eq!(provider.len(), 2);
+
+        let res = provider.call(ProviderRequest::Ttl(0)).await?;
+        assert!(matches!(res, ProviderResponse::Ttl(_, ttl) if ttl == Duration::from_secs(60)));
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_weighted_remove_expired() -> Result<(), Infallible> {
+        let mut provider =
+            WeightedLruProvider::with_ttl::<u32, String>(10, Duration::from_millis(20), |v| {
+                v.len()
+            });
+
+        provider
+            .call(ProviderRequest::Insert(1, "aaa".to_string(), None))
+            .await?;
+        provider
+            .call(ProviderRequest::InsertNegative(2, Duration::from_secs(1)))
+            .await?;
+        assert_eq!(provider.weight(), 4);
+
+        let res = provider.call(ProviderRequest::Remove(2)).await?;
+        assert!(matches!(res, ProviderResponse::Removed));
+        assert_eq!(provider.weight(), 3);
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        let res = provider.call(ProviderRequest::Get(1)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        assert_eq!(provider.weight(), 0);
+
+        Ok(())
+    }
+
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+
+    type LruFuture<K, V> = <LruProvider<'static, K, V> as Service<ProviderRequest<K, V>>>::Future;
+    type LocalFuture<K, V> =
+        <LocalLruProvider<'static, K, V> as Service<ProviderRequest<K, V>>>::Future;
+
+    #[test]
+    fn test_send_sync() {
+        assert_send::<LruProvider<String, String>>();
+        assert_sync::<LruProvider<String, String>>();
+        assert_send::<ArcLruProvider<String, String>>();
+        assert_sync::<ArcLruProvider<String, String>>();
+        assert_send::<WeightedLruProvider<String, String>>();
+        assert_sync::<WeightedLruProvider<String, String>>();
+        assert_send::<LruFuture<String, String>>();
+        assert_send::<LruFuture<String, Arc<String>>>();
+
+        // Local providers and their futures are `Send` when the values are.
+        assert_send::<LocalLruProvider<String, String>>();
+        assert_sync::<LocalLruProvider<String, String>>();
+        assert_send::<LocalFuture<String, String>>();
+    }
+
+    #[tokio::test]
+    async fn test_local() -> Result<(), Infallible> {
+        let mut provider = LocalLruProvider::new::<usize, std::rc::Rc<usize>>(2);
+        for i in 0..3 {
+            let value = std::rc::Rc::new(i);
+            provider
+                .call(ProviderRequest::Insert(i, value, None))
+                .await?;
+        }
+
+        let res = provider.call(ProviderRequest::Get(2)).await?;
+        assert!(matches!(res, ProviderResponse::Found(value) if *value == 2));
+        let res = provider.call(ProviderRequest::Get(0)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        // The wrapped provider shares the same entries.
+        assert_eq!(provider.into_inner().keys(), vec![2, 1]);
+
+        Ok(())
+    }
+}
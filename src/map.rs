@@ -0,0 +1,458 @@
+//! # HashMap cache provider
+//!
+//! This is an implementation of a cache provider for [`crate::CacheLayer`]
+//! using a [`HashMap`] behind a [`RwLock`]. It is mostly useful for tests,
+//! or for small and enumerable sets of keys.
+//!
+//! **Note:** this provider is unbounded. Entries are never evicted, so the
+//! map grows with every distinct key it sees. Use a bounded provider such as
+//! [`crate::lru::LruProvider`] if the set of keys isn't known in advance.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, service_fn};
+//! use tower_cache::{
+//!     CacheLayer,
+//!     map::MapProvider,
+//! };
+//! async fn handler(req: String) -> Result<String, Infallible> {
+//!     Ok(req.to_uppercase())
+//! }
+//!
+//! // Initialize the cache provider service
+//! let map_provider = MapProvider::new::<String, String>();
+//!
+//! // Wrap the service with CacheLayer.
+//! let mut my_service = ServiceBuilder::new()
+//!     .layer(CacheLayer::new(map_provider))
+//!     .service(service_fn(handler));
+//!
+//! # tokio_test::block_on(async move {
+//! // Call the service
+//! let res = my_service.call("Hello".to_string()).await.unwrap();
+//! assert_eq!(res, "HELLO".to_string());
+//! # })
+//! ```
+//!
+
+use crate::{
+    entry::Entry, time, trace, Configure, ProviderConfig, ProviderRequest, ProviderResponse,
+};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    future::{ready, Future},
+    hash::Hash,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{Arc, RwLock},
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower::Service;
+
+/// Local, unbounded cache provider
+#[derive(Debug)]
+pub struct MapProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    inner: Arc<RwLock<HashMap<K, Entry<V>>>>,
+    ttl: Option<Duration>,
+    stale_window: Option<Duration>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> MapProvider<'a, (), ()> {
+    /// Create a new unbounded cache provider
+    pub fn new<K, V>() -> MapProvider<'a, K, V>
+    where
+        K: Eq + Hash,
+    {
+        MapProvider {
+            inner: Arc::new(RwLock::new(HashMap::new())),
+            ttl: None,
+            stale_window: None,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V> MapProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    /// Keep entries for `window` after they expire, and return them as
+    /// [`ProviderResponse::FoundStale`] during that time.
+    ///
+    /// This allows [`crate::CacheLayer::stale_while_revalidate`] to serve
+    /// them while they are refreshed. Entries without a TTL never become
+    /// stale.
+    pub fn stale_window(mut self, window: Duration) -> Self {
+        self.stale_window = Some(window);
+        self
+    }
+}
+
+// Custom implementation of Clone as the Clone derive doesn't mark MapProvider
+// as Clone if K or V is not clone.
+impl<'a, K, V> Clone for MapProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            ttl: self.ttl,
+            stale_window: self.stale_window,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+/// Sets the TTL. The capacity is ignored, as the provider is unbounded.
+impl<'a, K, V> Configure for MapProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    fn configure(mut self, config: &ProviderConfig) -> Self {
+        if let Some(ttl) = config.ttl {
+            self.ttl = Some(ttl);
+        }
+        if config.capacity.is_some() {
+            trace::unsupported_config::<Self>("capacity");
+        }
+        self
+    }
+}
+
+impl<'a, K, V> Service<ProviderRequest<K, V>> for MapProvider<'a, K, V>
+where
+    K: Eq + Hash + Send + 'a,
+    V: Clone + Send + 'a,
+{
+    type Response = ProviderResponse<K, V>;
+    type Error = Infallible;
+    type Future = ProviderFuture<'a, K, V>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ProviderRequest<K, V>) -> Self::Future {
+        Box::pin(ready(Ok(match request {
+            ProviderRequest::Get(key) => {
+                let now = time::now();
+                let response = {
+                    let inner = self.inner.read().unwrap();
+                    inner.get(&key).map(|entry| entry.response_at(now))
+                };
+                match response {
+                    Some(Some(response)) => response,
+                    // The entry has expired: remove it.
+                    Some(None) => {
+                        let mut inner = self.inner.write().unwrap();
+                        if inner.get(&key).is_some_and(|entry| entry.is_expired(now)) {
+                            inner.remove(&key);
+                        }
+                        ProviderResponse::NotFound
+                    }
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            ProviderRequest::Insert(key, value, ttl) => {
+                let entry =
+                    Entry::new(value.clone(), ttl.or(self.ttl)).stale_for(self.stale_window);
+                self.inner.write().unwrap().insert(key, entry);
+                ProviderResponse::Found(value)
+            }
+            ProviderRequest::InsertNegative(key, ttl) => {
+                self.inner
+                    .write()
+                    .unwrap()
+                    .insert(key, Entry::negative(ttl));
+                ProviderResponse::FoundNegative
+            }
+            ProviderRequest::Clear => {
+                self.inner.write().unwrap().clear();
+                ProviderResponse::Cleared
+            }
+            ProviderRequest::Remove(key) => match self.inner.write().unwrap().remove(&key) {
+                Some(_) => ProviderResponse::Removed,
+                None => ProviderResponse::NotFound,
+            },
+            ProviderRequest::Contains(key) => {
+                let now = time::now();
+                let inner = self.inner.read().unwrap();
+                let present = inner.get(&key).is_some_and(|entry| !entry.is_expired(now));
+                ProviderResponse::Present(present)
+            }
+            ProviderRequest::Ttl(key) => {
+                let now = time::now();
+                let inner = self.inner.read().unwrap();
+                match inner.get(&key).and_then(|entry| entry.ttl_at(now)) {
+                    Some((remaining, ttl)) => ProviderResponse::Ttl(remaining, ttl),
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            ProviderRequest::Age(key) => {
+                let now = time::now();
+                let inner = self.inner.read().unwrap();
+                match inner.get(&key).and_then(|entry| entry.age_at(now)) {
+                    Some(age) => ProviderResponse::Age(age),
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            // Look up and insert under the same lock.
+            ProviderRequest::GetOrInsert(key, value) => {
+                let now = time::now();
+                let mut inner = self.inner.write().unwrap();
+                match inner.get(&key).and_then(|entry| entry.fresh_value_at(now)) {
+                    Some(existing) => ProviderResponse::Found(existing.clone()),
+                    None => {
+                        let entry =
+                            Entry::new(value.clone(), self.ttl).stale_for(self.stale_window);
+                        inner.insert(key, entry);
+                        ProviderResponse::Inserted(value)
+                    }
+                }
+            }
+            ProviderRequest::GetMany(keys) => {
+                let now = time::now();
+                let inner = self.inner.read().unwrap();
+                ProviderResponse::Many(
+                    keys.into_iter()
+                        .map(|key| {
+                            let value = inner
+                                .get(&key)
+                                .and_then(|entry| entry.value_at(now))
+                                .cloned();
+                            (key, value)
+                        })
+                        .collect(),
+                )
+            }
+            // Inserting entries one by one is as cheap as a batch in memory.
+            ProviderRequest::InsertMany(_) => ProviderResponse::NotFound,
+            // Evictions are not counted.
+            ProviderRequest::Stats => ProviderResponse::NotFound,
+        })))
+    }
+}
+
+type ProviderFuture<'a, K, V> =
+    Pin<Box<dyn Future<Output = Result<ProviderResponse<K, V>, Infallible>> + Send + 'a>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::{check_configure, check_provider};
+
+    #[tokio::test]
+    async fn test_conformance() -> Result<(), Infallible> {
+        check_provider(MapProvider::new::<String, String>()).await
+    }
+
+    #[tokio::test]
+    async fn test_never_evicts() -> Result<(), Infallible> {
+        let mut provider = MapProvider::new::<usize, usize>();
+
+        // An LRU provider with a capacity of 10 would only keep the last 10
+        // entries.
+        for i in 0..1000 {
+            provider
+                .call(ProviderRequest::Insert(i, i * 2, None))
+                .await?;
+        }
+        assert_eq!(provider.inner.read().unwrap().len(), 1000);
+
+        for i in 0..1000 {
+            let res = provider.call(ProviderRequest::Get(i)).await?;
+            assert!(matches!(res, ProviderResponse::Found(v) if v == i * 2));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_per_entry_ttl() -> Result<(), Infallible> {
+        let mut provider = MapProvider::new::<String, String>();
+
+        provider
+            .call(ProviderRequest::Insert(
+                "short".to_string(),
+                "S".to_string(),
+                Some(Duration::from_millis(50)),
+            ))
+            .await?;
+        provider
+            .call(ProviderRequest::Insert(
+                "long".to_string(),
+                "L".to_string(),
+                Some(Duration::from_millis(150)),
+            ))
+            .await?;
+        provider
+            .call(ProviderRequest::Insert(
+                "default".to_string(),
+                "D".to_string(),
+                None,
+            ))
+            .await?;
+
+        let res = provider
+            .call(ProviderRequest::Ttl("long".to_string()))
+            .await?;
+        assert!(matches!(
+            res,
+            ProviderResponse::Ttl(remaining, ttl)
+                if remaining <= ttl && ttl == Duration::from_millis(150)
+        ));
+        let res = provider
+            .call(ProviderRequest::Ttl("default".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let res = provider
+            .call(ProviderRequest::Get("short".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        let res = provider
+            .call(ProviderRequest::Get("long".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "L"));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let res = provider
+            .call(ProviderRequest::Get("long".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        // Entries without their own TTL fall back to the provider default.
+        let res = provider
+            .call(ProviderRequest::Get("default".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "D"));
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_insert_negative() -> Result<(), Infallible> {
+        let mut provider = MapProvider::new::<String, String>();
+
+        provider
+            .call(ProviderRequest::InsertNegative(
+                "a".to_string(),
+                Duration::from_millis(50),
+            ))
+            .await?;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::FoundNegative));
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        assert!(provider.inner.read().unwrap().is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_ttl() -> Result<(), Infallible> {
+        let mut provider = MapProvider::new::<String, String>();
+
+        // `Duration::MAX` means the entry never expires.
+        let request =
+            ProviderRequest::Insert("a".to_string(), "A".to_string(), Some(Duration::MAX));
+        provider.call(request).await?;
+        let request = ProviderRequest::InsertNegative("b".to_string(), Duration::MAX);
+        provider.call(request).await?;
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+        let res = provider.call(ProviderRequest::Get("b".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::FoundNegative));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remove_contains_clear() -> Result<(), Infallible> {
+        let mut provider = MapProvider::new::<String, String>();
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        provider
+            .call(ProviderRequest::Insert(
+                "b".to_string(),
+                "B".to_string(),
+                None,
+            ))
+            .await?;
+
+        let res = provider
+            .call(ProviderRequest::Contains("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(true)));
+
+        let res = provider
+            .call(ProviderRequest::Remove("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Removed));
+        let res = provider
+            .call(ProviderRequest::Contains("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(false)));
+
+        let res = provider.call(ProviderRequest::Clear).await?;
+        assert!(matches!(res, ProviderResponse::Cleared));
+        assert!(provider.inner.read().unwrap().is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stale_window() -> Result<(), Infallible> {
+        let mut provider =
+            MapProvider::new::<String, String>().stale_window(Duration::from_millis(100));
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                Some(Duration::from_millis(50)),
+            ))
+            .await?;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::FoundStale(v) if v == "A"));
+        let res = provider
+            .call(ProviderRequest::Contains("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(true)));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_configure() -> Result<(), Infallible> {
+        let configure =
+            |config: &ProviderConfig| MapProvider::new::<usize, usize>().configure(config);
+        check_configure(configure, false).await
+    }
+}
@@ -0,0 +1,731 @@
+//! # Memcached cache provider
+//!
+//! This is an implementation of a cache provider for [`crate::CacheLayer`]
+//! backed by [Memcached](https://memcached.org/), which allows multiple
+//! instances of a service to share the same cache.
+//!
+//! Keys are formatted using their [`Display`](fmt::Display) implementation,
+//! or with the [`KeyCodec`] passed to [`MemcachedProvider::with_key_codec`],
+//! and prefixed with the prefix passed to [`MemcachedProvider::new`]. Memcached keys are
+//! limited to 250 bytes and cannot contain whitespace or control characters.
+//! Values are serialized as JSON by default, see
+//! [`MemcachedProvider::with_codec`] to use another [`Codec`], and prefixed
+//! with a tag byte that tells them apart from negative entries.
+//!
+//! Per-entry TTLs are backed by the expiration time of Memcached. As it only
+//! has a resolution of one second, TTLs are rounded up to the next second.
+//!
+//! Memcached can't list the keys with a given prefix, so
+//! [`ProviderRequest::Clear`] is unsupported unless flushing the whole server
+//! is enabled with [`MemcachedProvider::flush_on_clear`].
+//!
+//! ## Usage
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, service_fn};
+//! use tower_cache::{
+//!     CacheLayer,
+//!     memcached::MemcachedProvider,
+//! };
+//! async fn handler(req: String) -> Result<String, Infallible> {
+//!     Ok(req.to_uppercase())
+//! }
+//!
+//! # tokio_test::block_on(async move {
+//! // Initialize the cache provider service
+//! let client = memcache::Client::connect("memcache://127.0.0.1:11211").unwrap();
+//! let memcached_provider = MemcachedProvider::new::<String, String, _>(client, "my-app:");
+//!
+//! // Wrap the service with CacheLayer.
+//! let mut my_service = ServiceBuilder::new()
+//!     .layer(CacheLayer::new(memcached_provider))
+//!     .service(service_fn(handler));
+//!
+//! // Call the service
+//! let res = my_service.call("Hello".to_string()).await.unwrap();
+//! assert_eq!(res, "HELLO".to_string());
+//! # })
+//! ```
+//!
+
+use crate::{
+    codec::{Codec, DisplayKey, JsonCodec, KeyCodec},
+    trace, Configure, ProviderConfig, ProviderRequest, ProviderResponse,
+};
+use std::{
+    error, fmt,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tower::Service;
+
+/// Client sending commands to Memcached
+///
+/// This is implemented for [`memcache::Client`], which keeps a pool of
+/// connections and can be cloned cheaply. Commands are blocking, so the
+/// provider runs them on the blocking thread pool of the Tokio runtime.
+pub trait MemcachedClient: Clone + Send + Sync + 'static {
+    /// Error returned by the client
+    type Error: error::Error + Send + Sync + 'static;
+
+    /// Return the value stored for `key`, if any
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Self::Error>;
+
+    /// Store `value` for `key`
+    ///
+    /// `expiration` follows the Memcached protocol: `0` never expires, up to
+    /// 30 days is a number of seconds, and anything above is a Unix
+    /// timestamp.
+    fn set(&self, key: &str, value: &[u8], expiration: u32) -> Result<(), Self::Error>;
+
+    /// Delete the value stored for `key`, returning whether there was one
+    fn delete(&self, key: &str) -> Result<bool, Self::Error>;
+
+    /// Remove all values from the server
+    fn flush(&self) -> Result<(), Self::Error>;
+}
+
+impl MemcachedClient for memcache::Client {
+    type Error = memcache::MemcacheError;
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Self::Error> {
+        memcache::Client::get(self, key)
+    }
+
+    fn set(&self, key: &str, value: &[u8], expiration: u32) -> Result<(), Self::Error> {
+        memcache::Client::set(self, key, value, expiration)
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, Self::Error> {
+        memcache::Client::delete(self, key)
+    }
+
+    fn flush(&self) -> Result<(), Self::Error> {
+        memcache::Client::flush(self)
+    }
+}
+
+/// Memcached cache provider
+///
+/// The provider is generic over the client, which is usually a
+/// [`memcache::Client`]. Cloning the provider shares the underlying
+/// connection pool.
+pub struct MemcachedProvider<'a, K, V, C, E = JsonCodec, KC = DisplayKey> {
+    client: C,
+    prefix: Arc<str>,
+    ttl: Option<Duration>,
+    codec: E,
+    key_codec: KC,
+    flush_on_clear: bool,
+    _types: PhantomData<fn() -> (K, V)>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> MemcachedProvider<'a, (), (), ()> {
+    /// Create a new Memcached cache provider
+    ///
+    /// All keys are prefixed by `prefix` before being sent to Memcached.
+    pub fn new<K, V, C>(client: C, prefix: impl Into<String>) -> MemcachedProvider<'a, K, V, C>
+    where
+        C: MemcachedClient,
+    {
+        MemcachedProvider {
+            client,
+            prefix: prefix.into().into(),
+            ttl: None,
+            codec: JsonCodec,
+            key_codec: DisplayKey,
+            flush_on_clear: false,
+            _types: PhantomData,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V, C, E, KC> MemcachedProvider<'a, K, V, C, E, KC> {
+    /// Use a different [`Codec`] to serialize values
+    pub fn with_codec<NE>(self, codec: NE) -> MemcachedProvider<'a, K, V, C, NE, KC> {
+        MemcachedProvider {
+            client: self.client,
+            prefix: self.prefix,
+            ttl: self.ttl,
+            codec,
+            key_codec: self.key_codec,
+            flush_on_clear: self.flush_on_clear,
+            _types: PhantomData,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Use a different [`KeyCodec`] to build Memcached keys
+    ///
+    /// ```rust,no_run
+    /// use tower_cache::{codec::TupleKey, memcached::MemcachedProvider};
+    ///
+    /// let client = memcache::Client::connect("memcache://127.0.0.1:11211").unwrap();
+    /// // Keys such as `(42, "en")` are stored as `my-app:42:en`
+    /// let memcached_provider =
+    ///     MemcachedProvider::new::<(u64, String), String, _>(client, "my-app:")
+    ///         .with_key_codec(TupleKey::new());
+    /// ```
+    pub fn with_key_codec<NK>(self, key_codec: NK) -> MemcachedProvider<'a, K, V, C, E, NK> {
+        MemcachedProvider {
+            client: self.client,
+            prefix: self.prefix,
+            ttl: self.ttl,
+            codec: self.codec,
+            key_codec,
+            flush_on_clear: self.flush_on_clear,
+            _types: PhantomData,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Flush the whole Memcached server on [`ProviderRequest::Clear`]
+    ///
+    /// Memcached can't list keys, so this removes every value on the server,
+    /// including those stored with other prefixes or by other applications.
+    /// When disabled, which is the default, `Clear` returns
+    /// [`ProviderResponse::NotFound`].
+    pub fn flush_on_clear(mut self, enabled: bool) -> Self {
+        self.flush_on_clear = enabled;
+        self
+    }
+}
+
+// Custom implementation of Clone as the Clone derive doesn't mark
+// MemcachedProvider as Clone if K or V is not clone.
+impl<'a, K, V, C, E, KC> Clone for MemcachedProvider<'a, K, V, C, E, KC>
+where
+    C: Clone,
+    E: Clone,
+    KC: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            prefix: self.prefix.clone(),
+            ttl: self.ttl,
+            codec: self.codec.clone(),
+            key_codec: self.key_codec.clone(),
+            flush_on_clear: self.flush_on_clear,
+            _types: PhantomData,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V, C, E, KC> fmt::Debug for MemcachedProvider<'a, K, V, C, E, KC> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MemcachedProvider")
+            .field("prefix", &self.prefix)
+            .field("ttl", &self.ttl)
+            .field("flush_on_clear", &self.flush_on_clear)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, K, V, C, E, KC> MemcachedProvider<'a, K, V, C, E, KC>
+where
+    KC: KeyCodec<K>,
+{
+    /// Return the Memcached key for a given cache key
+    fn key(&self, key: &K) -> String {
+        format!("{}{}", self.prefix, self.key_codec.encode_key(key))
+    }
+}
+
+/// Sets the default TTL of entries inserted without one. The capacity is
+/// ignored, as the memory limit is set on the Memcached server.
+impl<'a, K, V, C, E, KC> Configure for MemcachedProvider<'a, K, V, C, E, KC> {
+    fn configure(mut self, config: &ProviderConfig) -> Self {
+        if let Some(ttl) = config.ttl {
+            self.ttl = Some(ttl);
+        }
+        if config.capacity.is_some() {
+            trace::unsupported_config::<Self>("capacity");
+        }
+        self
+    }
+}
+
+impl<'a, K, V, C, E, KC> Service<ProviderRequest<K, V>> for MemcachedProvider<'a, K, V, C, E, KC>
+where
+    KC: KeyCodec<K>,
+    V: Send + 'a,
+    C: MemcachedClient,
+    E: Codec<V> + Clone + Send + 'a,
+{
+    type Response = ProviderResponse<K, V>;
+    type Error = Error;
+    type Future = ProviderFuture<'a, K, V>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ProviderRequest<K, V>) -> Self::Future {
+        let client = self.client.clone();
+        let codec = self.codec.clone();
+
+        match request {
+            ProviderRequest::Get(key) => {
+                let key = self.key(&key);
+                Box::pin(async move {
+                    let value = blocking(move || client.get(&key)).await?;
+                    Ok(match value.as_deref().map(untag).transpose()? {
+                        Some(Some(value)) => {
+                            ProviderResponse::Found(codec.decode(value).map_err(Error::codec)?)
+                        }
+                        Some(None) => ProviderResponse::FoundNegative,
+                        None => ProviderResponse::NotFound,
+                    })
+                })
+            }
+            ProviderRequest::Insert(key, value, ttl) => {
+                let key = self.key(&key);
+                let ttl = ttl.or(self.ttl);
+                Box::pin(async move {
+                    let data = tag(codec.encode(&value).map_err(Error::codec)?);
+                    let expiration = ttl.map(expiration).unwrap_or(0);
+                    blocking(move || client.set(&key, &data, expiration)).await?;
+                    Ok(ProviderResponse::Found(value))
+                })
+            }
+            ProviderRequest::InsertNegative(key, ttl) => {
+                let key = self.key(&key);
+                Box::pin(async move {
+                    let expiration = expiration(ttl);
+                    blocking(move || client.set(&key, NEGATIVE_SENTINEL, expiration)).await?;
+                    Ok(ProviderResponse::FoundNegative)
+                })
+            }
+            // Memcached doesn't support listing keys, so this flushes the
+            // whole server, regardless of the prefix.
+            ProviderRequest::Clear if self.flush_on_clear => Box::pin(async move {
+                blocking(move || client.flush()).await?;
+                Ok(ProviderResponse::Cleared)
+            }),
+            ProviderRequest::Clear => Box::pin(async { Ok(ProviderResponse::NotFound) }),
+            ProviderRequest::Remove(key) => {
+                let key = self.key(&key);
+                Box::pin(async move {
+                    Ok(match blocking(move || client.delete(&key)).await? {
+                        true => ProviderResponse::Removed,
+                        false => ProviderResponse::NotFound,
+                    })
+                })
+            }
+            ProviderRequest::Contains(key) => {
+                let key = self.key(&key);
+                Box::pin(async move {
+                    let value = blocking(move || client.get(&key)).await?;
+                    Ok(ProviderResponse::Present(value.is_some()))
+                })
+            }
+            // The expiration time of an entry cannot be read back.
+            ProviderRequest::Ttl(_)
+            | ProviderRequest::Age(_)
+            | ProviderRequest::GetOrInsert(_, _)
+            | ProviderRequest::GetMany(_)
+            | ProviderRequest::InsertMany(_)
+            | ProviderRequest::Stats => Box::pin(async { Ok(ProviderResponse::NotFound) }),
+        }
+    }
+}
+
+/// Run a blocking client command on the blocking thread pool
+async fn blocking<T, E, F>(f: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    E: error::Error + Send + Sync + 'static,
+    F: FnOnce() -> Result<T, E> + Send + 'static,
+{
+    // Keep the current span, so that the command is traced with the
+    // provider call.
+    let span = trace::current();
+    match tokio::task::spawn_blocking(move || trace::in_span(&span, f)).await {
+        Ok(res) => res.map_err(|e| Error::MemcachedError(Box::new(e))),
+        Err(e) => Err(Error::MemcachedError(Box::new(e))),
+    }
+}
+
+/// Convert a TTL to a Memcached expiration time
+///
+/// TTLs are rounded up to the next second, so that they never expire
+/// immediately. TTLs longer than 30 days are sent as a Unix timestamp.
+fn expiration(ttl: Duration) -> u32 {
+    let secs = ttl
+        .as_secs()
+        .saturating_add(u64::from(ttl.subsec_nanos() > 0));
+    let secs = secs.max(1);
+    if secs <= MAX_RELATIVE_EXPIRATION {
+        return secs as u32;
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    now.saturating_add(secs).min(u64::from(u32::MAX)) as u32
+}
+
+/// Tag byte prepended to serialized values
+const VALUE_TAG: u8 = 0;
+
+/// Value stored for negative entries
+///
+/// Serialized values start with [`VALUE_TAG`], so this cannot collide with
+/// them, even for codecs that encode some values as empty bytes.
+const NEGATIVE_SENTINEL: &[u8] = &[1];
+
+/// Prepend [`VALUE_TAG`] to a serialized value
+fn tag(data: Vec<u8>) -> Vec<u8> {
+    let mut tagged = Vec::with_capacity(data.len() + 1);
+    tagged.push(VALUE_TAG);
+    tagged.extend_from_slice(&data);
+    tagged
+}
+
+/// Split the serialized value from a stored value, or return `None` for
+/// negative entries
+fn untag(data: &[u8]) -> Result<Option<&[u8]>, Error> {
+    match data.split_first() {
+        Some((&VALUE_TAG, value)) => Ok(Some(value)),
+        _ if data == NEGATIVE_SENTINEL => Ok(None),
+        _ => Err(Error::CodecError("invalid tag for a stored value".into())),
+    }
+}
+
+/// Longest expiration time, in seconds, that Memcached treats as relative
+const MAX_RELATIVE_EXPIRATION: u64 = 60 * 60 * 24 * 30;
+
+type ProviderFuture<'a, K, V> =
+    Pin<Box<dyn Future<Output = Result<ProviderResponse<K, V>, Error>> + Send + 'a>>;
+
+/// Error returned by the [`MemcachedProvider`]
+#[derive(Debug)]
+pub enum Error {
+    /// Error returned by the Memcached client
+    MemcachedError(Box<dyn error::Error + Send + Sync>),
+    /// Error while serializing or deserializing a value
+    CodecError(Box<dyn error::Error + Send + Sync>),
+}
+
+impl Error {
+    fn codec<E>(e: E) -> Self
+    where
+        E: error::Error + Send + Sync + 'static,
+    {
+        Error::CodecError(Box::new(e))
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::MemcachedError(e) => Some(e.as_ref()),
+            Error::CodecError(e) => Some(e.as_ref()),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::MemcachedError(e) => write!(f, "memcached error: {}", e),
+            Error::CodecError(e) => write!(f, "serialization error: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::TupleKey;
+    use crate::conformance::check_provider;
+    use std::{
+        collections::HashMap,
+        convert::Infallible,
+        sync::{Arc, Mutex},
+    };
+
+    type MockData = HashMap<String, (Vec<u8>, u32)>;
+
+    /// In-memory client recording the expiration of each value
+    #[derive(Clone, Default)]
+    struct MockClient {
+        data: Arc<Mutex<MockData>>,
+    }
+
+    impl MemcachedClient for MockClient {
+        type Error = Infallible;
+
+        fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Self::Error> {
+            let data = self.data.lock().unwrap();
+            Ok(data.get(key).map(|(value, _)| value.clone()))
+        }
+
+        fn set(&self, key: &str, value: &[u8], expiration: u32) -> Result<(), Self::Error> {
+            let mut data = self.data.lock().unwrap();
+            data.insert(key.to_string(), (value.to_vec(), expiration));
+            Ok(())
+        }
+
+        fn delete(&self, key: &str) -> Result<bool, Self::Error> {
+            Ok(self.data.lock().unwrap().remove(key).is_some())
+        }
+
+        fn flush(&self) -> Result<(), Self::Error> {
+            self.data.lock().unwrap().clear();
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tuple_key() -> Result<(), Error> {
+        let client = MockClient::default();
+        let mut provider =
+            MemcachedProvider::new::<(u64, String), String, _>(client.clone(), "test:")
+                .with_key_codec(TupleKey::new());
+
+        let request = ProviderRequest::Insert((1, "en".to_string()), "A".to_string(), None);
+        provider.call(request).await?;
+        assert!(client.data.lock().unwrap().contains_key("test:1:en"));
+
+        // An equal key maps to the same entry, even from another provider
+        let mut other = MemcachedProvider::new::<(u64, String), String, _>(client, "test:")
+            .with_key_codec(TupleKey::new());
+        let res = other
+            .call(ProviderRequest::Get((1, "en".to_string())))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+        let res = other
+            .call(ProviderRequest::Get((1, "fr".to_string())))
+            .await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_insert() -> Result<(), Error> {
+        let client = MockClient::default();
+        check_provider(MemcachedProvider::new::<String, String, _>(
+            client.clone(),
+            "test:",
+        ))
+        .await?;
+
+        // Entries inserted without a TTL never expire
+        assert_eq!(client.data.lock().unwrap()["test:a"].1, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_insert_ttl() -> Result<(), Error> {
+        let client = MockClient::default();
+        let mut provider = MemcachedProvider::new::<String, String, _>(client.clone(), "test:");
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                Some(Duration::from_millis(1500)),
+            ))
+            .await?;
+        provider
+            .call(ProviderRequest::InsertNegative(
+                "b".to_string(),
+                Duration::from_millis(10),
+            ))
+            .await?;
+
+        {
+            let data = client.data.lock().unwrap();
+            assert_eq!(data["test:a"].1, 2);
+            assert_eq!(data["test:b"], (NEGATIVE_SENTINEL.to_vec(), 1));
+        }
+
+        let res = provider.call(ProviderRequest::Get("b".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::FoundNegative));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_configure() -> Result<(), Error> {
+        let client = MockClient::default();
+        let config = ProviderConfig::new()
+            .ttl(Duration::from_secs(60))
+            .capacity(2);
+        let mut provider =
+            MemcachedProvider::new::<String, String, _>(client.clone(), "test:").configure(&config);
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        provider
+            .call(ProviderRequest::Insert(
+                "b".to_string(),
+                "B".to_string(),
+                Some(Duration::from_secs(2)),
+            ))
+            .await?;
+
+        let data = client.data.lock().unwrap();
+        assert_eq!(data["test:a"].1, 60);
+        assert_eq!(data["test:b"].1, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiration() {
+        assert_eq!(expiration(Duration::ZERO), 1);
+        assert_eq!(expiration(Duration::from_secs(60)), 60);
+        assert_eq!(
+            expiration(Duration::from_secs(MAX_RELATIVE_EXPIRATION)),
+            MAX_RELATIVE_EXPIRATION as u32
+        );
+
+        // Long TTLs become absolute timestamps
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let expiration = expiration(Duration::from_secs(MAX_RELATIVE_EXPIRATION + 1)) as u64;
+        assert!(expiration >= now + MAX_RELATIVE_EXPIRATION);
+
+        assert_eq!(super::expiration(Duration::MAX), u32::MAX);
+    }
+
+    #[tokio::test]
+    async fn test_remove_contains_clear() -> Result<(), Error> {
+        let client = MockClient::default();
+        let mut provider = MemcachedProvider::new::<String, String, _>(client, "test:");
+
+        for key in ["a", "b"] {
+            provider
+                .call(ProviderRequest::Insert(
+                    key.to_string(),
+                    key.to_uppercase(),
+                    None,
+                ))
+                .await?;
+        }
+
+        let res = provider
+            .call(ProviderRequest::Remove("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Removed));
+        let res = provider
+            .call(ProviderRequest::Remove("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        let res = provider
+            .call(ProviderRequest::Contains("b".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(true)));
+
+        // Flushing the server must be enabled explicitly
+        let res = provider.call(ProviderRequest::Clear).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        let res = provider
+            .call(ProviderRequest::Contains("b".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(true)));
+
+        let mut provider = provider.flush_on_clear(true);
+        let res = provider.call(ProviderRequest::Clear).await?;
+        assert!(matches!(res, ProviderResponse::Cleared));
+        let res = provider
+            .call(ProviderRequest::Contains("b".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(false)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_deserialize_error() {
+        let client = MockClient::default();
+        client
+            .set("test:a", b"\0not json", 0)
+            .unwrap_or_else(|e| match e {});
+        let mut provider = MemcachedProvider::new::<String, String, _>(client, "test:");
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await;
+        assert!(matches!(res, Err(Error::CodecError(_))));
+    }
+
+    #[cfg(feature = "bincode")]
+    #[tokio::test]
+    async fn test_empty_encoding() -> Result<(), Error> {
+        use crate::codec::BincodeCodec;
+
+        let client = MockClient::default();
+        let mut provider = MemcachedProvider::new::<String, (), _>(client.clone(), "test:")
+            .with_codec(BincodeCodec);
+
+        // `()` is encoded as empty bytes, which must not be read as a negative
+        // entry
+        provider
+            .call(ProviderRequest::Insert("a".to_string(), (), None))
+            .await?;
+        provider
+            .call(ProviderRequest::InsertNegative(
+                "b".to_string(),
+                Duration::from_secs(1),
+            ))
+            .await?;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(())));
+        let res = provider.call(ProviderRequest::Get("b".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::FoundNegative));
+
+        // Values without a valid tag are rejected
+        client.set("test:c", b"", 0).unwrap_or_else(|e| match e {});
+        let res = provider.call(ProviderRequest::Get("c".to_string())).await;
+        assert!(matches!(res, Err(Error::CodecError(_))));
+
+        Ok(())
+    }
+
+    /// Runs against a real Memcached instance when `MEMCACHED_URL` is set.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_memcached_server() -> Result<(), Box<dyn error::Error>> {
+        let url = match std::env::var("MEMCACHED_URL") {
+            Ok(url) => url,
+            Err(_) => return Ok(()),
+        };
+        let client = memcache::Client::connect(url)?;
+        let mut provider = MemcachedProvider::new::<u64, String, _>(client, "tower-cache-test:");
+
+        provider
+            .call(ProviderRequest::Insert(
+                1,
+                "one".to_string(),
+                Some(Duration::from_secs(60)),
+            ))
+            .await?;
+        let res = provider.call(ProviderRequest::Get(1)).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "one"));
+
+        let res = provider.call(ProviderRequest::Remove(1)).await?;
+        assert!(matches!(res, ProviderResponse::Removed));
+        let res = provider.call(ProviderRequest::Get(1)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        Ok(())
+    }
+}
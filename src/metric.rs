@@ -0,0 +1,234 @@
+//! Optional `metrics` instrumentation
+//!
+//! Without the `metrics` feature, [`Metrics`] is empty and all of its methods
+//! compile down to nothing.
+
+#[cfg(feature = "metrics")]
+use crate::CacheEventListener;
+#[cfg(feature = "metrics")]
+use std::{sync::Arc, time::Instant};
+
+#[cfg(feature = "metrics")]
+const HITS: &str = "cache_hits_total";
+#[cfg(feature = "metrics")]
+const MISSES: &str = "cache_misses_total";
+#[cfg(feature = "metrics")]
+const INSERTS: &str = "cache_inserts_total";
+#[cfg(feature = "metrics")]
+const EVICTIONS: &str = "cache_evictions_total";
+#[cfg(feature = "metrics")]
+const PROVIDER_DURATION: &str = "cache_provider_duration_seconds";
+
+/// Metrics recorded by the services created by a [`crate::CacheLayer`]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Metrics {
+    #[cfg(feature = "metrics")]
+    inner: Option<CacheMetrics>,
+}
+
+impl Metrics {
+    #[cfg(feature = "metrics")]
+    pub(crate) fn new(metrics: CacheMetrics) -> Self {
+        Metrics {
+            inner: Some(metrics),
+        }
+    }
+
+    pub(crate) fn hit(&self) {
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.inner {
+            ::metrics::counter!(HITS, "cache" => metrics.name.clone()).increment(1);
+        }
+    }
+
+    pub(crate) fn miss(&self) {
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.inner {
+            ::metrics::counter!(MISSES, "cache" => metrics.name.clone()).increment(1);
+        }
+    }
+
+    pub(crate) fn insert(&self) {
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.inner {
+            ::metrics::counter!(INSERTS, "cache" => metrics.name.clone()).increment(1);
+        }
+    }
+
+    /// Start measuring the duration of a provider call
+    pub(crate) fn timer(&self) -> Timer {
+        Timer {
+            #[cfg(feature = "metrics")]
+            start: self.inner.as_ref().map(|_| Instant::now()),
+        }
+    }
+
+    /// Record the duration of a provider call for `operation`
+    pub(crate) fn record(&self, operation: &'static str, timer: Timer) {
+        #[cfg(feature = "metrics")]
+        if let (Some(metrics), Some(start)) = (&self.inner, timer.start) {
+            ::metrics::histogram!(
+                PROVIDER_DURATION,
+                "cache" => metrics.name.clone(),
+                "operation" => operation,
+            )
+            .record(start.elapsed());
+        }
+        #[cfg(not(feature = "metrics"))]
+        let _ = (operation, timer);
+    }
+}
+
+/// Measure the duration of a provider call
+pub(crate) struct Timer {
+    #[cfg(feature = "metrics")]
+    start: Option<Instant>,
+}
+
+/// Metrics exported for a cache with the [`metrics`](::metrics) crate
+///
+/// Use [`crate::CacheLayer::with_metrics`] to record metrics for the services
+/// created by a layer. All metrics have a `cache` label with the name of the
+/// cache:
+///
+/// * `cache_hits_total`: requests served from the cache,
+/// * `cache_misses_total`: requests sent to the inner service,
+/// * `cache_inserts_total`: responses stored in the cache provider,
+/// * `cache_evictions_total`: entries evicted by the cache provider,
+/// * `cache_provider_duration_seconds`: histogram of the duration of provider
+///   calls, with an `operation` label set to `get` or `insert`.
+///
+/// [`crate::CacheLayer`] doesn't know when the provider evicts entries. To
+/// count evictions, also pass the metrics to a provider supporting an
+/// eviction listener, such as [`crate::lru::LruProvider::on_evict`].
+///
+/// Metrics are sent to the recorder installed with the `metrics` crate, such
+/// as a Prometheus exporter.
+///
+/// ```rust
+/// use tower_cache::{CacheLayer, CacheMetrics, lru::LruProvider};
+///
+/// let metrics = CacheMetrics::new("users");
+/// let provider = LruProvider::new::<String, String>(20).on_evict(metrics.clone());
+/// let layer = CacheLayer::new(provider).with_metrics(metrics);
+/// ```
+#[cfg(feature = "metrics")]
+#[cfg_attr(docsrs, doc(cfg(feature = "metrics")))]
+#[derive(Clone, Debug)]
+pub struct CacheMetrics {
+    name: Arc<str>,
+}
+
+#[cfg(feature = "metrics")]
+impl CacheMetrics {
+    /// Create metrics labeled with the name of the cache
+    pub fn new(name: impl Into<Arc<str>>) -> Self {
+        Self { name: name.into() }
+    }
+
+    /// Return the name of the cache
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl<K, V> CacheEventListener<K, V> for CacheMetrics {
+    fn on_evict(&self, _key: &K, _value: &V) {
+        ::metrics::counter!(EVICTIONS, "cache" => self.name.clone()).increment(1);
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+    use crate::{lru::LruProvider, CacheLayer};
+    use metrics_util::{
+        debugging::{DebugValue, DebuggingRecorder},
+        CompositeKey, MetricKind,
+    };
+    use std::convert::Infallible;
+    use tower::{service_fn, Service, ServiceBuilder, ServiceExt};
+
+    fn counter(snapshot: &[(CompositeKey, DebugValue)], name: &str) -> Option<u64> {
+        snapshot.iter().find_map(|(key, value)| match value {
+            DebugValue::Counter(value)
+                if key.kind() == MetricKind::Counter
+                    && key.key().name() == name
+                    && key
+                        .key()
+                        .labels()
+                        .any(|label| label.key() == "cache" && label.value() == "users") =>
+            {
+                Some(*value)
+            }
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn test_counters() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+
+        ::metrics::with_local_recorder(&recorder, || {
+            tokio_test::block_on(async {
+                let metrics = CacheMetrics::new("users");
+                let provider = LruProvider::new::<String, String>(1).on_evict(metrics.clone());
+                let mut service = ServiceBuilder::new()
+                    .layer(CacheLayer::new(provider).with_metrics(metrics))
+                    .service(service_fn(|req: String| async move {
+                        Ok::<_, Infallible>(req.to_uppercase())
+                    }));
+
+                for req in ["a", "a", "b"] {
+                    service
+                        .ready()
+                        .await
+                        .unwrap()
+                        .call(req.to_string())
+                        .await
+                        .unwrap();
+                }
+            })
+        });
+
+        let snapshot: Vec<_> = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| (key, value))
+            .collect();
+        assert_eq!(counter(&snapshot, HITS), Some(1));
+        assert_eq!(counter(&snapshot, MISSES), Some(2));
+        assert_eq!(counter(&snapshot, INSERTS), Some(2));
+        // Inserting "b" evicts "a"
+        assert_eq!(counter(&snapshot, EVICTIONS), Some(1));
+
+        let durations = snapshot
+            .iter()
+            .filter_map(|(key, value)| match value {
+                DebugValue::Histogram(values) if key.key().name() == PROVIDER_DURATION => {
+                    Some(values.len())
+                }
+                _ => None,
+            })
+            .sum::<usize>();
+        // Three lookups and two inserts
+        assert_eq!(durations, 5);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+
+        ::metrics::with_local_recorder(&recorder, || {
+            let metrics = Metrics::default();
+            metrics.hit();
+            metrics.record("get", metrics.timer());
+        });
+
+        assert!(snapshotter.snapshot().into_vec().is_empty());
+    }
+}
@@ -0,0 +1,197 @@
+//! # Recording cache provider for tests
+//!
+//! [`RecordingProvider`] stores entries in a [`MapProvider`] and records
+//! every [`ProviderRequest`] it receives. This makes it possible to check
+//! which keys a [`crate::CacheLayer`] looked up and inserted, for example to
+//! test request transformers.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, service_fn};
+//! use tower_cache::{
+//!     CacheLayer, ProviderRequest,
+//!     mock::RecordingProvider,
+//! };
+//! async fn handler(req: String) -> Result<String, Infallible> {
+//!     Ok(req.to_uppercase())
+//! }
+//!
+//! // Keep a clone of the provider to read the requests later
+//! let provider = RecordingProvider::new::<usize, String>();
+//!
+//! let mut my_service = ServiceBuilder::new()
+//!     .layer(CacheLayer::new(provider.clone()).with_transformer(|req: String| req.len()))
+//!     .service(service_fn(handler));
+//!
+//! # tokio_test::block_on(async move {
+//! my_service.call("Hello".to_string()).await.unwrap();
+//!
+//! assert_eq!(
+//!     provider.requests(),
+//!     vec![
+//!         ProviderRequest::Get(5),
+//!         ProviderRequest::Insert(5, "HELLO".to_string(), None),
+//!     ],
+//! );
+//! # })
+//! ```
+//!
+
+use crate::{map::MapProvider, ProviderRequest, ProviderResponse};
+use std::{
+    convert::Infallible,
+    fmt,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+use tower::Service;
+
+/// Cache provider recording the requests it receives
+///
+/// Clones share the same entries and the same list of requests.
+pub struct RecordingProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    inner: MapProvider<'a, K, V>,
+    requests: Arc<Mutex<Vec<ProviderRequest<K, V>>>>,
+}
+
+impl<'a> RecordingProvider<'a, (), ()> {
+    /// Create a new recording cache provider
+    pub fn new<K, V>() -> RecordingProvider<'a, K, V>
+    where
+        K: Eq + Hash,
+    {
+        RecordingProvider {
+            inner: MapProvider::new(),
+            requests: Arc::default(),
+        }
+    }
+}
+
+impl<'a, K, V> RecordingProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    /// Return the requests received so far, in order
+    pub fn requests(&self) -> Vec<ProviderRequest<K, V>>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Forget the requests received so far
+    ///
+    /// This doesn't remove the entries from the provider.
+    pub fn clear_requests(&self) {
+        self.requests.lock().unwrap().clear();
+    }
+}
+
+// Custom implementation of Clone as the Clone derive doesn't mark
+// RecordingProvider as Clone if K or V is not clone.
+impl<'a, K, V> Clone for RecordingProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            requests: self.requests.clone(),
+        }
+    }
+}
+
+impl<'a, K, V> fmt::Debug for RecordingProvider<'a, K, V>
+where
+    K: Eq + Hash + fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RecordingProvider")
+            .field("requests", &self.requests)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, K, V> Service<ProviderRequest<K, V>> for RecordingProvider<'a, K, V>
+where
+    K: Eq + Hash + Clone + Send + 'a,
+    V: Clone + Send + 'a,
+{
+    type Response = ProviderResponse<K, V>;
+    type Error = Infallible;
+    type Future = <MapProvider<'a, K, V> as Service<ProviderRequest<K, V>>>::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: ProviderRequest<K, V>) -> Self::Future {
+        self.requests.lock().unwrap().push(request.clone());
+        self.inner.call(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CacheLayer;
+    use std::time::Duration;
+    use tower::{service_fn, ServiceBuilder};
+
+    async fn handler(req: String) -> Result<Option<String>, Infallible> {
+        let req = req.trim();
+        Ok((!req.is_empty()).then(|| req.to_uppercase()))
+    }
+
+    #[tokio::test]
+    async fn test_cache_service() -> Result<(), Infallible> {
+        let provider = RecordingProvider::new::<String, Option<String>>();
+        let mut my_service = ServiceBuilder::new()
+            .layer(
+                CacheLayer::new(provider.clone())
+                    .with_transformer(|req: String| req.trim().to_string())
+                    .cache_negative(Duration::from_secs(10)),
+            )
+            .service(service_fn(handler));
+
+        for req in ["a ", " a", ""] {
+            my_service
+                .call(req.to_string())
+                .await
+                .map_err(|e| e.into_service_error())?;
+        }
+
+        assert_eq!(
+            provider.requests(),
+            vec![
+                ProviderRequest::Get("a".to_string()),
+                ProviderRequest::Insert("a".to_string(), Some("A".to_string()), None),
+                ProviderRequest::Get("a".to_string()),
+                ProviderRequest::Get(String::new()),
+                ProviderRequest::InsertNegative(String::new(), Duration::from_secs(10)),
+            ]
+        );
+
+        provider.clear_requests();
+        assert!(provider.requests().is_empty());
+        // Entries are kept
+        my_service
+            .call("a".to_string())
+            .await
+            .map_err(|e| e.into_service_error())?;
+        assert_eq!(
+            provider.requests(),
+            vec![ProviderRequest::Get("a".to_string())]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,521 @@
+//! # Moka cache provider
+//!
+//! This is an implementation of a cache provider for [`crate::CacheLayer`]
+//! using [`moka::future::Cache`]. Unlike [`crate::lru::LruProvider`], it
+//! doesn't rely on a single lock: moka is designed for concurrent access and
+//! never holds a lock across an `.await` point.
+//!
+//! Entries are bounded by a maximum capacity, and can optionally expire after
+//! a time-to-live (since insertion) or a time-to-idle (since the last read).
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use std::{convert::Infallible, time::Duration};
+//! use tower::{Service, ServiceBuilder, service_fn};
+//! use tower_cache::{
+//!     CacheLayer,
+//!     moka::MokaProvider,
+//! };
+//! async fn handler(req: String) -> Result<String, Infallible> {
+//!     Ok(req.to_uppercase())
+//! }
+//!
+//! // Initialize the cache provider service
+//! let moka_provider = MokaProvider::builder::<String, String>()
+//!     .max_capacity(1_000)
+//!     .time_to_live(Duration::from_secs(60))
+//!     .time_to_idle(Duration::from_secs(10))
+//!     .build();
+//!
+//! // Wrap the service with CacheLayer.
+//! let mut my_service = ServiceBuilder::new()
+//!     .layer(CacheLayer::new(moka_provider))
+//!     .service(service_fn(handler));
+//!
+//! # tokio_test::block_on(async move {
+//! // Call the service
+//! let res = my_service.call("Hello".to_string()).await.unwrap();
+//! assert_eq!(res, "HELLO".to_string());
+//! # })
+//! ```
+//!
+
+use crate::{
+    entry::{Entry, EntryExpiry},
+    time, Configure, ProviderConfig, ProviderRequest, ProviderResponse,
+};
+use moka::{
+    future::Cache,
+    ops::compute::{CompResult, Op},
+};
+use std::{
+    convert::Infallible,
+    future::{ready, Future},
+    hash::Hash,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower::Service;
+
+/// Local, concurrent cache provider backed by [`moka`]
+pub struct MokaProvider<'a, K, V> {
+    inner: Cache<K, Entry<V>>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> MokaProvider<'a, (), ()> {
+    /// Create a builder for a moka cache provider
+    pub fn builder<K, V>() -> MokaProviderBuilder<'a, K, V> {
+        MokaProviderBuilder {
+            max_capacity: None,
+            time_to_live: None,
+            time_to_idle: None,
+            _types: PhantomData,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+// Custom implementation of Clone as the Clone derive doesn't mark MokaProvider
+// as Clone if K or V is not clone.
+impl<'a, K, V> Clone for MokaProvider<'a, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V> std::fmt::Debug for MokaProvider<'a, K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MokaProvider")
+            .field("entry_count", &self.inner.entry_count())
+            .finish()
+    }
+}
+
+/// Builder for a [`MokaProvider`]
+///
+/// By default, the cache is unbounded and entries never expire.
+#[derive(Clone, Copy, Debug)]
+pub struct MokaProviderBuilder<'a, K, V> {
+    max_capacity: Option<u64>,
+    time_to_live: Option<Duration>,
+    time_to_idle: Option<Duration>,
+    _types: PhantomData<fn() -> (K, V)>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a, K, V> MokaProviderBuilder<'a, K, V>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Maximum number of entries in the cache
+    pub fn max_capacity(mut self, max_capacity: u64) -> Self {
+        self.max_capacity = Some(max_capacity);
+        self
+    }
+
+    /// Expire entries `ttl` after they were inserted
+    pub fn time_to_live(mut self, ttl: Duration) -> Self {
+        self.time_to_live = Some(ttl);
+        self
+    }
+
+    /// Expire entries `tti` after they were last read or inserted
+    pub fn time_to_idle(mut self, tti: Duration) -> Self {
+        self.time_to_idle = Some(tti);
+        self
+    }
+
+    /// Build the cache provider
+    pub fn build(self) -> MokaProvider<'a, K, V> {
+        let mut builder = Cache::builder().expire_after(EntryExpiry);
+        if let Some(max_capacity) = self.max_capacity {
+            builder = builder.max_capacity(max_capacity);
+        }
+        if let Some(ttl) = self.time_to_live {
+            builder = builder.time_to_live(ttl);
+        }
+        if let Some(tti) = self.time_to_idle {
+            builder = builder.time_to_idle(tti);
+        }
+
+        MokaProvider {
+            inner: builder.build(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+/// Sets the maximum capacity and the time-to-live of the cache
+///
+/// The settings of a moka cache can't change once it is built, so the
+/// configuration applies to the builder.
+impl<'a, K, V> Configure for MokaProviderBuilder<'a, K, V> {
+    fn configure(mut self, config: &ProviderConfig) -> Self {
+        if let Some(ttl) = config.ttl {
+            self.time_to_live = Some(ttl);
+        }
+        if let Some(capacity) = config.capacity {
+            self.max_capacity = Some(capacity as u64);
+        }
+        self
+    }
+}
+
+impl<'a, K, V> Service<ProviderRequest<K, V>> for MokaProvider<'a, K, V>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    type Response = ProviderResponse<K, V>;
+    type Error = Infallible;
+    type Future = ProviderFuture<'a, K, V>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ProviderRequest<K, V>) -> Self::Future {
+        let inner = self.inner.clone();
+
+        Box::pin(async move {
+            Ok(match request {
+                ProviderRequest::Get(key) => inner
+                    .get(&key)
+                    .await
+                    .and_then(|entry| entry.response_at(time::now()))
+                    .unwrap_or(ProviderResponse::NotFound),
+                ProviderRequest::Insert(key, value, ttl) => {
+                    inner.insert(key, Entry::new(value.clone(), ttl)).await;
+                    ProviderResponse::Found(value)
+                }
+                ProviderRequest::InsertNegative(key, ttl) => {
+                    inner.insert(key, Entry::negative(ttl)).await;
+                    ProviderResponse::FoundNegative
+                }
+                ProviderRequest::Clear => {
+                    inner.invalidate_all();
+                    ProviderResponse::Cleared
+                }
+                ProviderRequest::Remove(key) => match inner.remove(&key).await {
+                    Some(_) => ProviderResponse::Removed,
+                    None => ProviderResponse::NotFound,
+                },
+                // contains_key doesn't count as a read for the time-to-idle.
+                ProviderRequest::Contains(key) => {
+                    ProviderResponse::Present(inner.contains_key(&key))
+                }
+                // Entries relying on the cache-wide time-to-live don't know
+                // when they were inserted.
+                ProviderRequest::Ttl(key) => match inner
+                    .get(&key)
+                    .await
+                    .and_then(|entry| entry.ttl_at(time::now()))
+                {
+                    Some((remaining, ttl)) => ProviderResponse::Ttl(remaining, ttl),
+                    None => ProviderResponse::NotFound,
+                },
+                ProviderRequest::Age(key) => match inner
+                    .get(&key)
+                    .await
+                    .and_then(|entry| entry.age_at(time::now()))
+                {
+                    Some(age) => ProviderResponse::Age(age),
+                    None => ProviderResponse::NotFound,
+                },
+                // Computations on the same key are serialized by the cache.
+                ProviderRequest::GetOrInsert(key, value) => {
+                    let now = time::now();
+                    let result = inner
+                        .entry(key)
+                        .and_compute_with(|existing| {
+                            ready(match existing {
+                                Some(existing)
+                                    if existing.value().fresh_value_at(now).is_some() =>
+                                {
+                                    Op::Nop
+                                }
+                                _ => Op::Put(Entry::new(value.clone(), None)),
+                            })
+                        })
+                        .await;
+                    match result {
+                        CompResult::Unchanged(existing) => existing
+                            .into_value()
+                            .value
+                            .map(ProviderResponse::Found)
+                            .unwrap_or(ProviderResponse::NotFound),
+                        _ => ProviderResponse::Inserted(value),
+                    }
+                }
+                ProviderRequest::GetMany(keys) => {
+                    let now = time::now();
+                    let mut values = Vec::with_capacity(keys.len());
+                    for key in keys {
+                        let entry = inner.get(&key).await;
+                        values.push((key, entry.and_then(|entry| entry.value_at(now).cloned())));
+                    }
+                    ProviderResponse::Many(values)
+                }
+                // Inserting entries one by one is as cheap as a batch in memory.
+                ProviderRequest::InsertMany(_) => ProviderResponse::NotFound,
+                // Evictions are not counted.
+                ProviderRequest::Stats => ProviderResponse::NotFound,
+            })
+        })
+    }
+}
+
+type ProviderFuture<'a, K, V> =
+    Pin<Box<dyn Future<Output = Result<ProviderResponse<K, V>, Infallible>> + Send + 'a>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::check_provider;
+
+    #[test]
+    fn test_configure() {
+        let config = ProviderConfig::new()
+            .ttl(Duration::from_secs(60))
+            .capacity(2);
+        let provider = MokaProvider::builder::<String, String>()
+            .configure(&config)
+            .build();
+
+        let policy = provider.inner.policy();
+        assert_eq!(policy.max_capacity(), Some(2));
+        assert_eq!(policy.time_to_live(), Some(Duration::from_secs(60)));
+    }
+
+    #[tokio::test]
+    async fn test_conformance() -> Result<(), Infallible> {
+        check_provider(MokaProvider::builder::<String, String>().build()).await
+    }
+
+    #[tokio::test]
+    // Moka expires entries on its own clock, which Tokio can't pause.
+    async fn test_ttl_expires() -> Result<(), Infallible> {
+        let mut provider = MokaProvider::builder::<String, String>()
+            .time_to_live(Duration::from_millis(50))
+            .build();
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_per_entry_ttl() -> Result<(), Infallible> {
+        let mut provider = MokaProvider::builder::<String, String>().build();
+
+        provider
+            .call(ProviderRequest::Insert(
+                "short".to_string(),
+                "S".to_string(),
+                Some(Duration::from_millis(50)),
+            ))
+            .await?;
+        provider
+            .call(ProviderRequest::Insert(
+                "long".to_string(),
+                "L".to_string(),
+                Some(Duration::from_millis(150)),
+            ))
+            .await?;
+        provider
+            .call(ProviderRequest::Insert(
+                "default".to_string(),
+                "D".to_string(),
+                None,
+            ))
+            .await?;
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let res = provider
+            .call(ProviderRequest::Get("short".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        let res = provider
+            .call(ProviderRequest::Get("long".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "L"));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let res = provider
+            .call(ProviderRequest::Get("long".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        // Entries without their own TTL fall back to the provider default.
+        let res = provider
+            .call(ProviderRequest::Get("default".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "D"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    // Moka expires entries on its own clock, which Tokio can't pause.
+    async fn test_tti_expires() -> Result<(), Infallible> {
+        let mut provider = MokaProvider::builder::<String, String>()
+            .time_to_idle(Duration::from_millis(100))
+            .build();
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+
+        // Reading the entry keeps it alive past the time-to-idle.
+        for _ in 0..3 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+            assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+        }
+
+        tokio::time::sleep(Duration::from_millis(110)).await;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_capacity_evicts() -> Result<(), Infallible> {
+        let mut provider = MokaProvider::builder::<usize, usize>()
+            .max_capacity(10)
+            .build();
+
+        for i in 0..100 {
+            provider.call(ProviderRequest::Insert(i, i, None)).await?;
+        }
+
+        // Eviction happens in the background, run it now.
+        provider.inner.run_pending_tasks().await;
+        assert!(provider.inner.entry_count() <= 10);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_or_insert() -> Result<(), Infallible> {
+        let mut provider = MokaProvider::builder::<String, String>().build();
+
+        let res = provider
+            .call(ProviderRequest::GetOrInsert(
+                "a".to_string(),
+                "A".to_string(),
+            ))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Inserted(v) if v == "A"));
+        let res = provider
+            .call(ProviderRequest::GetOrInsert(
+                "a".to_string(),
+                "B".to_string(),
+            ))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_insert_negative() -> Result<(), Infallible> {
+        let mut provider = MokaProvider::builder::<String, String>().build();
+
+        provider
+            .call(ProviderRequest::InsertNegative(
+                "a".to_string(),
+                Duration::from_millis(50),
+            ))
+            .await?;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::FoundNegative));
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        // Replacing a negative entry with a value doesn't keep its expiration.
+        provider
+            .call(ProviderRequest::InsertNegative(
+                "b".to_string(),
+                Duration::from_millis(50),
+            ))
+            .await?;
+        provider
+            .call(ProviderRequest::Insert(
+                "b".to_string(),
+                "B".to_string(),
+                None,
+            ))
+            .await?;
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let res = provider.call(ProviderRequest::Get("b".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "B"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remove_contains_clear() -> Result<(), Infallible> {
+        let mut provider = MokaProvider::builder::<String, String>().build();
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        provider
+            .call(ProviderRequest::Insert(
+                "b".to_string(),
+                "B".to_string(),
+                None,
+            ))
+            .await?;
+
+        let res = provider
+            .call(ProviderRequest::Contains("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(true)));
+
+        let res = provider
+            .call(ProviderRequest::Remove("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Removed));
+        let res = provider
+            .call(ProviderRequest::Remove("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        let res = provider.call(ProviderRequest::Clear).await?;
+        assert!(matches!(res, ProviderResponse::Cleared));
+        let res = provider.call(ProviderRequest::Get("b".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,459 @@
+//! # Synchronous moka cache provider
+//!
+//! This is an implementation of a cache provider for [`crate::CacheLayer`]
+//! using [`moka::sync::Cache`]. Unlike [`crate::moka::MokaProvider`], it
+//! doesn't need an async runtime: every request is handled while calling the
+//! provider, and the returned future is always ready.
+//!
+//! Entries are bounded by a maximum capacity, optionally measured with a
+//! weigher, and can expire after a time-to-live (since insertion) or a
+//! time-to-idle (since the last read).
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use std::{convert::Infallible, time::Duration};
+//! use tower::{Service, ServiceBuilder, service_fn};
+//! use tower_cache::{
+//!     CacheLayer,
+//!     moka_sync::MokaSyncProvider,
+//! };
+//! async fn handler(req: String) -> Result<String, Infallible> {
+//!     Ok(req.to_uppercase())
+//! }
+//!
+//! // Initialize the cache provider service, bounded to 1 MiB of values
+//! let moka_provider = MokaSyncProvider::builder::<String, String>()
+//!     .max_capacity(1024 * 1024)
+//!     .weigher(|_key, value: &String| value.len() as u32)
+//!     .time_to_live(Duration::from_secs(60))
+//!     .build();
+//!
+//! // Wrap the service with CacheLayer.
+//! let mut my_service = ServiceBuilder::new()
+//!     .layer(CacheLayer::new(moka_provider))
+//!     .service(service_fn(handler));
+//!
+//! # tokio_test::block_on(async move {
+//! // Call the service
+//! let res = my_service.call("Hello".to_string()).await.unwrap();
+//! assert_eq!(res, "HELLO".to_string());
+//! # })
+//! ```
+//!
+
+use crate::{
+    entry::{Entry, EntryExpiry},
+    time, Configure, ProviderConfig, ProviderRequest, ProviderResponse,
+};
+use moka::{
+    ops::compute::{CompResult, Op},
+    sync::Cache,
+};
+use std::{
+    convert::Infallible,
+    future::{ready, Ready},
+    hash::Hash,
+    marker::PhantomData,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower::Service;
+
+type Weigher<K, V> = Arc<dyn Fn(&K, &V) -> u32 + Send + Sync>;
+
+/// Local, concurrent cache provider backed by [`moka::sync::Cache`]
+pub struct MokaSyncProvider<'a, K, V> {
+    inner: Cache<K, Entry<V>>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> MokaSyncProvider<'a, (), ()> {
+    /// Create a builder for a synchronous moka cache provider
+    pub fn builder<K, V>() -> MokaSyncProviderBuilder<'a, K, V> {
+        MokaSyncProviderBuilder {
+            max_capacity: None,
+            time_to_live: None,
+            time_to_idle: None,
+            weigher: None,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V> MokaSyncProvider<'a, K, V>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    fn handle(&self, request: ProviderRequest<K, V>) -> ProviderResponse<K, V> {
+        match request {
+            ProviderRequest::Get(key) => self
+                .inner
+                .get(&key)
+                .and_then(|entry| entry.response_at(time::now()))
+                .unwrap_or(ProviderResponse::NotFound),
+            ProviderRequest::Insert(key, value, ttl) => {
+                self.inner.insert(key, Entry::new(value.clone(), ttl));
+                ProviderResponse::Found(value)
+            }
+            ProviderRequest::InsertNegative(key, ttl) => {
+                self.inner.insert(key, Entry::negative(ttl));
+                ProviderResponse::FoundNegative
+            }
+            ProviderRequest::Clear => {
+                self.inner.invalidate_all();
+                ProviderResponse::Cleared
+            }
+            ProviderRequest::Remove(key) => match self.inner.remove(&key) {
+                Some(_) => ProviderResponse::Removed,
+                None => ProviderResponse::NotFound,
+            },
+            // contains_key doesn't count as a read for the time-to-idle.
+            ProviderRequest::Contains(key) => {
+                ProviderResponse::Present(self.inner.contains_key(&key))
+            }
+            // Entries relying on the cache-wide time-to-live don't know when
+            // they were inserted.
+            ProviderRequest::Ttl(key) => match self
+                .inner
+                .get(&key)
+                .and_then(|entry| entry.ttl_at(time::now()))
+            {
+                Some((remaining, ttl)) => ProviderResponse::Ttl(remaining, ttl),
+                None => ProviderResponse::NotFound,
+            },
+            ProviderRequest::Age(key) => match self
+                .inner
+                .get(&key)
+                .and_then(|entry| entry.age_at(time::now()))
+            {
+                Some(age) => ProviderResponse::Age(age),
+                None => ProviderResponse::NotFound,
+            },
+            // Computations on the same key are serialized by the cache.
+            ProviderRequest::GetOrInsert(key, value) => {
+                let now = time::now();
+                let result = self
+                    .inner
+                    .entry(key)
+                    .and_compute_with(|existing| match existing {
+                        Some(existing) if existing.value().fresh_value_at(now).is_some() => Op::Nop,
+                        _ => Op::Put(Entry::new(value.clone(), None)),
+                    });
+                match result {
+                    CompResult::Unchanged(existing) => existing
+                        .into_value()
+                        .value
+                        .map(ProviderResponse::Found)
+                        .unwrap_or(ProviderResponse::NotFound),
+                    _ => ProviderResponse::Inserted(value),
+                }
+            }
+            ProviderRequest::GetMany(keys) => {
+                let now = time::now();
+                ProviderResponse::Many(
+                    keys.into_iter()
+                        .map(|key| {
+                            let value = self
+                                .inner
+                                .get(&key)
+                                .and_then(|entry| entry.value_at(now).cloned());
+                            (key, value)
+                        })
+                        .collect(),
+                )
+            }
+            // Inserting entries one by one is as cheap as a batch in memory.
+            ProviderRequest::InsertMany(_) => ProviderResponse::NotFound,
+            // Evictions are not counted.
+            ProviderRequest::Stats => ProviderResponse::NotFound,
+        }
+    }
+}
+
+// Custom implementation of Clone as the Clone derive doesn't mark
+// MokaSyncProvider as Clone if K or V is not clone.
+impl<'a, K, V> Clone for MokaSyncProvider<'a, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V> std::fmt::Debug for MokaSyncProvider<'a, K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MokaSyncProvider")
+            .field("entry_count", &self.inner.entry_count())
+            .field("weighted_size", &self.inner.weighted_size())
+            .finish()
+    }
+}
+
+/// Builder for a [`MokaSyncProvider`]
+///
+/// By default, the cache is unbounded and entries never expire.
+pub struct MokaSyncProviderBuilder<'a, K, V> {
+    max_capacity: Option<u64>,
+    time_to_live: Option<Duration>,
+    time_to_idle: Option<Duration>,
+    weigher: Option<Weigher<K, V>>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a, K, V> Clone for MokaSyncProviderBuilder<'a, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            max_capacity: self.max_capacity,
+            time_to_live: self.time_to_live,
+            time_to_idle: self.time_to_idle,
+            weigher: self.weigher.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V> std::fmt::Debug for MokaSyncProviderBuilder<'a, K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MokaSyncProviderBuilder")
+            .field("max_capacity", &self.max_capacity)
+            .field("time_to_live", &self.time_to_live)
+            .field("time_to_idle", &self.time_to_idle)
+            .field("weigher", &self.weigher.is_some())
+            .finish()
+    }
+}
+
+impl<'a, K, V> MokaSyncProviderBuilder<'a, K, V>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Maximum number of entries in the cache, or maximum total weight if a
+    /// weigher is set
+    pub fn max_capacity(mut self, max_capacity: u64) -> Self {
+        self.max_capacity = Some(max_capacity);
+        self
+    }
+
+    /// Expire entries `ttl` after they were inserted
+    pub fn time_to_live(mut self, ttl: Duration) -> Self {
+        self.time_to_live = Some(ttl);
+        self
+    }
+
+    /// Expire entries `tti` after they were last read or inserted
+    pub fn time_to_idle(mut self, tti: Duration) -> Self {
+        self.time_to_idle = Some(tti);
+        self
+    }
+
+    /// Measure entries with `weigher` against the maximum capacity
+    ///
+    /// Negative entries don't have a value, and weigh 1.
+    pub fn weigher<F>(mut self, weigher: F) -> Self
+    where
+        F: Fn(&K, &V) -> u32 + Send + Sync + 'static,
+    {
+        self.weigher = Some(Arc::new(weigher));
+        self
+    }
+
+    /// Build the cache provider
+    pub fn build(self) -> MokaSyncProvider<'a, K, V> {
+        let mut builder = Cache::builder().expire_after(EntryExpiry);
+        if let Some(max_capacity) = self.max_capacity {
+            builder = builder.max_capacity(max_capacity);
+        }
+        if let Some(ttl) = self.time_to_live {
+            builder = builder.time_to_live(ttl);
+        }
+        if let Some(tti) = self.time_to_idle {
+            builder = builder.time_to_idle(tti);
+        }
+        if let Some(weigher) = self.weigher {
+            builder = builder.weigher(move |key, entry: &Entry<V>| {
+                entry.value.as_ref().map_or(1, |value| weigher(key, value))
+            });
+        }
+
+        MokaSyncProvider {
+            inner: builder.build(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+/// Sets the maximum capacity and the time-to-live of the cache
+///
+/// The settings of a moka cache can't change once it is built, so the
+/// configuration applies to the builder.
+impl<'a, K, V> Configure for MokaSyncProviderBuilder<'a, K, V> {
+    fn configure(mut self, config: &ProviderConfig) -> Self {
+        if let Some(ttl) = config.ttl {
+            self.time_to_live = Some(ttl);
+        }
+        if let Some(capacity) = config.capacity {
+            self.max_capacity = Some(capacity as u64);
+        }
+        self
+    }
+}
+
+impl<'a, K, V> Service<ProviderRequest<K, V>> for MokaSyncProvider<'a, K, V>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    type Response = ProviderResponse<K, V>;
+    type Error = Infallible;
+    type Future = Ready<Result<ProviderResponse<K, V>, Infallible>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ProviderRequest<K, V>) -> Self::Future {
+        ready(Ok(self.handle(request)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::check_provider;
+    use std::thread::sleep;
+
+    /// Handle a request without an async runtime
+    fn call<K, V>(
+        provider: &mut MokaSyncProvider<'_, K, V>,
+        request: ProviderRequest<K, V>,
+    ) -> ProviderResponse<K, V>
+    where
+        K: Eq + Hash + Send + Sync + 'static,
+        V: Clone + Send + Sync + 'static,
+    {
+        match provider.call(request).into_inner() {
+            Ok(res) => res,
+            Err(err) => match err {},
+        }
+    }
+
+    #[test]
+    fn test_configure() {
+        let config = ProviderConfig::new()
+            .ttl(Duration::from_secs(60))
+            .capacity(2);
+        let provider = MokaSyncProvider::builder::<String, String>()
+            .configure(&config)
+            .build();
+
+        let policy = provider.inner.policy();
+        assert_eq!(policy.max_capacity(), Some(2));
+        assert_eq!(policy.time_to_live(), Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_get_insert() {
+        let mut provider = MokaSyncProvider::builder::<String, String>().build();
+        let Ok(()) = tokio_test::block_on(check_provider(provider.clone()));
+
+        let res = call(
+            &mut provider,
+            ProviderRequest::GetOrInsert("a".to_string(), "B".to_string()),
+        );
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+    }
+
+    #[test]
+    fn test_capacity_evicts() {
+        let mut provider = MokaSyncProvider::builder::<usize, usize>()
+            .max_capacity(10)
+            .build();
+
+        for i in 0..100 {
+            call(&mut provider, ProviderRequest::Insert(i, i, None));
+        }
+
+        // Eviction happens on later operations, run it now.
+        provider.inner.run_pending_tasks();
+        assert!(provider.inner.entry_count() <= 10);
+    }
+
+    #[test]
+    fn test_weight_evicts() {
+        let mut provider = MokaSyncProvider::builder::<usize, String>()
+            .max_capacity(100)
+            .weigher(|_, value: &String| value.len() as u32)
+            .build();
+
+        for i in 0..10 {
+            call(
+                &mut provider,
+                ProviderRequest::Insert(i, "x".repeat(40), None),
+            );
+        }
+
+        // Only two 40-byte values fit in the budget.
+        provider.inner.run_pending_tasks();
+        assert!(provider.inner.entry_count() <= 2);
+        assert!(provider.inner.weighted_size() <= 100);
+    }
+
+    #[test]
+    // Moka expires entries on its own clock, which Tokio can't pause.
+    fn test_ttl_expires() {
+        let mut provider = MokaSyncProvider::builder::<String, String>()
+            .time_to_live(Duration::from_millis(50))
+            .build();
+
+        call(
+            &mut provider,
+            ProviderRequest::Insert("a".to_string(), "A".to_string(), None),
+        );
+        call(
+            &mut provider,
+            ProviderRequest::InsertNegative("b".to_string(), Duration::from_millis(20)),
+        );
+        let res = call(&mut provider, ProviderRequest::Get("b".to_string()));
+        assert!(matches!(res, ProviderResponse::FoundNegative));
+
+        // Entries with their own TTL expire before the provider default.
+        sleep(Duration::from_millis(30));
+        let res = call(&mut provider, ProviderRequest::Get("a".to_string()));
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+        let res = call(&mut provider, ProviderRequest::Get("b".to_string()));
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        sleep(Duration::from_millis(30));
+        let res = call(&mut provider, ProviderRequest::Get("a".to_string()));
+        assert!(matches!(res, ProviderResponse::NotFound));
+    }
+
+    #[test]
+    // Moka expires entries on its own clock, which Tokio can't pause.
+    fn test_tti_expires() {
+        let mut provider = MokaSyncProvider::builder::<String, String>()
+            .time_to_idle(Duration::from_millis(100))
+            .build();
+
+        call(
+            &mut provider,
+            ProviderRequest::Insert("a".to_string(), "A".to_string(), None),
+        );
+
+        // Reading the entry keeps it alive past the time-to-idle.
+        for _ in 0..3 {
+            sleep(Duration::from_millis(50));
+            let res = call(&mut provider, ProviderRequest::Get("a".to_string()));
+            assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+        }
+
+        sleep(Duration::from_millis(110));
+        let res = call(&mut provider, ProviderRequest::Get("a".to_string()));
+        assert!(matches!(res, ProviderResponse::NotFound));
+    }
+}
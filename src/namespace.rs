@@ -0,0 +1,193 @@
+use crate::{Transform, TransformAsync};
+use std::future::{ready, Ready};
+
+/// # Key namespacing
+///
+/// When multiple services share the same cache provider, such as a Redis
+/// instance, their keys can collide. A namespace prepends a static prefix and
+/// a separator to the keys derived by another transformer, so that each
+/// service writes to its own part of the cache.
+///
+/// ## Usage
+///
+/// ```rust
+/// use tower_cache::{Namespace, Transform};
+///
+/// let users = Namespace::new("users").wrap(|id: u64| id.to_string());
+/// let orders = Namespace::new("orders").wrap(|id: u64| id.to_string());
+///
+/// assert_eq!(users.transform(42), "users:42");
+/// assert_eq!(orders.transform(42), "orders:42");
+/// ```
+///
+/// A namespace is also a transformer on its own, which prefixes the request
+/// itself. This can be chained after other transformers with
+/// [`Transform::then`]:
+///
+/// ```rust
+/// use tower_cache::{Namespace, Transform};
+///
+/// let t = (|req: String| req.to_lowercase()).then(Namespace::new("users").separator("/"));
+///
+/// assert_eq!(t.transform("Alice".to_string()), "users/alice");
+/// ```
+///
+/// Keys must implement [`WithPrefix`], which is implemented for [`String`].
+#[derive(Clone, Copy, Debug)]
+pub struct Namespace {
+    prefix: &'static str,
+    separator: &'static str,
+}
+
+impl Namespace {
+    /// Create a namespace with `prefix`, separated from keys by `:`
+    pub fn new(prefix: &'static str) -> Self {
+        Namespace {
+            prefix,
+            separator: ":",
+        }
+    }
+
+    /// Use `separator` between the prefix and keys
+    pub fn separator(mut self, separator: &'static str) -> Self {
+        self.separator = separator;
+        self
+    }
+
+    /// Prefix the keys derived by `inner`
+    pub fn wrap<T>(self, inner: T) -> Namespaced<T> {
+        Namespaced {
+            namespace: self,
+            inner,
+        }
+    }
+}
+
+impl<R> Transform<R> for Namespace
+where
+    R: WithPrefix,
+{
+    type Output = R;
+
+    fn transform(&self, req: R) -> Self::Output {
+        req.with_prefix(self.prefix, self.separator)
+    }
+}
+
+impl<R> TransformAsync<R> for Namespace
+where
+    R: WithPrefix + Clone,
+{
+    type Output = R;
+    type Future = Ready<Option<R>>;
+
+    fn transform_async(&self, req: &R) -> Self::Future {
+        ready(Some(self.transform(req.clone())))
+    }
+}
+
+/// Transformer prefixing the keys of another transformer, created by
+/// [`Namespace::wrap`].
+#[derive(Clone, Copy, Debug)]
+pub struct Namespaced<T> {
+    namespace: Namespace,
+    inner: T,
+}
+
+impl<T, R> Transform<R> for Namespaced<T>
+where
+    T: Transform<R>,
+    T::Output: WithPrefix,
+{
+    type Output = T::Output;
+
+    fn transform(&self, req: R) -> Self::Output {
+        self.namespace.transform(self.inner.transform(req))
+    }
+}
+
+impl<T, R> TransformAsync<R> for Namespaced<T>
+where
+    T: Transform<R>,
+    T::Output: WithPrefix,
+    R: Clone,
+{
+    type Output = T::Output;
+    type Future = Ready<Option<T::Output>>;
+
+    fn transform_async(&self, req: &R) -> Self::Future {
+        ready(Some(self.transform(req.clone())))
+    }
+}
+
+/// Keys that can be prefixed by a [`Namespace`]
+pub trait WithPrefix {
+    /// Return the key with `prefix` and `separator` prepended
+    fn with_prefix(self, prefix: &str, separator: &str) -> Self;
+}
+
+impl WithPrefix for String {
+    fn with_prefix(self, prefix: &str, separator: &str) -> Self {
+        let mut key = String::with_capacity(prefix.len() + separator.len() + self.len());
+        key.push_str(prefix);
+        key.push_str(separator);
+        key.push_str(&self);
+        key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{map::MapProvider, CacheLayer, ProviderRequest, ProviderResponse};
+    use std::convert::Infallible;
+    use tower::{service_fn, Service, ServiceBuilder, ServiceExt};
+
+    #[test]
+    fn test_no_collision() {
+        let key = |req: String| req.to_lowercase();
+        let users = Namespace::new("users").wrap(key);
+        let orders = Namespace::new("orders").wrap(key);
+
+        assert_eq!(users.transform("A".to_string()), "users:a");
+        assert_eq!(orders.transform("A".to_string()), "orders:a");
+        assert_ne!(
+            users.transform("A".to_string()),
+            orders.transform("A".to_string())
+        );
+    }
+
+    #[test]
+    fn test_separator() {
+        let t = Namespace::new("users").separator("/").wrap(());
+
+        assert_eq!(t.transform("a".to_string()), "users/a");
+    }
+
+    #[tokio::test]
+    async fn test_shared_provider() -> Result<(), Infallible> {
+        let provider = MapProvider::new::<String, String>();
+        let mut users = ServiceBuilder::new()
+            .layer(CacheLayer::new(provider.clone()).with_transformer(Namespace::new("users")))
+            .service(service_fn(|req: String| async move {
+                Ok::<_, Infallible>(format!("user {}", req))
+            }));
+        let mut orders = ServiceBuilder::new()
+            .layer(CacheLayer::new(provider.clone()).with_transformer(Namespace::new("orders")))
+            .service(service_fn(|req: String| async move {
+                Ok::<_, Infallible>(format!("order {}", req))
+            }));
+
+        assert_eq!(users.call("1".to_string()).await.unwrap(), "user 1");
+        assert_eq!(orders.call("1".to_string()).await.unwrap(), "order 1");
+        // Both responses are cached under their own key.
+        assert_eq!(users.call("1".to_string()).await.unwrap(), "user 1");
+
+        let res = provider
+            .oneshot(ProviderRequest::Get("orders:1".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "order 1"));
+
+        Ok(())
+    }
+}
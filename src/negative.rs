@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+/// # Negative caching policy
+///
+/// By default, every response from the inner service is stored as-is in the
+/// cache provider. Some lookups legitimately return "nothing", and caching
+/// that absence avoids calling the inner service again for the same key.
+///
+/// A negative caching policy decides which responses represent the absence
+/// of a value. Those are stored as a sentinel through
+/// [`crate::ProviderRequest::InsertNegative`] with their own TTL, and turned
+/// back into a response when the provider returns
+/// [`crate::ProviderResponse::FoundNegative`].
+///
+/// ## Usage
+///
+/// In most cases, you don't need to implement this trait directly. Use
+/// [`crate::CacheLayer::cache_negative`] for services returning an `Option`,
+/// where `None` is cached as a negative entry.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use tower_cache::{NegativeCache, NegativePolicy};
+///
+/// let policy = NegativeCache::new(Duration::from_secs(5));
+///
+/// assert_eq!(policy.negative_ttl(&None::<usize>), Some(Duration::from_secs(5)));
+/// assert_eq!(policy.negative_ttl(&Some(1)), None);
+/// ```
+///
+/// This is also implemented for `()`, which never caches negative entries:
+///
+/// ```rust
+/// use tower_cache::NegativePolicy;
+///
+/// assert_eq!(NegativePolicy::<Option<usize>>::negative_ttl(&(), &None), None);
+/// ```
+///
+pub trait NegativePolicy<Res> {
+    /// Return how long `res` should be cached as a negative entry, or `None`
+    /// if it should be cached normally.
+    fn negative_ttl(&self, res: &Res) -> Option<Duration>;
+
+    /// Build the response returned on a negative cache hit.
+    ///
+    /// Returning `None` treats a negative entry as a cache miss.
+    fn empty(&self) -> Option<Res>;
+}
+
+impl<Res> NegativePolicy<Res> for () {
+    fn negative_ttl(&self, _res: &Res) -> Option<Duration> {
+        None
+    }
+
+    fn empty(&self) -> Option<Res> {
+        None
+    }
+}
+
+/// Negative caching policy for services returning an `Option`
+///
+/// `None` responses are cached as negative entries for the given TTL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NegativeCache {
+    ttl: Duration,
+}
+
+impl NegativeCache {
+    /// Create a new negative caching policy with the given TTL
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl }
+    }
+}
+
+impl<T> NegativePolicy<Option<T>> for NegativeCache {
+    fn negative_ttl(&self, res: &Option<T>) -> Option<Duration> {
+        match res {
+            Some(_) => None,
+            None => Some(self.ttl),
+        }
+    }
+
+    fn empty(&self) -> Option<Option<T>> {
+        Some(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit() {
+        assert_eq!(NegativePolicy::<usize>::negative_ttl(&(), &0), None);
+        assert_eq!(NegativePolicy::<usize>::empty(&()), None);
+    }
+
+    #[test]
+    fn test_option() {
+        let policy = NegativeCache::new(Duration::from_secs(1));
+
+        assert_eq!(
+            policy.negative_ttl(&None::<usize>),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(policy.negative_ttl(&Some(1)), None);
+        assert_eq!(NegativePolicy::<Option<usize>>::empty(&policy), Some(None));
+    }
+}
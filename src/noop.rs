@@ -0,0 +1,164 @@
+//! # No-op cache provider
+//!
+//! This is an implementation of a cache provider for [`crate::CacheLayer`]
+//! that never stores anything. Every request goes to the inner service,
+//! which makes it possible to disable caching without changing the service
+//! stack, for example in tests or to measure the effect of the cache.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, service_fn};
+//! use tower_cache::{
+//!     CacheLayer,
+//!     noop::NoopProvider,
+//! };
+//! async fn handler(req: String) -> Result<String, Infallible> {
+//!     Ok(req.to_uppercase())
+//! }
+//!
+//! // Initialize the cache provider service
+//! let noop_provider = NoopProvider::new::<String, String>();
+//!
+//! // Wrap the service with CacheLayer.
+//! let mut my_service = ServiceBuilder::new()
+//!     .layer(CacheLayer::new(noop_provider))
+//!     .service(service_fn(handler));
+//!
+//! # tokio_test::block_on(async move {
+//! // Call the service
+//! let res = my_service.call("Hello".to_string()).await.unwrap();
+//! assert_eq!(res, "HELLO".to_string());
+//! # })
+//! ```
+//!
+
+use crate::{ProviderRequest, ProviderResponse};
+use std::{
+    convert::Infallible,
+    fmt,
+    future::{ready, Ready},
+    marker::PhantomData,
+    task::{Context, Poll},
+};
+use tower::Service;
+
+/// Cache provider that never stores anything
+pub struct NoopProvider<'a, K, V> {
+    _types: PhantomData<fn() -> (K, V)>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> NoopProvider<'a, (), ()> {
+    /// Create a new no-op cache provider
+    pub fn new<K, V>() -> NoopProvider<'a, K, V> {
+        NoopProvider {
+            _types: PhantomData,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V> Default for NoopProvider<'a, K, V> {
+    fn default() -> Self {
+        NoopProvider::new()
+    }
+}
+
+// Custom implementation of Clone as the Clone derive doesn't mark
+// NoopProvider as Clone if K or V is not clone.
+impl<'a, K, V> Clone for NoopProvider<'a, K, V> {
+    fn clone(&self) -> Self {
+        NoopProvider::new()
+    }
+}
+
+impl<'a, K, V> fmt::Debug for NoopProvider<'a, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NoopProvider").finish()
+    }
+}
+
+impl<'a, K, V> Service<ProviderRequest<K, V>> for NoopProvider<'a, K, V> {
+    type Response = ProviderResponse<K, V>;
+    type Error = Infallible;
+    type Future = Ready<Result<ProviderResponse<K, V>, Infallible>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ProviderRequest<K, V>) -> Self::Future {
+        ready(Ok(match request {
+            ProviderRequest::Insert(_, value, _) => ProviderResponse::Found(value),
+            ProviderRequest::InsertNegative(_, _) => ProviderResponse::FoundNegative,
+            ProviderRequest::Clear => ProviderResponse::Cleared,
+            ProviderRequest::Contains(_) => ProviderResponse::Present(false),
+            ProviderRequest::GetMany(keys) => {
+                ProviderResponse::Many(keys.into_iter().map(|key| (key, None)).collect())
+            }
+            ProviderRequest::Get(_)
+            | ProviderRequest::Remove(_)
+            | ProviderRequest::Ttl(_)
+            | ProviderRequest::Age(_)
+            | ProviderRequest::GetOrInsert(_, _)
+            | ProviderRequest::InsertMany(_)
+            | ProviderRequest::Stats => ProviderResponse::NotFound,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CacheLayer;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use tower::{service_fn, ServiceBuilder};
+
+    #[tokio::test]
+    async fn test_pass_through() -> Result<(), Infallible> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let handler = |req: String| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            ready(Ok::<_, Infallible>(req.to_uppercase()))
+        };
+        let mut my_service = ServiceBuilder::new()
+            .layer(CacheLayer::new(NoopProvider::new::<String, String>()))
+            .service(service_fn(handler));
+
+        for _ in 0..3 {
+            let res = my_service
+                .call("Hello".to_string())
+                .await
+                .map_err(|e| e.into_service_error())?;
+            assert_eq!(res, "HELLO");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_nothing_retained() -> Result<(), Infallible> {
+        let mut provider = NoopProvider::new::<&str, &str>();
+
+        let res = provider
+            .call(ProviderRequest::Insert("a", "A", None))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Found("A")));
+        let res = provider.call(ProviderRequest::Get("a")).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        let res = provider.call(ProviderRequest::Contains("a")).await?;
+        assert!(matches!(res, ProviderResponse::Present(false)));
+        let res = provider
+            .call(ProviderRequest::GetMany(vec!["a", "b"]))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Many(v) if v == [("a", None), ("b", None)]));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+/// # Response caching predicate
+///
+/// By default, every successful response from the inner service is stored in
+/// the cache provider. A predicate decides which responses should be stored,
+/// for example to skip empty bodies or error statuses. Responses rejected by
+/// the predicate are still returned to the caller.
+///
+/// ## Usage
+///
+/// In most cases, you don't need to implement this trait directly. It is
+/// automatically implemented for functions taking a reference to the response
+/// and returning a `bool`. Use [`crate::CacheLayer::cache_if`] to use it with
+/// a [`crate::CacheLayer`].
+///
+/// ```rust
+/// use tower_cache::CachePredicate;
+///
+/// let non_empty = |res: &String| !res.is_empty();
+///
+/// assert!(non_empty.should_cache(&"Hello".to_string()));
+/// assert!(!non_empty.should_cache(&String::new()));
+/// ```
+///
+/// This is also implemented for `()`, which caches every response:
+///
+/// ```rust
+/// use tower_cache::CachePredicate;
+///
+/// assert!(().should_cache(&String::new()));
+/// ```
+///
+pub trait CachePredicate<Res> {
+    /// Return `true` if `res` should be stored in the cache provider.
+    fn should_cache(&self, res: &Res) -> bool;
+}
+
+impl<Res> CachePredicate<Res> for () {
+    fn should_cache(&self, _res: &Res) -> bool {
+        true
+    }
+}
+
+impl<F, Res> CachePredicate<Res> for F
+where
+    F: Fn(&Res) -> bool,
+{
+    fn should_cache(&self, res: &Res) -> bool {
+        (self)(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit() {
+        assert!(().should_cache(&0));
+    }
+
+    #[test]
+    fn test_closure() {
+        let even = |v: &usize| v.is_multiple_of(2);
+
+        assert!(even.should_cache(&2));
+        assert!(!even.should_cache(&3));
+    }
+}
@@ -0,0 +1,481 @@
+//! # Random cache provider
+//!
+//! This is an implementation of a cache provider for [`crate::CacheLayer`]
+//! that evicts a uniformly random entry when it is full. It keeps no
+//! information about accesses, so lookups only take a shared lock, and it
+//! avoids the worst cases of LRU on workloads without temporal locality, such
+//! as scans over a key space slightly larger than the capacity.
+//!
+//! Use [`RandomProvider::with_seed`] to get the same evictions on every run,
+//! for example in tests.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, service_fn};
+//! use tower_cache::{
+//!     CacheLayer,
+//!     random::RandomProvider,
+//! };
+//! async fn handler(req: String) -> Result<String, Infallible> {
+//!     Ok(req.to_uppercase())
+//! }
+//!
+//! // Initialize the cache provider service
+//! let random_provider = RandomProvider::new::<String, String>(20);
+//!
+//! // Wrap the service with CacheLayer.
+//! let mut my_service = ServiceBuilder::new()
+//!     .layer(CacheLayer::new(random_provider))
+//!     .service(service_fn(handler));
+//!
+//! # tokio_test::block_on(async move {
+//! // Call the service
+//! let res = my_service.call("Hello".to_string()).await.unwrap();
+//! assert_eq!(res, "HELLO".to_string());
+//! # })
+//! ```
+//!
+
+use crate::{
+    entry::Entry, time, trace, Configure, ProviderConfig, ProviderRequest, ProviderResponse,
+};
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    convert::Infallible,
+    future::{ready, Future},
+    hash::{BuildHasher, Hash, Hasher},
+    marker::PhantomData,
+    pin::Pin,
+    sync::{Arc, RwLock},
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower::Service;
+
+/// Local random-eviction cache provider
+#[derive(Debug)]
+pub struct RandomProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    inner: Arc<RwLock<Random<K, V>>>,
+    ttl: Option<Duration>,
+    stale_window: Option<Duration>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> RandomProvider<'a, (), ()> {
+    /// Create a new random-eviction cache provider with the desired capacity
+    ///
+    /// Evictions are seeded differently every time. A capacity of `0` is
+    /// clamped to `1`.
+    pub fn new<K, V>(capacity: usize) -> RandomProvider<'a, K, V>
+    where
+        K: Eq + Hash,
+    {
+        // A new `RandomState` is seeded differently every time, which avoids
+        // depending on a random number generator for the seed.
+        let seed = RandomState::new().build_hasher().finish();
+        Self::with_seed(capacity, seed)
+    }
+
+    /// Create a new random-eviction cache provider where entries expire after
+    /// `ttl`
+    ///
+    /// As with [`RandomProvider::new`], a capacity of `0` is clamped to `1`.
+    pub fn with_ttl<K, V>(capacity: usize, ttl: Duration) -> RandomProvider<'a, K, V>
+    where
+        K: Eq + Hash,
+    {
+        RandomProvider {
+            ttl: Some(ttl),
+            ..Self::new(capacity)
+        }
+    }
+
+    /// Create a new random-eviction cache provider choosing the entries to
+    /// evict from `seed`
+    ///
+    /// The same seed and sequence of requests always evict the same entries.
+    /// As with [`RandomProvider::new`], a capacity of `0` is clamped to `1`.
+    pub fn with_seed<K, V>(capacity: usize, seed: u64) -> RandomProvider<'a, K, V>
+    where
+        K: Eq + Hash,
+    {
+        RandomProvider {
+            inner: Arc::new(RwLock::new(Random::new(capacity.max(1), seed))),
+            ttl: None,
+            stale_window: None,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V> RandomProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    /// Set how long entries are kept for when the request doesn't specify a
+    /// TTL
+    ///
+    /// This is mostly useful with [`RandomProvider::with_seed`].
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Keep entries for `window` after they expire, and return them as
+    /// [`ProviderResponse::FoundStale`] during that time.
+    ///
+    /// This allows [`crate::CacheLayer::stale_while_revalidate`] to serve
+    /// them while they are refreshed. Entries without a TTL never become
+    /// stale.
+    pub fn stale_window(mut self, window: Duration) -> Self {
+        self.stale_window = Some(window);
+        self
+    }
+}
+
+// Custom implementation of Clone as the Clone derive doesn't mark
+// RandomProvider as Clone if K or V is not clone.
+impl<'a, K, V> Clone for RandomProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            ttl: self.ttl,
+            stale_window: self.stale_window,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+/// Sets the TTL. Setting the capacity clears the cache, for all clones of the
+/// provider, so it should be done before using it.
+impl<'a, K, V> Configure for RandomProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    fn configure(mut self, config: &ProviderConfig) -> Self {
+        if let Some(ttl) = config.ttl {
+            self.ttl = Some(ttl);
+        }
+        if let Some(capacity) = config.capacity {
+            // Caches hold at least one entry.
+            if capacity == 0 {
+                trace::unsupported_config::<Self>("capacity");
+            }
+            let mut inner = self.inner.write().unwrap();
+            let seed = inner.state;
+            *inner = Random::new(capacity.max(1), seed);
+        }
+        self
+    }
+}
+
+impl<'a, K, V> Service<ProviderRequest<K, V>> for RandomProvider<'a, K, V>
+where
+    K: Eq + Hash + Clone + Send + 'a,
+    V: Clone + Send + 'a,
+{
+    type Response = ProviderResponse<K, V>;
+    type Error = Infallible;
+    type Future = ProviderFuture<'a, K, V>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ProviderRequest<K, V>) -> Self::Future {
+        Box::pin(ready(Ok(match request {
+            ProviderRequest::Get(key) => {
+                let now = time::now();
+                let response = {
+                    let inner = self.inner.read().unwrap();
+                    inner.get(&key).map(|entry| entry.response_at(now))
+                };
+                match response {
+                    Some(Some(response)) => response,
+                    // The entry has expired: remove it so it doesn't take up
+                    // capacity anymore.
+                    Some(None) => {
+                        let mut inner = self.inner.write().unwrap();
+                        if inner.get(&key).is_some_and(|entry| entry.is_expired(now)) {
+                            inner.remove(&key);
+                        }
+                        ProviderResponse::NotFound
+                    }
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            ProviderRequest::Insert(key, value, ttl) => {
+                let entry =
+                    Entry::new(value.clone(), ttl.or(self.ttl)).stale_for(self.stale_window);
+                self.inner.write().unwrap().insert(key, entry);
+                ProviderResponse::Found(value)
+            }
+            ProviderRequest::InsertNegative(key, ttl) => {
+                self.inner
+                    .write()
+                    .unwrap()
+                    .insert(key, Entry::negative(ttl));
+                ProviderResponse::FoundNegative
+            }
+            ProviderRequest::Clear => {
+                self.inner.write().unwrap().clear();
+                ProviderResponse::Cleared
+            }
+            ProviderRequest::Remove(key) => match self.inner.write().unwrap().remove(&key) {
+                Some(_) => ProviderResponse::Removed,
+                None => ProviderResponse::NotFound,
+            },
+            ProviderRequest::Contains(key) => {
+                let now = time::now();
+                let inner = self.inner.read().unwrap();
+                let present = inner.get(&key).is_some_and(|entry| !entry.is_expired(now));
+                ProviderResponse::Present(present)
+            }
+            ProviderRequest::Ttl(key) => {
+                let now = time::now();
+                let inner = self.inner.read().unwrap();
+                match inner.get(&key).and_then(|entry| entry.ttl_at(now)) {
+                    Some((remaining, ttl)) => ProviderResponse::Ttl(remaining, ttl),
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            ProviderRequest::Age(key) => {
+                let now = time::now();
+                let inner = self.inner.read().unwrap();
+                match inner.get(&key).and_then(|entry| entry.age_at(now)) {
+                    Some(age) => ProviderResponse::Age(age),
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            // Look up and insert under the same lock.
+            ProviderRequest::GetOrInsert(key, value) => {
+                let now = time::now();
+                let mut inner = self.inner.write().unwrap();
+                match inner.get(&key).and_then(|entry| entry.fresh_value_at(now)) {
+                    Some(existing) => ProviderResponse::Found(existing.clone()),
+                    None => {
+                        let entry =
+                            Entry::new(value.clone(), self.ttl).stale_for(self.stale_window);
+                        inner.insert(key, entry);
+                        ProviderResponse::Inserted(value)
+                    }
+                }
+            }
+            ProviderRequest::GetMany(keys) => {
+                let now = time::now();
+                let inner = self.inner.read().unwrap();
+                ProviderResponse::Many(
+                    keys.into_iter()
+                        .map(|key| {
+                            let value = inner
+                                .get(&key)
+                                .and_then(|entry| entry.value_at(now))
+                                .cloned();
+                            (key, value)
+                        })
+                        .collect(),
+                )
+            }
+            // Inserting entries one by one is as cheap as a batch in memory.
+            ProviderRequest::InsertMany(_) => ProviderResponse::NotFound,
+            // Evictions are not counted.
+            ProviderRequest::Stats => ProviderResponse::NotFound,
+        })))
+    }
+}
+
+type ProviderFuture<'a, K, V> =
+    Pin<Box<dyn Future<Output = Result<ProviderResponse<K, V>, Infallible>> + Send + 'a>>;
+
+/// Entries of a [`RandomProvider`], stored densely so that one can be picked
+/// at random
+#[derive(Debug)]
+struct Random<K, V> {
+    entries: Vec<(K, Entry<V>)>,
+    index: HashMap<K, usize>,
+    capacity: usize,
+    state: u64,
+}
+
+impl<K, V> Random<K, V>
+where
+    K: Eq + Hash,
+{
+    fn new(capacity: usize, seed: u64) -> Self {
+        Random {
+            entries: Vec::new(),
+            index: HashMap::new(),
+            capacity,
+            state: seed,
+        }
+    }
+
+    fn get(&self, key: &K) -> Option<&Entry<V>> {
+        let index = *self.index.get(key)?;
+        Some(&self.entries[index].1)
+    }
+
+    /// Store `entry`, evicting a random entry if full
+    fn insert(&mut self, key: K, entry: Entry<V>)
+    where
+        K: Clone,
+    {
+        if let Some(&index) = self.index.get(&key) {
+            self.entries[index].1 = entry;
+            return;
+        }
+        if self.entries.len() >= self.capacity {
+            let index = self.below(self.entries.len());
+            self.remove_at(index);
+        }
+        self.index.insert(key.clone(), self.entries.len());
+        self.entries.push((key, entry));
+    }
+
+    fn remove(&mut self, key: &K) -> Option<Entry<V>> {
+        let index = *self.index.get(key)?;
+        Some(self.remove_at(index))
+    }
+
+    /// Remove the entry at `index`, moving the last entry in its place
+    fn remove_at(&mut self, index: usize) -> Entry<V> {
+        let (key, entry) = self.entries.swap_remove(index);
+        self.index.remove(&key);
+        if let Some((moved, _)) = self.entries.get(index) {
+            if let Some(moved) = self.index.get_mut(moved) {
+                *moved = index;
+            }
+        }
+        entry
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.index.clear();
+    }
+
+    /// Return a random number in `0..n`
+    ///
+    /// This uses SplitMix64, which is fast and good enough to pick entries
+    /// to evict, and maps it to the range with a multiplication rather than
+    /// a modulo to avoid favouring low indices.
+    fn below(&mut self, n: usize) -> usize {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        ((z as u128 * n as u128) >> 64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::{check_configure, check_provider};
+
+    /// Insert `0..count` in a provider with the given capacity and seed, and
+    /// return the keys left
+    async fn survivors(capacity: usize, count: usize, seed: u64) -> Vec<usize> {
+        let mut provider = RandomProvider::with_seed::<usize, usize>(capacity, seed);
+        for key in 0..count {
+            let Ok(_) = provider.call(ProviderRequest::Insert(key, key, None)).await;
+        }
+        let inner = provider.inner.read().unwrap();
+        let mut keys: Vec<_> = inner.index.keys().copied().collect();
+        keys.sort();
+        keys
+    }
+
+    #[tokio::test]
+    async fn test_conformance() -> Result<(), Infallible> {
+        check_provider(RandomProvider::new::<String, String>(10)).await
+    }
+
+    #[tokio::test]
+    async fn test_seeded_evictions() -> Result<(), Infallible> {
+        let mut provider = RandomProvider::with_seed::<usize, usize>(3, 42);
+
+        for key in 0..3 {
+            provider
+                .call(ProviderRequest::Insert(key, key, None))
+                .await?;
+        }
+        let mut evicted = Vec::new();
+        for key in 3..8 {
+            provider
+                .call(ProviderRequest::Insert(key, key, None))
+                .await?;
+            for old in 0..key {
+                let res = provider.call(ProviderRequest::Contains(old)).await?;
+                if matches!(res, ProviderResponse::Present(false)) && !evicted.contains(&old) {
+                    evicted.push(old);
+                }
+            }
+            assert_eq!(provider.inner.read().unwrap().entries.len(), 3);
+        }
+        assert_eq!(evicted, [2, 0, 3, 1, 4]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_capacity() {
+        let keys = survivors(16, 1000, 7).await;
+        assert_eq!(keys.len(), 16);
+        assert_eq!(keys, survivors(16, 1000, 7).await);
+        assert_ne!(keys, survivors(16, 1000, 8).await);
+        // Random eviction keeps some old entries, unlike LRU or FIFO.
+        assert!(keys.iter().any(|key| *key < 984));
+    }
+
+    #[tokio::test]
+    async fn test_remove_clear() -> Result<(), Infallible> {
+        let mut provider = RandomProvider::with_ttl::<usize, usize>(10, Duration::ZERO);
+
+        provider.call(ProviderRequest::Insert(1, 1, None)).await?;
+        let res = provider.call(ProviderRequest::Get(1)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        assert!(provider.inner.read().unwrap().entries.is_empty());
+
+        for key in 2..5 {
+            provider
+                .call(ProviderRequest::Insert(
+                    key,
+                    key,
+                    Some(Duration::from_secs(10)),
+                ))
+                .await?;
+        }
+        // Removing an entry moves the last one in its place.
+        let res = provider.call(ProviderRequest::Remove(2)).await?;
+        assert!(matches!(res, ProviderResponse::Removed));
+        let res = provider.call(ProviderRequest::Get(4)).await?;
+        assert!(matches!(res, ProviderResponse::Found(4)));
+        let res = provider.call(ProviderRequest::Get(3)).await?;
+        assert!(matches!(res, ProviderResponse::Found(3)));
+
+        let res = provider.call(ProviderRequest::Clear).await?;
+        assert!(matches!(res, ProviderResponse::Cleared));
+        let res = provider.call(ProviderRequest::Get(3)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        assert!(provider.inner.read().unwrap().index.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_configure() -> Result<(), Infallible> {
+        let configure =
+            |config: &ProviderConfig| RandomProvider::new::<usize, usize>(10).configure(config);
+        check_configure(configure, true).await
+    }
+}
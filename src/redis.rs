@@ -0,0 +1,1556 @@
+//! # Redis cache provider
+//!
+//! This is an implementation of a cache provider for [`crate::CacheLayer`]
+//! backed by [Redis](https://redis.io/), which allows multiple instances of a
+//! service to share the same cache.
+//!
+//! Keys are formatted using their [`Display`](fmt::Display) implementation,
+//! or with the [`KeyCodec`] passed to [`RedisProvider::with_key_codec`], and
+//! prefixed with the prefix passed to [`RedisProvider::new`], so multiple
+//! applications can share a single Redis instance. Values are serialized as JSON by
+//! default, see [`RedisProvider::with_codec`] to use another [`Codec`], and
+//! prefixed with a tag byte that tells them apart from negative entries.
+//!
+//! With the `redis-pool` feature, requests can also be spread over a pool of
+//! connections with [`RedisPool`].
+//!
+//! ## Usage
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, service_fn};
+//! use tower_cache::{
+//!     CacheLayer,
+//!     redis::RedisProvider,
+//! };
+//! async fn handler(req: String) -> Result<String, Infallible> {
+//!     Ok(req.to_uppercase())
+//! }
+//!
+//! # tokio_test::block_on(async move {
+//! // Initialize the cache provider service
+//! let client = redis::Client::open("redis://127.0.0.1/").unwrap();
+//! let manager = redis::aio::ConnectionManager::new(client).await.unwrap();
+//! let redis_provider = RedisProvider::new::<String, String, _>(manager, "my-app:");
+//!
+//! // Wrap the service with CacheLayer.
+//! let mut my_service = ServiceBuilder::new()
+//!     .layer(CacheLayer::new(redis_provider))
+//!     .service(service_fn(handler));
+//!
+//! // Call the service
+//! let res = my_service.call("Hello".to_string()).await.unwrap();
+//! assert_eq!(res, "HELLO".to_string());
+//! # })
+//! ```
+//!
+//! ## Circuit breaker
+//!
+//! When Redis is unreachable, every request waits for the connection to time
+//! out before failing. With [`RedisProvider::circuit_breaker`], the provider
+//! stops sending requests after a number of consecutive connection errors,
+//! and reports misses right away instead. Once the cooldown is over, the next
+//! request probes the connection again: the breaker closes if it succeeds,
+//! and stays open for another cooldown if it fails.
+//!
+//! ```rust,no_run
+//! # tokio_test::block_on(async move {
+//! use std::time::Duration;
+//! use tower_cache::redis::RedisProvider;
+//!
+//! let client = redis::Client::open("redis://127.0.0.1/").unwrap();
+//! let manager = redis::aio::ConnectionManager::new(client).await.unwrap();
+//! let redis_provider = RedisProvider::new::<String, String, _>(manager, "my-app:")
+//!     .circuit_breaker(5, Duration::from_secs(10));
+//! # })
+//! ```
+//!
+//! ## Connection pool
+//!
+//! A single multiplexed connection handles concurrent requests, but a slow
+//! command delays every other command sent on it. [`RedisPool`] wraps a
+//! [`deadpool_redis::Pool`] so that each provider call uses its own
+//! connection. The provider is only ready when a connection is available,
+//! which makes [`crate::CacheLayer`] wait for one before sending its request.
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "redis-pool")]
+//! # {
+//! use tower_cache::redis::{RedisPool, RedisProvider};
+//!
+//! // Open at most 16 connections to Redis
+//! let pool = RedisPool::from_url("redis://127.0.0.1/", 16).unwrap();
+//! let redis_provider = RedisProvider::new::<String, String, _>(pool, "my-app:");
+//! # }
+//! ```
+//!
+
+use crate::{
+    codec::{Codec, DisplayKey, JsonCodec, KeyCodec},
+    time, trace, Configure, ProviderConfig, ProviderRequest, ProviderResponse,
+};
+use ::redis::{aio::ConnectionLike, AsyncCommands, ErrorKind, RedisError};
+use std::{
+    error, fmt,
+    future::{ready, Future},
+    marker::PhantomData,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tower::Service;
+
+/// Source of the connections used by a [`RedisProvider`]
+///
+/// This is implemented for any connection that can be cloned, such as a
+/// [`::redis::aio::ConnectionManager`], in which case all requests share the
+/// same connection. With the `redis-pool` feature, this is also implemented
+/// for [`RedisPool`].
+pub trait ConnectionSource: Clone {
+    /// Connection used to send the commands of a request
+    type Connection: ConnectionLike + Send + Sync;
+    /// Future returned by [`ConnectionSource::connection`]
+    type Future: Future<Output = Result<Self::Connection, Error>> + Send;
+
+    /// Poll whether a connection is available for the next request
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let _ = cx;
+        Poll::Ready(Ok(()))
+    }
+
+    /// Return a connection for a request
+    fn connection(&mut self) -> Self::Future;
+}
+
+impl<C> ConnectionSource for C
+where
+    C: ConnectionLike + Clone + Send + Sync,
+{
+    type Connection = C;
+    type Future = std::future::Ready<Result<C, Error>>;
+
+    fn connection(&mut self) -> Self::Future {
+        std::future::ready(Ok(self.clone()))
+    }
+}
+
+/// Redis cache provider
+///
+/// The provider is generic over the [`ConnectionSource`], which is usually a
+/// [`::redis::aio::ConnectionManager`] or a [`RedisPool`]. Cloning the
+/// provider shares the underlying connection or pool.
+pub struct RedisProvider<'a, K, V, C, E = JsonCodec, KC = DisplayKey> {
+    conn: C,
+    prefix: Arc<str>,
+    ttl: Option<Duration>,
+    breaker: Option<Arc<Breaker>>,
+    codec: E,
+    key_codec: KC,
+    _types: PhantomData<fn() -> (K, V)>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> RedisProvider<'a, (), (), ()> {
+    /// Create a new Redis cache provider
+    ///
+    /// All keys are prefixed by `prefix` before being sent to Redis.
+    pub fn new<K, V, C>(conn: C, prefix: impl Into<String>) -> RedisProvider<'a, K, V, C>
+    where
+        C: ConnectionSource,
+    {
+        RedisProvider {
+            conn,
+            prefix: prefix.into().into(),
+            ttl: None,
+            breaker: None,
+            codec: JsonCodec,
+            key_codec: DisplayKey,
+            _types: PhantomData,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V, C, E, KC> RedisProvider<'a, K, V, C, E, KC> {
+    /// Use a different [`Codec`] to serialize values
+    ///
+    /// ```rust,no_run
+    /// # #[cfg(feature = "bincode")]
+    /// # tokio_test::block_on(async move {
+    /// use tower_cache::{codec::BincodeCodec, redis::RedisProvider};
+    ///
+    /// let client = redis::Client::open("redis://127.0.0.1/").unwrap();
+    /// let manager = redis::aio::ConnectionManager::new(client).await.unwrap();
+    /// let redis_provider = RedisProvider::new::<String, String, _>(manager, "my-app:")
+    ///     .with_codec(BincodeCodec);
+    /// # })
+    /// ```
+    pub fn with_codec<NE>(self, codec: NE) -> RedisProvider<'a, K, V, C, NE, KC> {
+        RedisProvider {
+            conn: self.conn,
+            prefix: self.prefix,
+            ttl: self.ttl,
+            breaker: self.breaker,
+            codec,
+            key_codec: self.key_codec,
+            _types: PhantomData,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Use a different [`KeyCodec`] to build Redis keys
+    ///
+    /// ```rust,no_run
+    /// # tokio_test::block_on(async move {
+    /// use tower_cache::{codec::TupleKey, redis::RedisProvider};
+    ///
+    /// let client = redis::Client::open("redis://127.0.0.1/").unwrap();
+    /// let manager = redis::aio::ConnectionManager::new(client).await.unwrap();
+    /// // Keys such as `(42, "en")` are stored as `my-app:42:en`
+    /// let redis_provider = RedisProvider::new::<(u64, String), String, _>(manager, "my-app:")
+    ///     .with_key_codec(TupleKey::new());
+    /// # })
+    /// ```
+    pub fn with_key_codec<NK>(self, key_codec: NK) -> RedisProvider<'a, K, V, C, E, NK> {
+        RedisProvider {
+            conn: self.conn,
+            prefix: self.prefix,
+            ttl: self.ttl,
+            breaker: self.breaker,
+            codec: self.codec,
+            key_codec,
+            _types: PhantomData,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Stop sending requests to Redis for `cooldown` after `threshold`
+    /// consecutive connection errors
+    ///
+    /// While the breaker is open, lookups are reported as misses and writes
+    /// are dropped, without waiting for a connection. The breaker is shared
+    /// by all clones of the provider. A threshold of `0` is clamped to `1`.
+    ///
+    /// See the [module documentation](self#circuit-breaker).
+    pub fn circuit_breaker(mut self, threshold: u32, cooldown: Duration) -> Self {
+        self.breaker = Some(Arc::new(Breaker {
+            threshold: threshold.max(1),
+            cooldown,
+            state: Mutex::default(),
+        }));
+        self
+    }
+}
+
+// Custom implementation of Clone as the Clone derive doesn't mark RedisProvider
+// as Clone if K or V is not clone.
+impl<'a, K, V, C, E, KC> Clone for RedisProvider<'a, K, V, C, E, KC>
+where
+    C: Clone,
+    E: Clone,
+    KC: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            conn: self.conn.clone(),
+            prefix: self.prefix.clone(),
+            ttl: self.ttl,
+            breaker: self.breaker.clone(),
+            codec: self.codec.clone(),
+            key_codec: self.key_codec.clone(),
+            _types: PhantomData,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V, C, E, KC> fmt::Debug for RedisProvider<'a, K, V, C, E, KC> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RedisProvider")
+            .field("prefix", &self.prefix)
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, K, V, C, E, KC> RedisProvider<'a, K, V, C, E, KC>
+where
+    KC: KeyCodec<K>,
+{
+    /// Return the Redis key for a given cache key
+    fn key(&self, key: &K) -> String {
+        format!("{}{}", self.prefix, self.key_codec.encode_key(key))
+    }
+}
+
+/// Sets the default TTL of entries inserted without one. The capacity is
+/// ignored, as the memory limit is set on the Redis server.
+impl<'a, K, V, C, E, KC> Configure for RedisProvider<'a, K, V, C, E, KC> {
+    fn configure(mut self, config: &ProviderConfig) -> Self {
+        if let Some(ttl) = config.ttl {
+            self.ttl = Some(ttl);
+        }
+        if config.capacity.is_some() {
+            trace::unsupported_config::<Self>("capacity");
+        }
+        self
+    }
+}
+
+impl<'a, K, V, C, E, KC> Service<ProviderRequest<K, V>> for RedisProvider<'a, K, V, C, E, KC>
+where
+    KC: KeyCodec<K>,
+    K: Send + 'a,
+    V: Send + 'a,
+    C: ConnectionSource + 'a,
+    C::Connection: 'a,
+    C::Future: 'a,
+    E: Codec<V> + Clone + Send + 'a,
+{
+    type Response = ProviderResponse<K, V>;
+    type Error = Error;
+    type Future = ProviderFuture<'a, K, V>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Don't wait for a connection while the breaker is open, as requests
+        // won't use it.
+        if self
+            .breaker
+            .as_ref()
+            .is_some_and(|breaker| breaker.is_open())
+        {
+            return Poll::Ready(Ok(()));
+        }
+        let res = std::task::ready!(self.conn.poll_ready(cx));
+        if let (Some(breaker), Err(err)) = (&self.breaker, &res) {
+            breaker.record_error(err);
+        }
+        Poll::Ready(res)
+    }
+
+    fn call(&mut self, request: ProviderRequest<K, V>) -> Self::Future {
+        let breaker = self.breaker.clone();
+        if breaker.as_ref().is_some_and(|breaker| !breaker.allow()) {
+            return Box::pin(ready(Ok(short_circuit(request))));
+        }
+
+        let conn = self.conn.connection();
+        let codec = self.codec.clone();
+
+        let fut: ProviderFuture<'a, K, V> = match request {
+            ProviderRequest::Get(key) => {
+                let key = self.key(&key);
+                Box::pin(async move {
+                    let mut conn = conn.await?;
+                    let value: Option<Vec<u8>> = conn.get(key).await?;
+                    Ok(match value.as_deref().map(untag).transpose()? {
+                        Some(Some(value)) => {
+                            ProviderResponse::Found(codec.decode(value).map_err(Error::codec)?)
+                        }
+                        Some(None) => ProviderResponse::FoundNegative,
+                        None => ProviderResponse::NotFound,
+                    })
+                })
+            }
+            ProviderRequest::Insert(key, value, ttl) => {
+                let key = self.key(&key);
+                let ttl = ttl.or(self.ttl);
+                Box::pin(async move {
+                    let mut conn = conn.await?;
+                    let data = tag(codec.encode(&value).map_err(Error::codec)?);
+                    match ttl {
+                        Some(ttl) => {
+                            let ttl = ttl.as_millis().max(1) as u64;
+                            conn.pset_ex::<_, _, ()>(key, data, ttl).await?;
+                        }
+                        None => conn.set::<_, _, ()>(key, data).await?,
+                    }
+                    Ok(ProviderResponse::Found(value))
+                })
+            }
+            ProviderRequest::InsertNegative(key, ttl) => {
+                let key = self.key(&key);
+                Box::pin(async move {
+                    let mut conn = conn.await?;
+                    let ttl = ttl.as_millis().max(1) as u64;
+                    conn.pset_ex::<_, _, ()>(key, NEGATIVE_SENTINEL, ttl)
+                        .await?;
+                    Ok(ProviderResponse::FoundNegative)
+                })
+            }
+            ProviderRequest::Clear => {
+                let pattern = format!("{}*", escape_pattern(&self.prefix));
+                Box::pin(async move {
+                    let mut conn = conn.await?;
+                    // SCAN doesn't block the server, unlike KEYS.
+                    let mut cursor = 0;
+                    loop {
+                        let (next, keys): (u64, Vec<Vec<u8>>) = ::redis::cmd("SCAN")
+                            .arg(cursor)
+                            .arg("MATCH")
+                            .arg(&pattern)
+                            .arg("COUNT")
+                            .arg(SCAN_COUNT)
+                            .query_async(&mut conn)
+                            .await?;
+                        if !keys.is_empty() {
+                            conn.del::<_, ()>(keys).await?;
+                        }
+                        if next == 0 {
+                            break;
+                        }
+                        cursor = next;
+                    }
+                    Ok(ProviderResponse::Cleared)
+                })
+            }
+            ProviderRequest::Remove(key) => {
+                let key = self.key(&key);
+                Box::pin(async move {
+                    let mut conn = conn.await?;
+                    let removed: u64 = conn.del(key).await?;
+                    Ok(match removed {
+                        0 => ProviderResponse::NotFound,
+                        _ => ProviderResponse::Removed,
+                    })
+                })
+            }
+            ProviderRequest::Contains(key) => {
+                let key = self.key(&key);
+                Box::pin(async move {
+                    let mut conn = conn.await?;
+                    let present: bool = conn.exists(key).await?;
+                    Ok(ProviderResponse::Present(present))
+                })
+            }
+            // The original TTL of an entry isn't stored in Redis.
+            ProviderRequest::Ttl(_) => Box::pin(async { Ok(ProviderResponse::NotFound) }),
+            // SET NX cannot replace negative entries, so this would need a
+            // script to be atomic.
+            ProviderRequest::GetOrInsert(_, _) => {
+                Box::pin(async { Ok(ProviderResponse::NotFound) })
+            }
+            // Fetch all keys in a single round-trip.
+            ProviderRequest::GetMany(keys) => {
+                let redis_keys: Vec<_> = keys.iter().map(|key| self.key(key)).collect();
+                Box::pin(async move {
+                    // MGET requires at least one key.
+                    if keys.is_empty() {
+                        return Ok(ProviderResponse::Many(Vec::new()));
+                    }
+                    let mut conn = conn.await?;
+                    let values: Vec<Option<Vec<u8>>> = ::redis::cmd("MGET")
+                        .arg(&redis_keys)
+                        .query_async(&mut conn)
+                        .await?;
+                    // Values are paired with keys by position.
+                    if values.len() != keys.len() {
+                        return Err(Error::RedisError(RedisError::from((
+                            ErrorKind::UnexpectedReturnType,
+                            "MGET returned a different number of values than keys",
+                        ))));
+                    }
+                    let values = keys
+                        .into_iter()
+                        .zip(values)
+                        .map(
+                            |(key, value)| match value.as_deref().map(untag).transpose()? {
+                                Some(Some(value)) => codec
+                                    .decode(value)
+                                    .map(|value| (key, Some(value)))
+                                    .map_err(Error::codec),
+                                _ => Ok((key, None)),
+                            },
+                        )
+                        .collect::<Result<_, _>>()?;
+                    Ok(ProviderResponse::Many(values))
+                })
+            }
+            // Pipeline the writes to send them in a single round-trip. MSET
+            // would not support per-entry TTLs.
+            ProviderRequest::InsertMany(entries) => {
+                let entries: Vec<_> = entries
+                    .into_iter()
+                    .map(|(key, value, ttl)| (self.key(&key), key, value, ttl.or(self.ttl)))
+                    .collect();
+                Box::pin(async move {
+                    if entries.is_empty() {
+                        return Ok(ProviderResponse::Many(Vec::new()));
+                    }
+                    let mut pipe = ::redis::pipe();
+                    let mut values = Vec::with_capacity(entries.len());
+                    for (redis_key, key, value, ttl) in entries {
+                        let data = tag(codec.encode(&value).map_err(Error::codec)?);
+                        match ttl {
+                            Some(ttl) => {
+                                pipe.pset_ex(redis_key, data, ttl.as_millis().max(1) as u64)
+                            }
+                            None => pipe.set(redis_key, data),
+                        }
+                        .ignore();
+                        values.push((key, Some(value)));
+                    }
+                    let mut conn = conn.await?;
+                    pipe.query_async::<()>(&mut conn).await?;
+                    Ok(ProviderResponse::Many(values))
+                })
+            }
+            // Redis evicts keys on its own, and shares the database with
+            // other clients.
+            ProviderRequest::Stats => Box::pin(async { Ok(ProviderResponse::NotFound) }),
+            // The insertion time of an entry isn't stored in Redis.
+            ProviderRequest::Age(_) => Box::pin(async { Ok(ProviderResponse::NotFound) }),
+        };
+
+        match breaker {
+            Some(breaker) => Box::pin(async move {
+                let res = fut.await;
+                match &res {
+                    Ok(_) => breaker.record_success(),
+                    Err(err) => breaker.record_error(err),
+                }
+                res
+            }),
+            None => fut,
+        }
+    }
+}
+
+/// Response to a request while the circuit breaker is open
+///
+/// Lookups are reported as misses, and nothing is written.
+fn short_circuit<K, V>(request: ProviderRequest<K, V>) -> ProviderResponse<K, V> {
+    match request {
+        ProviderRequest::Contains(_) => ProviderResponse::Present(false),
+        ProviderRequest::GetMany(keys) => {
+            ProviderResponse::Many(keys.into_iter().map(|key| (key, None)).collect())
+        }
+        _ => ProviderResponse::NotFound,
+    }
+}
+
+/// Circuit breaker shared by the clones of a [`RedisProvider`]
+#[derive(Debug)]
+struct Breaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    /// Number of consecutive connection errors
+    failures: u32,
+    /// End of the cooldown, if the breaker is open
+    open_until: Option<Instant>,
+}
+
+impl Breaker {
+    fn state(&self) -> MutexGuard<'_, BreakerState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Check if requests are short-circuited
+    fn is_open(&self) -> bool {
+        matches!(self.state().open_until, Some(open_until) if time::now() < open_until)
+    }
+
+    /// Check if a request can be sent to Redis
+    ///
+    /// Once the cooldown is over, this lets a single request through to
+    /// probe the connection, and keeps the breaker open for the others until
+    /// the probe completes.
+    fn allow(&self) -> bool {
+        let mut state = self.state();
+        let now = time::now();
+        match state.open_until {
+            None => true,
+            Some(open_until) if now < open_until => false,
+            Some(_) => {
+                state.open_until = Some(now + self.cooldown);
+                true
+            }
+        }
+    }
+
+    fn record_success(&self) {
+        let mut state = self.state();
+        state.failures = 0;
+        state.open_until = None;
+    }
+
+    /// Count connection errors, and open the breaker after too many of them
+    ///
+    /// Other errors, such as codec errors, mean that Redis is reachable.
+    fn record_error(&self, err: &Error) {
+        if !err.is_connection_error() {
+            return self.record_success();
+        }
+        let mut state = self.state();
+        state.failures = state.failures.saturating_add(1);
+        if state.failures >= self.threshold {
+            if state.open_until.is_none() {
+                trace::circuit_opened();
+            }
+            state.open_until = Some(time::now() + self.cooldown);
+        }
+    }
+}
+
+/// Tag byte prepended to serialized values
+const VALUE_TAG: u8 = 0;
+
+/// Value stored for negative entries
+///
+/// Serialized values start with [`VALUE_TAG`], so this cannot collide with
+/// them, even for codecs that encode some values as empty bytes.
+const NEGATIVE_SENTINEL: &[u8] = &[1];
+
+/// Prepend [`VALUE_TAG`] to a serialized value
+fn tag(data: Vec<u8>) -> Vec<u8> {
+    let mut tagged = Vec::with_capacity(data.len() + 1);
+    tagged.push(VALUE_TAG);
+    tagged.extend_from_slice(&data);
+    tagged
+}
+
+/// Split the serialized value from a stored value, or return `None` for
+/// negative entries
+fn untag(data: &[u8]) -> Result<Option<&[u8]>, Error> {
+    match data.split_first() {
+        Some((&VALUE_TAG, value)) => Ok(Some(value)),
+        _ if data == NEGATIVE_SENTINEL => Ok(None),
+        _ => Err(Error::CodecError("invalid tag for a stored value".into())),
+    }
+}
+
+/// Number of keys to request per SCAN iteration when clearing the cache
+const SCAN_COUNT: usize = 100;
+
+/// Escape glob characters in a key prefix for use in a MATCH pattern
+fn escape_pattern(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len());
+    for c in prefix.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern
+}
+
+type ProviderFuture<'a, K, V> =
+    Pin<Box<dyn Future<Output = Result<ProviderResponse<K, V>, Error>> + Send + 'a>>;
+
+/// Error returned by the [`RedisProvider`]
+#[derive(Debug)]
+pub enum Error {
+    /// Error returned by Redis
+    RedisError(RedisError),
+    /// Error while serializing or deserializing a value
+    CodecError(Box<dyn error::Error + Send + Sync>),
+    /// Error while getting a connection from a [`RedisPool`]
+    #[cfg(feature = "redis-pool")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "redis-pool")))]
+    PoolError(deadpool_redis::PoolError),
+}
+
+impl Error {
+    fn codec<E>(e: E) -> Self
+    where
+        E: error::Error + Send + Sync + 'static,
+    {
+        Error::CodecError(Box::new(e))
+    }
+
+    /// Check if the error means that Redis could not be reached
+    fn is_connection_error(&self) -> bool {
+        match self {
+            Error::RedisError(e) => {
+                e.is_io_error()
+                    || e.is_connection_refusal()
+                    || e.is_connection_dropped()
+                    || e.is_timeout()
+            }
+            Error::CodecError(_) => false,
+            #[cfg(feature = "redis-pool")]
+            Error::PoolError(_) => true,
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::RedisError(e) => Some(e),
+            Error::CodecError(e) => Some(e.as_ref()),
+            #[cfg(feature = "redis-pool")]
+            Error::PoolError(e) => Some(e),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::RedisError(e) => write!(f, "redis error: {}", e),
+            Error::CodecError(e) => write!(f, "serialization error: {}", e),
+            #[cfg(feature = "redis-pool")]
+            Error::PoolError(e) => write!(f, "connection pool error: {}", e),
+        }
+    }
+}
+
+impl From<RedisError> for Error {
+    fn from(e: RedisError) -> Self {
+        Error::RedisError(e)
+    }
+}
+
+#[cfg(feature = "redis-pool")]
+impl From<deadpool_redis::PoolError> for Error {
+    fn from(e: deadpool_redis::PoolError) -> Self {
+        Error::PoolError(e)
+    }
+}
+
+#[cfg(feature = "redis-pool")]
+type Checkout = Pin<Box<dyn Future<Output = Result<deadpool_redis::Connection, Error>> + Send>>;
+
+/// Pool of Redis connections
+///
+/// Each request sent to a [`RedisProvider`] using this pool gets its own
+/// connection, which is returned to the pool once the request completes.
+///
+/// [`Service::poll_ready`] on the provider waits until a connection is
+/// available and reserves it for the next call. Cloning the pool shares the
+/// underlying connections, but not the reserved connection.
+#[cfg(feature = "redis-pool")]
+#[cfg_attr(docsrs, doc(cfg(feature = "redis-pool")))]
+pub struct RedisPool {
+    pool: deadpool_redis::Pool,
+    checkout: Option<Checkout>,
+    reserved: Option<deadpool_redis::Connection>,
+}
+
+#[cfg(feature = "redis-pool")]
+impl RedisPool {
+    /// Wrap an existing connection pool
+    pub fn new(pool: deadpool_redis::Pool) -> Self {
+        Self {
+            pool,
+            checkout: None,
+            reserved: None,
+        }
+    }
+
+    /// Create a pool of at most `max_size` connections to the Redis instance
+    /// at `url`
+    pub fn from_url(
+        url: impl Into<String>,
+        max_size: usize,
+    ) -> Result<Self, deadpool_redis::CreatePoolError> {
+        let mut config = deadpool_redis::Config::from_url(url);
+        config.pool = Some(deadpool_redis::PoolConfig::new(max_size));
+        let pool = config.create_pool(Some(deadpool_redis::Runtime::Tokio1))?;
+        Ok(Self::new(pool))
+    }
+
+    /// Return a reference to the underlying pool
+    pub fn get_ref(&self) -> &deadpool_redis::Pool {
+        &self.pool
+    }
+
+    /// Return the current status of the pool
+    pub fn status(&self) -> deadpool_redis::Status {
+        self.pool.status()
+    }
+}
+
+/// Get a connection from the pool
+#[cfg(feature = "redis-pool")]
+fn checkout(pool: &deadpool_redis::Pool) -> Checkout {
+    let pool = pool.clone();
+    Box::pin(async move { Ok(pool.get().await?) })
+}
+
+#[cfg(feature = "redis-pool")]
+impl Clone for RedisPool {
+    fn clone(&self) -> Self {
+        Self::new(self.pool.clone())
+    }
+}
+
+#[cfg(feature = "redis-pool")]
+impl fmt::Debug for RedisPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RedisPool")
+            .field("status", &self.pool.status())
+            .field("reserved", &self.reserved.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "redis-pool")]
+impl From<deadpool_redis::Pool> for RedisPool {
+    fn from(pool: deadpool_redis::Pool) -> Self {
+        Self::new(pool)
+    }
+}
+
+#[cfg(feature = "redis-pool")]
+impl ConnectionSource for RedisPool {
+    type Connection = deadpool_redis::Connection;
+    type Future = Checkout;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        if self.reserved.is_some() {
+            return Poll::Ready(Ok(()));
+        }
+        let pool = &self.pool;
+        let checkout = self.checkout.get_or_insert_with(|| checkout(pool));
+        let res = std::task::ready!(checkout.as_mut().poll(cx));
+        self.checkout = None;
+        self.reserved = Some(res?);
+        Poll::Ready(Ok(()))
+    }
+
+    fn connection(&mut self) -> Self::Future {
+        match (self.reserved.take(), self.checkout.take()) {
+            (Some(conn), _) => Box::pin(std::future::ready(Ok(conn))),
+            // The caller didn't wait for readiness, so finish the pending
+            // checkout instead of starting another one.
+            (None, Some(checkout)) => checkout,
+            (None, None) => checkout(&self.pool),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::TupleKey;
+    use crate::conformance::check_provider;
+    use ::redis::{Arg, Cmd, Pipeline, RedisFuture, Value};
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    /// In-memory connection that understands the commands used by the
+    /// provider.
+    #[derive(Clone, Default)]
+    struct MockConnection {
+        data: Arc<Mutex<HashMap<Vec<u8>, Vec<u8>>>>,
+        ttls: Arc<Mutex<HashMap<Vec<u8>, u64>>>,
+        round_trips: Arc<AtomicUsize>,
+    }
+
+    impl MockConnection {
+        fn execute(&self, cmd: &Cmd) -> Value {
+            let args: Vec<Vec<u8>> = cmd
+                .args_iter()
+                .filter_map(|arg| match arg {
+                    Arg::Simple(arg) => Some(arg.to_vec()),
+                    _ => None,
+                })
+                .collect();
+            let mut data = self.data.lock().unwrap();
+
+            match args[0].as_slice() {
+                b"GET" => match data.get(&args[1]) {
+                    Some(value) => Value::BulkString(value.clone()),
+                    None => Value::Nil,
+                },
+                b"SET" => {
+                    data.insert(args[1].clone(), args[2].clone());
+                    self.ttls.lock().unwrap().remove(&args[1]);
+                    Value::Okay
+                }
+                // Expiration is not simulated
+                b"PSETEX" => {
+                    data.insert(args[1].clone(), args[3].clone());
+                    let ttl = std::str::from_utf8(&args[2]).unwrap().parse().unwrap();
+                    self.ttls.lock().unwrap().insert(args[1].clone(), ttl);
+                    Value::Okay
+                }
+                // Only prefix patterns are supported, and everything is
+                // returned in a single iteration.
+                b"SCAN" => {
+                    let prefix = args[3].strip_suffix(b"*").unwrap();
+                    let keys = data
+                        .keys()
+                        .filter(|key| key.starts_with(prefix))
+                        .map(|key| Value::BulkString(key.clone()))
+                        .collect();
+                    Value::Array(vec![Value::BulkString(b"0".to_vec()), Value::Array(keys)])
+                }
+                b"MGET" => Value::Array(
+                    args[1..]
+                        .iter()
+                        .map(|key| match data.get(key) {
+                            Some(value) => Value::BulkString(value.clone()),
+                            None => Value::Nil,
+                        })
+                        .collect(),
+                ),
+                b"EXISTS" => Value::Int(data.contains_key(&args[1]) as i64),
+                b"DEL" => {
+                    let count = args[1..].iter().filter(|key| data.remove(*key).is_some());
+                    Value::Int(count.count() as i64)
+                }
+                cmd => panic!("unsupported command {:?}", String::from_utf8_lossy(cmd)),
+            }
+        }
+    }
+
+    impl ConnectionLike for MockConnection {
+        fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+            self.round_trips.fetch_add(1, Ordering::Relaxed);
+            let value = self.execute(cmd);
+            Box::pin(async move { Ok(value) })
+        }
+
+        fn req_packed_commands<'a>(
+            &'a mut self,
+            pipeline: &'a Pipeline,
+            offset: usize,
+            count: usize,
+        ) -> RedisFuture<'a, Vec<Value>> {
+            self.round_trips.fetch_add(1, Ordering::Relaxed);
+            let values = pipeline
+                .cmd_iter()
+                .map(|cmd| self.execute(cmd))
+                .skip(offset)
+                .take(count)
+                .collect();
+            Box::pin(async move { Ok(values) })
+        }
+
+        fn get_db(&self) -> i64 {
+            0
+        }
+    }
+
+    /// Connection source refusing connections while `down` is set
+    #[derive(Clone, Default)]
+    struct FlakySource {
+        conn: MockConnection,
+        down: Arc<AtomicBool>,
+        attempts: Arc<AtomicUsize>,
+    }
+
+    impl ConnectionSource for FlakySource {
+        type Connection = MockConnection;
+        type Future = std::future::Ready<Result<MockConnection, Error>>;
+
+        fn connection(&mut self) -> Self::Future {
+            self.attempts.fetch_add(1, Ordering::Relaxed);
+            if self.down.load(Ordering::Relaxed) {
+                let err = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+                return ready(Err(RedisError::from(err).into()));
+            }
+            ready(Ok(self.conn.clone()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_insert() -> Result<(), Error> {
+        let conn = MockConnection::default();
+        check_provider(RedisProvider::new::<String, String, _>(
+            conn.clone(),
+            "test:",
+        ))
+        .await?;
+
+        // The key is prefixed and the value tagged and serialized as JSON
+        assert_eq!(
+            conn.data.lock().unwrap().get(b"test:a".as_slice()),
+            Some(&b"\0\"A\"".to_vec())
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "bincode")]
+    #[tokio::test]
+    async fn test_bincode_codec() -> Result<(), Error> {
+        use crate::codec::BincodeCodec;
+
+        let conn = MockConnection::default();
+        let mut provider = RedisProvider::new::<String, Vec<u32>, _>(conn.clone(), "test:")
+            .with_codec(BincodeCodec);
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                vec![1, 2, 3],
+                None,
+            ))
+            .await?;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == [1, 2, 3]));
+
+        // The value is stored in the binary format
+        let data = conn.data.lock().unwrap().get(b"test:a".as_slice()).cloned();
+        assert_eq!(
+            data,
+            Some(tag(BincodeCodec.encode(&vec![1u32, 2, 3]).unwrap()))
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tuple_key() -> Result<(), Error> {
+        let conn = MockConnection::default();
+        let mut provider = RedisProvider::new::<(u64, String), String, _>(conn.clone(), "test:")
+            .with_key_codec(TupleKey::new());
+
+        let request = ProviderRequest::Insert((1, "en".to_string()), "A".to_string(), None);
+        provider.call(request).await?;
+        assert!(conn
+            .data
+            .lock()
+            .unwrap()
+            .contains_key(b"test:1:en".as_slice()));
+
+        // An equal key maps to the same entry, even from another provider
+        let mut other = RedisProvider::new::<(u64, String), String, _>(conn, "test:")
+            .with_key_codec(TupleKey::new());
+        let res = other
+            .call(ProviderRequest::Get((1, "en".to_string())))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+        let res = other
+            .call(ProviderRequest::Get((1, "fr".to_string())))
+            .await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_prefix_isolation() -> Result<(), Error> {
+        let conn = MockConnection::default();
+        let mut provider_a = RedisProvider::new::<String, String, _>(conn.clone(), "a:");
+        let mut provider_b = RedisProvider::new::<String, String, _>(conn, "b:");
+
+        provider_a
+            .call(ProviderRequest::Insert(
+                "key".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        let res = provider_b
+            .call(ProviderRequest::Get("key".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_insert_negative() -> Result<(), Error> {
+        let conn = MockConnection::default();
+        let mut provider = RedisProvider::new::<String, String, _>(conn, "test:");
+
+        provider
+            .call(ProviderRequest::InsertNegative(
+                "a".to_string(),
+                std::time::Duration::from_secs(1),
+            ))
+            .await?;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::FoundNegative));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "bincode")]
+    #[tokio::test]
+    async fn test_empty_encoding() -> Result<(), Error> {
+        use crate::codec::BincodeCodec;
+
+        let conn = MockConnection::default();
+        let mut provider =
+            RedisProvider::new::<String, (), _>(conn.clone(), "test:").with_codec(BincodeCodec);
+
+        // `()` is encoded as empty bytes, which must not be read as a negative
+        // entry
+        provider
+            .call(ProviderRequest::Insert("a".to_string(), (), None))
+            .await?;
+        provider
+            .call(ProviderRequest::InsertNegative(
+                "b".to_string(),
+                std::time::Duration::from_secs(1),
+            ))
+            .await?;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(())));
+        let res = provider.call(ProviderRequest::Get("b".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::FoundNegative));
+
+        // Values without a valid tag are rejected
+        conn.data
+            .lock()
+            .unwrap()
+            .insert(b"test:c".to_vec(), Vec::new());
+        let res = provider.call(ProviderRequest::Get("c".to_string())).await;
+        assert!(matches!(res, Err(Error::CodecError(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_insert_ttl() -> Result<(), Error> {
+        let conn = MockConnection::default();
+        let mut provider = RedisProvider::new::<String, String, _>(conn.clone(), "test:");
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                Some(std::time::Duration::from_secs(2)),
+            ))
+            .await?;
+        provider
+            .call(ProviderRequest::Insert(
+                "b".to_string(),
+                "B".to_string(),
+                None,
+            ))
+            .await?;
+
+        let ttls = conn.ttls.lock().unwrap().clone();
+        assert_eq!(ttls.get(b"test:a".as_slice()), Some(&2000));
+        assert_eq!(ttls.get(b"test:b".as_slice()), None);
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_circuit_breaker() -> Result<(), Error> {
+        let source = FlakySource::default();
+        source.down.store(true, Ordering::Relaxed);
+        let mut provider = RedisProvider::new::<String, String, _>(source.clone(), "test:")
+            .circuit_breaker(2, Duration::from_millis(50));
+
+        // The breaker opens after two consecutive connection errors.
+        for _ in 0..2 {
+            let res = provider.call(ProviderRequest::Get("a".to_string())).await;
+            assert!(matches!(res, Err(Error::RedisError(_))));
+        }
+
+        // Requests are then short-circuited without a connection.
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        let res = provider
+            .clone()
+            .call(ProviderRequest::Contains("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(false)));
+        assert_eq!(source.attempts.load(Ordering::Relaxed), 2);
+
+        // After the cooldown, a failed probe opens the breaker again.
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await;
+        assert!(res.is_err());
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        assert_eq!(source.attempts.load(Ordering::Relaxed), 3);
+
+        // A successful probe closes it.
+        source.down.store(false, Ordering::Relaxed);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+        assert_eq!(source.attempts.load(Ordering::Relaxed), 5);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_codec_error_closes_breaker() -> Result<(), Error> {
+        let source = FlakySource::default();
+        let mut provider = RedisProvider::new::<String, String, _>(source.clone(), "test:")
+            .circuit_breaker(2, Duration::from_secs(60));
+
+        source.down.store(true, Ordering::Relaxed);
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await;
+        assert!(res.is_err());
+
+        // Redis answered, even though the value is invalid.
+        source.down.store(false, Ordering::Relaxed);
+        source
+            .conn
+            .data
+            .lock()
+            .unwrap()
+            .insert(b"test:a".to_vec(), b"not json".to_vec());
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await;
+        assert!(matches!(res, Err(Error::CodecError(_))));
+
+        source.down.store(true, Ordering::Relaxed);
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await;
+        assert!(matches!(res, Err(Error::RedisError(_))));
+        assert_eq!(source.attempts.load(Ordering::Relaxed), 3);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_configure() -> Result<(), Error> {
+        let conn = MockConnection::default();
+        let config = ProviderConfig::new()
+            .ttl(std::time::Duration::from_secs(60))
+            .capacity(2);
+        let mut provider =
+            RedisProvider::new::<String, String, _>(conn.clone(), "test:").configure(&config);
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        // Entries inserted with a TTL keep it.
+        provider
+            .call(ProviderRequest::Insert(
+                "b".to_string(),
+                "B".to_string(),
+                Some(std::time::Duration::from_secs(2)),
+            ))
+            .await?;
+
+        let ttls = conn.ttls.lock().unwrap().clone();
+        assert_eq!(ttls.get(b"test:a".as_slice()), Some(&60000));
+        assert_eq!(ttls.get(b"test:b".as_slice()), Some(&2000));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_clear() -> Result<(), Error> {
+        let conn = MockConnection::default();
+        let mut provider = RedisProvider::new::<String, String, _>(conn.clone(), "test:");
+        let mut other = RedisProvider::new::<String, String, _>(conn.clone(), "other:");
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        other
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+
+        let res = provider.call(ProviderRequest::Clear).await?;
+        assert!(matches!(res, ProviderResponse::Cleared));
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        // Keys with a different prefix are kept.
+        let res = other.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(_)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remove() -> Result<(), Error> {
+        let conn = MockConnection::default();
+        let mut provider = RedisProvider::new::<String, String, _>(conn, "test:");
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        let res = provider
+            .call(ProviderRequest::Remove("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Removed));
+        let res = provider
+            .call(ProviderRequest::Remove("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_many() -> Result<(), Error> {
+        let conn = MockConnection::default();
+        let mut provider = RedisProvider::new::<String, String, _>(conn, "test:");
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        provider
+            .call(ProviderRequest::InsertNegative(
+                "c".to_string(),
+                std::time::Duration::from_secs(10),
+            ))
+            .await?;
+
+        let keys = ["b", "a", "c"].map(|key| key.to_string()).to_vec();
+        let res = provider.call(ProviderRequest::GetMany(keys)).await?;
+        let expected = [
+            ("b".to_string(), None),
+            ("a".to_string(), Some("A".to_string())),
+            ("c".to_string(), None),
+        ];
+        assert!(matches!(res, ProviderResponse::Many(v) if v == expected));
+
+        let res = provider.call(ProviderRequest::GetMany(Vec::new())).await?;
+        assert!(matches!(res, ProviderResponse::Many(v) if v.is_empty()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_insert_many() -> Result<(), Error> {
+        let batched = MockConnection::default();
+        let individual = MockConnection::default();
+        let mut batched_provider =
+            RedisProvider::new::<String, String, _>(batched.clone(), "test:");
+        let mut individual_provider =
+            RedisProvider::new::<String, String, _>(individual.clone(), "test:");
+
+        let entries = vec![
+            ("a".to_string(), "A".to_string(), None),
+            (
+                "b".to_string(),
+                "B".to_string(),
+                Some(std::time::Duration::from_secs(5)),
+            ),
+        ];
+        let res = batched_provider
+            .call(ProviderRequest::InsertMany(entries.clone()))
+            .await?;
+        let expected = [
+            ("a".to_string(), Some("A".to_string())),
+            ("b".to_string(), Some("B".to_string())),
+        ];
+        assert!(matches!(res, ProviderResponse::Many(v) if v == expected));
+        // All writes are sent in a single pipeline.
+        assert_eq!(batched.round_trips.load(Ordering::Relaxed), 1);
+
+        for (key, value, ttl) in entries {
+            individual_provider
+                .call(ProviderRequest::Insert(key, value, ttl))
+                .await?;
+        }
+        assert_eq!(
+            *batched.data.lock().unwrap(),
+            *individual.data.lock().unwrap()
+        );
+        assert_eq!(
+            *batched.ttls.lock().unwrap(),
+            *individual.ttls.lock().unwrap()
+        );
+
+        let res = batched_provider
+            .call(ProviderRequest::InsertMany(Vec::new()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Many(v) if v.is_empty()));
+        assert_eq!(batched.round_trips.load(Ordering::Relaxed), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_contains() -> Result<(), Error> {
+        let conn = MockConnection::default();
+        let mut provider = RedisProvider::new::<String, String, _>(conn, "test:");
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        let res = provider
+            .call(ProviderRequest::Contains("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(true)));
+        let res = provider
+            .call(ProviderRequest::Contains("b".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(false)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_escape_pattern() {
+        assert_eq!(escape_pattern("app:"), "app:");
+        assert_eq!(escape_pattern("a*b?[c]\\"), "a\\*b\\?\\[c\\]\\\\");
+    }
+
+    #[tokio::test]
+    async fn test_deserialize_error() {
+        let conn = MockConnection::default();
+        conn.data
+            .lock()
+            .unwrap()
+            .insert(b"test:a".to_vec(), b"not json".to_vec());
+        let mut provider = RedisProvider::new::<String, String, _>(conn, "test:");
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await;
+        assert!(matches!(res, Err(Error::CodecError(_))));
+    }
+
+    /// Runs against a real Redis instance when `REDIS_URL` is set.
+    #[tokio::test]
+    async fn test_redis_server() -> Result<(), Box<dyn error::Error>> {
+        let url = match std::env::var("REDIS_URL") {
+            Ok(url) => url,
+            Err(_) => return Ok(()),
+        };
+        let client = ::redis::Client::open(url)?;
+        let manager = ::redis::aio::ConnectionManager::new(client).await?;
+        let mut provider = RedisProvider::new::<u64, String, _>(manager, "tower-cache-test:");
+
+        provider
+            .call(ProviderRequest::Insert(1, "one".to_string(), None))
+            .await?;
+        let res = provider.call(ProviderRequest::Get(1)).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "one"));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "redis-pool")]
+    #[tokio::test]
+    async fn test_pool_unavailable() {
+        use tower::ServiceExt;
+
+        // Nothing listens on port 1, so no connection can be created.
+        let pool = RedisPool::from_url("redis://127.0.0.1:1/", 2).unwrap();
+        let mut provider = RedisProvider::new::<u64, String, _>(pool, "test:");
+
+        let res = provider.ready().await;
+        assert!(matches!(res, Err(Error::PoolError(_))));
+        let res = provider.call(ProviderRequest::Get(1)).await;
+        assert!(matches!(res, Err(Error::PoolError(_))));
+    }
+
+    /// Runs concurrent requests through a pool when `REDIS_URL` is set.
+    #[cfg(feature = "redis-pool")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_redis_pool() -> Result<(), Box<dyn error::Error>> {
+        use tower::ServiceExt;
+
+        let url = match std::env::var("REDIS_URL") {
+            Ok(url) => url,
+            Err(_) => return Ok(()),
+        };
+        let pool = RedisPool::from_url(url, 4)?;
+        let provider = RedisProvider::new::<u64, u64, _>(pool.clone(), "tower-cache-pool-test:");
+
+        let tasks: Vec<_> = (0..64)
+            .map(|i| {
+                let provider = provider.clone();
+                tokio::spawn(async move {
+                    let mut provider = provider;
+                    provider
+                        .ready()
+                        .await?
+                        .call(ProviderRequest::Insert(i, i * 2, None))
+                        .await?;
+                    provider.ready().await?.call(ProviderRequest::Get(i)).await
+                })
+            })
+            .collect();
+        for (i, task) in tasks.into_iter().enumerate() {
+            let res = task.await??;
+            assert!(matches!(res, ProviderResponse::Found(v) if v == i as u64 * 2));
+        }
+
+        // Connections are reused rather than opened for each request.
+        let status = pool.status();
+        assert!(status.size <= 4);
+        assert_eq!(status.available, status.size);
+
+        let mut provider = provider;
+        provider.ready().await?.call(ProviderRequest::Clear).await?;
+
+        Ok(())
+    }
+
+    /// Compares batched and individual requests when `REDIS_URL` is set.
+    #[tokio::test]
+    async fn test_redis_batch() -> Result<(), Box<dyn error::Error>> {
+        let url = match std::env::var("REDIS_URL") {
+            Ok(url) => url,
+            Err(_) => return Ok(()),
+        };
+        let client = ::redis::Client::open(url)?;
+        let manager = ::redis::aio::ConnectionManager::new(client).await?;
+        let mut batched =
+            RedisProvider::new::<u64, String, _>(manager.clone(), "tower-cache-batch-test:");
+        let mut individual =
+            RedisProvider::new::<u64, String, _>(manager, "tower-cache-single-test:");
+
+        let entries: Vec<_> = (0..32)
+            .map(|i| (i, i.to_string(), Some(std::time::Duration::from_secs(60))))
+            .collect();
+        batched
+            .call(ProviderRequest::InsertMany(entries.clone()))
+            .await?;
+        for (key, value, ttl) in entries {
+            individual
+                .call(ProviderRequest::Insert(key, value, ttl))
+                .await?;
+        }
+
+        let keys: Vec<u64> = (0..40).collect();
+        let mut expected = Vec::new();
+        for key in &keys {
+            let value = match individual.call(ProviderRequest::Get(*key)).await? {
+                ProviderResponse::Found(value) => Some(value),
+                _ => None,
+            };
+            expected.push((*key, value));
+        }
+        let res = batched.call(ProviderRequest::GetMany(keys)).await?;
+        assert!(matches!(res, ProviderResponse::Many(values) if values == expected));
+
+        batched.call(ProviderRequest::Clear).await?;
+        individual.call(ProviderRequest::Clear).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+//! Background refresh of stale cache entries
+//!
+//! Refreshes run outside of the request that triggered them, so they are
+//! handed to a spawner. Only one refresh runs at a time for a given key:
+//! requests that find a stale entry while it is already being refreshed
+//! don't start another one.
+//!
+//! The same spawner runs the inserts of [`crate::CacheLayer::write_back`],
+//! which are never deduplicated.
+
+use crate::coalesce::{Inflight, Role};
+use std::{future::Future, hash::Hash, pin::Pin, sync::Arc};
+use tokio::runtime::Handle;
+
+type BoxFuture<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+/// Function running a future in the background, or returning it if it can't
+type Spawner<'a> = dyn Fn(BoxFuture<'a>) -> Result<(), BoxFuture<'a>> + Send + Sync + 'a;
+
+/// Spawner for background refreshes, shared between all services created by
+/// a [`crate::CacheLayer`]
+///
+/// Refreshes are disabled when there is no spawner.
+#[derive(Clone, Default)]
+pub(crate) struct Refresh<'a> {
+    spawner: Option<Arc<Spawner<'a>>>,
+    inflight: Inflight,
+}
+
+impl Refresh<'static> {
+    /// Spawn refreshes on the current Tokio runtime
+    ///
+    /// The runtime is looked up for each future, as services can be called
+    /// outside of the runtime they were created in. Without a runtime,
+    /// refreshes are skipped, and detached futures are returned to the
+    /// caller.
+    pub(crate) fn on_tokio(self) -> Self {
+        Refresh {
+            spawner: Some(Arc::new(|fut| match Handle::try_current() {
+                Ok(handle) => {
+                    handle.spawn(fut);
+                    Ok(())
+                }
+                Err(_) => Err(fut),
+            })),
+            ..self
+        }
+    }
+}
+
+impl<'a> Refresh<'a> {
+    /// Run `fut` in the background, unless a refresh for `key` is already
+    /// running
+    pub(crate) fn spawn<K, F>(&self, key: &K, fut: F)
+    where
+        K: Hash,
+        F: Future<Output = ()> + Send + 'a,
+    {
+        let spawner = match &self.spawner {
+            Some(spawner) => spawner,
+            None => return,
+        };
+        if let Role::Leader(guard) = self.inflight.join(key) {
+            // Dropping the future also drops the guard, so the refresh can be
+            // attempted again by the next request.
+            let _ = spawner(Box::pin(async move {
+                fut.await;
+                drop(guard);
+            }));
+        }
+    }
+
+    /// Run `fut` in the background, even if other tasks are running for the
+    /// same key
+    ///
+    /// Returns `fut` back if there is no spawner, or if it can't spawn it, so
+    /// that the caller can run it instead.
+    pub(crate) fn detach<F>(&self, fut: F) -> Result<(), BoxFuture<'a>>
+    where
+        F: Future<Output = ()> + Send + 'a,
+    {
+        match &self.spawner {
+            Some(spawner) => spawner(Box::pin(fut)),
+            None => Err(Box::pin(fut)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn test_deduplicated() {
+        let refresh = Refresh::default().on_tokio();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let (sender, receiver) = oneshot::channel::<()>();
+
+        let first = runs.clone();
+        refresh.spawn(&"key", async move {
+            first.fetch_add(1, Ordering::SeqCst);
+            let _ = receiver.await;
+        });
+        // The first refresh is still running.
+        let second = runs.clone();
+        refresh.spawn(&"key", async move {
+            second.fetch_add(1, Ordering::SeqCst);
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        sender.send(()).unwrap();
+        while refresh.inflight.len() > 0 {
+            tokio::task::yield_now().await;
+        }
+        let third = runs.clone();
+        refresh.spawn(&"key", async move {
+            third.fetch_add(1, Ordering::SeqCst);
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_detach() {
+        let refresh = Refresh::default().on_tokio();
+        let (sender, receiver) = oneshot::channel();
+        assert!(refresh
+            .detach(async move {
+                sender.send(()).unwrap();
+            })
+            .is_ok());
+        receiver.await.unwrap();
+
+        // Without a spawner, the future is returned to the caller.
+        assert!(Refresh::default().detach(async {}).is_err());
+    }
+
+    #[test]
+    fn test_no_runtime() {
+        let refresh = Refresh::default().on_tokio();
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        // Refreshes are skipped, and can be attempted again later.
+        refresh.spawn(&"key", async { unreachable!() });
+        assert_eq!(refresh.inflight.len(), 0);
+
+        // Detached futures are returned to run them inline.
+        let detached = runs.clone();
+        let fut = refresh
+            .detach(async move {
+                detached.fetch_add(1, Ordering::SeqCst);
+            })
+            .err()
+            .unwrap();
+        tokio_test::block_on(fut);
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let refresh = Refresh::default();
+        // Nothing to run the future on, so it is dropped.
+        refresh.spawn(&"key", async { unreachable!() });
+        assert_eq!(refresh.inflight.len(), 0);
+    }
+}
@@ -0,0 +1,509 @@
+//! # Sled cache provider
+//!
+//! This is an implementation of a cache provider for [`crate::CacheLayer`]
+//! backed by a [sled](https://sled.rs/) tree, which persists cached values to
+//! disk so they survive process restarts.
+//!
+//! Keys are formatted using their [`Display`] implementation. Values are
+//! serialized as JSON by default, see [`SledProvider::with_codec`] to use
+//! another [`Codec`]. Sled flushes writes to disk in the background, so the
+//! most recent inserts may be lost if the process crashes.
+//!
+//! Expiration times are stored alongside the values as wall-clock time, so
+//! TTLs keep running while the process is stopped. Expired entries are
+//! removed lazily when they are read.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, service_fn};
+//! use tower_cache::{
+//!     CacheLayer,
+//!     sled::SledProvider,
+//! };
+//! async fn handler(req: String) -> Result<String, Infallible> {
+//!     Ok(req.to_uppercase())
+//! }
+//!
+//! # tokio_test::block_on(async move {
+//! // Initialize the cache provider service
+//! # let db = sled::Config::new().temporary(true).open().unwrap();
+//! # /*
+//! let db = sled::open("my-app-cache").unwrap();
+//! # */
+//! let sled_provider = SledProvider::new::<String, String>(db.open_tree("cache").unwrap());
+//!
+//! // Wrap the service with CacheLayer.
+//! let mut my_service = ServiceBuilder::new()
+//!     .layer(CacheLayer::new(sled_provider))
+//!     .service(service_fn(handler));
+//!
+//! // Call the service
+//! let res = my_service.call("Hello".to_string()).await.unwrap();
+//! assert_eq!(res, "HELLO".to_string());
+//! # })
+//! ```
+//!
+
+use crate::{
+    codec::{Codec, JsonCodec},
+    time, trace, Configure, ProviderConfig, ProviderRequest, ProviderResponse,
+};
+use std::{
+    error,
+    fmt::{self, Display},
+    future::{ready, Future},
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, UNIX_EPOCH},
+};
+use tower::Service;
+
+/// Sled cache provider
+///
+/// Cloning the provider shares the underlying [`sled::Tree`].
+pub struct SledProvider<'a, K, V, E = JsonCodec> {
+    tree: sled::Tree,
+    ttl: Option<Duration>,
+    codec: E,
+    _types: PhantomData<fn() -> (K, V)>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> SledProvider<'a, (), ()> {
+    /// Create a new sled cache provider storing entries in `tree`
+    ///
+    /// The tree should be dedicated to the cache, as [`ProviderRequest::Clear`]
+    /// removes all of its entries.
+    pub fn new<K, V>(tree: sled::Tree) -> SledProvider<'a, K, V> {
+        SledProvider {
+            tree,
+            ttl: None,
+            codec: JsonCodec,
+            _types: PhantomData,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V, E> SledProvider<'a, K, V, E> {
+    /// Use a different [`Codec`] to serialize values
+    pub fn with_codec<NE>(self, codec: NE) -> SledProvider<'a, K, V, NE> {
+        SledProvider {
+            tree: self.tree,
+            ttl: self.ttl,
+            codec,
+            _types: PhantomData,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+// Custom implementation of Clone as the Clone derive doesn't mark
+// SledProvider as Clone if K or V is not clone.
+impl<'a, K, V, E> Clone for SledProvider<'a, K, V, E>
+where
+    E: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            tree: self.tree.clone(),
+            ttl: self.ttl,
+            codec: self.codec.clone(),
+            _types: PhantomData,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V, E> fmt::Debug for SledProvider<'a, K, V, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SledProvider")
+            .field("tree", &self.tree.name())
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Sets the default TTL of entries inserted without one. The capacity is
+/// ignored, as sled doesn't bound the size of a tree.
+impl<'a, K, V, E> Configure for SledProvider<'a, K, V, E> {
+    fn configure(mut self, config: &ProviderConfig) -> Self {
+        if let Some(ttl) = config.ttl {
+            self.ttl = Some(ttl);
+        }
+        if config.capacity.is_some() {
+            trace::unsupported_config::<Self>("capacity");
+        }
+        self
+    }
+}
+
+impl<'a, K, V, E> SledProvider<'a, K, V, E>
+where
+    K: Display,
+    E: Codec<V>,
+{
+    /// Read a record, removing it if it has expired
+    fn get(&self, key: &str) -> Result<Option<Record>, Error> {
+        let data = match self.tree.get(key)? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let record = Record::decode(&data)?;
+        if record.is_expired(now_millis()) {
+            // Only remove the entry if it wasn't replaced in the meantime.
+            let _ = self
+                .tree
+                .compare_and_swap(key, Some(data), None as Option<&[u8]>)?;
+            return Ok(None);
+        }
+        Ok(Some(record))
+    }
+
+    fn handle(&self, request: ProviderRequest<K, V>) -> Result<ProviderResponse<K, V>, Error> {
+        Ok(match request {
+            ProviderRequest::Get(key) => match self.get(&key.to_string())? {
+                Some(Record {
+                    value: Some(data), ..
+                }) => ProviderResponse::Found(self.codec.decode(&data).map_err(Error::codec)?),
+                Some(Record { value: None, .. }) => ProviderResponse::FoundNegative,
+                None => ProviderResponse::NotFound,
+            },
+            ProviderRequest::Insert(key, value, ttl) => {
+                let data = self.codec.encode(&value).map_err(Error::codec)?;
+                let record = Record::encode(Some(&data), ttl.or(self.ttl));
+                self.tree.insert(key.to_string(), record)?;
+                ProviderResponse::Found(value)
+            }
+            ProviderRequest::InsertNegative(key, ttl) => {
+                let record = Record::encode(None, Some(ttl));
+                self.tree.insert(key.to_string(), record)?;
+                ProviderResponse::FoundNegative
+            }
+            ProviderRequest::Clear => {
+                self.tree.clear()?;
+                ProviderResponse::Cleared
+            }
+            ProviderRequest::Remove(key) => match self.tree.remove(key.to_string())? {
+                Some(data) if !Record::decode(&data)?.is_expired(now_millis()) => {
+                    ProviderResponse::Removed
+                }
+                _ => ProviderResponse::NotFound,
+            },
+            ProviderRequest::Contains(key) => {
+                ProviderResponse::Present(self.get(&key.to_string())?.is_some())
+            }
+            ProviderRequest::Ttl(_)
+            | ProviderRequest::Age(_)
+            | ProviderRequest::GetOrInsert(_, _)
+            | ProviderRequest::GetMany(_)
+            | ProviderRequest::InsertMany(_)
+            | ProviderRequest::Stats => ProviderResponse::NotFound,
+        })
+    }
+}
+
+impl<'a, K, V, E> Service<ProviderRequest<K, V>> for SledProvider<'a, K, V, E>
+where
+    K: Display + Send + 'a,
+    V: Send + 'a,
+    E: Codec<V>,
+{
+    type Response = ProviderResponse<K, V>;
+    type Error = Error;
+    type Future = ProviderFuture<'a, K, V>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ProviderRequest<K, V>) -> Self::Future {
+        Box::pin(ready(self.handle(request)))
+    }
+}
+
+/// Stored representation of an entry
+///
+/// Records start with a tag byte (`0` for values, `1` for negative entries)
+/// followed by the expiration time in milliseconds since the Unix epoch as a
+/// big-endian `u64` (`0` if the entry never expires), then the serialized
+/// value.
+struct Record {
+    value: Option<Vec<u8>>,
+    expires_at: u64,
+}
+
+impl Record {
+    const HEADER_LEN: usize = 9;
+
+    fn encode(value: Option<&[u8]>, ttl: Option<Duration>) -> Vec<u8> {
+        let expires_at = match ttl {
+            // Never store 0 for an entry with a TTL, as it means no expiration.
+            Some(ttl) => {
+                let ttl = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX);
+                now_millis().saturating_add(ttl).max(1)
+            }
+            None => 0,
+        };
+        let mut data = Vec::with_capacity(Self::HEADER_LEN + value.map_or(0, <[u8]>::len));
+        data.push(u8::from(value.is_none()));
+        data.extend_from_slice(&expires_at.to_be_bytes());
+        data.extend_from_slice(value.unwrap_or_default());
+        data
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, Error> {
+        if data.len() < Self::HEADER_LEN {
+            return Err(Error::InvalidRecord);
+        }
+        let mut expires_at = [0; 8];
+        expires_at.copy_from_slice(&data[1..Self::HEADER_LEN]);
+        let value = match data[0] {
+            0 => Some(data[Self::HEADER_LEN..].to_vec()),
+            1 => None,
+            _ => return Err(Error::InvalidRecord),
+        };
+        Ok(Self {
+            value,
+            expires_at: u64::from_be_bytes(expires_at),
+        })
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at != 0 && self.expires_at <= now
+    }
+}
+
+fn now_millis() -> u64 {
+    time::system_now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+type ProviderFuture<'a, K, V> =
+    Pin<Box<dyn Future<Output = Result<ProviderResponse<K, V>, Error>> + Send + 'a>>;
+
+/// Error returned by the [`SledProvider`]
+#[derive(Debug)]
+pub enum Error {
+    /// Error returned by sled
+    SledError(sled::Error),
+    /// Error while serializing or deserializing a value
+    CodecError(Box<dyn error::Error + Send + Sync>),
+    /// A stored entry could not be read, for example because the tree is
+    /// shared with data not written by the provider
+    InvalidRecord,
+}
+
+impl Error {
+    fn codec<E>(e: E) -> Self
+    where
+        E: error::Error + Send + Sync + 'static,
+    {
+        Error::CodecError(Box::new(e))
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::SledError(e) => Some(e),
+            Error::CodecError(e) => Some(e.as_ref()),
+            Error::InvalidRecord => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::SledError(e) => write!(f, "sled error: {}", e),
+            Error::CodecError(e) => write!(f, "serialization error: {}", e),
+            Error::InvalidRecord => write!(f, "invalid cache record"),
+        }
+    }
+}
+
+impl From<sled::Error> for Error {
+    fn from(e: sled::Error) -> Self {
+        Error::SledError(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use tower::ServiceExt;
+
+    /// Temporary directory removed when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "tower-cache-{}-{}-{}",
+                name,
+                std::process::id(),
+                now_millis()
+            ));
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn provider(db: &sled::Db) -> SledProvider<'static, String, String> {
+        SledProvider::new::<String, String>(db.open_tree("cache").unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_persistence() -> Result<(), Error> {
+        let dir = TempDir::new("persistence");
+
+        {
+            let db = sled::open(&dir.0)?;
+            provider(&db)
+                .oneshot(ProviderRequest::Insert(
+                    "a".to_string(),
+                    "A".to_string(),
+                    None,
+                ))
+                .await?;
+            provider(&db)
+                .oneshot(ProviderRequest::InsertNegative(
+                    "b".to_string(),
+                    Duration::from_secs(60),
+                ))
+                .await?;
+        }
+
+        let db = sled::open(&dir.0)?;
+        let res = provider(&db)
+            .oneshot(ProviderRequest::Get("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+        let res = provider(&db)
+            .oneshot(ProviderRequest::Get("b".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::FoundNegative));
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ttl() -> Result<(), Error> {
+        let db = sled::Config::new().temporary(true).open()?;
+        let mut provider = provider(&db);
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                Some(Duration::from_millis(20)),
+            ))
+            .await?;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(_)));
+
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        // The expired entry was removed on read
+        assert!(db.open_tree("cache")?.is_empty());
+
+        let request =
+            ProviderRequest::Insert("b".to_string(), "B".to_string(), Some(Duration::MAX));
+        provider.call(request).await?;
+        let res = provider.call(ProviderRequest::Get("b".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "B"));
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_configure() -> Result<(), Error> {
+        let db = sled::Config::new().temporary(true).open()?;
+        let config = ProviderConfig::new()
+            .ttl(Duration::from_millis(20))
+            .capacity(2);
+        let mut provider = provider(&db).configure(&config);
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(_)));
+
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remove_contains_clear() -> Result<(), Error> {
+        let db = sled::Config::new().temporary(true).open()?;
+        let mut provider = provider(&db);
+
+        for key in ["a", "b"] {
+            provider
+                .call(ProviderRequest::Insert(
+                    key.to_string(),
+                    key.to_uppercase(),
+                    None,
+                ))
+                .await?;
+        }
+
+        let res = provider
+            .call(ProviderRequest::Remove("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Removed));
+        let res = provider
+            .call(ProviderRequest::Remove("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        let res = provider
+            .call(ProviderRequest::Contains("b".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(true)));
+
+        let res = provider.call(ProviderRequest::Clear).await?;
+        assert!(matches!(res, ProviderResponse::Cleared));
+        let res = provider
+            .call(ProviderRequest::Contains("b".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Present(false)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invalid_record() -> Result<(), Error> {
+        let db = sled::Config::new().temporary(true).open()?;
+        db.open_tree("cache")?.insert("a", "not a record")?;
+        db.open_tree("cache")?.insert("b", &[0; 1][..])?;
+        let mut provider = provider(&db);
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await;
+        assert!(matches!(res, Err(Error::InvalidRecord)));
+        let res = provider.call(ProviderRequest::Get("b".to_string())).await;
+        assert!(matches!(res, Err(Error::InvalidRecord)));
+
+        Ok(())
+    }
+}
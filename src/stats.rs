@@ -0,0 +1,120 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// Snapshot of the statistics of a [`crate::CacheService`]
+///
+/// Statistics are shared between all services created by the same
+/// [`crate::CacheLayer`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of requests served from the cache
+    pub hits: u64,
+    /// Number of requests that were sent to the inner service
+    pub misses: u64,
+    /// Number of responses stored in the cache provider
+    pub inserts: u64,
+}
+
+impl CacheStats {
+    /// Ratio of requests served from the cache, between `0.0` and `1.0`
+    ///
+    /// Returns `0.0` if there were no requests.
+    pub fn hit_ratio(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+/// Statistics reported by a cache provider
+///
+/// Returned by [`crate::CacheService::provider_stats`]. See
+/// [`crate::ProviderRequest::Stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProviderStats {
+    /// Number of entries currently stored
+    pub len: usize,
+    /// Maximum number of entries
+    pub capacity: usize,
+    /// Number of entries evicted to make room for others
+    pub evictions: u64,
+}
+
+/// Handle to the statistics of the services created by a
+/// [`crate::CacheLayer`]
+///
+/// The handle can be kept after the layer has been consumed to read the
+/// statistics at any time.
+#[derive(Clone, Debug, Default)]
+pub struct StatsHandle {
+    inner: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    inserts: AtomicU64,
+}
+
+impl StatsHandle {
+    /// Return a snapshot of the current statistics
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.inner.hits.load(Ordering::Relaxed),
+            misses: self.inner.misses.load(Ordering::Relaxed),
+            inserts: self.inner.inserts.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn hit(&self) {
+        self.inner.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn miss(&self) {
+        self.inner.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn insert(&self) {
+        self.inner.inserts.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hit_ratio() {
+        assert_eq!(CacheStats::default().hit_ratio(), 0.0);
+
+        let stats = CacheStats {
+            hits: 3,
+            misses: 1,
+            inserts: 1,
+        };
+        assert_eq!(stats.hit_ratio(), 0.75);
+    }
+
+    #[test]
+    fn test_handle() {
+        let handle = StatsHandle::default();
+        let other = handle.clone();
+
+        handle.hit();
+        handle.miss();
+        other.insert();
+
+        assert_eq!(
+            other.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 1,
+                inserts: 1,
+            }
+        );
+    }
+}
@@ -0,0 +1,1268 @@
+//! # Two-tier cache provider
+//!
+//! This is an implementation of a cache provider for [`crate::CacheLayer`]
+//! combining two other providers: a fast local cache (L1), such as
+//! [`crate::lru::LruProvider`], in front of a shared remote cache (L2), such
+//! as a Redis provider.
+//!
+//! Lookups query L1 first, and fall back to L2 on a miss. Values found in L2
+//! are written back to L1 with their remaining TTL in L2, so that subsequent
+//! lookups are served locally. Writes go to both providers. As L2 holds the
+//! values, errors when writing them to L1 are ignored.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, service_fn};
+//! use tower_cache::{
+//!     CacheLayer,
+//!     lru::LruProvider,
+//!     map::MapProvider,
+//!     tiered::TieredProvider,
+//! };
+//! async fn handler(req: String) -> Result<String, Infallible> {
+//!     Ok(req.to_uppercase())
+//! }
+//!
+//! // Initialize the cache provider service
+//! let tiered_provider = TieredProvider::new(
+//!     LruProvider::new::<String, String>(20),
+//!     MapProvider::new::<String, String>(),
+//! );
+//!
+//! // Wrap the service with CacheLayer.
+//! let mut my_service = ServiceBuilder::new()
+//!     .layer(CacheLayer::new(tiered_provider))
+//!     .service(service_fn(handler));
+//!
+//! # tokio_test::block_on(async move {
+//! // Call the service
+//! let res = my_service.call("Hello".to_string()).await.unwrap();
+//! assert_eq!(res, "HELLO".to_string());
+//! # })
+//! ```
+//!
+//! ## More levels
+//!
+//! [`ChainProvider`] queries any number of providers of the same type in
+//! order, without nesting [`TieredProvider`]s. Values found in a provider are
+//! written back to all the providers before it. See
+//! [`crate::CacheLayer::with_providers`].
+//!
+
+use crate::{trace, ProviderRequest, ProviderResponse};
+use std::{
+    error, fmt,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower::{Service, ServiceExt};
+
+/// Cache provider querying a local provider before a remote one
+#[derive(Clone, Debug)]
+pub struct TieredProvider<'a, P1, P2> {
+    l1: P1,
+    l2: P2,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a, P1, P2> TieredProvider<'a, P1, P2> {
+    /// Create a new two-tier cache provider
+    ///
+    /// `l1` is queried first, and `l2` only on a miss in `l1`.
+    pub fn new(l1: P1, l2: P2) -> Self {
+        TieredProvider {
+            l1,
+            l2,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, P1, P2, K, V> Service<ProviderRequest<K, V>> for TieredProvider<'a, P1, P2>
+where
+    P1: Service<ProviderRequest<K, V>, Response = ProviderResponse<K, V>> + Clone + Send + 'a,
+    P1::Error: Send + 'a,
+    P1::Future: Send + 'a,
+    P2: Service<ProviderRequest<K, V>, Response = ProviderResponse<K, V>> + Clone + Send + 'a,
+    P2::Error: Send + 'a,
+    P2::Future: Send + 'a,
+    K: Clone + PartialEq + Send + 'a,
+    V: Clone + Send + 'a,
+{
+    type Response = ProviderResponse<K, V>;
+    type Error = TieredError<P1::Error, P2::Error>;
+    type Future = ProviderFuture<'a, K, V, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Each request drives clones of the providers to readiness.
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ProviderRequest<K, V>) -> Self::Future {
+        let l1 = self.l1.clone();
+        let l2 = self.l2.clone();
+
+        Box::pin(async move {
+            Ok(match request {
+                ProviderRequest::Get(key) => {
+                    let l1_res = l1
+                        .clone()
+                        .oneshot(ProviderRequest::Get(key.clone()))
+                        .await
+                        .map_err(TieredError::L1)?;
+                    if let ProviderResponse::Found(_) | ProviderResponse::FoundNegative = l1_res {
+                        return Ok(l1_res);
+                    }
+
+                    let res = l2
+                        .clone()
+                        .oneshot(ProviderRequest::Get(key.clone()))
+                        .await
+                        .map_err(TieredError::L2)?;
+                    // A stale entry in L1 is better than nothing.
+                    if let (ProviderResponse::FoundStale(_), ProviderResponse::NotFound) =
+                        (&l1_res, &res)
+                    {
+                        return Ok(l1_res);
+                    }
+                    // Back-fill L1 so that the next lookup is served locally.
+                    if let ProviderResponse::Found(value) = &res {
+                        let value = value.clone();
+                        let ttl = remaining_ttl(l2, key.clone()).await;
+                        backfill(l1, key, value, ttl).await;
+                    }
+                    res
+                }
+                ProviderRequest::Insert(key, value, ttl) => {
+                    l2.oneshot(ProviderRequest::Insert(key.clone(), value.clone(), ttl))
+                        .await
+                        .map_err(TieredError::L2)?;
+                    let request = ProviderRequest::Insert(key.clone(), value.clone(), ttl);
+                    match l1.clone().oneshot(request).await {
+                        Ok(res) => res,
+                        Err(_) => {
+                            evict(l1, key).await;
+                            ProviderResponse::Found(value)
+                        }
+                    }
+                }
+                ProviderRequest::InsertNegative(key, ttl) => {
+                    l2.oneshot(ProviderRequest::InsertNegative(key.clone(), ttl))
+                        .await
+                        .map_err(TieredError::L2)?;
+                    let request = ProviderRequest::InsertNegative(key.clone(), ttl);
+                    match l1.clone().oneshot(request).await {
+                        Ok(res) => res,
+                        Err(_) => {
+                            evict(l1, key).await;
+                            ProviderResponse::FoundNegative
+                        }
+                    }
+                }
+                ProviderRequest::Clear => {
+                    l2.oneshot(ProviderRequest::Clear)
+                        .await
+                        .map_err(TieredError::L2)?;
+                    l1.oneshot(ProviderRequest::Clear)
+                        .await
+                        .map_err(TieredError::L1)?;
+                    ProviderResponse::Cleared
+                }
+                ProviderRequest::Remove(key) => {
+                    let removed_l2 = l2
+                        .oneshot(ProviderRequest::Remove(key.clone()))
+                        .await
+                        .map_err(TieredError::L2)?;
+                    let removed_l1 = l1
+                        .oneshot(ProviderRequest::Remove(key))
+                        .await
+                        .map_err(TieredError::L1)?;
+                    match (removed_l1, removed_l2) {
+                        (ProviderResponse::Removed, _) | (_, ProviderResponse::Removed) => {
+                            ProviderResponse::Removed
+                        }
+                        _ => ProviderResponse::NotFound,
+                    }
+                }
+                ProviderRequest::Contains(key) => {
+                    let res = l1
+                        .oneshot(ProviderRequest::Contains(key.clone()))
+                        .await
+                        .map_err(TieredError::L1)?;
+                    match res {
+                        ProviderResponse::Present(true) => res,
+                        _ => l2
+                            .oneshot(ProviderRequest::Contains(key))
+                            .await
+                            .map_err(TieredError::L2)?,
+                    }
+                }
+                ProviderRequest::Ttl(key) => {
+                    let res = l1
+                        .oneshot(ProviderRequest::Ttl(key.clone()))
+                        .await
+                        .map_err(TieredError::L1)?;
+                    match res {
+                        ProviderResponse::Ttl(_, _) => res,
+                        _ => l2
+                            .oneshot(ProviderRequest::Ttl(key))
+                            .await
+                            .map_err(TieredError::L2)?,
+                    }
+                }
+                ProviderRequest::Age(key) => {
+                    let res = l1
+                        .oneshot(ProviderRequest::Age(key.clone()))
+                        .await
+                        .map_err(TieredError::L1)?;
+                    match res {
+                        ProviderResponse::Age(_) => res,
+                        _ => l2
+                            .oneshot(ProviderRequest::Age(key))
+                            .await
+                            .map_err(TieredError::L2)?,
+                    }
+                }
+                // L2 is shared, so it decides which response wins. L1 then
+                // stores that response.
+                ProviderRequest::GetOrInsert(key, value) => {
+                    let res = l2
+                        .clone()
+                        .oneshot(ProviderRequest::GetOrInsert(key.clone(), value))
+                        .await
+                        .map_err(TieredError::L2)?;
+                    if let ProviderResponse::Found(value) | ProviderResponse::Inserted(value) = &res
+                    {
+                        let value = value.clone();
+                        let ttl = remaining_ttl(l2, key.clone()).await;
+                        backfill(l1, key, value, ttl).await;
+                    }
+                    res
+                }
+                // Look up all keys in L1, then the missing ones in L2.
+                ProviderRequest::GetMany(keys) => {
+                    let mut values = match l1
+                        .clone()
+                        .oneshot(ProviderRequest::GetMany(keys.clone()))
+                        .await
+                        .map_err(TieredError::L1)?
+                    {
+                        ProviderResponse::Many(values) if values.len() == keys.len() => values,
+                        _ => keys.into_iter().map(|key| (key, None)).collect(),
+                    };
+                    let missing: Vec<_> = values
+                        .iter()
+                        .filter(|(_, value)| value.is_none())
+                        .map(|(key, _)| key.clone())
+                        .collect();
+                    if missing.is_empty() {
+                        return Ok(ProviderResponse::Many(values));
+                    }
+                    let l2_values = match l2
+                        .clone()
+                        .oneshot(ProviderRequest::GetMany(missing.clone()))
+                        .await
+                        .map_err(TieredError::L2)?
+                    {
+                        ProviderResponse::Many(l2_values) => l2_values,
+                        _ => Vec::new(),
+                    };
+                    // Match L2 values by key, whatever order they come in.
+                    for (key, value) in l2_values {
+                        let Some(value) = value else { continue };
+                        let Some((_, slot)) = values
+                            .iter_mut()
+                            .find(|(slot_key, slot)| slot.is_none() && *slot_key == key)
+                        else {
+                            continue;
+                        };
+                        // Back-fill L1, as for a single lookup.
+                        let ttl = remaining_ttl(l2.clone(), key.clone()).await;
+                        backfill(l1.clone(), key, value.clone(), ttl).await;
+                        *slot = Some(value);
+                    }
+                    ProviderResponse::Many(values)
+                }
+                ProviderRequest::InsertMany(entries) => {
+                    insert_many(l2, entries.clone())
+                        .await
+                        .map_err(TieredError::L2)?;
+                    insert_many(l1, entries).await.map_err(TieredError::L1)?
+                }
+                // The two tiers can't be reported as a single cache.
+                ProviderRequest::Stats => ProviderResponse::NotFound,
+            })
+        })
+    }
+}
+
+/// Return the remaining TTL of the entry for `key` in `provider`, or `None`
+/// if it isn't known
+async fn remaining_ttl<P, K, V>(provider: P, key: K) -> Option<Duration>
+where
+    P: Service<ProviderRequest<K, V>, Response = ProviderResponse<K, V>>,
+{
+    match provider.oneshot(ProviderRequest::Ttl(key)).await {
+        Ok(ProviderResponse::Ttl(remaining, _)) => Some(remaining),
+        _ => None,
+    }
+}
+
+/// Store a value found in a later level in `provider`
+///
+/// The value is returned to the caller either way, so errors are only traced.
+async fn backfill<P, K, V>(provider: P, key: K, value: V, ttl: Option<Duration>)
+where
+    P: Service<ProviderRequest<K, V>, Response = ProviderResponse<K, V>>,
+{
+    let request = ProviderRequest::Insert(key, value, ttl);
+    if provider.oneshot(request).await.is_err() {
+        trace::backfill_failed();
+    }
+}
+
+/// Remove the entry for `key` from `provider` after failing to replace it,
+/// so that it doesn't keep serving the previous value
+async fn evict<P, K, V>(provider: P, key: K)
+where
+    P: Service<ProviderRequest<K, V>, Response = ProviderResponse<K, V>>,
+{
+    trace::backfill_failed();
+    let _ = provider.oneshot(ProviderRequest::Remove(key)).await;
+}
+
+/// Insert entries into a provider with a single batch request, or one by one
+/// if the provider doesn't support batch inserts
+async fn insert_many<P, K, V>(
+    provider: P,
+    entries: Vec<(K, V, Option<Duration>)>,
+) -> Result<ProviderResponse<K, V>, P::Error>
+where
+    P: Service<ProviderRequest<K, V>, Response = ProviderResponse<K, V>> + Clone,
+    K: Clone,
+    V: Clone,
+{
+    let res = provider
+        .clone()
+        .oneshot(ProviderRequest::InsertMany(entries.clone()))
+        .await?;
+    if let ProviderResponse::Many(_) = res {
+        return Ok(res);
+    }
+
+    let mut values = Vec::with_capacity(entries.len());
+    for (key, value, ttl) in entries {
+        provider
+            .clone()
+            .oneshot(ProviderRequest::Insert(key.clone(), value.clone(), ttl))
+            .await?;
+        values.push((key, Some(value)));
+    }
+    Ok(ProviderResponse::Many(values))
+}
+
+type ProviderFuture<'a, K, V, E> =
+    Pin<Box<dyn Future<Output = Result<ProviderResponse<K, V>, E>> + Send + 'a>>;
+
+/// Error returned by a [`TieredProvider`]
+#[derive(Debug)]
+pub enum TieredError<E1, E2> {
+    /// The local (L1) provider returned an error
+    L1(E1),
+    /// The remote (L2) provider returned an error
+    L2(E2),
+}
+
+impl<E1, E2> error::Error for TieredError<E1, E2>
+where
+    E1: error::Error + 'static,
+    E2: error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            TieredError::L1(e) => Some(e),
+            TieredError::L2(e) => Some(e),
+        }
+    }
+}
+
+impl<E1, E2> fmt::Display for TieredError<E1, E2>
+where
+    E1: fmt::Display,
+    E2: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TieredError::L1(e) => write!(f, "L1 provider error: {}", e),
+            TieredError::L2(e) => write!(f, "L2 provider error: {}", e),
+        }
+    }
+}
+
+/// Cache provider querying a list of providers in order
+///
+/// This generalizes [`TieredProvider`] to any number of levels sharing the
+/// same provider type, such as several [`crate::map::MapProvider`]s, or boxed
+/// providers. Lookups query each level in turn, and back-fill the earlier
+/// levels on a hit, with the remaining TTL of the entry and ignoring errors.
+/// Writes go to all levels, from the last to the first.
+/// Responses to writes are those of the first level.
+///
+/// By default, an error at any level is returned. With
+/// [`ChainProvider::fallback_on_error`], a level that fails is skipped
+/// instead, and an error is only returned if every level fails.
+#[derive(Clone, Debug)]
+pub struct ChainProvider<'a, P> {
+    levels: Vec<P>,
+    fallback_on_error: bool,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a, P> ChainProvider<'a, P> {
+    /// Create a new cache provider from a list of providers
+    ///
+    /// The first provider is queried first, and each following one only on
+    /// a miss in the previous ones. Without providers, this never stores
+    /// anything, like [`crate::noop::NoopProvider`].
+    pub fn new(levels: Vec<P>) -> Self {
+        ChainProvider {
+            levels,
+            fallback_on_error: false,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Skip the levels returning an error, instead of returning it.
+    ///
+    /// Lookups then treat an error as a miss, and writes still go to the
+    /// other levels.
+    pub fn fallback_on_error(mut self, enabled: bool) -> Self {
+        self.fallback_on_error = enabled;
+        self
+    }
+}
+
+impl<'a, P, K, V> Service<ProviderRequest<K, V>> for ChainProvider<'a, P>
+where
+    P: Service<ProviderRequest<K, V>, Response = ProviderResponse<K, V>> + Clone + Send + 'a,
+    P::Error: Send + 'a,
+    P::Future: Send + 'a,
+    K: Clone + PartialEq + Send + 'a,
+    V: Clone + Send + 'a,
+{
+    type Response = ProviderResponse<K, V>;
+    type Error = ChainError<P::Error>;
+    type Future = ProviderFuture<'a, K, V, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Each request drives clones of the providers to readiness.
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ProviderRequest<K, V>) -> Self::Future {
+        let mut chain = Chain {
+            levels: self.levels.clone(),
+            fallback_on_error: self.fallback_on_error,
+            succeeded: false,
+            error: None,
+        };
+
+        Box::pin(async move {
+            let count = chain.levels.len();
+            let res = match request {
+                ProviderRequest::Get(key) => {
+                    // A stale entry is better than nothing.
+                    let mut stale = None;
+                    for level in 0..count {
+                        match chain.call(level, ProviderRequest::Get(key.clone())).await? {
+                            Some(ProviderResponse::Found(value)) => {
+                                chain.backfill(level, key, value.clone()).await;
+                                return Ok(ProviderResponse::Found(value));
+                            }
+                            Some(res @ ProviderResponse::FoundNegative) => return Ok(res),
+                            Some(res @ ProviderResponse::FoundStale(_)) if stale.is_none() => {
+                                stale = Some(res);
+                            }
+                            _ => {}
+                        }
+                    }
+                    stale.unwrap_or(ProviderResponse::NotFound)
+                }
+                ProviderRequest::Insert(key, value, ttl) => {
+                    let mut res = None;
+                    for level in (0..count).rev() {
+                        let request = ProviderRequest::Insert(key.clone(), value.clone(), ttl);
+                        res = chain.call(level, request).await?.or(res);
+                    }
+                    res.unwrap_or(ProviderResponse::Found(value))
+                }
+                ProviderRequest::InsertNegative(key, ttl) => {
+                    let mut res = None;
+                    for level in (0..count).rev() {
+                        let request = ProviderRequest::InsertNegative(key.clone(), ttl);
+                        res = chain.call(level, request).await?.or(res);
+                    }
+                    res.unwrap_or(ProviderResponse::FoundNegative)
+                }
+                ProviderRequest::Clear => {
+                    for level in (0..count).rev() {
+                        chain.call(level, ProviderRequest::Clear).await?;
+                    }
+                    ProviderResponse::Cleared
+                }
+                ProviderRequest::Remove(key) => {
+                    let mut removed = false;
+                    for level in (0..count).rev() {
+                        let res = chain
+                            .call(level, ProviderRequest::Remove(key.clone()))
+                            .await?;
+                        removed |= matches!(res, Some(ProviderResponse::Removed));
+                    }
+                    match removed {
+                        true => ProviderResponse::Removed,
+                        false => ProviderResponse::NotFound,
+                    }
+                }
+                ProviderRequest::Contains(key) => {
+                    let mut present = false;
+                    for level in 0..count {
+                        let res = chain
+                            .call(level, ProviderRequest::Contains(key.clone()))
+                            .await?;
+                        if let Some(ProviderResponse::Present(true)) = res {
+                            present = true;
+                            break;
+                        }
+                    }
+                    ProviderResponse::Present(present)
+                }
+                ProviderRequest::Ttl(key) => {
+                    let mut res = ProviderResponse::NotFound;
+                    for level in 0..count {
+                        if let Some(ttl @ ProviderResponse::Ttl(_, _)) =
+                            chain.call(level, ProviderRequest::Ttl(key.clone())).await?
+                        {
+                            res = ttl;
+                            break;
+                        }
+                    }
+                    res
+                }
+                ProviderRequest::Age(key) => {
+                    let mut res = ProviderResponse::NotFound;
+                    for level in 0..count {
+                        if let Some(age @ ProviderResponse::Age(_)) =
+                            chain.call(level, ProviderRequest::Age(key.clone())).await?
+                        {
+                            res = age;
+                            break;
+                        }
+                    }
+                    res
+                }
+                // As with two tiers, the last level decides which response
+                // wins, and the earlier levels store it.
+                ProviderRequest::GetOrInsert(key, value) => {
+                    let mut res = None;
+                    for level in (0..count).rev() {
+                        let request = ProviderRequest::GetOrInsert(key.clone(), value.clone());
+                        if let Some(found) = chain.call(level, request).await? {
+                            if let ProviderResponse::Found(winner)
+                            | ProviderResponse::Inserted(winner) = &found
+                            {
+                                chain.backfill(level, key, winner.clone()).await;
+                            }
+                            res = Some(found);
+                            break;
+                        }
+                    }
+                    res.unwrap_or(ProviderResponse::NotFound)
+                }
+                // Look up the keys that are still missing at each level.
+                ProviderRequest::GetMany(keys) => {
+                    let mut values: Vec<Option<V>> = keys.iter().map(|_| None).collect();
+                    for level in 0..count {
+                        let missing: Vec<_> = (0..keys.len())
+                            .filter(|&index| values[index].is_none())
+                            .collect();
+                        if missing.is_empty() {
+                            break;
+                        }
+                        let request = ProviderRequest::GetMany(
+                            missing.iter().map(|&index| keys[index].clone()).collect(),
+                        );
+                        let Some(ProviderResponse::Many(found)) =
+                            chain.call(level, request).await?
+                        else {
+                            continue;
+                        };
+                        for (key, value) in found {
+                            let Some(value) = value else { continue };
+                            let Some(index) = missing
+                                .iter()
+                                .copied()
+                                .find(|&index| values[index].is_none() && keys[index] == key)
+                            else {
+                                continue;
+                            };
+                            chain.backfill(level, key, value.clone()).await;
+                            values[index] = Some(value);
+                        }
+                    }
+                    ProviderResponse::Many(keys.into_iter().zip(values).collect())
+                }
+                ProviderRequest::InsertMany(entries) => {
+                    let mut res = None;
+                    for level in (0..count).rev() {
+                        let inserted =
+                            insert_many(chain.levels[level].clone(), entries.clone()).await;
+                        res = chain.record(level, inserted)?.or(res);
+                    }
+                    res.unwrap_or(ProviderResponse::NotFound)
+                }
+                // The levels can't be reported as a single cache.
+                ProviderRequest::Stats => ProviderResponse::NotFound,
+            };
+            chain.finish(res)
+        })
+    }
+}
+
+/// State of a request sent to a [`ChainProvider`]
+struct Chain<P, E> {
+    levels: Vec<P>,
+    fallback_on_error: bool,
+    succeeded: bool,
+    error: Option<ChainError<E>>,
+}
+
+impl<P, E> Chain<P, E> {
+    /// Record the result of a level, returning `None` for a skipped error
+    fn record<T>(&mut self, level: usize, res: Result<T, E>) -> Result<Option<T>, ChainError<E>> {
+        match res {
+            Ok(res) => {
+                self.succeeded = true;
+                Ok(Some(res))
+            }
+            Err(error) if self.fallback_on_error => {
+                trace::provider_fallback();
+                self.error.get_or_insert(ChainError { level, error });
+                Ok(None)
+            }
+            Err(error) => Err(ChainError { level, error }),
+        }
+    }
+
+    /// Return the response, or the first error if every level failed
+    fn finish<K, V>(
+        self,
+        res: ProviderResponse<K, V>,
+    ) -> Result<ProviderResponse<K, V>, ChainError<E>> {
+        match (self.succeeded, self.error) {
+            (false, Some(error)) => Err(error),
+            _ => Ok(res),
+        }
+    }
+
+    /// Send a request to a level
+    async fn call<K, V>(
+        &mut self,
+        level: usize,
+        request: ProviderRequest<K, V>,
+    ) -> Result<Option<ProviderResponse<K, V>>, ChainError<E>>
+    where
+        P: Service<ProviderRequest<K, V>, Response = ProviderResponse<K, V>, Error = E> + Clone,
+    {
+        let res = self.levels[level].clone().oneshot(request).await;
+        self.record(level, res)
+    }
+
+    /// Store a value found at a level in all the levels before it, with its
+    /// remaining TTL at that level
+    ///
+    /// Errors are ignored, as the value is returned either way.
+    async fn backfill<K, V>(&mut self, level: usize, key: K, value: V)
+    where
+        P: Service<ProviderRequest<K, V>, Response = ProviderResponse<K, V>, Error = E> + Clone,
+        K: Clone,
+        V: Clone,
+    {
+        if level == 0 {
+            return;
+        }
+        let ttl = remaining_ttl(self.levels[level].clone(), key.clone()).await;
+        for earlier in 0..level {
+            let provider = self.levels[earlier].clone();
+            backfill(provider, key.clone(), value.clone(), ttl).await;
+        }
+    }
+}
+
+/// Error returned by a [`ChainProvider`]
+#[derive(Debug)]
+pub struct ChainError<E> {
+    level: usize,
+    error: E,
+}
+
+impl<E> ChainError<E> {
+    /// Return the index of the provider that returned the error, starting
+    /// from `0` for the first one
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    /// Return the error of the provider
+    pub fn into_inner(self) -> E {
+        self.error
+    }
+}
+
+impl<E> error::Error for ChainError<E>
+where
+    E: error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl<E> fmt::Display for ChainError<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "L{} provider error: {}", self.level + 1, self.error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::MapProvider;
+    use std::convert::Infallible;
+
+    type Error = TieredError<Infallible, Infallible>;
+
+    #[tokio::test]
+    async fn test_l2_hit_backfills_l1() -> Result<(), Error> {
+        let mut l1 = MapProvider::new::<String, String>();
+        let mut l2 = MapProvider::new::<String, String>();
+        let mut provider = TieredProvider::new(l1.clone(), l2.clone());
+
+        // Only the remote cache knows about this entry
+        l2.call(ProviderRequest::Insert(
+            "a".to_string(),
+            "A".to_string(),
+            None,
+        ))
+        .await
+        .map_err(TieredError::L2)?;
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+
+        // The entry was written back to L1
+        let res = l1
+            .call(ProviderRequest::Get("a".to_string()))
+            .await
+            .map_err(TieredError::L1)?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+
+        // Subsequent lookups are served by L1, even if L2 loses the entry
+        l2.call(ProviderRequest::Remove("a".to_string()))
+            .await
+            .map_err(TieredError::L2)?;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_backfill_keeps_l2_ttl() -> Result<(), Error> {
+        let mut l1 = MapProvider::new::<String, String>();
+        let mut l2 = MapProvider::new::<String, String>();
+        let mut provider = TieredProvider::new(l1.clone(), l2.clone());
+
+        l2.call(ProviderRequest::Insert(
+            "a".to_string(),
+            "A".to_string(),
+            Some(Duration::from_secs(60)),
+        ))
+        .await
+        .map_err(TieredError::L2)?;
+        provider.call(ProviderRequest::Get("a".to_string())).await?;
+
+        // L1 expires the entry at the same time as L2
+        let res = l1
+            .call(ProviderRequest::Ttl("a".to_string()))
+            .await
+            .map_err(TieredError::L1)?;
+        assert!(matches!(
+            res,
+            ProviderResponse::Ttl(remaining, _) if remaining <= Duration::from_secs(60)
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_l1_write_errors_ignored() -> Result<(), TieredError<&'static str, Infallible>> {
+        /// Provider that fails on writes
+        #[derive(Clone)]
+        struct ReadOnly(MapProvider<'static, String, String>);
+
+        impl Service<ProviderRequest<String, String>> for ReadOnly {
+            type Response = ProviderResponse<String, String>;
+            type Error = &'static str;
+            type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+            fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+                Poll::Ready(Ok(()))
+            }
+
+            fn call(&mut self, req: ProviderRequest<String, String>) -> Self::Future {
+                match req {
+                    ProviderRequest::Insert(_, _, _) | ProviderRequest::InsertNegative(_, _) => {
+                        Box::pin(std::future::ready(Err("read-only")))
+                    }
+                    req => {
+                        let fut = self.0.call(req);
+                        Box::pin(async move {
+                            let Ok(res) = fut.await;
+                            Ok(res)
+                        })
+                    }
+                }
+            }
+        }
+
+        let mut l2 = MapProvider::new::<String, String>();
+        let mut provider = TieredProvider::new(ReadOnly(MapProvider::new()), l2.clone());
+
+        // Lookups still return values found in L2
+        l2.call(ProviderRequest::Insert(
+            "a".to_string(),
+            "A".to_string(),
+            None,
+        ))
+        .await
+        .map_err(TieredError::L2)?;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+
+        // Writes succeed as long as L2 stored the value
+        let res = provider
+            .call(ProviderRequest::Insert(
+                "b".to_string(),
+                "B".to_string(),
+                None,
+            ))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "B"));
+        let res = provider
+            .call(ProviderRequest::InsertNegative(
+                "c".to_string(),
+                Duration::from_secs(1),
+            ))
+            .await?;
+        assert!(matches!(res, ProviderResponse::FoundNegative));
+        let res = l2
+            .call(ProviderRequest::Get("b".to_string()))
+            .await
+            .map_err(TieredError::L2)?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "B"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_insert_both() -> Result<(), Error> {
+        let mut l1 = MapProvider::new::<String, String>();
+        let mut l2 = MapProvider::new::<String, String>();
+        let mut provider = TieredProvider::new(l1.clone(), l2.clone());
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+
+        let res = l1
+            .call(ProviderRequest::Contains("a".to_string()))
+            .await
+            .map_err(TieredError::L1)?;
+        assert!(matches!(res, ProviderResponse::Present(true)));
+        let res = l2
+            .call(ProviderRequest::Contains("a".to_string()))
+            .await
+            .map_err(TieredError::L2)?;
+        assert!(matches!(res, ProviderResponse::Present(true)));
+
+        let res = provider
+            .call(ProviderRequest::Remove("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Removed));
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_or_insert_l2_wins() -> Result<(), Error> {
+        let mut l1 = MapProvider::new::<String, String>();
+        let mut l2 = MapProvider::new::<String, String>();
+        let mut provider = TieredProvider::new(l1.clone(), l2.clone());
+
+        // Another instance already stored a response in L2
+        l2.call(ProviderRequest::Insert(
+            "a".to_string(),
+            "A".to_string(),
+            None,
+        ))
+        .await
+        .map_err(TieredError::L2)?;
+
+        let res = provider
+            .call(ProviderRequest::GetOrInsert(
+                "a".to_string(),
+                "B".to_string(),
+            ))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+        let res = l1
+            .call(ProviderRequest::Get("a".to_string()))
+            .await
+            .map_err(TieredError::L1)?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_many() -> Result<(), Error> {
+        let mut l1 = MapProvider::new::<String, String>();
+        let mut l2 = MapProvider::new::<String, String>();
+        let mut provider = TieredProvider::new(l1.clone(), l2.clone());
+
+        l1.call(ProviderRequest::Insert(
+            "a".to_string(),
+            "A1".to_string(),
+            None,
+        ))
+        .await
+        .map_err(TieredError::L1)?;
+        for key in ["a", "b"] {
+            l2.call(ProviderRequest::Insert(
+                key.to_string(),
+                format!("{}2", key.to_uppercase()),
+                None,
+            ))
+            .await
+            .map_err(TieredError::L2)?;
+        }
+
+        let keys = ["c", "b", "a"].map(|key| key.to_string()).to_vec();
+        let res = provider.call(ProviderRequest::GetMany(keys)).await?;
+        assert!(matches!(
+            res,
+            ProviderResponse::Many(v) if v == [
+                ("c".to_string(), None),
+                ("b".to_string(), Some("B2".to_string())),
+                ("a".to_string(), Some("A1".to_string())),
+            ]
+        ));
+
+        // "b" was written back to L1
+        let res = l1
+            .call(ProviderRequest::Get("b".to_string()))
+            .await
+            .map_err(TieredError::L1)?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "B2"));
+
+        Ok(())
+    }
+
+    /// Provider returning batch responses in reverse order
+    #[derive(Clone)]
+    struct Reversed(MapProvider<'static, String, String>);
+
+    impl Service<ProviderRequest<String, String>> for Reversed {
+        type Response = ProviderResponse<String, String>;
+        type Error = Infallible;
+        type Future = ProviderFuture<'static, String, String, Infallible>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.0.poll_ready(cx)
+        }
+
+        fn call(&mut self, req: ProviderRequest<String, String>) -> Self::Future {
+            let fut = self.0.call(req);
+            Box::pin(async move {
+                Ok(match fut.await? {
+                    ProviderResponse::Many(mut pairs) => {
+                        pairs.reverse();
+                        ProviderResponse::Many(pairs)
+                    }
+                    res => res,
+                })
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_many_paired_by_key() -> Result<(), Error> {
+        let l2 = MapProvider::new::<String, String>();
+        for key in ["a", "b"] {
+            let Ok(_) = l2
+                .clone()
+                .call(ProviderRequest::Insert(
+                    key.to_string(),
+                    key.to_uppercase(),
+                    None,
+                ))
+                .await;
+        }
+        let keys = ["a", "c", "b"].map(|key| key.to_string()).to_vec();
+        let expected = [
+            ("a".to_string(), Some("A".to_string())),
+            ("c".to_string(), None),
+            ("b".to_string(), Some("B".to_string())),
+        ];
+
+        let l1 = MapProvider::new::<String, String>();
+        let mut provider = TieredProvider::new(l1.clone(), Reversed(l2.clone()));
+        let res = provider
+            .call(ProviderRequest::GetMany(keys.clone()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Many(v) if v == expected));
+        // Each value was written back to L1 under its own key
+        let Ok(res) = l1.clone().call(ProviderRequest::Get("a".to_string())).await;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+
+        let levels = vec![Reversed(MapProvider::new::<String, String>()), Reversed(l2)];
+        let mut provider = ChainProvider::new(levels.clone());
+        let res = provider.call(ProviderRequest::GetMany(keys)).await.unwrap();
+        assert!(matches!(res, ProviderResponse::Many(v) if v == expected));
+        let Ok(res) = levels[0]
+            .clone()
+            .call(ProviderRequest::Get("b".to_string()))
+            .await;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "B"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_insert_many() -> Result<(), Error> {
+        let mut l1 = MapProvider::new::<String, String>();
+        let mut l2 = MapProvider::new::<String, String>();
+        let mut provider = TieredProvider::new(l1.clone(), l2.clone());
+
+        // Neither tier supports batch inserts, so entries are inserted one by
+        // one.
+        let entries = ["a", "b"]
+            .map(|key| (key.to_string(), key.to_uppercase(), None))
+            .to_vec();
+        let res = provider.call(ProviderRequest::InsertMany(entries)).await?;
+        assert!(matches!(
+            res,
+            ProviderResponse::Many(v) if v == [
+                ("a".to_string(), Some("A".to_string())),
+                ("b".to_string(), Some("B".to_string())),
+            ]
+        ));
+
+        let keys = ["a", "b"].map(|key| key.to_string()).to_vec();
+        let res = l1
+            .call(ProviderRequest::GetMany(keys.clone()))
+            .await
+            .map_err(TieredError::L1)?;
+        assert!(
+            matches!(res, ProviderResponse::Many(v) if v.iter().all(|(_, value)| value.is_some()))
+        );
+        let res = l2
+            .call(ProviderRequest::GetMany(keys))
+            .await
+            .map_err(TieredError::L2)?;
+        assert!(
+            matches!(res, ProviderResponse::Many(v) if v.iter().all(|(_, value)| value.is_some()))
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_error_unified() {
+        #[derive(Clone)]
+        struct Failing;
+
+        impl Service<ProviderRequest<String, String>> for Failing {
+            type Response = ProviderResponse<String, String>;
+            type Error = &'static str;
+            type Future = std::future::Ready<Result<Self::Response, Self::Error>>;
+
+            fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+                Poll::Ready(Ok(()))
+            }
+
+            fn call(&mut self, _req: ProviderRequest<String, String>) -> Self::Future {
+                std::future::ready(Err("unavailable"))
+            }
+        }
+
+        let mut provider = TieredProvider::new(MapProvider::new::<String, String>(), Failing);
+
+        // A miss in L1 surfaces the error from L2
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await;
+        assert!(matches!(res, Err(TieredError::L2("unavailable"))));
+        assert_eq!(
+            res.err().unwrap().to_string(),
+            "L2 provider error: unavailable"
+        );
+    }
+
+    type ChainResult = Result<(), ChainError<Infallible>>;
+
+    #[tokio::test]
+    async fn test_chain_backfills() -> ChainResult {
+        let levels: Vec<_> = (0..3)
+            .map(|_| MapProvider::new::<String, String>())
+            .collect();
+        let mut provider = ChainProvider::new(levels.clone());
+
+        // Only the last level knows about this entry
+        let request = ProviderRequest::Insert("a".to_string(), "A".to_string(), None);
+        let Ok(_) = levels[2].clone().call(request).await;
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+
+        // The entry was written back to the first two levels
+        for level in &levels[..2] {
+            let Ok(res) = level
+                .clone()
+                .call(ProviderRequest::Get("a".to_string()))
+                .await;
+            assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+        }
+
+        // Writes and removals go to all levels
+        provider
+            .call(ProviderRequest::Insert(
+                "b".to_string(),
+                "B".to_string(),
+                None,
+            ))
+            .await?;
+        let res = provider
+            .call(ProviderRequest::Remove("a".to_string()))
+            .await?;
+        assert!(matches!(res, ProviderResponse::Removed));
+        for level in &levels {
+            let Ok(res) = level
+                .clone()
+                .call(ProviderRequest::Contains("a".to_string()))
+                .await;
+            assert!(matches!(res, ProviderResponse::Present(false)));
+            let Ok(res) = level
+                .clone()
+                .call(ProviderRequest::Contains("b".to_string()))
+                .await;
+            assert!(matches!(res, ProviderResponse::Present(true)));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_chain_get_many() -> ChainResult {
+        let levels: Vec<_> = (0..3).map(|_| MapProvider::new::<usize, usize>()).collect();
+        let mut provider = ChainProvider::new(levels.clone());
+        for (level, key) in [(1, 1), (2, 2)] {
+            let Ok(_) = levels[level]
+                .clone()
+                .call(ProviderRequest::Insert(key, key * 10, None))
+                .await;
+        }
+
+        let res = provider
+            .call(ProviderRequest::GetMany(vec![0, 1, 2]))
+            .await?;
+        assert!(
+            matches!(res, ProviderResponse::Many(v) if v == vec![(0, None), (1, Some(10)), (2, Some(20))])
+        );
+        let Ok(res) = levels[0]
+            .clone()
+            .call(ProviderRequest::GetMany(vec![1, 2]))
+            .await;
+        assert!(
+            matches!(res, ProviderResponse::Many(v) if v == vec![(1, Some(10)), (2, Some(20))])
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_chain_fallback_on_error() {
+        #[derive(Clone)]
+        enum Level {
+            Map(MapProvider<'static, String, String>),
+            Failing,
+        }
+
+        impl Service<ProviderRequest<String, String>> for Level {
+            type Response = ProviderResponse<String, String>;
+            type Error = &'static str;
+            type Future = ProviderFuture<'static, String, String, &'static str>;
+
+            fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+                Poll::Ready(Ok(()))
+            }
+
+            fn call(&mut self, req: ProviderRequest<String, String>) -> Self::Future {
+                match self {
+                    Level::Map(map) => {
+                        let fut = map.call(req);
+                        Box::pin(async move { Ok(fut.await.unwrap_or_else(|e| match e {})) })
+                    }
+                    Level::Failing => Box::pin(std::future::ready(Err("unavailable"))),
+                }
+            }
+        }
+
+        let map = MapProvider::new::<String, String>();
+        let levels = vec![Level::Map(map.clone()), Level::Failing, Level::Map(map)];
+
+        // Without fallback, the error of the second level is returned
+        let mut provider = ChainProvider::new(levels.clone());
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await;
+        assert_eq!(res.as_ref().err().map(ChainError::level), Some(1));
+        assert_eq!(
+            res.err().unwrap().to_string(),
+            "L2 provider error: unavailable"
+        );
+
+        // With fallback, the failing level is skipped
+        let mut provider = ChainProvider::new(levels).fallback_on_error(true);
+        let request = ProviderRequest::Insert("a".to_string(), "A".to_string(), None);
+        let res = provider.call(request).await;
+        assert!(matches!(res, Ok(ProviderResponse::Found(v)) if v == "A"));
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await;
+        assert!(matches!(res, Ok(ProviderResponse::Found(v)) if v == "A"));
+
+        // An error is returned when every level fails
+        let mut provider = ChainProvider::new(vec![Level::Failing]).fallback_on_error(true);
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await;
+        assert!(res.is_err_and(|e| e.into_inner() == "unavailable"));
+    }
+}
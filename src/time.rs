@@ -0,0 +1,38 @@
+//! Current time for expiration checks
+//!
+//! Expiration times are read from the Tokio clock, which is the same as the
+//! system clock unless it is paused, as with
+//! `#[tokio::test(start_paused = true)]`. Tests can then advance time instead
+//! of sleeping.
+
+use std::time::{Instant, SystemTime};
+
+/// Return the current instant of the Tokio clock
+pub(crate) fn now() -> Instant {
+    tokio::time::Instant::now().into_std()
+}
+
+/// Return the current wall-clock time, for expiration times that are stored
+/// outside of the process
+///
+/// In tests, this follows the Tokio clock from a fixed starting point, so that
+/// it moves forward when the clock is advanced.
+#[cfg(not(test))]
+#[cfg_attr(not(any(feature = "http", feature = "sled")), allow(dead_code))]
+pub(crate) fn system_now() -> SystemTime {
+    SystemTime::now()
+}
+
+#[cfg(test)]
+#[cfg_attr(not(any(feature = "http", feature = "sled")), allow(dead_code))]
+pub(crate) fn system_now() -> SystemTime {
+    use std::sync::OnceLock;
+
+    static START: OnceLock<(Instant, SystemTime)> = OnceLock::new();
+    let (start, system_start) = *START.get_or_init(|| (Instant::now(), SystemTime::now()));
+    let now = now();
+    match now.checked_duration_since(start) {
+        Some(elapsed) => system_start + elapsed,
+        None => system_start - start.duration_since(now),
+    }
+}
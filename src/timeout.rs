@@ -0,0 +1,238 @@
+//! # Timeout for cache providers
+//!
+//! [`TimeoutProvider`] wraps another cache provider and fails every call
+//! that takes longer than a given duration with [`TimeoutError::Elapsed`].
+//! This prevents a stalled remote cache from blocking requests.
+//!
+//! The timeout applies to each call to the provider, not to the overall
+//! request: a lookup and the insertion that follows a cache miss each get the
+//! full duration, and the time spent in the inner service doesn't count.
+//!
+//! Combined with [`crate::CacheLayer::fallback_on_provider_error`], a lookup
+//! that times out is treated as a cache miss, and an insertion that times out
+//! is ignored.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use std::{convert::Infallible, time::Duration};
+//! use tower::{Service, ServiceBuilder, service_fn};
+//! use tower_cache::{
+//!     CacheLayer,
+//!     map::MapProvider,
+//!     timeout::TimeoutProvider,
+//! };
+//! async fn handler(req: String) -> Result<String, Infallible> {
+//!     Ok(req.to_uppercase())
+//! }
+//!
+//! // Initialize the cache provider service
+//! let provider = TimeoutProvider::new(
+//!     MapProvider::new::<String, String>(),
+//!     Duration::from_millis(50),
+//! );
+//!
+//! // Wrap the service with CacheLayer.
+//! let mut my_service = ServiceBuilder::new()
+//!     .layer(CacheLayer::new(provider).fallback_on_provider_error(true))
+//!     .service(service_fn(handler));
+//!
+//! # tokio_test::block_on(async move {
+//! // Call the service
+//! let res = my_service.call("Hello".to_string()).await.unwrap();
+//! assert_eq!(res, "HELLO".to_string());
+//! # })
+//! ```
+//!
+
+use crate::{ProviderRequest, ProviderResponse};
+use std::{
+    error, fmt,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower::Service;
+
+/// Cache provider failing calls to another provider after a timeout
+#[derive(Clone, Debug)]
+pub struct TimeoutProvider<'a, P> {
+    inner: P,
+    timeout: Duration,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a, P> TimeoutProvider<'a, P> {
+    /// Create a new cache provider with a timeout for each call to `inner`
+    pub fn new(inner: P, timeout: Duration) -> Self {
+        TimeoutProvider {
+            inner,
+            timeout,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, P, K, V> Service<ProviderRequest<K, V>> for TimeoutProvider<'a, P>
+where
+    P: Service<ProviderRequest<K, V>, Response = ProviderResponse<K, V>>,
+    P::Future: Send + 'a,
+    P::Error: 'a,
+{
+    type Response = ProviderResponse<K, V>;
+    type Error = TimeoutError<P::Error>;
+    type Future = ProviderFuture<'a, K, V, P::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(TimeoutError::Provider)
+    }
+
+    fn call(&mut self, request: ProviderRequest<K, V>) -> Self::Future {
+        let fut = self.inner.call(request);
+        let timeout = self.timeout;
+
+        Box::pin(async move {
+            match tokio::time::timeout(timeout, fut).await {
+                Ok(res) => res.map_err(TimeoutError::Provider),
+                Err(_) => Err(TimeoutError::Elapsed),
+            }
+        })
+    }
+}
+
+type ProviderFuture<'a, K, V, E> =
+    Pin<Box<dyn Future<Output = Result<ProviderResponse<K, V>, TimeoutError<E>>> + Send + 'a>>;
+
+/// Error returned by a [`TimeoutProvider`]
+#[derive(Debug)]
+pub enum TimeoutError<E> {
+    /// The inner provider returned an error
+    Provider(E),
+    /// The inner provider didn't respond in time
+    Elapsed,
+}
+
+impl<E> error::Error for TimeoutError<E>
+where
+    E: error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            TimeoutError::Provider(e) => Some(e),
+            TimeoutError::Elapsed => None,
+        }
+    }
+}
+
+impl<E> fmt::Display for TimeoutError<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimeoutError::Provider(e) => write!(f, "provider error: {}", e),
+            TimeoutError::Elapsed => write!(f, "provider timed out"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{map::MapProvider, CacheError, CacheLayer};
+    use std::{
+        convert::Infallible,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+    use tower::{service_fn, ServiceBuilder};
+
+    /// Provider that never answers in time
+    #[derive(Clone)]
+    struct SlowProvider;
+
+    impl Service<ProviderRequest<String, String>> for SlowProvider {
+        type Response = ProviderResponse<String, String>;
+        type Error = Infallible;
+        type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _req: ProviderRequest<String, String>) -> Self::Future {
+            Box::pin(async {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                Ok(ProviderResponse::NotFound)
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timeout_fires() {
+        let mut provider = TimeoutProvider::new(SlowProvider, Duration::from_millis(10));
+
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await;
+        assert!(matches!(res, Err(TimeoutError::Elapsed)));
+    }
+
+    #[tokio::test]
+    async fn test_fast_provider() -> Result<(), TimeoutError<Infallible>> {
+        let mut provider = TimeoutProvider::new(
+            MapProvider::new::<String, String>(),
+            Duration::from_millis(100),
+        );
+
+        provider
+            .call(ProviderRequest::Insert(
+                "a".to_string(),
+                "A".to_string(),
+                None,
+            ))
+            .await?;
+        let res = provider.call(ProviderRequest::Get("a".to_string())).await?;
+        assert!(matches!(res, ProviderResponse::Found(v) if v == "A"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_layer_error() {
+        let provider = TimeoutProvider::new(SlowProvider, Duration::from_millis(10));
+        let mut service = ServiceBuilder::new()
+            .layer(CacheLayer::new(provider))
+            .service(service_fn(|req: String| async move {
+                Ok::<_, Infallible>(req.to_uppercase())
+            }));
+
+        let res = service.call("hello".to_string()).await;
+        assert!(matches!(
+            res,
+            Err(CacheError::ProviderError(TimeoutError::Elapsed))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_layer_fallback() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let provider = TimeoutProvider::new(SlowProvider, Duration::from_millis(10));
+        let mut service = ServiceBuilder::new()
+            .layer(CacheLayer::new(provider).fallback_on_provider_error(true))
+            .service(service_fn({
+                let calls = calls.clone();
+                move |req: String| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    async move { Ok::<_, Infallible>(req.to_uppercase()) }
+                }
+            }));
+
+        // The lookup times out and is treated as a miss
+        let res = service.call("hello".to_string()).await.unwrap();
+        assert_eq!(res, "HELLO");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
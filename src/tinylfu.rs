@@ -0,0 +1,552 @@
+//! # TinyLFU cache provider
+//!
+//! This is an implementation of a cache provider for [`crate::CacheLayer`]
+//! that evicts the least-recently-used entry when it is full, but only if the
+//! incoming key is requested more often than that entry. This keeps a key
+//! that is requested only once from evicting one that is requested often,
+//! which improves the hit ratio over plain LRU on skewed workloads.
+//!
+//! How often keys are requested is estimated with a count-min sketch, which
+//! also tracks keys that are not in the cache. Its memory is bounded by the
+//! capacity of the provider, and counts are halved periodically so that keys
+//! that used to be popular don't stay in the cache forever.
+//!
+//! Replacing an existing entry is always allowed.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use std::convert::Infallible;
+//! use tower::{Service, ServiceBuilder, service_fn};
+//! use tower_cache::{
+//!     CacheLayer,
+//!     tinylfu::TinyLfuProvider,
+//! };
+//! async fn handler(req: String) -> Result<String, Infallible> {
+//!     Ok(req.to_uppercase())
+//! }
+//!
+//! // Initialize the cache provider service
+//! let tinylfu_provider = TinyLfuProvider::new::<String, String>(20);
+//!
+//! // Wrap the service with CacheLayer.
+//! let mut my_service = ServiceBuilder::new()
+//!     .layer(CacheLayer::new(tinylfu_provider))
+//!     .service(service_fn(handler));
+//!
+//! # tokio_test::block_on(async move {
+//! // Call the service
+//! let res = my_service.call("Hello".to_string()).await.unwrap();
+//! assert_eq!(res, "HELLO".to_string());
+//! # })
+//! ```
+//!
+
+use crate::{
+    entry::Entry, time, trace, Configure, ProviderConfig, ProviderRequest, ProviderResponse,
+};
+use std::{
+    collections::{hash_map::RandomState, BTreeMap, HashMap},
+    convert::Infallible,
+    future::{ready, Future},
+    hash::{BuildHasher, Hash},
+    marker::PhantomData,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tower::Service;
+
+/// Local TinyLFU cache provider
+#[derive(Debug)]
+pub struct TinyLfuProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    inner: Arc<Mutex<TinyLfu<K, V>>>,
+    ttl: Option<Duration>,
+    stale_window: Option<Duration>,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> TinyLfuProvider<'a, (), ()> {
+    /// Create a new TinyLFU cache provider with the desired capacity
+    ///
+    /// A capacity of `0` is clamped to `1`.
+    pub fn new<K, V>(capacity: usize) -> TinyLfuProvider<'a, K, V>
+    where
+        K: Eq + Hash,
+    {
+        TinyLfuProvider {
+            inner: Arc::new(Mutex::new(TinyLfu::new(capacity.max(1)))),
+            ttl: None,
+            stale_window: None,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Create a new TinyLFU cache provider where entries expire after `ttl`
+    ///
+    /// As with [`TinyLfuProvider::new`], a capacity of `0` is clamped to `1`.
+    pub fn with_ttl<K, V>(capacity: usize, ttl: Duration) -> TinyLfuProvider<'a, K, V>
+    where
+        K: Eq + Hash,
+    {
+        TinyLfuProvider {
+            ttl: Some(ttl),
+            ..Self::new(capacity)
+        }
+    }
+}
+
+impl<'a, K, V> TinyLfuProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    /// Keep entries for `window` after they expire, and return them as
+    /// [`ProviderResponse::FoundStale`] during that time.
+    ///
+    /// This allows [`crate::CacheLayer::stale_while_revalidate`] to serve
+    /// them while they are refreshed. Entries without a TTL never become
+    /// stale.
+    pub fn stale_window(mut self, window: Duration) -> Self {
+        self.stale_window = Some(window);
+        self
+    }
+}
+
+// Custom implementation of Clone as the Clone derive doesn't mark
+// TinyLfuProvider as Clone if K or V is not clone.
+impl<'a, K, V> Clone for TinyLfuProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            ttl: self.ttl,
+            stale_window: self.stale_window,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+/// Sets the TTL. Setting the capacity clears the cache, for all clones of the
+/// provider, so it should be done before using it.
+impl<'a, K, V> Configure for TinyLfuProvider<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    fn configure(mut self, config: &ProviderConfig) -> Self {
+        if let Some(ttl) = config.ttl {
+            self.ttl = Some(ttl);
+        }
+        if let Some(capacity) = config.capacity {
+            // Caches hold at least one entry.
+            if capacity == 0 {
+                trace::unsupported_config::<Self>("capacity");
+            }
+            *self.inner.lock().unwrap() = TinyLfu::new(capacity.max(1));
+        }
+        self
+    }
+}
+
+impl<'a, K, V> Service<ProviderRequest<K, V>> for TinyLfuProvider<'a, K, V>
+where
+    K: Eq + Hash + Clone + Send + 'a,
+    V: Clone + Send + 'a,
+{
+    type Response = ProviderResponse<K, V>;
+    type Error = Infallible;
+    type Future = ProviderFuture<'a, K, V>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ProviderRequest<K, V>) -> Self::Future {
+        let mut inner = self.inner.lock().unwrap();
+        Box::pin(ready(Ok(match request {
+            ProviderRequest::Get(key) => {
+                let now = time::now();
+                match inner.get(&key).map(|entry| entry.response_at(now)) {
+                    Some(Some(response)) => response,
+                    // The entry has expired: remove it so it doesn't take up
+                    // capacity anymore.
+                    Some(None) => {
+                        inner.remove(&key);
+                        ProviderResponse::NotFound
+                    }
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            ProviderRequest::Insert(key, value, ttl) => {
+                let entry =
+                    Entry::new(value.clone(), ttl.or(self.ttl)).stale_for(self.stale_window);
+                inner.insert(key, entry, time::now());
+                ProviderResponse::Found(value)
+            }
+            ProviderRequest::InsertNegative(key, ttl) => {
+                inner.insert(key, Entry::negative(ttl), time::now());
+                ProviderResponse::FoundNegative
+            }
+            ProviderRequest::Clear => {
+                inner.clear();
+                ProviderResponse::Cleared
+            }
+            ProviderRequest::Remove(key) => match inner.remove(&key) {
+                Some(_) => ProviderResponse::Removed,
+                None => ProviderResponse::NotFound,
+            },
+            // Peek at the entry to avoid counting it as an access.
+            ProviderRequest::Contains(key) => {
+                let now = time::now();
+                let present = inner.peek(&key).is_some_and(|entry| !entry.is_expired(now));
+                ProviderResponse::Present(present)
+            }
+            ProviderRequest::Ttl(key) => {
+                let now = time::now();
+                match inner.peek(&key).and_then(|entry| entry.ttl_at(now)) {
+                    Some((remaining, ttl)) => ProviderResponse::Ttl(remaining, ttl),
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            ProviderRequest::Age(key) => {
+                let now = time::now();
+                match inner.peek(&key).and_then(|entry| entry.age_at(now)) {
+                    Some(age) => ProviderResponse::Age(age),
+                    None => ProviderResponse::NotFound,
+                }
+            }
+            ProviderRequest::GetOrInsert(key, value) => {
+                let now = time::now();
+                match inner.get(&key).and_then(|entry| entry.fresh_value_at(now)) {
+                    Some(existing) => ProviderResponse::Found(existing.clone()),
+                    None => {
+                        let entry =
+                            Entry::new(value.clone(), self.ttl).stale_for(self.stale_window);
+                        inner.insert(key, entry, now);
+                        ProviderResponse::Inserted(value)
+                    }
+                }
+            }
+            ProviderRequest::GetMany(keys) => {
+                let now = time::now();
+                ProviderResponse::Many(
+                    keys.into_iter()
+                        .map(|key| {
+                            let value = inner
+                                .get(&key)
+                                .and_then(|entry| entry.value_at(now))
+                                .cloned();
+                            (key, value)
+                        })
+                        .collect(),
+                )
+            }
+            // Inserting entries one by one is as cheap as a batch in memory.
+            ProviderRequest::InsertMany(_) => ProviderResponse::NotFound,
+            // Evictions are not counted.
+            ProviderRequest::Stats => ProviderResponse::NotFound,
+        })))
+    }
+}
+
+type ProviderFuture<'a, K, V> =
+    Pin<Box<dyn Future<Output = Result<ProviderResponse<K, V>, Infallible>> + Send + 'a>>;
+
+/// Entries of a [`TinyLfuProvider`], indexed by last access
+#[derive(Debug)]
+struct TinyLfu<K, V> {
+    entries: HashMap<K, (Entry<V>, u64)>,
+    order: BTreeMap<u64, K>,
+    sketch: Sketch,
+    capacity: usize,
+    tick: u64,
+}
+
+impl<K, V> TinyLfu<K, V>
+where
+    K: Eq + Hash,
+{
+    fn new(capacity: usize) -> Self {
+        TinyLfu {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            sketch: Sketch::new(capacity),
+            capacity,
+            tick: 0,
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    /// Return the entry for `key`, counting it as an access
+    ///
+    /// Keys that are not in the cache are counted too, so that they can be
+    /// admitted once they are requested often enough.
+    fn get(&mut self, key: &K) -> Option<&Entry<V>> {
+        self.sketch.increment(key);
+        let tick = self.next_tick();
+        let (entry, last) = self.entries.get_mut(key)?;
+        let k = self.order.remove(last).expect("every entry is indexed");
+        *last = tick;
+        self.order.insert(tick, k);
+        Some(entry)
+    }
+
+    /// Return the entry for `key` without counting it as an access
+    fn peek(&self, key: &K) -> Option<&Entry<V>> {
+        self.entries.get(key).map(|(entry, _)| entry)
+    }
+
+    /// Store `entry` if there is room for it, or if `key` is accessed more
+    /// often than the least-recently-used entry, which is then evicted
+    ///
+    /// Expired entries are always evicted to make room. Returns whether the
+    /// entry was stored.
+    fn insert(&mut self, key: K, entry: Entry<V>, now: Instant) -> bool
+    where
+        K: Clone,
+    {
+        self.sketch.increment(&key);
+        let tick = self.next_tick();
+        if let Some((old, last)) = self.entries.get_mut(&key) {
+            *old = entry;
+            let k = self.order.remove(last).expect("every entry is indexed");
+            *last = tick;
+            self.order.insert(tick, k);
+            return true;
+        }
+        if self.entries.len() >= self.capacity {
+            let Some((_, victim)) = self.order.first_key_value() else {
+                return false;
+            };
+            let expired = self.entries[victim].0.is_expired(now);
+            if !expired && self.sketch.frequency(&key) <= self.sketch.frequency(victim) {
+                return false;
+            }
+            if let Some((_, victim)) = self.order.pop_first() {
+                self.entries.remove(&victim);
+            }
+        }
+        self.order.insert(tick, key.clone());
+        self.entries.insert(key, (entry, tick));
+        true
+    }
+
+    fn remove(&mut self, key: &K) -> Option<Entry<V>> {
+        let (entry, tick) = self.entries.remove(key)?;
+        self.order.remove(&tick);
+        Some(entry)
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+/// Number of rows of a [`Sketch`]
+const ROWS: usize = 4;
+
+/// Highest count a [`Sketch`] counter can reach
+const MAX_COUNT: u8 = 15;
+
+/// Count-min sketch estimating how often keys are accessed
+///
+/// Each key maps to one counter per row, and its frequency is the lowest of
+/// them, so that collisions can only overestimate it. Counters saturate at
+/// [`MAX_COUNT`], and are all halved after ten increments per counter in a
+/// row.
+#[derive(Debug)]
+struct Sketch {
+    counters: Vec<u8>,
+    mask: usize,
+    hasher: RandomState,
+    additions: usize,
+    sample_size: usize,
+}
+
+impl Sketch {
+    fn new(capacity: usize) -> Self {
+        let width = capacity.next_power_of_two().max(16);
+        Sketch {
+            counters: vec![0; ROWS * width],
+            mask: width - 1,
+            hasher: RandomState::new(),
+            additions: 0,
+            sample_size: width.saturating_mul(10),
+        }
+    }
+
+    /// Return the index of the counter for `key` in each row
+    fn indices<K: Hash>(&self, key: &K) -> [usize; ROWS] {
+        let hash = self.hasher.hash_one(key);
+        let width = self.mask + 1;
+        std::array::from_fn(|row| {
+            // Derive a different hash for each row from the same one.
+            let mut h = hash.wrapping_add((row as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+            h = (h ^ (h >> 32)).wrapping_mul(0xd6e8_feb8_6659_fd93);
+            h ^= h >> 32;
+            row * width + (h as usize & self.mask)
+        })
+    }
+
+    fn frequency<K: Hash>(&self, key: &K) -> u8 {
+        self.indices(key)
+            .into_iter()
+            .map(|index| self.counters[index])
+            .min()
+            .unwrap_or(0)
+    }
+
+    fn increment<K: Hash>(&mut self, key: &K) {
+        for index in self.indices(key) {
+            if self.counters[index] < MAX_COUNT {
+                self.counters[index] += 1;
+            }
+        }
+        self.additions += 1;
+        if self.additions >= self.sample_size {
+            self.age();
+        }
+    }
+
+    /// Halve all counters, so that old accesses weigh less than recent ones
+    fn age(&mut self) {
+        for counter in &mut self.counters {
+            *counter /= 2;
+        }
+        self.additions /= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::{check_configure, check_provider};
+
+    #[tokio::test]
+    async fn test_conformance() -> Result<(), Infallible> {
+        check_provider(TinyLfuProvider::new::<String, String>(10)).await
+    }
+
+    #[tokio::test]
+    async fn test_rejects_one_hit_wonder() -> Result<(), Infallible> {
+        let mut provider = TinyLfuProvider::new::<usize, usize>(2);
+
+        provider.call(ProviderRequest::Insert(1, 1, None)).await?;
+        provider.call(ProviderRequest::Insert(2, 2, None)).await?;
+        for _ in 0..5 {
+            provider.call(ProviderRequest::Get(1)).await?;
+            provider.call(ProviderRequest::Get(2)).await?;
+        }
+
+        // 3 is only requested once, so it doesn't replace 1, even though 1
+        // is the least recently used entry.
+        provider.call(ProviderRequest::Get(3)).await?;
+        provider.call(ProviderRequest::Insert(3, 3, None)).await?;
+        let res = provider.call(ProviderRequest::Contains(3)).await?;
+        assert!(matches!(res, ProviderResponse::Present(false)));
+        let res = provider.call(ProviderRequest::Get(1)).await?;
+        assert!(matches!(res, ProviderResponse::Found(1)));
+        let res = provider.call(ProviderRequest::Get(2)).await?;
+        assert!(matches!(res, ProviderResponse::Found(2)));
+
+        // Once 4 is requested more often than the least recently used entry,
+        // it is admitted in its place.
+        for _ in 0..10 {
+            provider.call(ProviderRequest::Get(4)).await?;
+        }
+        provider.call(ProviderRequest::Insert(4, 4, None)).await?;
+        let res = provider.call(ProviderRequest::Get(4)).await?;
+        assert!(matches!(res, ProviderResponse::Found(4)));
+        let res = provider.call(ProviderRequest::Contains(1)).await?;
+        assert!(matches!(res, ProviderResponse::Present(false)));
+        let res = provider.call(ProviderRequest::Contains(2)).await?;
+        assert!(matches!(res, ProviderResponse::Present(true)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expired_victim() -> Result<(), Infallible> {
+        let mut provider = TinyLfuProvider::new::<usize, usize>(1);
+
+        provider
+            .call(ProviderRequest::Insert(1, 1, Some(Duration::ZERO)))
+            .await?;
+        for _ in 0..5 {
+            provider.call(ProviderRequest::Get(1)).await?;
+        }
+        provider.call(ProviderRequest::Insert(2, 2, None)).await?;
+        let res = provider.call(ProviderRequest::Get(2)).await?;
+        assert!(matches!(res, ProviderResponse::Found(2)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sketch() {
+        let mut sketch = Sketch::new(100);
+        let size = sketch.counters.len();
+
+        for _ in 0..20 {
+            sketch.increment(&"hot");
+        }
+        assert_eq!(sketch.frequency(&"hot"), MAX_COUNT);
+        assert!(sketch.frequency(&"cold") < MAX_COUNT);
+
+        // Counts are halved periodically, and memory doesn't grow with the
+        // number of keys.
+        let mut key = 0;
+        while sketch.additions < sketch.sample_size - 1 {
+            sketch.increment(&key);
+            key += 1;
+        }
+        sketch.increment(&key);
+        assert_eq!(sketch.additions, sketch.sample_size / 2);
+        assert!(sketch.frequency(&"hot") <= MAX_COUNT / 2);
+        assert_eq!(sketch.counters.len(), size);
+    }
+
+    #[tokio::test]
+    async fn test_remove_clear() -> Result<(), Infallible> {
+        let mut provider = TinyLfuProvider::with_ttl::<usize, usize>(10, Duration::ZERO);
+
+        provider.call(ProviderRequest::Insert(1, 1, None)).await?;
+        let res = provider.call(ProviderRequest::Get(1)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        assert!(provider.inner.lock().unwrap().order.is_empty());
+
+        provider
+            .call(ProviderRequest::Insert(2, 2, Some(Duration::from_secs(10))))
+            .await?;
+        let res = provider.call(ProviderRequest::Remove(2)).await?;
+        assert!(matches!(res, ProviderResponse::Removed));
+
+        provider
+            .call(ProviderRequest::Insert(3, 3, Some(Duration::from_secs(10))))
+            .await?;
+        let res = provider.call(ProviderRequest::Clear).await?;
+        assert!(matches!(res, ProviderResponse::Cleared));
+        let res = provider.call(ProviderRequest::Get(3)).await?;
+        assert!(matches!(res, ProviderResponse::NotFound));
+        assert!(provider.inner.lock().unwrap().entries.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_configure() -> Result<(), Infallible> {
+        let configure =
+            |config: &ProviderConfig| TinyLfuProvider::new::<usize, usize>(10).configure(config);
+        check_configure(configure, true).await
+    }
+}
@@ -0,0 +1,50 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Handle to turn caching on and off for the services created by a
+/// [`crate::CacheLayer`]
+///
+/// While caching is disabled, requests bypass the cache provider and go
+/// directly to the inner service. Entries already in the provider are kept,
+/// and are served again once caching is enabled.
+#[derive(Clone, Debug)]
+pub struct CacheToggle {
+    enabled: Arc<AtomicBool>,
+}
+
+impl Default for CacheToggle {
+    fn default() -> Self {
+        Self {
+            enabled: Arc::new(AtomicBool::new(true)),
+        }
+    }
+}
+
+impl CacheToggle {
+    /// Turn caching on or off
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Return `true` if caching is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared() {
+        let toggle = CacheToggle::default();
+        let clone = toggle.clone();
+        assert!(clone.is_enabled());
+
+        toggle.set_enabled(false);
+        assert!(!clone.is_enabled());
+    }
+}
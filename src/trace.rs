@@ -0,0 +1,384 @@
+//! Optional `tracing` instrumentation
+//!
+//! Without the `tracing` feature, all of these helpers compile down to
+//! nothing.
+//!
+//! Calls to the cache provider get their own `cache.provider` span, with
+//! `cache.operation`, `cache.provider` and, for lookups, `cache.hit` fields.
+//! These spans set `otel.kind`, so that they are exported as client spans by
+//! `tracing-opentelemetry`.
+
+use crate::{ProviderRequest, ProviderResponse};
+use std::future::Future;
+
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+#[cfg(feature = "tracing")]
+use tracing::{field, Instrument};
+
+#[cfg(feature = "tracing")]
+pub(crate) type Span = tracing::Span;
+
+#[cfg(not(feature = "tracing"))]
+#[derive(Clone)]
+pub(crate) struct Span;
+
+/// Create the span wrapping a single request to the cache service
+pub(crate) fn request_span() -> Span {
+    #[cfg(feature = "tracing")]
+    return tracing::debug_span!(
+        "cache",
+        cache.hit = field::Empty,
+        cache.provider_duration_us = field::Empty,
+    );
+    #[cfg(not(feature = "tracing"))]
+    Span
+}
+
+/// Return the current span
+///
+/// This is used to carry the span over to code running on another thread.
+#[cfg_attr(not(feature = "memcached"), allow(dead_code))]
+pub(crate) fn current() -> Span {
+    #[cfg(feature = "tracing")]
+    return Span::current();
+    #[cfg(not(feature = "tracing"))]
+    Span
+}
+
+/// Run `f` inside the span
+pub(crate) fn in_span<T>(span: &Span, f: impl FnOnce() -> T) -> T {
+    #[cfg(feature = "tracing")]
+    return span.in_scope(f);
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = span;
+        f()
+    }
+}
+
+/// Call the cache provider inside a `cache.provider` span
+pub(crate) fn call_provider<P, K, V>(
+    provider: &mut P,
+    request: ProviderRequest<K, V>,
+) -> impl Future<Output = Result<ProviderResponse<K, V>, P::Error>>
+where
+    P: tower::Service<ProviderRequest<K, V>, Response = ProviderResponse<K, V>>,
+{
+    let lookup = matches!(request, ProviderRequest::Get(_));
+    let span = provider_span(&request, provider_name::<P>());
+    let fut = in_span(&span, || provider.call(request));
+    instrument(
+        async move {
+            let response = fut.await;
+            #[cfg(feature = "tracing")]
+            if let (true, Ok(response)) = (lookup, &response) {
+                let hit = matches!(
+                    response,
+                    ProviderResponse::Found(_)
+                        | ProviderResponse::FoundStale(_)
+                        | ProviderResponse::FoundNegative
+                );
+                Span::current().record("cache.hit", hit);
+            }
+            #[cfg(not(feature = "tracing"))]
+            let _ = lookup;
+            response
+        },
+        span,
+    )
+}
+
+/// Create the span wrapping a single call to the cache provider
+fn provider_span<K, V>(request: &ProviderRequest<K, V>, provider: &'static str) -> Span {
+    #[cfg(feature = "tracing")]
+    return tracing::debug_span!(
+        "cache.provider",
+        otel.kind = "client",
+        cache.operation = operation(request),
+        cache.provider = provider,
+        cache.hit = field::Empty,
+    );
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = (request, provider);
+        Span
+    }
+}
+
+/// Name of the operation performed by `request`
+#[cfg(feature = "tracing")]
+fn operation<K, V>(request: &ProviderRequest<K, V>) -> &'static str {
+    match request {
+        ProviderRequest::Get(_) => "get",
+        ProviderRequest::Insert(_, _, _) => "insert",
+        ProviderRequest::InsertNegative(_, _) => "insert_negative",
+        ProviderRequest::Clear => "clear",
+        ProviderRequest::Remove(_) => "remove",
+        ProviderRequest::Contains(_) => "contains",
+        ProviderRequest::Ttl(_) => "ttl",
+        ProviderRequest::GetOrInsert(_, _) => "get_or_insert",
+        ProviderRequest::GetMany(_) => "get_many",
+        ProviderRequest::InsertMany(_) => "insert_many",
+        ProviderRequest::Stats => "stats",
+        ProviderRequest::Age(_) => "age",
+    }
+}
+
+/// Name of the provider type, without its module path or generics
+fn provider_name<P>() -> &'static str {
+    let name = std::any::type_name::<P>();
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// Run the future inside the span
+pub(crate) fn instrument<F: Future>(fut: F, span: Span) -> impl Future<Output = F::Output> {
+    #[cfg(feature = "tracing")]
+    return fut.instrument(span);
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = span;
+        fut
+    }
+}
+
+/// Measure the duration of a provider call
+pub(crate) struct Timer {
+    #[cfg(feature = "tracing")]
+    start: Instant,
+}
+
+impl Timer {
+    pub(crate) fn start() -> Self {
+        Timer {
+            #[cfg(feature = "tracing")]
+            start: Instant::now(),
+        }
+    }
+
+    /// Record the elapsed time on the current span
+    pub(crate) fn record(self) {
+        #[cfg(feature = "tracing")]
+        Span::current().record(
+            "cache.provider_duration_us",
+            self.start.elapsed().as_micros() as u64,
+        );
+    }
+}
+
+pub(crate) fn hit() {
+    #[cfg(feature = "tracing")]
+    {
+        Span::current().record("cache.hit", true);
+        tracing::debug!("cache.hit");
+    }
+}
+
+pub(crate) fn miss() {
+    #[cfg(feature = "tracing")]
+    {
+        Span::current().record("cache.hit", false);
+        tracing::debug!("cache.miss");
+    }
+}
+
+/// A stale entry was served while it is refreshed in the background
+pub(crate) fn stale() {
+    #[cfg(feature = "tracing")]
+    {
+        Span::current().record("cache.hit", true);
+        tracing::debug!("cache.stale");
+    }
+}
+
+pub(crate) fn bypass() {
+    #[cfg(feature = "tracing")]
+    tracing::debug!("cache.bypass");
+}
+
+pub(crate) fn force_refresh() {
+    #[cfg(feature = "tracing")]
+    tracing::debug!("cache.force_refresh");
+}
+
+pub(crate) fn insert() {
+    #[cfg(feature = "tracing")]
+    tracing::debug!("cache.insert");
+}
+
+/// The provider returned an error, but the service falls back to the inner
+/// service
+pub(crate) fn provider_fallback() {
+    #[cfg(feature = "tracing")]
+    tracing::warn!("cache provider error, falling back to the inner service");
+}
+
+/// Writing a value to an earlier level of a tiered provider failed, but the
+/// value is still returned
+pub(crate) fn backfill_failed() {
+    #[cfg(feature = "tracing")]
+    tracing::warn!("cache back-fill failed, ignoring it");
+}
+
+/// The circuit breaker of a provider opened after repeated connection errors
+#[cfg_attr(not(feature = "redis"), allow(dead_code))]
+pub(crate) fn circuit_opened() {
+    #[cfg(feature = "tracing")]
+    tracing::warn!("cache provider unreachable, skipping it until the cooldown is over");
+}
+
+/// A background sweep removed `removed` expired entries
+#[cfg_attr(not(feature = "lru"), allow(dead_code))]
+pub(crate) fn swept(removed: usize) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(cache.removed = removed, "cache.sweep");
+    #[cfg(not(feature = "tracing"))]
+    let _ = removed;
+}
+
+/// The output of a transform couldn't be converted into a cache key, so the
+/// request bypasses the cache
+pub(crate) fn key_conversion_error(error: &crate::KeyConversionError) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(cache.error = %error, "cache key conversion failed, bypassing the cache");
+    #[cfg(not(feature = "tracing"))]
+    let _ = error;
+}
+
+/// A provider ignored a field of a [`crate::ProviderConfig`] it can't honor
+pub(crate) fn unsupported_config<P>(field: &'static str) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        cache.provider = provider_name::<P>(),
+        cache.config = field,
+        "unsupported provider configuration, ignoring it"
+    );
+    #[cfg(not(feature = "tracing"))]
+    let _ = (field, std::marker::PhantomData::<P>);
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use super::*;
+    use crate::{map::MapProvider, CacheLayer};
+    use std::{
+        collections::HashMap,
+        convert::Infallible,
+        fmt,
+        sync::{Arc, Mutex},
+    };
+    use tower::{service_fn, Service, ServiceBuilder, ServiceExt};
+    use tracing::{
+        field::{Field, Visit},
+        span, Subscriber,
+    };
+    use tracing_subscriber::{
+        layer::{Context, SubscriberExt},
+        registry::LookupSpan,
+        Layer, Registry,
+    };
+
+    type SpanFields = HashMap<String, String>;
+
+    /// Layer recording the fields of `cache.provider` spans, in creation
+    /// order
+    #[derive(Clone, Default)]
+    struct Spans(Arc<Mutex<Vec<(span::Id, SpanFields)>>>);
+
+    struct Fields<'a>(&'a mut SpanFields);
+
+    impl Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+    }
+
+    impl<S> Layer<S> for Spans
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, _ctx: Context<'_, S>) {
+            if attrs.metadata().name() != "cache.provider" {
+                return;
+            }
+            let mut fields = HashMap::new();
+            attrs.record(&mut Fields(&mut fields));
+            self.0.lock().unwrap().push((id.clone(), fields));
+        }
+
+        fn on_record(&self, id: &span::Id, values: &span::Record<'_>, _ctx: Context<'_, S>) {
+            // Span IDs are reused once spans are closed.
+            let mut spans = self.0.lock().unwrap();
+            if let Some((_, fields)) = spans.iter_mut().rev().find(|(span, _)| span == id) {
+                values.record(&mut Fields(fields));
+            }
+        }
+    }
+
+    #[test]
+    fn test_provider_spans() {
+        let spans = Spans::default();
+        let subscriber = Registry::default().with(spans.clone());
+
+        tracing::subscriber::with_default(subscriber, || {
+            tokio_test::block_on(async {
+                let mut service = ServiceBuilder::new()
+                    .layer(CacheLayer::new(MapProvider::new::<String, String>()))
+                    .service(service_fn(|req: String| async move {
+                        Ok::<_, Infallible>(req.to_uppercase())
+                    }));
+
+                for _ in 0..2 {
+                    service
+                        .ready()
+                        .await
+                        .unwrap()
+                        .call("a".to_string())
+                        .await
+                        .unwrap();
+                }
+            })
+        });
+
+        let spans: Vec<_> = spans
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, fields)| fields.clone())
+            .collect();
+        let field = |span: &SpanFields, name: &str| span.get(name).cloned();
+
+        assert_eq!(spans.len(), 3);
+        for span in &spans {
+            assert_eq!(field(span, "otel.kind").as_deref(), Some("client"));
+            assert_eq!(
+                field(span, "cache.provider").as_deref(),
+                Some("MapProvider")
+            );
+        }
+        let operations: Vec<_> = spans
+            .iter()
+            .map(|span| field(span, "cache.operation").unwrap())
+            .collect();
+        assert_eq!(operations, ["get", "insert", "get"]);
+        assert_eq!(field(&spans[0], "cache.hit").as_deref(), Some("false"));
+        assert_eq!(field(&spans[1], "cache.hit"), None);
+        assert_eq!(field(&spans[2], "cache.hit").as_deref(), Some("true"));
+    }
+
+    #[test]
+    fn test_provider_name() {
+        assert_eq!(
+            provider_name::<MapProvider<String, String>>(),
+            "MapProvider"
+        );
+        assert_eq!(provider_name::<()>(), "()");
+    }
+}
@@ -1,3 +1,167 @@
+use pin_project_lite::pin_project;
+use std::{
+    collections::hash_map::DefaultHasher,
+    error, fmt,
+    future::{ready, Future, Ready},
+    hash::{BuildHasher, BuildHasherDefault, Hash},
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// # Cache key trait
+///
+/// Keys sent to the cache provider need to be hashed and cloned, so the
+/// output of the transform must implement `Clone`, `Hash` and `Send`. This
+/// trait is implemented for all such types, and only exists to give a
+/// clearer error when a key doesn't.
+///
+/// With the default `()` transform, the request itself is the key, so a
+/// request that doesn't implement `Hash` fails to compile:
+///
+/// ```compile_fail
+/// use std::convert::Infallible;
+/// use tower::{Service, ServiceBuilder, service_fn};
+/// use tower_cache::{CacheLayer, noop::NoopProvider};
+///
+/// #[derive(Clone)]
+/// struct Search {
+///     query: String,
+///     min_score: f64,
+/// }
+///
+/// async fn handler(req: Search) -> Result<String, Infallible> {
+///     Ok(req.query)
+/// }
+///
+/// let mut my_service = ServiceBuilder::new()
+///     .layer(CacheLayer::new(NoopProvider::new::<Search, String>()))
+///     .service(service_fn(handler));
+///
+/// // error: `Search` cannot be used as a cache key
+/// let _ = Service::call(&mut my_service, Search { query: "a".to_string(), min_score: 0.5 });
+/// ```
+///
+/// Derive the key from a field of the request instead, for example with
+/// [`ByKey`] or [`CacheLayer::with_ref_transformer`](crate::CacheLayer::with_ref_transformer).
+///
+/// ```rust
+/// use std::convert::Infallible;
+/// use tower::{Service, ServiceBuilder, service_fn};
+/// use tower_cache::{ByKey, CacheLayer, Keyed, noop::NoopProvider};
+///
+/// struct Search {
+///     query: String,
+///     min_score: f64,
+/// }
+///
+/// impl Keyed for Search {
+///     type Key = String;
+///
+///     fn key(&self) -> String {
+///         self.query.clone()
+///     }
+/// }
+///
+/// async fn handler(req: Search) -> Result<String, Infallible> {
+///     Ok(req.query)
+/// }
+///
+/// let mut my_service = ServiceBuilder::new()
+///     .layer(CacheLayer::new(NoopProvider::new::<String, String>()).with_transformer(ByKey))
+///     .service(service_fn(handler));
+///
+/// # tokio_test::block_on(async move {
+/// let res = my_service.call(Search { query: "a".to_string(), min_score: 0.5 }).await;
+/// assert_eq!(res.unwrap(), "a");
+/// # })
+/// ```
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` cannot be used as a cache key",
+    label = "cache keys must implement `Clone`, `Hash` and `Send`",
+    note = "if `{Self}` is the request, derive a key from it with `CacheLayer::with_ref_transformer` or `ByKey`"
+)]
+pub trait CacheKey: Clone + Hash + Send {}
+
+impl<K> CacheKey for K where K: Clone + Hash + Send {}
+
+/// # Cache key conversion trait
+///
+/// The output of the transform is sent as-is to the cache provider, so it
+/// must have the same type as the keys of the provider. With
+/// [`CacheLayer::with_key_transformer`](crate::CacheLayer::with_key_transformer),
+/// the output is converted into the key type of the provider with this
+/// trait instead, and a mismatch is reported where the transformer is set:
+///
+/// ```compile_fail
+/// use tower_cache::{CacheLayer, lru::LruProvider};
+///
+/// // error: the transform output `String` cannot be used as a `u64` cache key
+/// let layer = CacheLayer::new(LruProvider::new::<u64, String>(10))
+///     .with_key_transformer(|req: String| req.to_lowercase());
+/// ```
+///
+/// Every type converts into itself. Implement this trait to convert other
+/// outputs, returning a [`KeyConversionError`] for outputs that have no
+/// matching key. Requests for which the conversion fails bypass the cache.
+///
+/// ```rust
+/// use tower_cache::{CacheLayer, IntoCacheKey, KeyConversionError, lru::LruProvider};
+///
+/// struct UserId(String);
+///
+/// impl IntoCacheKey<u64> for UserId {
+///     fn into_cache_key(self) -> Result<u64, KeyConversionError> {
+///         self.0.parse().map_err(KeyConversionError::new)
+///     }
+/// }
+///
+/// let layer = CacheLayer::new(LruProvider::new::<u64, String>(10))
+///     .with_key_transformer(|req: String| UserId(req));
+/// ```
+#[diagnostic::on_unimplemented(
+    message = "the transform output `{Self}` cannot be used as a `{K}` cache key",
+    label = "the cache provider expects `{K}` keys",
+    note = "return `{K}` from the transform, or implement `IntoCacheKey<{K}>` for `{Self}`"
+)]
+pub trait IntoCacheKey<K> {
+    /// Convert the output of a transform into a key of the cache provider.
+    fn into_cache_key(self) -> Result<K, KeyConversionError>;
+}
+
+impl<K> IntoCacheKey<K> for K {
+    fn into_cache_key(self) -> Result<K, KeyConversionError> {
+        Ok(self)
+    }
+}
+
+/// Error returned when the output of a transform cannot be converted into a
+/// cache key
+///
+/// See [`IntoCacheKey`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyConversionError {
+    message: String,
+}
+
+impl KeyConversionError {
+    /// Create a new error describing why the conversion failed
+    pub fn new(message: impl fmt::Display) -> Self {
+        Self {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl error::Error for KeyConversionError {}
+
+impl fmt::Display for KeyConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cannot convert into a cache key: {}", self.message)
+    }
+}
+
 /// # Request transformation trait
 ///
 /// In many cases, it's not useful to cache based on the entire request payload,
@@ -30,12 +194,69 @@
 /// assert_eq!(().transform(2), 2);
 /// ```
 ///
+/// Transformers can be chained with [`Transform::then`] or [`compose`]:
+///
+/// ```rust
+/// use tower_cache::Transform;
+///
+/// let t = (|req: usize| req * 2).then(|req: usize| req + 1);
+/// assert_eq!(t.transform(2), 5);
+/// ```
+///
+/// Transforms that need configuration, such as a salt or a tenant map, can
+/// implement this trait on a struct holding it, and be passed to
+/// [`CacheLayer::with_transform`](crate::CacheLayer::with_transform). The
+/// layer clones the transform into each service, so it must implement
+/// `Clone`; put large state behind an `Arc`.
+///
+/// ```rust
+/// use tower_cache::{CacheLayer, Transform, lru::LruProvider};
+///
+/// #[derive(Clone)]
+/// struct Prefixed {
+///     prefix: String,
+/// }
+///
+/// impl Transform<String> for Prefixed {
+///     type Output = String;
+///
+///     fn transform(&self, req: String) -> Self::Output {
+///         format!("{}:{}", self.prefix, req)
+///     }
+/// }
+///
+/// let transform = Prefixed { prefix: "v1".to_string() };
+/// assert_eq!(transform.transform("a".to_string()), "v1:a");
+///
+/// let layer = CacheLayer::new(LruProvider::new::<String, String>(10))
+///     .with_transform(transform);
+/// ```
+///
 pub trait Transform<R> {
     /// Output of the transformer
     type Output;
 
     /// Transform a key into a reference value for a cache provider.
     fn transform(&self, req: R) -> Self::Output;
+
+    /// Chain another transformer, taking the output of this one as input.
+    fn then<T2>(self, next: T2) -> Then<Self, T2>
+    where
+        Self: Sized,
+        T2: Transform<Self::Output>,
+    {
+        compose(self, next)
+    }
+
+    /// Hash the output of this transformer into a compact `u64` key.
+    ///
+    /// See [`HashTransform`].
+    fn hashed(self) -> HashTransform<Self>
+    where
+        Self: Sized,
+    {
+        HashTransform::new(self)
+    }
 }
 
 impl<R> Transform<R> for () {
@@ -57,6 +278,591 @@ where
     }
 }
 
+/// Create a transformer applying `first`, then `second` on its output.
+///
+/// ```rust
+/// use tower_cache::{compose, Transform};
+///
+/// let t = compose((), |req: usize| req * 2);
+/// assert_eq!(t.transform(2), 4);
+/// ```
+pub fn compose<A, B>(first: A, second: B) -> Then<A, B> {
+    Then { first, second }
+}
+
+/// Transformer chaining two transformers, created by [`Transform::then`] or
+/// [`compose`].
+#[derive(Clone, Copy, Debug)]
+pub struct Then<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B, R> Transform<R> for Then<A, B>
+where
+    A: Transform<R>,
+    B: Transform<A::Output>,
+{
+    type Output = B::Output;
+
+    fn transform(&self, req: R) -> Self::Output {
+        self.second.transform(self.first.transform(req))
+    }
+}
+
+/// Transformer hashing the output of another transformer into a `u64` key
+///
+/// Large keys, such as full URLs or request payloads, take as much memory in
+/// the cache provider as the values themselves. Storing their hash instead
+/// bounds the size of keys, at the cost of a negligible risk of collisions,
+/// where two requests would get the same response.
+///
+/// Keys are hashed with `S`, which defaults to the hasher of the standard
+/// library. Its output is stable within a process, but not guaranteed across
+/// Rust releases: for providers shared between processes, such as Redis, use
+/// [`HashTransform::with_hasher`] with a hasher designed for stable output,
+/// such as xxHash.
+///
+/// ```rust
+/// use tower_cache::{CacheLayer, HashTransform, Transform, lru::LruProvider};
+///
+/// #[derive(Clone)]
+/// struct Request {
+///     url: String,
+/// }
+///
+/// let transform = (|req: Request| req.url).hashed();
+/// let key = transform.transform(Request { url: "https://example.com/".to_string() });
+/// assert_eq!(key, HashTransform::new(()).transform("https://example.com/"));
+///
+/// let layer = CacheLayer::new(LruProvider::new::<u64, String>(10)).with_transform(transform);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HashTransform<T, S = BuildHasherDefault<DefaultHasher>> {
+    inner: T,
+    hasher: S,
+}
+
+impl<T> HashTransform<T> {
+    /// Hash the output of `inner` with the default hasher
+    pub fn new(inner: T) -> Self {
+        Self::with_hasher(inner, BuildHasherDefault::default())
+    }
+}
+
+impl<T, S> HashTransform<T, S> {
+    /// Hash the output of `inner` with `hasher`
+    pub fn with_hasher(inner: T, hasher: S) -> Self {
+        Self { inner, hasher }
+    }
+}
+
+impl<T, S, R> Transform<R> for HashTransform<T, S>
+where
+    T: Transform<R>,
+    T::Output: Hash,
+    S: BuildHasher,
+{
+    type Output = u64;
+
+    fn transform(&self, req: R) -> Self::Output {
+        self.hasher.hash_one(self.inner.transform(req))
+    }
+}
+
+/// # Fallible request transformation trait
+///
+/// Some requests shouldn't be cached at all, such as authenticated or
+/// non-idempotent ones. This trait is similar to [`Transform`], but returning
+/// `None` signals that the request should bypass the cache and go straight to
+/// the inner service.
+///
+/// ## Usage
+///
+/// This trait is automatically implemented for functions that take one
+/// argument and return an `Option`. Use
+/// [`CacheLayer::with_try_transformer`](crate::CacheLayer::with_try_transformer)
+/// to use it with a [`CacheLayer`](crate::CacheLayer).
+///
+/// ```rust
+/// use tower_cache::TryTransform;
+///
+/// fn skip_private(req: String) -> Option<String> {
+///     (!req.starts_with("/private")).then_some(req)
+/// }
+///
+/// assert_eq!(skip_private.try_transform("/public".to_string()), Some("/public".to_string()));
+/// assert_eq!(skip_private.try_transform("/private".to_string()), None);
+/// ```
+///
+pub trait TryTransform<R> {
+    /// Output of the transformer
+    type Output;
+
+    /// Transform a key into a reference value for a cache provider, or
+    /// return `None` to bypass the cache.
+    fn try_transform(&self, req: R) -> Option<Self::Output>;
+}
+
+impl<R> TryTransform<R> for () {
+    type Output = R;
+
+    fn try_transform(&self, req: R) -> Option<Self::Output> {
+        Some(req)
+    }
+}
+
+impl<F, R, O> TryTransform<R> for F
+where
+    F: Fn(R) -> Option<O>,
+{
+    type Output = O;
+
+    fn try_transform(&self, req: R) -> Option<Self::Output> {
+        (self)(req)
+    }
+}
+
+/// # Asynchronous request transformation trait
+///
+/// Some cache keys depend on data that has to be looked up asynchronously,
+/// such as resolving a tenant identifier. This is the trait used by
+/// [`CacheService`](crate::CacheService) to derive keys, and every other
+/// transformer is converted into it.
+///
+/// If the future resolves to `None`, the request bypasses the cache: the
+/// provider is not called and the response is not stored.
+///
+/// The future is stored inside the future returned by the
+/// [`CacheService`](crate::CacheService), which is allocated once per request
+/// whatever the transformer. Synchronous transformers return a [`Ready`]
+/// future, so deriving the key doesn't allocate on its own. See the
+/// `transform` benchmark. The future of the service itself stays boxed, as
+/// naming it otherwise needs `impl Trait` in associated types, which isn't
+/// stable yet.
+///
+/// ## Usage
+///
+/// Like [`Transform`], this is implemented for `()` and for functions that
+/// take one argument and return another, through an immediately ready future.
+/// Custom [`Transform`] implementations are wrapped in a [`TransformFn`] to be
+/// used with a [`CacheService`](crate::CacheService), which
+/// [`CacheLayer::with_transform`](crate::CacheLayer::with_transform) does.
+///
+/// To use an async function, wrap it in an [`AsyncTransformFn`]. To use a
+/// [`TryTransform`], wrap it in a [`TryTransformFn`].
+///
+/// ```rust
+/// use tower_cache::{AsyncTransformFn, TransformAsync};
+///
+/// async fn resolve_tenant(req: usize) -> String {
+///     format!("tenant-{req}")
+/// }
+///
+/// # tokio_test::block_on(async {
+/// let transformer = AsyncTransformFn::new(resolve_tenant);
+/// assert_eq!(transformer.transform_async(&2).await, Some("tenant-2".to_string()));
+/// # });
+/// ```
+///
+pub trait TransformAsync<R> {
+    /// Output of the transformer
+    type Output;
+
+    /// Future resolving to the output of the transformer, or `None` to bypass
+    /// the cache
+    type Future: Future<Output = Option<Self::Output>>;
+
+    /// Transform a key into a reference value for a cache provider.
+    ///
+    /// The request is borrowed so that it can be passed to the inner service
+    /// afterwards. Implementations that need an owned request clone it.
+    fn transform_async(&self, req: &R) -> Self::Future;
+
+    /// Return the maximum age of a cached response served for `req`.
+    ///
+    /// Cached responses older than this are treated as misses, even if they
+    /// haven't expired yet. Defaults to `None`, which serves cached responses
+    /// until they expire. See [`crate::CacheLayer::with_max_age`].
+    fn max_age(&self, req: &R) -> Option<Duration> {
+        let _ = req;
+        None
+    }
+
+    /// Return `true` to skip the cache lookup for `req`.
+    ///
+    /// Unlike a transformer resolving to `None`, the response of the inner
+    /// service is still stored, which forces a refresh of the entry. Defaults
+    /// to `false`. See [`crate::CacheLayer::bypass_if`].
+    fn bypass(&self, req: &R) -> bool {
+        let _ = req;
+        false
+    }
+}
+
+impl<R: Clone> TransformAsync<R> for () {
+    type Output = R;
+    type Future = Ready<Option<R>>;
+
+    fn transform_async(&self, req: &R) -> Self::Future {
+        ready(Some(req.clone()))
+    }
+}
+
+impl<F, R, O> TransformAsync<R> for F
+where
+    F: Fn(R) -> O,
+    R: Clone,
+{
+    type Output = O;
+    type Future = Ready<Option<O>>;
+
+    fn transform_async(&self, req: &R) -> Self::Future {
+        ready(Some((self)(req.clone())))
+    }
+}
+
+impl<A, B, R> TransformAsync<R> for Then<A, B>
+where
+    A: Transform<R>,
+    B: Transform<A::Output>,
+    R: Clone,
+{
+    type Output = B::Output;
+    type Future = Ready<Option<B::Output>>;
+
+    fn transform_async(&self, req: &R) -> Self::Future {
+        ready(Some(self.transform(req.clone())))
+    }
+}
+
+/// Adapter implementing [`TransformAsync`] for async functions and closures
+/// returning a future.
+#[derive(Clone, Copy, Debug)]
+pub struct AsyncTransformFn<F>(F);
+
+impl<F> AsyncTransformFn<F> {
+    /// Wrap an async function into a transformer
+    pub fn new(f: F) -> Self {
+        Self(f)
+    }
+}
+
+impl<F, R, Fut> TransformAsync<R> for AsyncTransformFn<F>
+where
+    F: Fn(R) -> Fut,
+    Fut: Future,
+    R: Clone,
+{
+    type Output = Fut::Output;
+    type Future = AsyncTransformFuture<Fut>;
+
+    fn transform_async(&self, req: &R) -> Self::Future {
+        AsyncTransformFuture {
+            inner: (self.0)(req.clone()),
+        }
+    }
+}
+
+pin_project! {
+    /// Future returned by [`AsyncTransformFn`]
+    #[derive(Debug)]
+    pub struct AsyncTransformFuture<Fut> {
+        #[pin]
+        inner: Fut,
+    }
+}
+
+impl<Fut: Future> Future for AsyncTransformFuture<Fut> {
+    type Output = Option<Fut::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.project().inner.poll(cx).map(Some)
+    }
+}
+
+/// Adapter implementing [`TransformAsync`] for a [`TryTransform`].
+#[derive(Clone, Copy, Debug)]
+pub struct TryTransformFn<T>(T);
+
+impl<T> TryTransformFn<T> {
+    /// Wrap a fallible transformer
+    pub fn new(transformer: T) -> Self {
+        Self(transformer)
+    }
+}
+
+impl<T, R> TransformAsync<R> for TryTransformFn<T>
+where
+    T: TryTransform<R>,
+    R: Clone,
+{
+    type Output = T::Output;
+    type Future = Ready<Option<T::Output>>;
+
+    fn transform_async(&self, req: &R) -> Self::Future {
+        ready(self.0.try_transform(req.clone()))
+    }
+}
+
+/// Adapter implementing [`TransformAsync`] for any [`Transform`]
+///
+/// Functions and `()` already implement [`TransformAsync`], but custom
+/// [`Transform`] implementations don't. Requests are cloned before being
+/// transformed, as with functions.
+#[derive(Clone, Copy, Debug)]
+pub struct TransformFn<T>(T);
+
+impl<T> TransformFn<T> {
+    /// Wrap a transformer
+    pub fn new(transformer: T) -> Self {
+        Self(transformer)
+    }
+}
+
+impl<T, R> TransformAsync<R> for TransformFn<T>
+where
+    T: Transform<R>,
+    R: Clone,
+{
+    type Output = T::Output;
+    type Future = Ready<Option<T::Output>>;
+
+    fn transform_async(&self, req: &R) -> Self::Future {
+        ready(Some(self.0.transform(req.clone())))
+    }
+}
+
+/// Adapter implementing [`TransformAsync`] for a [`Transform`] whose output
+/// is converted into `K` with [`IntoCacheKey`]
+///
+/// Requests for which the conversion fails bypass the cache. See
+/// [`CacheLayer::with_key_transformer`](crate::CacheLayer::with_key_transformer).
+pub struct KeyTransformFn<T, K> {
+    transformer: T,
+    _key: PhantomData<fn() -> K>,
+}
+
+impl<T, K> KeyTransformFn<T, K> {
+    /// Wrap a transformer
+    pub fn new(transformer: T) -> Self {
+        Self {
+            transformer,
+            _key: PhantomData,
+        }
+    }
+}
+
+// Custom implementation of Clone as the Clone derive doesn't mark
+// KeyTransformFn as Clone if K is not clone.
+impl<T: Clone, K> Clone for KeyTransformFn<T, K> {
+    fn clone(&self) -> Self {
+        Self::new(self.transformer.clone())
+    }
+}
+
+impl<T: fmt::Debug, K> fmt::Debug for KeyTransformFn<T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("KeyTransformFn")
+            .field(&self.transformer)
+            .finish()
+    }
+}
+
+impl<T, K, R> TransformAsync<R> for KeyTransformFn<T, K>
+where
+    T: Transform<R>,
+    T::Output: IntoCacheKey<K>,
+    R: Clone,
+{
+    type Output = K;
+    type Future = Ready<Option<K>>;
+
+    fn transform_async(&self, req: &R) -> Self::Future {
+        let key = self.transformer.transform(req.clone()).into_cache_key();
+        ready(key.map_err(|e| crate::trace::key_conversion_error(&e)).ok())
+    }
+}
+
+/// # Borrowing request transformation trait
+///
+/// [`Transform`] takes the request by value, which forces the
+/// [`CacheService`](crate::CacheService) to clone it so that it can still be
+/// passed to the inner service. This trait derives the value from a reference
+/// to the request instead, so requests that aren't cheaply cloneable, or not
+/// cloneable at all, can be used.
+///
+/// ## Usage
+///
+/// This trait is automatically implemented for functions that take a
+/// reference to the request. Use
+/// [`CacheLayer::with_ref_transformer`](crate::CacheLayer::with_ref_transformer)
+/// to use it with a [`CacheLayer`](crate::CacheLayer).
+///
+/// ```rust
+/// use tower_cache::TransformRef;
+///
+/// fn path_len(req: &String) -> usize {
+///     req.len()
+/// }
+///
+/// assert_eq!(path_len.transform_ref(&"/hello".to_string()), 6);
+/// ```
+///
+pub trait TransformRef<R> {
+    /// Output of the transformer
+    type Output;
+
+    /// Transform a borrowed key into a reference value for a cache provider.
+    fn transform_ref(&self, req: &R) -> Self::Output;
+}
+
+impl<F, R, O> TransformRef<R> for F
+where
+    F: Fn(&R) -> O,
+{
+    type Output = O;
+
+    fn transform_ref(&self, req: &R) -> Self::Output {
+        (self)(req)
+    }
+}
+
+/// Adapter implementing [`TransformAsync`] for a [`TransformRef`].
+#[derive(Clone, Copy, Debug)]
+pub struct TransformRefFn<T>(T);
+
+impl<T> TransformRefFn<T> {
+    /// Wrap a borrowing transformer
+    pub fn new(transformer: T) -> Self {
+        Self(transformer)
+    }
+}
+
+impl<T, R> TransformAsync<R> for TransformRefFn<T>
+where
+    T: TransformRef<R>,
+{
+    type Output = T::Output;
+    type Future = Ready<Option<T::Output>>;
+
+    fn transform_async(&self, req: &R) -> Self::Future {
+        ready(Some(self.0.transform_ref(req)))
+    }
+}
+
+/// Adapter setting a maximum age on the cached responses served for each
+/// request
+///
+/// Keys are derived by the inner transformer. Created by
+/// [`crate::CacheLayer::with_max_age`].
+#[derive(Clone, Copy, Debug)]
+pub struct WithMaxAge<T, F> {
+    inner: T,
+    max_age: F,
+}
+
+impl<T, F> WithMaxAge<T, F> {
+    /// Wrap a transformer with a function returning the maximum age of the
+    /// cached responses served for a request
+    pub fn new(inner: T, max_age: F) -> Self {
+        Self { inner, max_age }
+    }
+}
+
+impl<T, F, R> TransformAsync<R> for WithMaxAge<T, F>
+where
+    T: TransformAsync<R>,
+    F: Fn(&R) -> Option<Duration>,
+{
+    type Output = T::Output;
+    type Future = T::Future;
+
+    fn transform_async(&self, req: &R) -> Self::Future {
+        self.inner.transform_async(req)
+    }
+
+    fn max_age(&self, req: &R) -> Option<Duration> {
+        (self.max_age)(req)
+    }
+
+    fn bypass(&self, req: &R) -> bool {
+        self.inner.bypass(req)
+    }
+}
+
+/// Adapter skipping the cache lookup for the requests matching a predicate
+///
+/// Keys are derived by the inner transformer. Created by
+/// [`crate::CacheLayer::bypass_if`].
+#[derive(Clone, Copy, Debug)]
+pub struct BypassIf<T, F> {
+    inner: T,
+    predicate: F,
+}
+
+impl<T, F> BypassIf<T, F> {
+    /// Wrap a transformer with a predicate returning `true` for the requests
+    /// that skip the cache lookup
+    pub fn new(inner: T, predicate: F) -> Self {
+        Self { inner, predicate }
+    }
+}
+
+impl<T, F, R> TransformAsync<R> for BypassIf<T, F>
+where
+    T: TransformAsync<R>,
+    F: Fn(&R) -> bool,
+{
+    type Output = T::Output;
+    type Future = T::Future;
+
+    fn transform_async(&self, req: &R) -> Self::Future {
+        self.inner.transform_async(req)
+    }
+
+    fn max_age(&self, req: &R) -> Option<Duration> {
+        self.inner.max_age(req)
+    }
+
+    fn bypass(&self, req: &R) -> bool {
+        (self.predicate)(req) || self.inner.bypass(req)
+    }
+}
+
+/// # Keyed request trait
+///
+/// Requests implementing this trait carry their own cache key, for example
+/// an ID field, which is used by the [`ByKey`] transform. This is useful when
+/// the same request type is cached by several layers, or when the request
+/// can't be used as a key itself. See [`CacheKey`] for an example.
+pub trait Keyed {
+    /// Key of the request
+    type Key;
+
+    /// Return the cache key of the request.
+    fn key(&self) -> Self::Key;
+}
+
+/// Transform using the [`Keyed::key`] of requests as the cache key
+///
+/// The key is derived from a reference to the request, so the request
+/// doesn't need to be cloned.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ByKey;
+
+impl<R> TransformAsync<R> for ByKey
+where
+    R: Keyed,
+{
+    type Output = R::Key;
+    type Future = Ready<Option<R::Key>>;
+
+    fn transform_async(&self, req: &R) -> Self::Future {
+        ready(Some(req.key()))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -79,4 +885,165 @@ mod tests {
 
         assert_eq!(t.transform(2), 4);
     }
+
+    #[test]
+    fn test_then() {
+        let t = (|s: String| s.trim().to_string()).then(|s: String| s.to_lowercase());
+
+        assert_eq!(t.transform("  Hello World ".to_string()), "hello world");
+    }
+
+    #[test]
+    fn test_compose_unit() {
+        let t = compose((), |v: usize| v * 2).then(());
+
+        assert_eq!(t.transform(2), 4);
+    }
+
+    #[test]
+    fn test_ref_closure() {
+        let t = |v: &Vec<usize>| v.len();
+
+        assert_eq!(t.transform_ref(&vec![1, 2, 3]), 3);
+    }
+
+    #[tokio::test]
+    async fn test_by_key() {
+        struct User {
+            id: u64,
+            _name: String,
+        }
+
+        impl Keyed for User {
+            type Key = u64;
+
+            fn key(&self) -> u64 {
+                self.id
+            }
+        }
+
+        let user = User {
+            id: 7,
+            _name: "a".to_string(),
+        };
+        assert_eq!(ByKey.transform_async(&user).await, Some(7));
+    }
+
+    #[tokio::test]
+    async fn test_sync_as_async() {
+        assert_eq!((|v| v * 2).transform_async(&2).await, Some(4));
+    }
+
+    #[tokio::test]
+    async fn test_async_function() {
+        let transformer = AsyncTransformFn::new(|v: usize| async move {
+            tokio::task::yield_now().await;
+            v * 2
+        });
+
+        assert_eq!(transformer.transform_async(&2).await, Some(4));
+    }
+
+    #[test]
+    fn test_try_closure() {
+        let t = |v: usize| v.is_multiple_of(2).then_some(v * 2);
+
+        assert_eq!(t.try_transform(2), Some(4));
+        assert_eq!(t.try_transform(3), None);
+    }
+
+    #[tokio::test]
+    async fn test_try_as_async() {
+        let transformer = TryTransformFn::new(|v: usize| v.is_multiple_of(2).then_some(v * 2));
+
+        assert_eq!(transformer.transform_async(&2).await, Some(4));
+        assert_eq!(transformer.transform_async(&3).await, None);
+    }
+
+    #[test]
+    fn test_hash() {
+        let large = |c: char| std::iter::repeat_n(c, 4096).collect::<String>();
+        let transform = HashTransform::new(());
+
+        assert_ne!(
+            transform.transform(large('a')),
+            transform.transform(large('b'))
+        );
+        assert_eq!(
+            transform.transform(large('a')),
+            transform.transform(large('a'))
+        );
+        assert_eq!(
+            transform.transform(large('a')),
+            HashTransform::new(()).transform(large('a'))
+        );
+    }
+
+    #[test]
+    fn test_hash_composed() {
+        let transform = (|req: (String, usize)| req.0).hashed();
+        assert_eq!(
+            transform.transform(("a".to_string(), 1)),
+            transform.transform(("a".to_string(), 2))
+        );
+        assert_eq!(
+            transform.transform(("a".to_string(), 1)),
+            HashTransform::new(()).transform("a".to_string())
+        );
+
+        // Hashing after another transform
+        let transform = compose(|req: usize| req % 10, HashTransform::new(()));
+        assert_eq!(transform.transform(12), transform.transform(22));
+        assert_ne!(transform.transform(12), transform.transform(13));
+    }
+
+    #[tokio::test]
+    async fn test_with_max_age() {
+        let transformer = WithMaxAge::new((), |v: &u64| Some(Duration::from_secs(*v)));
+
+        assert_eq!(transformer.transform_async(&2).await, Some(2));
+        assert_eq!(transformer.max_age(&2), Some(Duration::from_secs(2)));
+        assert_eq!(().max_age(&2), None);
+    }
+
+    #[tokio::test]
+    async fn test_bypass_if() {
+        let transformer = BypassIf::new(
+            WithMaxAge::new((), |_: &u64| Some(Duration::from_secs(1))),
+            |v: &u64| *v == 0,
+        );
+
+        assert_eq!(transformer.transform_async(&2).await, Some(2));
+        assert!(transformer.bypass(&0));
+        assert!(!transformer.bypass(&2));
+        assert_eq!(transformer.max_age(&2), Some(Duration::from_secs(1)));
+        assert!(!().bypass(&2));
+    }
+
+    struct Even(u64);
+
+    impl IntoCacheKey<u64> for Even {
+        fn into_cache_key(self) -> Result<u64, KeyConversionError> {
+            match self.0 % 2 {
+                0 => Ok(self.0),
+                _ => Err(KeyConversionError::new(format!("{} is odd", self.0))),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_key_transform() {
+        let transformer = KeyTransformFn::<_, u64>::new(|v: u64| Even(v));
+        assert_eq!(transformer.transform_async(&2).await, Some(2));
+        assert_eq!(transformer.transform_async(&3).await, None);
+
+        let transformer = KeyTransformFn::<_, u64>::new(|v: u64| v + 1);
+        assert_eq!(transformer.clone().transform_async(&3).await, Some(4));
+
+        let error = IntoCacheKey::<u64>::into_cache_key(Even(3)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "cannot convert into a cache key: 3 is odd"
+        );
+    }
 }
@@ -0,0 +1,126 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+/// # Per-response TTL policy
+///
+/// By default, responses are stored with the default expiration policy of
+/// the cache provider. A TTL policy lets the inner service influence how
+/// long each response is cached, for example based on a `Cache-Control`
+/// `max-age` it computed. The TTL is sent to the provider with
+/// [`crate::ProviderRequest::Insert`].
+///
+/// ## Usage
+///
+/// In most cases, you don't need to implement this trait directly. It is
+/// automatically implemented for functions taking a reference to the response
+/// and returning an `Option<Duration>`. Use
+/// [`crate::CacheLayer::with_ttl_policy`] to use it with a
+/// [`crate::CacheLayer`].
+///
+/// ```rust
+/// use std::time::Duration;
+/// use tower_cache::TtlPolicy;
+///
+/// let max_age = |res: &(u64, String)| Some(Duration::from_secs(res.0));
+///
+/// assert_eq!(max_age.ttl(&(60, "Hello".to_string())), Some(Duration::from_secs(60)));
+/// ```
+///
+/// This is also implemented for `()`, which always uses the provider default:
+///
+/// ```rust
+/// use tower_cache::TtlPolicy;
+///
+/// assert_eq!(().ttl(&"Hello"), None);
+/// ```
+///
+pub trait TtlPolicy<Res> {
+    /// Return how long `res` should be cached, or `None` to use the default
+    /// of the cache provider.
+    fn ttl(&self, res: &Res) -> Option<Duration>;
+}
+
+impl<Res> TtlPolicy<Res> for () {
+    fn ttl(&self, _res: &Res) -> Option<Duration> {
+        None
+    }
+}
+
+impl<F, Res> TtlPolicy<Res> for F
+where
+    F: Fn(&Res) -> Option<Duration>,
+{
+    fn ttl(&self, res: &Res) -> Option<Duration> {
+        (self)(res)
+    }
+}
+
+/// Shortest TTL returned by [`jitter`], unless the TTL itself is shorter
+const MIN_JITTERED_TTL: Duration = Duration::from_millis(1);
+
+/// Randomize `ttl` within `[ttl - jitter, ttl + jitter]`
+///
+/// The result is at least 1ms, or `ttl` if it is shorter, so that a large
+/// jitter doesn't make entries expire immediately. It saturates at
+/// [`Duration::MAX`].
+pub(crate) fn jitter(ttl: Duration, jitter: Duration) -> Duration {
+    // A new `RandomState` is seeded differently every time, which is enough
+    // to spread out expirations without depending on a random number
+    // generator.
+    let random = RandomState::new().build_hasher().finish();
+    let unit = random as f64 / u64::MAX as f64 * 2.0 - 1.0;
+    let secs = ttl.as_secs_f64() + jitter.as_secs_f64() * unit;
+    Duration::try_from_secs_f64(secs.max(0.0))
+        .unwrap_or(Duration::MAX)
+        .max(MIN_JITTERED_TTL.min(ttl))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit() {
+        assert_eq!(().ttl(&0), None);
+    }
+
+    #[test]
+    fn test_closure() {
+        let ttl = |v: &u64| Some(Duration::from_secs(*v));
+
+        assert_eq!(ttl.ttl(&5), Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_jitter() {
+        let ttl = Duration::from_secs(10);
+        let values: Vec<_> = (0..100)
+            .map(|_| jitter(ttl, Duration::from_secs(2)))
+            .collect();
+
+        assert!(values
+            .iter()
+            .all(|v| *v >= Duration::from_secs(8) && *v <= Duration::from_secs(12)));
+        assert!(values.iter().any(|v| *v != values[0]));
+
+        assert_eq!(jitter(ttl, Duration::ZERO), ttl);
+        assert!(
+            jitter(Duration::from_secs(1), Duration::from_secs(100)) <= Duration::from_secs(101)
+        );
+    }
+
+    #[test]
+    fn test_jitter_bounds() {
+        // Large TTLs saturate instead of panicking
+        assert!(jitter(Duration::MAX, Duration::MAX) >= MIN_JITTERED_TTL);
+        assert!(jitter(Duration::from_secs(1), Duration::MAX) >= MIN_JITTERED_TTL);
+
+        // A jitter larger than the TTL never makes it expire immediately
+        for _ in 0..100 {
+            assert!(jitter(Duration::from_millis(5), Duration::from_secs(1)) >= MIN_JITTERED_TTL);
+        }
+    }
+}
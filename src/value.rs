@@ -0,0 +1,279 @@
+/// # Value transformation trait
+///
+/// By default, responses from the inner service are stored as-is in the
+/// cache provider. A value transformation stores a derived value instead,
+/// such as a compact summary of a large payload, and rebuilds the response
+/// from it on a cache hit.
+///
+/// On a cache miss, the caller gets the response of the inner service, not
+/// the one rebuilt from the stored value.
+///
+/// ## Usage
+///
+/// In most cases, you don't need to implement this trait directly. Use
+/// [`crate::CacheLayer::with_value_transform`] with a pair of functions,
+/// which creates a [`ValueTransformFn`].
+///
+/// ```rust
+/// use tower_cache::{ValueTransform, ValueTransformFn};
+///
+/// let lengths = ValueTransformFn::new(
+///     |res: &Vec<u8>| res.len(),
+///     |len: usize| vec![0; len],
+/// );
+///
+/// assert_eq!(lengths.store(&vec![1, 2, 3]), 3);
+/// assert_eq!(lengths.restore(3), vec![0, 0, 0]);
+/// ```
+///
+/// This is also implemented for `()`, which stores responses as-is:
+///
+/// ```rust
+/// use tower_cache::ValueTransform;
+///
+/// assert_eq!(ValueTransform::<String>::store(&(), &"a".to_string()), "a");
+/// ```
+///
+/// As the response is returned to the caller as well, `()` stores a clone of
+/// it. For large responses, return an `Arc` from the inner service: the
+/// clone then only copies the pointer, and the provider and the caller share
+/// the same value.
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use tower_cache::ValueTransform;
+///
+/// let res = Arc::new(vec![0u8; 1024]);
+/// assert!(Arc::ptr_eq(&().store(&res), &res));
+/// ```
+///
+pub trait ValueTransform<Res> {
+    /// Value stored in the cache provider
+    type Stored;
+
+    /// Derive the value to store from a response of the inner service.
+    fn store(&self, res: &Res) -> Self::Stored;
+
+    /// Rebuild a response from a value returned by the cache provider.
+    fn restore(&self, stored: Self::Stored) -> Res;
+
+    /// Rebuild a response from a stale value served while it is refreshed.
+    ///
+    /// See [`crate::CacheLayer::stale_while_revalidate`]. Defaults to
+    /// [`ValueTransform::restore`].
+    fn restore_stale(&self, stored: Self::Stored) -> Res {
+        self.restore(stored)
+    }
+}
+
+impl<Res> ValueTransform<Res> for ()
+where
+    Res: Clone,
+{
+    type Stored = Res;
+
+    fn store(&self, res: &Res) -> Self::Stored {
+        res.clone()
+    }
+
+    fn restore(&self, stored: Self::Stored) -> Res {
+        stored
+    }
+}
+
+/// Adapter implementing [`ValueTransform`] for a pair of functions.
+#[derive(Clone, Copy, Debug)]
+pub struct ValueTransformFn<F, G> {
+    store: F,
+    restore: G,
+}
+
+impl<F, G> ValueTransformFn<F, G> {
+    /// Create a value transformation from a function deriving the stored
+    /// value, and one rebuilding the response from it
+    pub fn new(store: F, restore: G) -> Self {
+        Self { store, restore }
+    }
+}
+
+impl<F, G, Res, V> ValueTransform<Res> for ValueTransformFn<F, G>
+where
+    F: Fn(&Res) -> V,
+    G: Fn(V) -> Res,
+{
+    type Stored = V;
+
+    fn store(&self, res: &Res) -> Self::Stored {
+        (self.store)(res)
+    }
+
+    fn restore(&self, stored: Self::Stored) -> Res {
+        (self.restore)(stored)
+    }
+}
+
+/// # Request-aware value transformation trait
+///
+/// Like a [`ValueTransform`], this stores a value derived from the responses
+/// of the inner service, but the response is rebuilt with access to the
+/// current request. This allows caching only the serializable part of a
+/// response, and rehydrating the rest from the request on a cache hit.
+///
+/// Use [`crate::CacheLayer::with_value_loader`] with a pair of functions,
+/// which creates a [`ValueLoaderFn`]. A [`ValueTransform`] is wrapped in a
+/// [`ValueTransformLoader`], which ignores the request.
+///
+/// ```rust
+/// use tower_cache::{ValueLoader, ValueLoaderFn};
+///
+/// let greetings = ValueLoaderFn::new(
+///     |res: &String| res.len(),
+///     |len: usize, req: &String| format!("{len} bytes for {req}"),
+/// );
+///
+/// assert_eq!(greetings.save(&"hello".to_string()), 5);
+/// assert_eq!(greetings.load(5, &"a".to_string()), "5 bytes for a");
+/// ```
+pub trait ValueLoader<Req, Res> {
+    /// Value stored in the cache provider
+    type Stored;
+
+    /// Derive the value to store from a response of the inner service.
+    fn save(&self, res: &Res) -> Self::Stored;
+
+    /// Rebuild a response for `req` from a value returned by the cache
+    /// provider.
+    fn load(&self, stored: Self::Stored, req: &Req) -> Res;
+
+    /// Rebuild a response for `req` from a stale value served while it is
+    /// refreshed.
+    ///
+    /// See [`crate::CacheLayer::stale_while_revalidate`]. Defaults to
+    /// [`ValueLoader::load`].
+    fn load_stale(&self, stored: Self::Stored, req: &Req) -> Res {
+        self.load(stored, req)
+    }
+}
+
+impl<Req, Res> ValueLoader<Req, Res> for ()
+where
+    Res: Clone,
+{
+    type Stored = Res;
+
+    fn save(&self, res: &Res) -> Self::Stored {
+        res.clone()
+    }
+
+    fn load(&self, stored: Self::Stored, _req: &Req) -> Res {
+        stored
+    }
+}
+
+/// Adapter implementing [`ValueLoader`] for a [`ValueTransform`], ignoring
+/// the request
+#[derive(Clone, Copy, Debug)]
+pub struct ValueTransformLoader<T>(T);
+
+impl<T> ValueTransformLoader<T> {
+    /// Wrap a value transformation
+    pub fn new(transform: T) -> Self {
+        Self(transform)
+    }
+}
+
+impl<T, Req, Res> ValueLoader<Req, Res> for ValueTransformLoader<T>
+where
+    T: ValueTransform<Res>,
+{
+    type Stored = T::Stored;
+
+    fn save(&self, res: &Res) -> Self::Stored {
+        self.0.store(res)
+    }
+
+    fn load(&self, stored: Self::Stored, _req: &Req) -> Res {
+        self.0.restore(stored)
+    }
+
+    fn load_stale(&self, stored: Self::Stored, _req: &Req) -> Res {
+        self.0.restore_stale(stored)
+    }
+}
+
+/// Adapter implementing [`ValueLoader`] for a pair of functions.
+#[derive(Clone, Copy, Debug)]
+pub struct ValueLoaderFn<F, G> {
+    save: F,
+    load: G,
+}
+
+impl<F, G> ValueLoaderFn<F, G> {
+    /// Create a value transformation from a function deriving the stored
+    /// value, and one rebuilding the response from it and the request
+    pub fn new(save: F, load: G) -> Self {
+        Self { save, load }
+    }
+}
+
+impl<F, G, Req, Res, V> ValueLoader<Req, Res> for ValueLoaderFn<F, G>
+where
+    F: Fn(&Res) -> V,
+    G: Fn(V, &Req) -> Res,
+{
+    type Stored = V;
+
+    fn save(&self, res: &Res) -> Self::Stored {
+        (self.save)(res)
+    }
+
+    fn load(&self, stored: Self::Stored, req: &Req) -> Res {
+        (self.load)(stored, req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit() {
+        assert_eq!(().store(&5), 5);
+        assert_eq!(ValueTransform::<usize>::restore(&(), 5), 5);
+    }
+
+    #[test]
+    fn test_fn() {
+        let transform = ValueTransformFn::new(
+            |res: &String| res.split(',').count(),
+            |count: usize| vec!["?"; count].join(","),
+        );
+
+        assert_eq!(transform.store(&"a,b,c".to_string()), 3);
+        assert_eq!(transform.restore(2), "?,?");
+    }
+
+    #[test]
+    fn test_loader() {
+        let loader = ValueLoaderFn::new(
+            |res: &(u32, String)| res.0,
+            |id: u32, req: &String| (id, req.to_uppercase()),
+        );
+
+        assert_eq!(loader.save(&(1, "a".to_string())), 1);
+        assert_eq!(loader.load(1, &"b".to_string()), (1, "B".to_string()));
+        assert_eq!(loader.load_stale(1, &"c".to_string()), (1, "C".to_string()));
+
+        // Value transformations ignore the request
+        assert_eq!(ValueLoader::<_, usize>::load(&(), 5, &"a"), 5);
+        let lengths = ValueTransformLoader::new(ValueTransformFn::new(
+            |res: &String| res.len(),
+            |len: usize| "?".repeat(len),
+        ));
+        assert_eq!(
+            ValueLoader::<&str, _>::save(&lengths, &"abc".to_string()),
+            3
+        );
+        assert_eq!(lengths.load(2, &"a"), "??");
+    }
+}
@@ -0,0 +1,24 @@
+use std::convert::Infallible;
+use tower::{service_fn, Service, ServiceBuilder};
+use tower_cache::{noop::NoopProvider, CacheLayer};
+
+#[derive(Clone)]
+struct Search {
+    query: String,
+    min_score: f64,
+}
+
+async fn handler(req: Search) -> Result<String, Infallible> {
+    Ok(req.query)
+}
+
+fn main() {
+    let mut service = ServiceBuilder::new()
+        .layer(CacheLayer::new(NoopProvider::new::<Search, String>()))
+        .service(service_fn(handler));
+
+    let _ = Service::call(&mut service, Search {
+        query: "a".to_string(),
+        min_score: 0.5,
+    });
+}
@@ -0,0 +1,6 @@
+use tower_cache::{map::MapProvider, CacheLayer};
+
+fn main() {
+    let _layer = CacheLayer::new(MapProvider::new::<u64, String>())
+        .with_key_transformer(|req: String| req.to_lowercase());
+}